@@ -0,0 +1,4 @@
+pub mod syscalls;
+mod syscall_trace;
+
+pub use syscall_trace::SyscallTracer;
\ No newline at end of file
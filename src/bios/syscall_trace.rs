@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+use crate::emotion_engine::Cpu;
+use crate::emotion_engine::cpu::{A0_REG, T0_REG, V0_REG, V1_REG};
+use super::syscalls::{get_syscall, SyscallArgument};
+
+//Strings longer than this are cut off so a bad pointer doesn't flood the log
+const MAX_STRING_LENGTH: usize = 256;
+const MAX_STRING_ARRAY_LENGTH: u32 = 16;
+//Syscalls like Exit or LoadExecPS2 never come back, so don't let them pile up forever
+const MAX_PENDING_SYSCALLS: usize = 64;
+
+struct PendingSyscall {
+    return_address: u32,
+    name: String,
+}
+
+pub struct SyscallTracer {
+    pending: Vec<PendingSyscall>,
+    //stdout unless something else wants the log
+    output: Box<dyn Write>,
+}
+
+//EE kernel calls use a0-a3 followed by t0-t3 for arguments
+fn read_argument_register(cpu: &Cpu, index: usize) -> u32 {
+    let register = if index < 4 {
+        A0_REG + index as u8
+    } else {
+        T0_REG + (index - 4) as u8
+    };
+
+    cpu.read_ee_register_32(register)
+}
+
+fn read_string(cpu: &Cpu, address: u32) -> Option<String> {
+    let mut data: Vec<u8> = vec![];
+
+    for offset in 0..MAX_STRING_LENGTH {
+        match cpu.memory.try_read_address(address as usize + offset)? {
+            0 => break,
+            byte => data.push(byte),
+        }
+    }
+
+    Some(String::from_utf8_lossy(&data).to_string())
+}
+
+fn read_word(cpu: &Cpu, address: u32) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+
+    for (offset, byte) in bytes.iter_mut().enumerate() {
+        *byte = cpu.memory.try_read_address(address as usize + offset)?;
+    }
+
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn format_string(cpu: &Cpu, address: u32) -> String {
+    match read_string(cpu, address) {
+        Some(string) => format!("{:?}", string),
+        None => format!("0x{:08x} <unmapped>", address),
+    }
+}
+
+fn format_string_array(cpu: &Cpu, address: u32, count: u32) -> String {
+    let strings: Vec<String> = (0..count.min(MAX_STRING_ARRAY_LENGTH))
+        .map(|index| match read_word(cpu, address + index * 4) {
+            Some(string_address) => format_string(cpu, string_address),
+            None => "<unmapped>".to_string(),
+        })
+        .collect();
+
+    format!("[{}]", strings.join(", "))
+}
+
+pub fn format_syscall(cpu: &Cpu) -> String {
+    let number = cpu.read_ee_register_32(V1_REG) as i32;
+
+    match get_syscall(number) {
+        Some(syscall) => {
+            let mut arguments: Vec<String> = vec![];
+            let mut previous_value: u32 = 0;
+
+            for (index, argument) in syscall.arguments.iter().enumerate() {
+                let value = read_argument_register(cpu, index);
+
+                arguments.push(match argument {
+                    SyscallArgument::Int => format!("{}", value as i32),
+                    SyscallArgument::Hex => format!("0x{:x}", value),
+                    SyscallArgument::Bool => format!("{}", value != 0),
+                    SyscallArgument::Pointer => format!("0x{:08x}", value),
+                    SyscallArgument::CString => format_string(cpu, value),
+                    SyscallArgument::CStringArray => format_string_array(cpu, value, previous_value),
+                });
+
+                previous_value = value;
+            }
+
+            format!("{}({})", syscall.name, arguments.join(", "))
+        },
+        None => format!(
+            "Unknown_{}{:02x}(0x{:x}, 0x{:x}, 0x{:x}, 0x{:x})",
+            if number < 0 { "-" } else { "" },
+            number.unsigned_abs(),
+            read_argument_register(cpu, 0),
+            read_argument_register(cpu, 1),
+            read_argument_register(cpu, 2),
+            read_argument_register(cpu, 3),
+        ),
+    }
+}
+
+impl SyscallTracer {
+    pub fn new() -> SyscallTracer {
+        SyscallTracer::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> SyscallTracer {
+        SyscallTracer {
+            pending: vec![],
+            output,
+        }
+    }
+
+    //A log that can't be written to isn't worth stopping emulation for
+    fn log(&mut self, line: &str) {
+        let _ = writeln!(self.output, "{}", line);
+    }
+
+    //Should be called when the SYSCALL instruction is executed, before the exception is taken
+    pub fn trace_call(&mut self, cpu: &Cpu) {
+        let call = format_syscall(cpu);
+
+        self.log(&format!("[EE SYSCALL] 0x{:08x}: {}", cpu.pc, call));
+
+        if self.pending.len() >= MAX_PENDING_SYSCALLS {
+            self.pending.remove(0);
+        }
+
+        self.pending.push(PendingSyscall {
+            return_address: cpu.pc.wrapping_add(4),
+            name: call.split('(').next().unwrap_or_default().to_string(),
+        });
+    }
+
+    //Should be called before every instruction, logs v0 once the kernel returns to the caller
+    pub fn trace_return(&mut self, cpu: &Cpu) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        if let Some(index) = self.pending.iter().rposition(|pending| pending.return_address == cpu.pc) {
+            let pending = self.pending.remove(index);
+            let value = cpu.read_ee_register_32(V0_REG);
+
+            self.log(&format!("[EE SYSCALL] 0x{:08x}: {} -> {} (0x{:x})", pending.return_address.wrapping_sub(4), pending.name, value as i32, value));
+        }
+    }
+}
+
+impl Default for SyscallTracer {
+    fn default() -> SyscallTracer {
+        SyscallTracer::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SyscallTracer;
+    use crate::emotion_engine::cpu::test::create_mock_cpu;
+    use crate::emotion_engine::cpu::{A0_REG, V0_REG, V1_REG};
+    use crate::test_utils::SharedBuffer;
+
+    #[test]
+    fn test_trace_load_exec() {
+        let mut cpu = create_mock_cpu();
+        cpu.memory.write_address(0x1000, 16, b"cdrom0:\\MAIN;1\0");
+        cpu.memory.write_address(0x2000, 4, &0x1000u32.to_le_bytes());
+
+        //LoadExecPS2("cdrom0:\MAIN;1", 1, ["cdrom0:\MAIN;1"])
+        cpu.pc = 0x100;
+        cpu.write_ee_register_32(V1_REG, 0x06);
+        cpu.write_ee_register_32(A0_REG, 0x1000);
+        cpu.write_ee_register_32(A0_REG + 1, 1);
+        cpu.write_ee_register_32(A0_REG + 2, 0x2000);
+
+        let output = SharedBuffer::new();
+        let mut tracer = SyscallTracer::with_output(Box::new(output.clone()));
+        tracer.trace_call(&cpu);
+
+        //Nothing is logged until the kernel comes back to the instruction after the SYSCALL
+        cpu.pc = 0x104;
+        cpu.write_ee_register_32(V0_REG, -1i32 as u32);
+        tracer.trace_return(&cpu);
+
+        assert_eq!(output.text(), concat!(
+            "[EE SYSCALL] 0x00000100: LoadExecPS2(\"cdrom0:\\\\MAIN;1\", 1, [\"cdrom0:\\\\MAIN;1\"])\n",
+            "[EE SYSCALL] 0x00000100: LoadExecPS2 -> -1 (0xffffffff)\n",
+        ));
+    }
+}
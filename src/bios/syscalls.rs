@@ -1,9 +1,163 @@
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SyscallArgument {
+    Int,
+    Hex,
+    Bool,
+    Pointer,
+    CString,
+    //Array of string pointers, the length is taken from the previous argument (argc/argv pairs)
+    CStringArray,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Syscall {
+    pub number: u8,
+    pub name: &'static str,
+    pub arguments: &'static [SyscallArgument],
+}
+
+use SyscallArgument::*;
+
+const fn syscall(number: u8, name: &'static str, arguments: &'static [SyscallArgument]) -> Syscall {
+    Syscall {
+        number,
+        name,
+        arguments,
+    }
+}
+
+//Names mostly follow the ps2sdk/PCSX2 tables
+//Interrupt safe variants (iSignalSema etc) are called with a negated number and share the table
+pub const SYSCALLS: &[Syscall] = &[
+    syscall(0x00, "RFU000_FullReset", &[]),
+    syscall(0x01, "ResetEE", &[Hex]),
+    syscall(0x02, "SetGsCrt", &[Bool, Int, Bool]),
+    syscall(0x04, "Exit", &[Int]),
+    syscall(0x05, "_ExceptionEpilogue", &[]),
+    syscall(0x06, "LoadExecPS2", &[CString, Int, CStringArray]),
+    syscall(0x07, "ExecPS2", &[Pointer, Pointer, Int, CStringArray]),
+    syscall(0x0A, "AddSbusIntcHandler", &[Int, Pointer]),
+    syscall(0x0B, "RemoveSbusIntcHandler", &[Int]),
+    syscall(0x0C, "Interrupt2Iop", &[Int]),
+    syscall(0x0D, "SetVTLBRefillHandler", &[Int, Pointer]),
+    syscall(0x0E, "SetVCommonHandler", &[Int, Pointer]),
+    syscall(0x0F, "SetVInterruptHandler", &[Int, Pointer]),
+    syscall(0x10, "AddIntcHandler", &[Int, Pointer, Int, Pointer]),
+    syscall(0x11, "RemoveIntcHandler", &[Int, Int]),
+    syscall(0x12, "AddDmacHandler", &[Int, Pointer, Int, Pointer]),
+    syscall(0x13, "RemoveDmacHandler", &[Int, Int]),
+    syscall(0x14, "_EnableIntc", &[Int]),
+    syscall(0x15, "_DisableIntc", &[Int]),
+    syscall(0x16, "_EnableDmac", &[Int]),
+    syscall(0x17, "_DisableDmac", &[Int]),
+    syscall(0x18, "SetAlarm", &[Int, Pointer, Pointer]),
+    syscall(0x19, "ReleaseAlarm", &[Int]),
+    syscall(0x1A, "_iEnableIntc", &[Int]),
+    syscall(0x1B, "_iDisableIntc", &[Int]),
+    syscall(0x1C, "_iEnableDmac", &[Int]),
+    syscall(0x1D, "_iDisableDmac", &[Int]),
+    syscall(0x1E, "iSetAlarm", &[Int, Pointer, Pointer]),
+    syscall(0x1F, "iReleaseAlarm", &[Int]),
+    syscall(0x20, "CreateThread", &[Pointer]),
+    syscall(0x21, "DeleteThread", &[Int]),
+    syscall(0x22, "StartThread", &[Int, Pointer]),
+    syscall(0x23, "ExitThread", &[]),
+    syscall(0x24, "ExitDeleteThread", &[]),
+    syscall(0x25, "TerminateThread", &[Int]),
+    syscall(0x26, "iTerminateThread", &[Int]),
+    syscall(0x27, "DisableDispatchThread", &[]),
+    syscall(0x28, "EnableDispatchThread", &[]),
+    syscall(0x29, "ChangeThreadPriority", &[Int, Int]),
+    syscall(0x2A, "iChangeThreadPriority", &[Int, Int]),
+    syscall(0x2B, "RotateThreadReadyQueue", &[Int]),
+    syscall(0x2C, "_iRotateThreadReadyQueue", &[Int]),
+    syscall(0x2D, "ReleaseWaitThread", &[Int]),
+    syscall(0x2E, "iReleaseWaitThread", &[Int]),
+    syscall(0x2F, "GetThreadId", &[]),
+    syscall(0x30, "ReferThreadStatus", &[Int, Pointer]),
+    syscall(0x31, "iReferThreadStatus", &[Int, Pointer]),
+    syscall(0x32, "SleepThread", &[]),
+    syscall(0x33, "WakeupThread", &[Int]),
+    syscall(0x34, "_iWakeupThread", &[Int]),
+    syscall(0x35, "CancelWakeupThread", &[Int]),
+    syscall(0x36, "iCancelWakeupThread", &[Int]),
+    syscall(0x37, "SuspendThread", &[Int]),
+    syscall(0x38, "_iSuspendThread", &[Int]),
+    syscall(0x39, "ResumeThread", &[Int]),
+    syscall(0x3A, "iResumeThread", &[Int]),
+    syscall(0x3B, "JoinThread", &[]),
+    syscall(0x3C, "SetupThread", &[Pointer, Pointer, Int, Pointer, Pointer]),
+    syscall(0x3D, "SetupHeap", &[Pointer, Int]),
+    syscall(0x3E, "EndOfHeap", &[]),
+    syscall(0x40, "CreateSema", &[Pointer]),
+    syscall(0x41, "DeleteSema", &[Int]),
+    syscall(0x42, "SignalSema", &[Int]),
+    syscall(0x43, "iSignalSema", &[Int]),
+    syscall(0x44, "WaitSema", &[Int]),
+    syscall(0x45, "PollSema", &[Int]),
+    syscall(0x46, "iPollSema", &[Int]),
+    syscall(0x47, "ReferSemaStatus", &[Int, Pointer]),
+    syscall(0x48, "iReferSemaStatus", &[Int, Pointer]),
+    syscall(0x4A, "SetOsdConfigParam", &[Pointer]),
+    syscall(0x4B, "GetOsdConfigParam", &[Pointer]),
+    syscall(0x4C, "GetGsHParam", &[Pointer, Pointer, Pointer, Pointer]),
+    syscall(0x4D, "GetGsVParam", &[Pointer, Pointer, Pointer, Pointer]),
+    syscall(0x4E, "SetGsHParam", &[Int, Int, Int, Int]),
+    syscall(0x4F, "SetGsVParam", &[Int, Int, Int, Int]),
+    syscall(0x5B, "GetEntryAddress", &[Int]),
+    syscall(0x5C, "EnableIntcHandler", &[Int]),
+    syscall(0x5D, "DisableIntcHandler", &[Int]),
+    syscall(0x5E, "EnableDmacHandler", &[Int]),
+    syscall(0x5F, "DisableDmacHandler", &[Int]),
+    syscall(0x60, "KSeg0", &[Bool]),
+    syscall(0x61, "EnableCache", &[Hex]),
+    syscall(0x62, "DisableCache", &[Hex]),
+    syscall(0x63, "GetCop0", &[Int]),
+    syscall(0x64, "FlushCache", &[Hex]),
+    syscall(0x66, "CpuConfig", &[Hex]),
+    syscall(0x67, "iGetCop0", &[Int]),
+    syscall(0x68, "iFlushCache", &[Hex]),
+    syscall(0x6A, "iCpuConfig", &[Hex]),
+    syscall(0x6B, "sceSifStopDma", &[]),
+    syscall(0x6C, "SetCPUTimerHandler", &[Pointer]),
+    syscall(0x6D, "SetCPUTimer", &[Int]),
+    syscall(0x6E, "SetOsdConfigParam2", &[Pointer, Int, Int]),
+    syscall(0x6F, "GetOsdConfigParam2", &[Pointer, Int, Int]),
+    syscall(0x70, "GsGetIMR", &[]),
+    syscall(0x71, "GsPutIMR", &[Hex]),
+    syscall(0x72, "SetPgifHandler", &[Pointer]),
+    syscall(0x73, "SetVSyncFlag", &[Pointer, Pointer]),
+    syscall(0x74, "SetSyscall", &[Int, Pointer]),
+    syscall(0x75, "_print", &[CString]),
+    syscall(0x76, "sceSifDmaStat", &[Int]),
+    syscall(0x77, "sceSifSetDma", &[Pointer, Int]),
+    syscall(0x78, "sceSifSetDChain", &[]),
+    syscall(0x79, "sceSifSetReg", &[Hex, Hex]),
+    syscall(0x7A, "sceSifGetReg", &[Hex]),
+    syscall(0x7B, "ExecOSD", &[Int, CStringArray]),
+    syscall(0x7C, "Deci2Call", &[Int, Pointer]),
+    syscall(0x7D, "PSMode", &[]),
+    syscall(0x7E, "MachineType", &[]),
+    syscall(0x7F, "GetMemorySize", &[]),
+];
+
+//The syscall number is passed in v1, negative numbers are the interrupt safe versions
+pub fn get_syscall(number: i32) -> Option<&'static Syscall> {
+    let index = number.unsigned_abs();
+
+    SYSCALLS.iter().find(|syscall| syscall.number as u32 == index)
+}
+
 fn reset_ee(reset_flag: u8) {
     //idk
 }
 
 fn set_gs_crt(interlaced: bool, display_mode: u8, frame: bool) {
-    
+
 
     load_exec_ps2("rom0:OSDSYS", 1, "BootBrowser");
-}
\ No newline at end of file
+}
+
+fn load_exec_ps2(filename: &str, argc: u32, argv: &str) {
+    //idk
+}
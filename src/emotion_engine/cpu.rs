@@ -1,11 +1,39 @@
-use super::{instructions::{Instruction, NOP}, memory::{Memory, MiB}};
-
+use std::error::Error;
+use super::memory::{Address, Memory, RomImages};
+use super::instruction_parser::decode_instruction;
+use crate::mips::{Instruction, NOP};
+use crate::bios::SyscallTracer;
+
+pub const V0_REG: u8 = 2;
+pub const V1_REG: u8 = 3;
+pub const A0_REG: u8 = 4;
+pub const T0_REG: u8 = 8;
 pub const SP_REG: u8 = 29;
 pub const RA_REG: u8 = 31;
 
+//Kernel calls that print, _print(string) and Deci2Call(DECI2_KPUTS, parameters) with the string
+//pointer first in the parameters
+const SYSCALL_PRINT: u32 = 0x75;
+const SYSCALL_DECI2_CALL: u32 = 0x7C;
+const DECI2_KPUTS: u32 = 0x10;
+//Stops a bad pointer from printing the whole of memory
+const MAX_TTY_STRING_LENGTH: usize = 4096;
+
+//Both the EE and IOP start executing from the start of ROM0
+pub const RESET_VECTOR: u32 = 0xBFC00000;
+
+//KSEG0 and KSEG1 are unmapped windows onto the bottom 512MiB, the kernel reaches devices through KSEG1
+fn physical_address(address: u32) -> Address {
+    match address {
+        0x80000000..=0xBFFFFFFF => (address & 0x1FFFFFFF) as Address,
+        _ => address as Address,
+    }
+}
+
 pub enum Exception {
     IntegerOverflow,
     Breakpoint(u32),
+    Syscall(u32),
 }
 
 pub struct Cpu {
@@ -17,12 +45,19 @@ pub struct Cpu {
     pub lo1: u64,
     pub sa: u32,
     pub memory: Memory,
+    pub syscall_tracer: Option<SyscallTracer>,
 }
 
 impl Cpu {
-    pub fn new(bios: &[u8; 4 * MiB], pc: u32) -> Cpu {
-        println!("Hello Debug!");
+    pub fn new(bios: &[u8], pc: u32) -> Result<Cpu, Box<dyn Error>> {
+        Ok(Cpu::with_memory(Memory::new(bios)?, pc))
+    }
+
+    pub fn from_roms(roms: &RomImages) -> Result<Cpu, Box<dyn Error>> {
+        Ok(Cpu::with_memory(Memory::with_roms(roms)?, RESET_VECTOR))
+    }
 
+    fn with_memory(memory: Memory, pc: u32) -> Cpu {
         Cpu {
             ee_registers: [0; 32],
             pc,
@@ -31,7 +66,8 @@ impl Cpu {
             hi1: 0,
             lo1: 0,
             sa: 0,
-            memory: Memory::new(bios),
+            memory,
+            syscall_tracer: None,
         }
     }
 
@@ -81,9 +117,87 @@ impl Cpu {
         //idk
     }
 
+    pub fn set_syscall_tracing(&mut self, enabled: bool) {
+        self.syscall_tracer = if enabled {
+            Some(SyscallTracer::new())
+        } else {
+            None
+        };
+    }
+
     #[inline(always)]
     pub fn execute_instruction(&mut self, instruction: Instruction) {
-        
+        //Tracer is taken out so it can look at the whole cpu state
+        if let Some(mut tracer) = self.syscall_tracer.take() {
+            tracer.trace_return(self);
+
+            if let Instruction::SYSCALL { .. } = instruction {
+                tracer.trace_call(self);
+            }
+
+            self.syscall_tracer = Some(tracer);
+        }
+
+        //Just enough for the kernel to build a device address and store to it, branches aren't taken yet
+        match instruction {
+            Instruction::SYSCALL { code } => {
+                self.capture_tty();
+                self.throw_exception(Exception::Syscall(code));
+            },
+            Instruction::LUI { rt, imm } => self.write_ee_register_64(rt, ((imm as u32) << 16) as i32 as u64),
+            Instruction::ORI { rt, rs, imm } => self.write_ee_register_64(rt, self.read_ee_register_64(rs) | imm as u64),
+            Instruction::ADDIU { rt, rs, imm } => self.write_ee_register_64(rt, self.read_ee_register_32(rs).wrapping_add(imm as u32) as i32 as u64),
+            Instruction::SB { rt, off, base } => self.store(rt, base, off, 1),
+            Instruction::SH { rt, off, base } => self.store(rt, base, off, 2),
+            Instruction::SW { rt, off, base } => self.store(rt, base, off, 4),
+            Instruction::SD { rt, off, base } => self.store(rt, base, off, 8),
+            Instruction::SQ { rt, off, base } => self.store(rt, base, off, 16),
+            _ => {},
+        }
+    }
+
+    //The bottom bytes of rt, SQ ignores the bottom 4 address bits instead of needing them aligned. Address
+    //errors aren't raised yet so a misaligned store is dropped
+    fn store(&mut self, rt: u8, base: u8, off: u16, length: usize) {
+        let address = self.read_ee_register_32(base).wrapping_add(off as i16 as u32);
+        let address = if length == 16 { address & !0xF } else { address };
+
+        if address.is_multiple_of(length as u32) {
+            let value = self.read_ee_register(rt).to_le_bytes();
+            self.memory.try_write_address(physical_address(address), length, &value[..length]);
+        }
+    }
+
+    //Runs one instruction, branches aren't taken yet so the EE just walks forward through memory
+    pub fn step(&mut self) {
+        let instruction = decode_instruction(self.read_word(self.pc)).unwrap_or(NOP);
+
+        self.execute_instruction(instruction);
+        self.pc = self.pc.wrapping_add(4);
+    }
+
+    fn read_string(&self, address: u32) -> Vec<u8> {
+        (0..MAX_TTY_STRING_LENGTH)
+            .map(|offset| self.memory.try_read_address(physical_address(address) + offset).unwrap_or(0))
+            .take_while(|byte| *byte != 0)
+            .collect()
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        (0..4).rev().fold(0, |word, offset| word << 8 | self.memory.try_read_address(physical_address(address) + offset).unwrap_or(0) as u32)
+    }
+
+    //The kernel would send these to a devkit, they go to the TTY instead
+    fn capture_tty(&mut self) {
+        let (number, a0, a1) = (self.read_ee_register_32(V1_REG), self.read_ee_register_32(A0_REG), self.read_ee_register_32(A0_REG + 1));
+
+        let string = match number {
+            SYSCALL_PRINT => self.read_string(a0),
+            SYSCALL_DECI2_CALL if a0 == DECI2_KPUTS && a1 != 0 => self.read_string(self.read_word(a1)),
+            _ => return,
+        };
+
+        self.memory.tty.write(&string);
     }
 
     fn execute(&mut self, address: usize) {
@@ -98,9 +212,14 @@ impl Cpu {
 #[cfg(test)]
 pub mod test {
     use super::Cpu;
-    use super::super::memory::MiB;
+    use super::super::memory::{Memory, MiB};
 
     pub fn create_mock_cpu() -> Cpu {
-        Cpu::new(&[0u8; 4 * MiB], 0)
+        Cpu::new(&vec![0u8; 4 * MiB], 0).unwrap()
+    }
+
+    //A zeroed BIOS, for tests that only need the memory map
+    pub fn create_mock_memory() -> Memory {
+        Memory::new(&vec![0u8; 4 * MiB]).unwrap()
     }
 }
\ No newline at end of file
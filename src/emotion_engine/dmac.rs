@@ -0,0 +1,397 @@
+use crate::sif::{SharedSif, SIF_TAG_WORDS};
+
+//Only the SIF channels are here so far, 5 (SIF0), 6 (SIF1) and 7 (SIF2) live at 0x1000C000, 0x1000C400 and 0x1000C800
+//Finished channels are flagged in D_STAT but the EE doesn't take interrupts yet so nothing raises INT1 from them
+const CHANNELS_BASE: u32 = 0x1000C000;
+const FIRST_CHANNEL: u32 = 5;
+const CHANNEL_COUNT: usize = 3;
+
+const CHCR: u32 = 0x00;
+const MADR: u32 = 0x10;
+const QWC: u32 = 0x20;
+const TADR: u32 = 0x30;
+const ASR0: u32 = 0x40;
+const ASR1: u32 = 0x50;
+const SADR: u32 = 0x80;
+
+pub const D_CTRL: u32 = 0x1000E000;
+pub const D_STAT: u32 = 0x1000E010;
+pub const D_PCR: u32 = 0x1000E020;
+pub const D_SQWC: u32 = 0x1000E030;
+pub const D_RBSR: u32 = 0x1000E040;
+pub const D_RBOR: u32 = 0x1000E050;
+pub const D_STADR: u32 = 0x1000E060;
+
+const CHCR_CHAIN_MODE: u32 = 1 << 2;
+const CHCR_TAG_TRANSFER: u32 = 1 << 6;
+const CHCR_TAG_INTERRUPT: u32 = 1 << 7;
+const CHCR_START: u32 = 1 << 8;
+
+const D_CTRL_ENABLE: u32 = 1 << 0;
+//Status bits are cleared by writing a 1, mask bits are flipped by writing a 1
+const D_STAT_CLEAR_BITS: u32 = 0x0000e3ff;
+const D_STAT_TOGGLE_BITS: u32 = 0x63ff0000;
+
+//Only main RAM is reachable from the SIF channels, tags pointing at the scratchpad are wrapped into it
+const RAM_MASK: u32 = 0x01fffff0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EeDmaChannel {
+    Sif0 = 5,
+    Sif1 = 6,
+    Sif2 = 7,
+}
+
+const CHANNELS: [EeDmaChannel; CHANNEL_COUNT] = [EeDmaChannel::Sif0, EeDmaChannel::Sif1, EeDmaChannel::Sif2];
+
+#[derive(Copy, Clone, Debug, Default)]
+struct ChannelRegisters {
+    chcr: u32,
+    madr: u32,
+    qwc: u32,
+    tadr: u32,
+    asr: [u32; 2],
+    sadr: u32,
+    //Set once the tag being worked on says the chain stops after its data
+    last_tag: bool,
+}
+
+//One of the 128 bit DMAtags the chain modes follow
+struct DmaTag {
+    qwc: u32,
+    id: u32,
+    irq: bool,
+    address: u32,
+}
+
+impl DmaTag {
+    fn new(tag: u64) -> DmaTag {
+        DmaTag {
+            qwc: tag as u32 & 0xffff,
+            id: (tag >> 28) as u32 & 7,
+            irq: tag >> 31 & 1 != 0,
+            address: (tag >> 32) as u32 & RAM_MASK,
+        }
+    }
+}
+
+pub struct Dmac {
+    channels: [ChannelRegisters; CHANNEL_COUNT],
+    ctrl: u32,
+    stat: u32,
+    pcr: u32,
+    sqwc: u32,
+    rbsr: u32,
+    rbor: u32,
+    stadr: u32,
+    sif: SharedSif,
+}
+
+fn channel_for_address(address: u32) -> Option<(usize, u32)> {
+    match address {
+        0x1000C000..=0x1000CBFF => Some((((address - CHANNELS_BASE) >> 10) as usize, address & 0xff)),
+        _ => None,
+    }
+}
+
+fn read_word(ram: &[u8], address: u32) -> u32 {
+    let address = address as usize % ram.len();
+    u32::from_le_bytes([ram[address], ram[address + 1], ram[address + 2], ram[address + 3]])
+}
+
+fn write_word(ram: &mut [u8], address: u32, value: u32) {
+    let address = address as usize % ram.len();
+    ram[address..address + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_qword(ram: &[u8], address: u32) -> [u32; 4] {
+    [0, 4, 8, 12].map(|offset| read_word(ram, address + offset))
+}
+
+impl Dmac {
+    pub fn new(sif: SharedSif) -> Dmac {
+        Dmac {
+            channels: [ChannelRegisters::default(); CHANNEL_COUNT],
+            ctrl: 0,
+            stat: 0,
+            pcr: 0,
+            sqwc: 0,
+            rbsr: 0,
+            rbor: 0,
+            stadr: 0,
+            sif,
+        }
+    }
+
+    pub fn read(&self, address: u32) -> u32 {
+        if let Some((channel, register)) = channel_for_address(address) {
+            let channel = &self.channels[channel];
+
+            return match register {
+                CHCR => channel.chcr,
+                MADR => channel.madr,
+                QWC => channel.qwc,
+                TADR => channel.tadr,
+                ASR0 => channel.asr[0],
+                ASR1 => channel.asr[1],
+                SADR => channel.sadr,
+                _ => 0,
+            };
+        }
+
+        match address {
+            D_CTRL => self.ctrl,
+            D_STAT => self.stat,
+            D_PCR => self.pcr,
+            D_SQWC => self.sqwc,
+            D_RBSR => self.rbsr,
+            D_RBOR => self.rbor,
+            D_STADR => self.stadr,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32, ram: &mut [u8]) {
+        if let Some((channel, register)) = channel_for_address(address) {
+            let channel = &mut self.channels[channel];
+
+            match register {
+                CHCR => {
+                    //A new transfer starts from a fresh tag
+                    if channel.chcr & CHCR_START == 0 && value & CHCR_START != 0 {
+                        channel.last_tag = false;
+                    }
+
+                    channel.chcr = value;
+                },
+                MADR => channel.madr = value,
+                QWC => channel.qwc = value & 0xffff,
+                TADR => channel.tadr = value,
+                ASR0 => channel.asr[0] = value,
+                ASR1 => channel.asr[1] = value,
+                SADR => channel.sadr = value & 0x3ff0,
+                _ => {},
+            }
+        } else {
+            match address {
+                D_CTRL => self.ctrl = value,
+                D_STAT => self.stat = (self.stat & !(value & D_STAT_CLEAR_BITS)) ^ (value & D_STAT_TOGGLE_BITS),
+                D_PCR => self.pcr = value,
+                D_SQWC => self.sqwc = value,
+                D_RBSR => self.rbsr = value,
+                D_RBOR => self.rbor = value,
+                D_STADR => self.stadr = value,
+                _ => {},
+            }
+        }
+
+        self.run_pending(ram);
+    }
+
+    //Channels waiting on the IOP pick up where they left off, called again whenever the IOP has had a chance to run
+    pub fn run_pending(&mut self, ram: &mut [u8]) {
+        if self.ctrl & D_CTRL_ENABLE == 0 {
+            return;
+        }
+
+        for (index, channel) in CHANNELS.iter().copied().enumerate() {
+            if self.channels[index].chcr & CHCR_START == 0 {
+                continue;
+            }
+
+            let finished = match channel {
+                EeDmaChannel::Sif0 => self.receive_sif0(ram),
+                EeDmaChannel::Sif1 => self.send_sif1(ram),
+                //Nothing on the IOP side listens to SIF2 yet
+                EeDmaChannel::Sif2 => true,
+            };
+
+            if finished {
+                self.channels[index].chcr &= !CHCR_START;
+                self.stat |= 1 << channel as u32;
+            }
+        }
+    }
+
+    fn send_qwords(&mut self, ram: &[u8], channel: usize) {
+        let registers = &mut self.channels[channel];
+        let mut sif = self.sif.borrow_mut();
+
+        while registers.qwc > 0 {
+            sif.sif1_fifo.extend(&read_qword(ram, registers.madr & RAM_MASK));
+            registers.madr = registers.madr.wrapping_add(16);
+            registers.qwc -= 1;
+        }
+    }
+
+    //SIF1 is a source chain, the whole chain is pushed into the FIFO for the IOP to take as it wants
+    fn send_sif1(&mut self, ram: &[u8]) -> bool {
+        let channel = EeDmaChannel::Sif1 as usize - FIRST_CHANNEL as usize;
+
+        if self.channels[channel].chcr & CHCR_CHAIN_MODE == 0 {
+            self.send_qwords(ram, channel);
+            return true;
+        }
+
+        loop {
+            let registers = &mut self.channels[channel];
+            let [low, high, iop_low, iop_high] = read_qword(ram, registers.tadr & RAM_MASK);
+            let tag = DmaTag::new(low as u64 | (high as u64) << 32);
+            let mut last_tag = registers.chcr & CHCR_TAG_INTERRUPT != 0 && tag.irq;
+
+            registers.chcr = (registers.chcr & 0xffff) | (low & 0xffff0000);
+            registers.qwc = tag.qwc;
+
+            //The upper half of the tag is the IOP's tag for the data that follows
+            if registers.chcr & CHCR_TAG_TRANSFER != 0 {
+                self.sif.borrow_mut().sif1_fifo.extend(&[iop_low, iop_high, 0, 0]);
+            }
+
+            let after_tag = registers.tadr.wrapping_add(16);
+            let after_data = after_tag.wrapping_add(tag.qwc * 16);
+
+            match tag.id {
+                //refe
+                0 => {
+                    registers.madr = tag.address;
+                    registers.tadr = after_tag;
+                    last_tag = true;
+                },
+                //cnt
+                1 => {
+                    registers.madr = after_tag;
+                    registers.tadr = after_data;
+                },
+                //next
+                2 => {
+                    registers.madr = after_tag;
+                    registers.tadr = tag.address;
+                },
+                //ref and refs
+                3 | 4 => {
+                    registers.madr = tag.address;
+                    registers.tadr = after_tag;
+                },
+                //call pushes where to carry on onto the address stack
+                5 => {
+                    let stack_pointer = (registers.chcr >> 4 & 3) as usize;
+
+                    registers.madr = after_tag;
+                    registers.tadr = tag.address;
+
+                    if stack_pointer < 2 {
+                        registers.asr[stack_pointer] = after_data;
+                        registers.chcr = (registers.chcr & !0x30) | ((stack_pointer as u32 + 1) << 4);
+                    } else {
+                        last_tag = true;
+                    }
+                },
+                //ret pops it again, with nothing to return to the chain ends
+                6 => {
+                    let stack_pointer = (registers.chcr >> 4 & 3) as usize;
+
+                    registers.madr = after_tag;
+
+                    if stack_pointer > 0 {
+                        registers.tadr = registers.asr[stack_pointer - 1];
+                        registers.chcr = (registers.chcr & !0x30) | ((stack_pointer as u32 - 1) << 4);
+                    } else {
+                        last_tag = true;
+                    }
+                },
+                //end
+                _ => {
+                    registers.madr = after_tag;
+                    last_tag = true;
+                },
+            }
+
+            self.send_qwords(ram, channel);
+
+            if last_tag {
+                return true;
+            }
+        }
+    }
+
+    //SIF0 is a destination chain, tags come out of the FIFO in front of the data they describe
+    //Returns false while waiting for the IOP to send more
+    fn receive_sif0(&mut self, ram: &mut [u8]) -> bool {
+        let channel = EeDmaChannel::Sif0 as usize - FIRST_CHANNEL as usize;
+        let chain = self.channels[channel].chcr & CHCR_CHAIN_MODE != 0;
+        let mut sif = self.sif.borrow_mut();
+        let registers = &mut self.channels[channel];
+
+        loop {
+            while registers.qwc > 0 && sif.sif0_fifo.len() >= 4 {
+                for offset in [0, 4, 8, 12] {
+                    let word = sif.sif0_fifo.pop_front().unwrap_or(0);
+                    write_word(ram, (registers.madr & RAM_MASK) + offset, word);
+                }
+
+                registers.madr = registers.madr.wrapping_add(16);
+                registers.qwc -= 1;
+            }
+
+            if registers.qwc > 0 {
+                return false;
+            }
+
+            if !chain || registers.last_tag {
+                return true;
+            }
+
+            if sif.sif0_fifo.len() < SIF_TAG_WORDS {
+                return false;
+            }
+
+            let tag: Vec<u32> = sif.sif0_fifo.drain(..SIF_TAG_WORDS).collect();
+            let dma_tag = DmaTag::new(tag[0] as u64 | (tag[1] as u64) << 32);
+
+            registers.chcr = (registers.chcr & 0xffff) | (tag[0] & 0xffff0000);
+            registers.qwc = dma_tag.qwc;
+            registers.madr = dma_tag.address;
+            registers.last_tag = dma_tag.id == 7 || (registers.chcr & CHCR_TAG_INTERRUPT != 0 && dma_tag.irq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dmac, CHCR_START, D_CTRL, D_STAT};
+    use crate::sif::create_sif;
+
+    fn write_qword(ram: &mut [u8], address: usize, words: [u32; 4]) {
+        for (index, word) in words.iter().enumerate() {
+            ram[address + index * 4..address + index * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_sif_chains() {
+        let sif = create_sif();
+        let mut dmac = Dmac::new(sif.clone());
+        let mut ram = vec![0; 32 * 1024 * 1024];
+        dmac.write(D_CTRL, 1, &mut ram);
+
+        //refe tag sending one quadword from 0x2000 with an IOP tag in the upper half
+        write_qword(&mut ram, 0x1000, [0x00000001, 0x00002000, 0x80001000, 4]);
+        write_qword(&mut ram, 0x2000, [1, 2, 3, 4]);
+        dmac.write(0x1000C430, 0x1000, &mut ram);
+        dmac.write(0x1000C400, 0x145, &mut ram);
+
+        assert_eq!(dmac.read(0x1000C400) & CHCR_START, 0);
+        assert_eq!(sif.borrow().sif1_fifo.iter().copied().collect::<Vec<u32>>(), vec![0x80001000, 4, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(dmac.read(D_STAT), 1 << 6);
+
+        //SIF0 waits for the IOP to send an end tag and its data
+        dmac.write(0x1000C000, 0x104, &mut ram);
+        assert_eq!(dmac.read(0x1000C000) & CHCR_START, CHCR_START);
+
+        sif.borrow_mut().sif0_fifo.extend(&[0x70000001, 0x00003000, 0, 0, 5, 6, 7, 8]);
+        dmac.run_pending(&mut ram);
+        assert_eq!(dmac.read(0x1000C000) & CHCR_START, 0);
+        assert_eq!(&ram[0x3000..0x3008], &[5, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(dmac.read(D_STAT), 1 << 6 | 1 << 5);
+    }
+}
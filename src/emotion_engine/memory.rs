@@ -1,11 +1,40 @@
 pub const KiB: usize = 1024;
 pub const MiB: usize = 1024 * KiB;
 
+use std::error::Error;
+use std::io::{Read, Seek};
+use std::path::Path;
+use crate::io::{BiosFileReader, ImageError};
+use crate::io_processor::{create_iop_ram, IopRam};
+use crate::sif::{create_sif, SharedSif, EE_SIF_BASE, SIF_MSCOM};
+use crate::tty::Tty;
+use super::dmac::{Dmac, D_STAT};
+use super::sio::Sio;
+
 pub type Address = usize;
 
+pub const ROM0_SIZE: usize = 4 * MiB;
+pub const ROM1_SIZE: usize = 256 * KiB;
+pub const ROM2_SIZE: usize = 512 * KiB;
+pub const EROM_SIZE: usize = 0x1C0000;
+
+//ROM0 is the main BIOS, the rest only exist on some models
+//ROM1 holds the DVD player, ROM2 the Chinese fonts and EROM the encrypted DVD player
+#[derive(Clone, Debug, Default)]
+pub struct RomImages {
+    pub rom0: Vec<u8>,
+    pub rom1: Option<Vec<u8>>,
+    pub rom2: Option<Vec<u8>>,
+    pub erom: Option<Vec<u8>>,
+}
+
 enum AddressLocation {
     MainEEMemory(Address),
     IORegisters(Address),
+    //Registers with side effects carry the physical address and go to their device instead of io_registers
+    Dmac(Address),
+    Sio(Address),
+    Sif(Address),
     VU0CodeMemory(Address),
     VU0DataMemory(Address),
     VU1CodeMemory(Address),
@@ -13,6 +42,9 @@ enum AddressLocation {
     GSPrivilegedRegisters(Address),
     IOPMemory(Address),
     BIOSMemory(Address),
+    ROM1Memory(Address),
+    ROM2Memory(Address),
+    EROMMemory(Address),
     Scratchpad(Address),
 }
 
@@ -24,12 +56,19 @@ pub struct Memory {
     vu1_code_memory: Box<[u8]>,
     vu1_data_memory: Box<[u8]>,
     gs_privileged_registers: Box<[u8]>,
-    iop_memory: Box<[u8]>,
+    iop_memory: IopRam,
     bios: Box<[u8]>,
+    rom1: Box<[u8]>,
+    rom2: Box<[u8]>,
+    erom: Box<[u8]>,
     scratchpad: Box<[u8]>,
     gs_vram: Box<[u8]>,
     spu2_work_ram: Box<[u8]>,
     memory_card: Box<[u8]>,
+    sif: SharedSif,
+    dmac: Dmac,
+    sio: Sio,
+    pub tty: Tty,
 }
 
 fn translate_virt_address(address: Address) -> Option<AddressLocation> {
@@ -37,6 +76,9 @@ fn translate_virt_address(address: Address) -> Option<AddressLocation> {
         0x00000000..=0x01FFFFFF => Some(AddressLocation::MainEEMemory(address)),
         0x20000000..=0x21FFFFFF => Some(AddressLocation::MainEEMemory(address - 0x20000000)),
         0x30100000..=0x31FFFFFF => Some(AddressLocation::MainEEMemory(address - 0x30000000)),
+        0x1000C000..=0x1000CBFF | 0x1000E000..=0x1000E06F => Some(AddressLocation::Dmac(address)),
+        0x1000F100..=0x1000F1FF => Some(AddressLocation::Sio(address)),
+        0x1000F200..=0x1000F26F => Some(AddressLocation::Sif(address)),
         0x10000000..=0x10018FFF => Some(AddressLocation::IORegisters(address - 0x10000000)),
         0x11000000..=0x11000FFF => Some(AddressLocation::VU0CodeMemory(address - 0x11000000)),
         0x11004000..=0x11004FFF => Some(AddressLocation::VU0DataMemory(address - 0x11004000)),
@@ -47,14 +89,91 @@ fn translate_virt_address(address: Address) -> Option<AddressLocation> {
         0x1FC00000..=0x1FFFFFFF => Some(AddressLocation::BIOSMemory(address - 0x1FC00000)),
         0x9FC00000..=0x9FFFFFFF => Some(AddressLocation::BIOSMemory(address - 0x9FC00000)),
         0xBFC00000..=0xBFFFFFFF => Some(AddressLocation::BIOSMemory(address - 0xBFC00000)),
+        0x1E000000..=0x1E03FFFF => Some(AddressLocation::ROM1Memory(address - 0x1E000000)),
+        0x9E000000..=0x9E03FFFF => Some(AddressLocation::ROM1Memory(address - 0x9E000000)),
+        0xBE000000..=0xBE03FFFF => Some(AddressLocation::ROM1Memory(address - 0xBE000000)),
+        0x1E040000..=0x1E1FFFFF => Some(AddressLocation::EROMMemory(address - 0x1E040000)),
+        0x9E040000..=0x9E1FFFFF => Some(AddressLocation::EROMMemory(address - 0x9E040000)),
+        0xBE040000..=0xBE1FFFFF => Some(AddressLocation::EROMMemory(address - 0xBE040000)),
+        0x1E400000..=0x1E47FFFF => Some(AddressLocation::ROM2Memory(address - 0x1E400000)),
+        0x9E400000..=0x9E47FFFF => Some(AddressLocation::ROM2Memory(address - 0x9E400000)),
+        0xBE400000..=0xBE47FFFF => Some(AddressLocation::ROM2Memory(address - 0xBE400000)),
         0x70000000..=0x70003FFF => Some(AddressLocation::Scratchpad(address - 0x70000000)),
         _ => None,
     }
 }
 
+//Optional roms smaller than their space are padded with zeroes, sizes are checked beforehand
+fn load_rom(data: &[u8], size: usize) -> Box<[u8]> {
+    let mut rom = vec![0; size];
+    let length = data.len().min(size);
+
+    rom[..length].copy_from_slice(&data[..length]);
+
+    rom.into_boxed_slice()
+}
+
+fn read_optional_rom(path: &Path) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    if path.is_file() {
+        Ok(Some(std::fs::read(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+impl RomImages {
+    pub fn new(rom0: Vec<u8>) -> RomImages {
+        RomImages {
+            rom0,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_bios_reader<R: Read + Seek>(reader: &mut BiosFileReader<R>) -> Result<RomImages, Box<dyn Error>> {
+        Ok(RomImages::new(reader.read_image()?))
+    }
+
+    //Extra roms are expected next to the bios with the same name, eg. SCPH-70004.BIN and SCPH-70004.rom1
+    //This is the same convention PCSX2 uses
+    pub fn from_bios_path(path: &str) -> Result<RomImages, Box<dyn Error>> {
+        let mut reader = BiosFileReader::new(path)?;
+        let mut roms = RomImages::from_bios_reader(&mut reader)?;
+
+        let path = Path::new(path);
+        roms.rom1 = read_optional_rom(&path.with_extension("rom1"))?;
+        roms.rom2 = read_optional_rom(&path.with_extension("rom2"))?;
+        roms.erom = read_optional_rom(&path.with_extension("erom"))?;
+
+        Ok(roms)
+    }
+
+    //ROM0 has to be a whole dump, a short one is almost certainly bad and would run into zeroes.
+    //The optional roms vary more between models so they only have to fit
+    pub fn check_sizes(&self) -> Result<(), ImageError> {
+        if self.rom0.len() != ROM0_SIZE {
+            return Err(ImageError::WrongRomSize { rom: "ROM0", size: self.rom0.len(), expected: ROM0_SIZE });
+        }
+
+        for (rom, data, expected) in [("ROM1", &self.rom1, ROM1_SIZE), ("ROM2", &self.rom2, ROM2_SIZE), ("EROM", &self.erom, EROM_SIZE)] {
+            if let Some(data) = data.as_ref().filter(|data| data.len() > expected) {
+                return Err(ImageError::WrongRomSize { rom, size: data.len(), expected });
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl Memory {
-    pub fn new(bios: &[u8; 4 * MiB]) -> Memory {
-        Memory {
+    pub fn new(bios: &[u8]) -> Result<Memory, Box<dyn Error>> {
+        Memory::with_roms(&RomImages::new(bios.to_vec()))
+    }
+
+    pub fn with_roms(roms: &RomImages) -> Result<Memory, Box<dyn Error>> {
+        roms.check_sizes()?;
+        let sif = create_sif();
+
+        Ok(Memory {
             ee_main_memory: vec![0; 32 * MiB].into_boxed_slice(),
             io_registers: vec![0; 64 * KiB].into_boxed_slice(),
             vu0_code_memory: vec![0; 4 * KiB].into_boxed_slice(),
@@ -62,52 +181,193 @@ impl Memory {
             vu1_code_memory: vec![0; 16 * KiB].into_boxed_slice(),
             vu1_data_memory: vec![0; 16 * KiB].into_boxed_slice(),
             gs_privileged_registers: vec![0; 8 * KiB].into_boxed_slice(),
-            iop_memory: vec![0; 2 * MiB].into_boxed_slice(),
-            bios: Box::new(*bios),
+            iop_memory: create_iop_ram(),
+            bios: load_rom(&roms.rom0, ROM0_SIZE),
+            rom1: load_rom(roms.rom1.as_deref().unwrap_or_default(), ROM1_SIZE),
+            rom2: load_rom(roms.rom2.as_deref().unwrap_or_default(), ROM2_SIZE),
+            erom: load_rom(roms.erom.as_deref().unwrap_or_default(), EROM_SIZE),
             scratchpad: vec![0; 16 * KiB].into_boxed_slice(),
             gs_vram: vec![0; 4 * MiB].into_boxed_slice(),
             spu2_work_ram: vec![0; 2 * MiB].into_boxed_slice(),
             memory_card: vec![0; 8 * MiB].into_boxed_slice(),
-        }
+            dmac: Dmac::new(sif.clone()),
+            sif,
+            sio: Sio::new(),
+            tty: Tty::new("EE TTY"),
+        })
+    }
+
+    pub fn iop_ram(&self) -> IopRam {
+        self.iop_memory.clone()
+    }
+
+    pub fn sif(&self) -> SharedSif {
+        self.sif.clone()
     }
 
+    //Lets SIF transfers that were waiting on the IOP carry on
+    pub fn run_dma(&mut self) {
+        self.dmac.run_pending(&mut self.ee_main_memory);
+    }
 
+    //Device registers are 32 bits wide, this picks the byte being read out of one
+    fn read_register_byte(address: Address, read: impl FnOnce(u32) -> u32) -> u8 {
+        (read((address & !3) as u32) >> ((address & 3) * 8)) as u8
+    }
+
+    //Wider stores are split into one write per register. Narrower ones are shifted into place and merged
+    //with what the register holds, except for flag registers where writing a bit sets or clears it and
+    //the other bytes have to stay zero
+    fn write_registers(&mut self, address: Address, length: usize, values: &[u8]) {
+        for offset in (0..length).step_by(4) {
+            let register = ((address + offset) & !3) as u32;
+            let bytes = &values[offset..length.min(offset + 4)];
+            let shift = ((address + offset) & 3) * 8;
+            let mask = (u32::MAX >> (32 - bytes.len() * 8)) << shift;
+            let value = bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u32) << shift;
+            let merge = |current: u32| (current & !mask) | value;
+
+            match translate_virt_address(register as Address) {
+                Some(AddressLocation::Dmac(_)) => {
+                    let value = if register == D_STAT { value } else { merge(self.dmac.read(register)) };
+                    self.dmac.write(register, value, &mut self.ee_main_memory);
+                },
+                Some(AddressLocation::Sio(_)) => {
+                    let value = merge(self.sio.read(register));
+                    self.sio.write(register, value, &mut self.tty);
+                },
+                Some(AddressLocation::Sif(_)) => {
+                    let mut sif = self.sif.borrow_mut();
+                    let sif_register = register - EE_SIF_BASE;
+                    let value = if sif_register == SIF_MSCOM { merge(sif.read(sif_register)) } else { value };
+                    sif.write_ee(sif_register, value);
+                },
+                _ => {},
+            }
+        }
+    }
 
     pub fn read_address(&self, virt_address: Address) -> u8 {
-        let address_location = translate_virt_address(virt_address).unwrap();
+        self.try_read_address(virt_address).unwrap()
+    }
+
+    //Same as read_address but doesn't panic on unmapped addresses, useful for debug tooling
+    pub fn try_read_address(&self, virt_address: Address) -> Option<u8> {
+        let address_location = translate_virt_address(virt_address)?;
 
-        match address_location {
+        Some(match address_location {
             AddressLocation::MainEEMemory(address) => self.ee_main_memory[address],
-            AddressLocation::IORegisters(address) => self.iop_memory[address],
+            AddressLocation::IORegisters(address) => self.io_registers[address],
+            AddressLocation::Dmac(address) => Memory::read_register_byte(address, |register| self.dmac.read(register)),
+            AddressLocation::Sio(address) => Memory::read_register_byte(address, |register| self.sio.read(register)),
+            AddressLocation::Sif(address) => Memory::read_register_byte(address, |register| self.sif.borrow().read(register - EE_SIF_BASE)),
             AddressLocation::VU0CodeMemory(address) => self.vu0_code_memory[address],
             AddressLocation::VU0DataMemory(address) => self.vu0_data_memory[address],
             AddressLocation::VU1CodeMemory(address) => self.vu1_code_memory[address],
             AddressLocation::VU1DataMemory(address) => self.vu1_data_memory[address],
             AddressLocation::GSPrivilegedRegisters(address) => self.gs_privileged_registers[address],
-            AddressLocation::IOPMemory(address) => self.iop_memory[address],
+            AddressLocation::IOPMemory(address) => self.iop_memory.borrow()[address],
             AddressLocation::BIOSMemory(address) => self.bios[address],
+            AddressLocation::ROM1Memory(address) => self.rom1[address],
+            AddressLocation::ROM2Memory(address) => self.rom2[address],
+            AddressLocation::EROMMemory(address) => self.erom[address],
             AddressLocation::Scratchpad(address) => self.scratchpad[address],
-        }
+        })
     }
 
     pub fn write_address(&mut self, virt_address: Address, length: usize, values: &[u8]) {
-        let address_location = translate_virt_address(virt_address).unwrap();
+        self.try_write_address(virt_address, length, values).unwrap()
+    }
+
+    //Same as write_address but stores to unmapped addresses are dropped, guest code can store anywhere
+    pub fn try_write_address(&mut self, virt_address: Address, length: usize, values: &[u8]) -> Option<()> {
+        let address_location = translate_virt_address(virt_address)?;
+
+        //IOP RAM is behind a RefCell since the IOP shares it
+        if let AddressLocation::IOPMemory(address) = address_location {
+            let mut iop_memory = self.iop_memory.borrow_mut();
+
+            for (memory, value) in iop_memory[address..address+length].iter_mut().zip(values) {
+                *memory = *value;
+            }
+
+            return Some(());
+        }
+
+        if let AddressLocation::Dmac(address) | AddressLocation::Sio(address) | AddressLocation::Sif(address) = address_location {
+            self.write_registers(address, length, values);
+            return Some(());
+        }
 
         let set_memory: &mut [u8] = match address_location {
             AddressLocation::MainEEMemory(address) => &mut self.ee_main_memory[address..address+length],
-            AddressLocation::IORegisters(address) => &mut self.iop_memory[address..address+length],
+            AddressLocation::IORegisters(address) => &mut self.io_registers[address..address+length],
             AddressLocation::VU0CodeMemory(address) => &mut self.vu0_code_memory[address..address+length],
             AddressLocation::VU0DataMemory(address) => &mut self.vu0_data_memory[address..address+length],
             AddressLocation::VU1CodeMemory(address) => &mut self.vu1_code_memory[address..address+length],
             AddressLocation::VU1DataMemory(address) => &mut self.vu1_data_memory[address..address+length],
             AddressLocation::GSPrivilegedRegisters(address) => &mut self.gs_privileged_registers[address..address+length],
-            AddressLocation::IOPMemory(address) => &mut self.iop_memory[address..address+length],
+            AddressLocation::IOPMemory(_) | AddressLocation::Dmac(_) | AddressLocation::Sio(_) | AddressLocation::Sif(_) => return Some(()),
             AddressLocation::BIOSMemory(address) => &mut self.bios[address..address+length],
+            AddressLocation::ROM1Memory(address) => &mut self.rom1[address..address+length],
+            AddressLocation::ROM2Memory(address) => &mut self.rom2[address..address+length],
+            AddressLocation::EROMMemory(address) => &mut self.erom[address..address+length],
             AddressLocation::Scratchpad(address) => &mut self.scratchpad[address..address+length],
         };
 
         for (memory, value) in set_memory.iter_mut().zip(values) {
             *memory = *value;
         }
+
+        Some(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::{Memory, RomImages, ROM0_SIZE, ROM1_SIZE};
+    use crate::io::ImageError;
+    use super::super::cpu::test::create_mock_memory;
+
+    #[test]
+    fn test_rom_sizes_and_mirrors() {
+        let short = Memory::new(&[0; 1024]).err().unwrap();
+        assert_eq!(short.downcast_ref::<ImageError>(), Some(&ImageError::WrongRomSize { rom: "ROM0", size: 1024, expected: ROM0_SIZE }));
+
+        let mut roms = RomImages::new(vec![0; ROM0_SIZE]);
+        roms.rom0[0x100] = 0x12;
+        roms.rom1 = Some(vec![0x34; 16]);
+        let memory = Memory::with_roms(&roms).unwrap();
+
+        //Physical, kseg0 and kseg1 all see the same rom
+        for base in [0x1FC00000, 0x9FC00000, 0xBFC00000] {
+            assert_eq!(memory.read_address(base + 0x100), 0x12);
+        }
+
+        for base in [0x1E000000, 0x9E000000, 0xBE000000] {
+            assert_eq!(memory.read_address(base), 0x34);
+        }
+
+        roms.rom1 = Some(vec![0; ROM1_SIZE + 1]);
+        assert!(Memory::with_roms(&roms).is_err());
+    }
+
+    #[test]
+    fn test_unaligned_register_stores() {
+        let mut memory = create_mock_memory();
+
+        //sb to the second byte of the SIF1 channel's MADR only replaces that byte
+        memory.write_address(0x1000C410, 4, &0x11223344u32.to_le_bytes());
+        memory.write_address(0x1000C411, 1, &[0xaa]);
+        assert_eq!(memory.read_address(0x1000C411), 0xaa);
+        assert_eq!((memory.read_address(0x1000C410), memory.read_address(0x1000C413)), (0x44, 0x11));
+
+        //sh to the top half of MSCOM
+        memory.write_address(0x1000F200, 4, &0x11223344u32.to_le_bytes());
+        memory.write_address(0x1000F202, 2, &0xbeefu16.to_le_bytes());
+        assert_eq!(memory.sif().borrow().read(0), 0xbeef3344);
+
+        //sb to the third byte of MSFLG sets bit 16 and nothing else
+        memory.write_address(0x1000F222, 1, &[0x01]);
+        assert_eq!(memory.sif().borrow().read(0x20), 0x10000);
+    }
+}
@@ -1,7 +1,9 @@
-mod cpu;
+pub mod cpu;
+mod dmac;
 mod instruction_parser;
-mod instructions;
 mod memory;
+mod sio;
 pub mod instruction_impl;
 
-pub use cpu::Cpu;
\ No newline at end of file
+pub use cpu::Cpu;
+pub use memory::{Memory, RomImages};
\ No newline at end of file
@@ -0,0 +1,104 @@
+use crate::tty::Tty;
+
+//The debug UART at 0x1000F100, only ever used to print so the transmitter is always ready and
+//nothing is ever received
+pub const SIO_LCR: u32 = 0x1000F100;
+pub const SIO_LSR: u32 = 0x1000F110;
+pub const SIO_IER: u32 = 0x1000F120;
+pub const SIO_ISR: u32 = 0x1000F130;
+pub const SIO_FCR: u32 = 0x1000F140;
+pub const SIO_BGR: u32 = 0x1000F150;
+pub const SIO_TXFIFO: u32 = 0x1000F180;
+pub const SIO_RXFIFO: u32 = 0x1000F1C0;
+
+//Transmit holding register and shifter both empty
+const LSR_TRANSMIT_EMPTY: u32 = 0x60;
+//Bits 8-11 count bytes in the receive FIFO and 12-15 the transmit FIFO, the kernel's putc waits while
+//the transmit FIFO is full so both stay at 0
+const ISR_FIFOS_EMPTY: u32 = 0;
+
+#[derive(Debug, Default)]
+pub struct Sio {
+    lcr: u32,
+    ier: u32,
+    fcr: u32,
+    bgr: u32,
+}
+
+impl Sio {
+    pub fn new() -> Sio {
+        Sio::default()
+    }
+
+    pub fn read(&self, address: u32) -> u32 {
+        match address {
+            SIO_LCR => self.lcr,
+            SIO_LSR => LSR_TRANSMIT_EMPTY,
+            SIO_IER => self.ier,
+            SIO_ISR => ISR_FIFOS_EMPTY,
+            SIO_FCR => self.fcr,
+            SIO_BGR => self.bgr,
+            //Nothing is ever received
+            SIO_RXFIFO => 0,
+            _ => 0,
+        }
+    }
+
+    //LSR and ISR are acknowledged by writing them back, nothing ever stays set in them so those are dropped
+    pub fn write(&mut self, address: u32, value: u32, tty: &mut Tty) {
+        match address {
+            SIO_LCR => self.lcr = value,
+            SIO_IER => self.ier = value,
+            SIO_FCR => self.fcr = value,
+            SIO_BGR => self.bgr = value,
+            SIO_TXFIFO => tty.write(&[value as u8]),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SIO_ISR, SIO_LSR, SIO_TXFIFO};
+    use super::super::cpu::test::{create_mock_cpu, create_mock_memory};
+    use crate::test_utils::SharedBuffer;
+
+    #[test]
+    fn test_txfifo_writes_go_to_the_tty() {
+        let log = SharedBuffer::new();
+        let mut memory = create_mock_memory();
+        memory.tty.echo = false;
+        memory.tty.log_to(Box::new(log.clone()));
+
+        //The kernel waits for room in the FIFO before every byte
+        for byte in b"PS2DIAG\n".iter() {
+            assert_eq!(memory.read_address(SIO_ISR as usize + 1) & 0xf0, 0);
+            memory.write_address(SIO_TXFIFO as usize, 1, &[*byte]);
+        }
+
+        assert_eq!(memory.read_address(SIO_LSR as usize), 0x60);
+        assert_eq!(log.text(), "PS2DIAG\n");
+    }
+
+    #[test]
+    fn test_ee_stores_reach_the_txfifo() {
+        let log = SharedBuffer::new();
+        let mut cpu = create_mock_cpu();
+        cpu.memory.tty.echo = false;
+        cpu.memory.tty.log_to(Box::new(log.clone()));
+
+        //lui $t0, 0xB001, then addiu $t1, $zero, <byte> and sb $t1, -0xE80($t0) for each byte, the
+        //kernel goes through KSEG1 to 0xB000F180
+        let program = [0x3C08B001u32, 0x24090048, 0xA109F180, 0x24090069, 0xA109F180, 0x2409000A, 0xA109F180];
+
+        for (index, word) in program.iter().enumerate() {
+            cpu.memory.write_address(index * 4, 4, &word.to_le_bytes());
+        }
+
+        for _ in 0..program.len() {
+            cpu.step();
+        }
+
+        assert_eq!(log.text(), "Hi\n");
+    }
+}
@@ -0,0 +1,144 @@
+use std::error::Error;
+use super::bios_file_reader::{BiosHashes, BiosVersion};
+
+#[derive(Debug)]
+pub struct KnownBios {
+    pub romver: &'static str,
+    pub models: &'static [&'static str],
+    pub region: &'static str,
+    pub revision: &'static str,
+    //MD5 of a good dump, None until one has been checked against a real console
+    pub md5: Option<&'static str>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BiosDumpStatus {
+    //Hash matches a known good dump
+    Verified,
+    //ROMVER is known but the image doesn't hash to the known good dump, so it's bad or modified
+    HashMismatch,
+    //Neither the table nor the hash list has a reference hash for this ROMVER
+    NoReferenceHash,
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct BiosIdentification {
+    pub version: BiosVersion,
+    pub hashes: BiosHashes,
+    pub known_bios: Option<&'static KnownBios>,
+    pub status: BiosDumpStatus,
+    //Structural problems found in the image regardless of whether it is known
+    pub problems: Vec<String>,
+}
+
+//Several models share the same BIOS so entries are keyed on ROMVER
+//A BiosHashList can add reference hashes for entries that don't have one here yet
+pub const KNOWN_BIOSES: &[KnownBios] = &[
+    KnownBios {
+        romver: "0100JC20000117",
+        models: &["SCPH-10000"],
+        region: "Japan",
+        revision: "V1",
+        md5: None,
+    },
+    KnownBios {
+        romver: "0200JC20040614",
+        models: &["SCPH-70000"],
+        region: "Japan",
+        revision: "V12",
+        md5: None,
+    },
+    KnownBios {
+        romver: "0200AC20040614",
+        models: &["SCPH-70001", "SCPH-70011", "SCPH-70012"],
+        region: "USA",
+        revision: "V12",
+        md5: None,
+    },
+    KnownBios {
+        romver: "0200EC20040614",
+        models: &["SCPH-70002", "SCPH-70003", "SCPH-70004", "SCPH-70008"],
+        region: "Europe",
+        revision: "V12",
+        md5: None,
+    },
+];
+
+//Extra reference MD5s of good dumps on top of the built in ones, one "<md5> <ROMVER>" per line with
+//anything after that and lines starting with # ignored, eg. from a redump dat someone has checked their own consoles against
+#[derive(Clone, Debug, Default)]
+pub struct BiosHashList {
+    entries: Vec<(String, String)>,
+}
+
+impl BiosHashList {
+    pub fn new() -> BiosHashList {
+        BiosHashList::default()
+    }
+
+    pub fn from_file(path: &str) -> Result<BiosHashList, Box<dyn Error>> {
+        Ok(BiosHashList::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> BiosHashList {
+        let mut list = BiosHashList::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let mut fields = line.split_whitespace();
+
+            if let (Some(md5), Some(romver)) = (fields.next(), fields.next()) {
+                list.add(md5, romver);
+            }
+        }
+
+        list
+    }
+
+    pub fn add(&mut self, md5: &str, romver: &str) {
+        self.entries.push((md5.to_ascii_lowercase(), romver.to_string()));
+    }
+
+    //The ROMVER a good dump with this hash has
+    pub fn find_md5(&self, md5: &str) -> Option<&str> {
+        self.entries.iter().find(|(known_md5, _)| known_md5.eq_ignore_ascii_case(md5)).map(|(_, romver)| romver.as_str())
+    }
+
+    pub fn has_romver(&self, romver: &str) -> bool {
+        self.entries.iter().any(|(_, known_romver)| known_romver == romver)
+    }
+}
+
+pub fn find_known_bios_by_romver(romver: &str) -> Option<&'static KnownBios> {
+    KNOWN_BIOSES.iter().find(|known_bios| known_bios.romver == romver)
+}
+
+pub fn find_known_bios_by_md5(md5: &str) -> Option<&'static KnownBios> {
+    KNOWN_BIOSES.iter().find(|known_bios| known_bios.md5.is_some_and(|known_md5| known_md5.eq_ignore_ascii_case(md5)))
+}
+
+pub fn identify_bios(version: BiosVersion, hashes: BiosHashes, problems: Vec<String>, hash_list: &BiosHashList) -> BiosIdentification {
+    //A hash match wins even if ROMVER has been tampered with
+    let (known_bios, status) = if let Some(romver) = hash_list.find_md5(&hashes.md5) {
+        (find_known_bios_by_romver(romver), BiosDumpStatus::Verified)
+    } else if let Some(known_bios) = find_known_bios_by_md5(&hashes.md5) {
+        (Some(known_bios), BiosDumpStatus::Verified)
+    } else {
+        let known_bios = find_known_bios_by_romver(&version.romver);
+        let has_reference = hash_list.has_romver(&version.romver) || known_bios.is_some_and(|known_bios| known_bios.md5.is_some());
+
+        match (known_bios, has_reference) {
+            (_, true) => (known_bios, BiosDumpStatus::HashMismatch),
+            (Some(_), false) => (known_bios, BiosDumpStatus::NoReferenceHash),
+            (None, false) => (None, BiosDumpStatus::Unknown),
+        }
+    };
+
+    BiosIdentification {
+        version,
+        hashes,
+        known_bios,
+        status,
+        problems,
+    }
+}
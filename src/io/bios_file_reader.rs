@@ -1,11 +1,11 @@
-use std::error::Error;
 use std::fs::File;
 use std::io::{self, Read, Seek};
-use std::ptr::read;
-use super::utils::{create_io_error, read_bytes};
+use super::utils::{read_bytes, ByteReader};
+use super::image_error::ImageError;
+use super::bios_database::{identify_bios, BiosHashList, BiosIdentification};
 use std::collections::BTreeMap;
 
-#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
 pub struct RomDirEntry {
     name: [u8; 10],
     ext_info_size: u16,
@@ -17,19 +17,52 @@ struct RomDirLocation {
     offset: u64,
     file_offset: u64,
     file_length: usize,
+    ext_info_offset: u64,
+    ext_info_length: usize,
+    ext_info: RomDirExtInfo,
+}
+
+//10 byte name, u16 ext info size then u32 file size
+const ROM_DIR_ENTRY_SIZE: u64 = 16;
+
+const EXTINFO_FIELD_TYPE_DATE: u8 = 0x01;
+const EXTINFO_FIELD_TYPE_VERSION: u8 = 0x02;
+const EXTINFO_FIELD_TYPE_COMMENT: u8 = 0x03;
+const EXTINFO_FIELD_TYPE_FIXED: u8 = 0x7f;
+
+//Dates are stored as BCD in the form 0xYYYYMMDD
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RomDirDate(pub u32);
+
+#[derive(Clone, Debug, Default)]
+pub struct RomDirExtInfo {
+    pub date: Option<RomDirDate>,
+    pub version: Option<u16>,
+    pub comment: Option<String>,
+    //Module has to stay at the same offset in the rom
+    pub fixed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RomDirCatalogEntry {
+    pub name: String,
+    pub entry_offset: u64,
+    pub file_offset: u64,
+    pub file_length: usize,
+    pub ext_info: RomDirExtInfo,
 }
 
-pub struct BiosFileReader {
-    file: File,
+pub struct BiosFileReader<R: Read + Seek = File> {
+    file: R,
     rom_dirs: BTreeMap<String, RomDirLocation>,
 }
 
-#[derive(Copy, Clone, Debug)]
-enum BiosZone {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BiosZone {
     T10K,
     Test,
     Japan,
-    USA,
+    Usa,
     Europe,
     HK,
     Free,
@@ -37,8 +70,8 @@ enum BiosZone {
     Unknown(char),
 }
 
-#[derive(Copy, Clone, Debug)]
-enum BiosType {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BiosType {
     Console,
     Devel,
     Unknown,
@@ -46,60 +79,194 @@ enum BiosType {
 
 #[derive(Clone, Debug)]
 pub struct BiosVersion {
-    zone: BiosZone,
-    major_version: String,
-    minor_version: String,
-    day: String,
-    month: String,
-    year: String,
-    bios_type: BiosType,
+    pub romver: String,
+    pub zone: BiosZone,
+    pub major_version: String,
+    pub minor_version: String,
+    pub day: String,
+    pub month: String,
+    pub year: String,
+    pub bios_type: BiosType,
 }
 
-fn read_rom_dir(file: &mut File, offset: u64) -> Result<RomDirEntry, Box<dyn Error>> {
-    file.seek(io::SeekFrom::Start(offset));
+//ROMVER is formatted as VVvvZTYYYYMMDD
+const ROMVER_LENGTH: usize = 14;
 
-    let length = std::mem::size_of::<RomDirEntry>();
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiosHashes {
+    pub size: u64,
+    pub crc32: u32,
+    pub md5: String,
+}
 
-    let data = read_bytes(file, offset, length)?;
-    
-    unsafe {
-        Ok(std::ptr::read(data.as_ptr() as *const RomDirEntry))
+impl std::fmt::Display for BiosZone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BiosZone::T10K => write!(f, "T10K"),
+            BiosZone::Test => write!(f, "Test"),
+            BiosZone::Japan => write!(f, "Japan"),
+            BiosZone::Usa => write!(f, "USA"),
+            BiosZone::Europe => write!(f, "Europe"),
+            BiosZone::HK => write!(f, "HK"),
+            BiosZone::Free => write!(f, "Free"),
+            BiosZone::China => write!(f, "China"),
+            BiosZone::Unknown(zone) => write!(f, "Unknown ({})", zone),
+        }
+    }
+}
+
+impl std::fmt::Display for BiosType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BiosType::Console => write!(f, "Console"),
+            BiosType::Devel => write!(f, "Devel"),
+            BiosType::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+//Same format PCSX2 uses in its bios list, eg. "Europe v02.00(14/06/2004) Console"
+impl std::fmt::Display for BiosVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} v{}.{}({}/{}/{}) {}", self.zone, self.major_version, self.minor_version, self.day, self.month, self.year, self.bios_type)
+    }
+}
+
+const BIOS_IMAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+fn from_bcd(value: u32) -> u32 {
+    (value >> 4) * 10 + (value & 0xf)
+}
+
+impl RomDirDate {
+    pub fn year(&self) -> u32 {
+        from_bcd(self.0 >> 24) * 100 + from_bcd(self.0 >> 16 & 0xff)
+    }
+
+    pub fn month(&self) -> u32 {
+        from_bcd(self.0 >> 8 & 0xff)
+    }
+
+    pub fn day(&self) -> u32 {
+        from_bcd(self.0 & 0xff)
+    }
+}
+
+impl std::fmt::Display for RomDirDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}/{:02}/{:02}", self.year(), self.month(), self.day())
+    }
+}
+
+//One line per module, eg. "RESET at 0x0, 32 bytes v2.0 2004/06/14 "PS2 BIOS"" with whatever ext info it has
+impl std::fmt::Display for RomDirCatalogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {:#x}, {} bytes", self.name, self.file_offset, self.file_length)?;
+
+        if let Some(version) = self.ext_info.version_string() {
+            write!(f, " v{}", version)?;
+        }
+
+        if let Some(date) = self.ext_info.date {
+            write!(f, " {}", date)?;
+        }
+
+        if let Some(comment) = &self.ext_info.comment {
+            write!(f, " \"{}\"", comment)?;
+        }
+
+        if self.ext_info.fixed {
+            write!(f, " fixed")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl RomDirExtInfo {
+    //Versions are stored as 0xMMmm
+    pub fn version_string(&self) -> Option<String> {
+        self.version.map(|version| format!("{}.{}", version >> 8, version & 0xff))
     }
 }
 
-fn find_first_rom_dir(file: &mut File) -> Result<u64, Box<dyn Error>> {
+fn parse_ext_info(data: &[u8]) -> RomDirExtInfo {
+    let mut ext_info = RomDirExtInfo::default();
+    let mut offset: usize = 0;
+
+    //Each field is a 4 byte header (u16 value, u8 payload length, u8 type) followed by the payload
+    while offset + 4 <= data.len() {
+        let value = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let payload_length = data[offset + 2] as usize;
+        let field_type = data[offset + 3];
+
+        let payload_start = offset + 4;
+        let payload_end = (payload_start + payload_length).min(data.len());
+        let payload = &data[payload_start..payload_end];
+
+        match field_type {
+            EXTINFO_FIELD_TYPE_DATE if payload.len() >= 4 => {
+                ext_info.date = Some(RomDirDate(u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]])));
+            },
+            EXTINFO_FIELD_TYPE_VERSION => ext_info.version = Some(value),
+            EXTINFO_FIELD_TYPE_COMMENT => {
+                ext_info.comment = Some(String::from_utf8_lossy(payload).trim_end_matches('\u{0}').to_string());
+            },
+            EXTINFO_FIELD_TYPE_FIXED => ext_info.fixed = true,
+            _ => {},
+        }
+
+        offset = payload_start + payload_length;
+    }
+
+    ext_info
+}
+
+fn read_rom_dir<R: Read + Seek>(file: &mut R, offset: u64) -> Result<RomDirEntry, ImageError> {
+    let data = read_bytes(file, offset, ROM_DIR_ENTRY_SIZE as usize)?;
+    let mut reader = ByteReader::new("RomDirEntry", &data, offset);
+
+    Ok(RomDirEntry {
+        name: reader.array("name")?,
+        ext_info_size: reader.u16_le("ext_info_size")?,
+        file_size: reader.u32_le("file_size")?,
+    })
+}
+
+fn find_first_rom_dir<R: Read + Seek>(file: &mut R) -> Result<u64, ImageError> {
     let mut offset: u64 = 0;
 
     //The assumption is no rom_dirs can be found after this many iterations
     //PCSX2 makes this assumption so I'll trust them
     for _ in 0..(512 * 1024) {
-        //If the file isn't a BIOS file it will eventually error
-        //TODO fix the error so it explains the file was not a BIOS file
-        let potential_rom_dir = read_rom_dir(file, offset)?;
-
-
+        //Running off the end of the file before finding RESET means it isn't a BIOS file
+        let potential_rom_dir = match read_rom_dir(file, offset) {
+            Ok(rom_dir) => rom_dir,
+            Err(ImageError::Io(error)) => return Err(ImageError::Io(error)),
+            Err(_) => return Err(ImageError::NotABios),
+        };
 
         let potential_rom_dir_identifier = std::str::from_utf8(&potential_rom_dir.name);
 
-        match potential_rom_dir_identifier {
-            Ok(identifier) => if identifier.starts_with("RESET") { 
+        if let Ok(identifier) = potential_rom_dir_identifier {
+            if identifier.starts_with("RESET") {
                 return Ok(offset);
-            },
-            Err(_) => {},
-        };
+            }
+        }
 
-        offset += std::mem::size_of::<RomDirEntry>() as u64;
+        offset += ROM_DIR_ENTRY_SIZE;
     }
 
-    Err(create_io_error(io::ErrorKind::InvalidData, "bios file seems to be incorrect or corrupted!"))
+    Err(ImageError::NotABios)
 }
 
-fn read_all_rom_dirs(file: &mut File) -> Result<BTreeMap<String, RomDirLocation>, Box<dyn Error>> {
+fn read_all_rom_dirs<R: Read + Seek>(file: &mut R) -> Result<BTreeMap<String, RomDirLocation>, ImageError> {
     let mut rom_dirs: BTreeMap<String, RomDirLocation> = BTreeMap::new();
     
     let mut offset = find_first_rom_dir(file)?;
 
     let mut file_offset: u64 = 0;
+    let mut ext_info_offset: u64 = 0;
 
     loop {
         let rom_dir = read_rom_dir(file, offset)?;
@@ -109,33 +276,67 @@ fn read_all_rom_dirs(file: &mut File) -> Result<BTreeMap<String, RomDirLocation>
             break;
         }
 
-        rom_dirs.insert(std::str::from_utf8(&rom_dir.name)?.trim_matches('\u{0}').to_string(), RomDirLocation {
+        let name = match std::str::from_utf8(&rom_dir.name) {
+            Ok(name) => name.trim_matches('\u{0}').to_string(),
+            Err(_) => return Err(ImageError::CorruptRomDir { offset }),
+        };
+
+        rom_dirs.insert(name, RomDirLocation {
             offset,
-            file_offset: file_offset,
+            file_offset,
             file_length: rom_dir.file_size as usize,
+            ext_info_offset,
+            ext_info_length: rom_dir.ext_info_size as usize,
+            ext_info: RomDirExtInfo::default(),
         });
 
+        //Ext info for every entry is packed one after another inside the EXTINFO file
+        ext_info_offset += rom_dir.ext_info_size as u64;
+
         //Again stolen from PCSX2
         //I suppose it means files in the bios are aligned to the nearest 16 byte interval
-        //Ext info size seems useless, maybe it's all packed at the end or something
         let additional_file_offset = rom_dir.file_size as u64;
-        file_offset += if additional_file_offset % 0x10 == 0 {
+        file_offset += if additional_file_offset.is_multiple_of(0x10) {
             additional_file_offset
         } else {
             (additional_file_offset + 0x10) & 0xfffffff0
         };
 
-        offset += std::mem::size_of::<RomDirEntry>() as u64;
+        offset += ROM_DIR_ENTRY_SIZE;
     }
 
     Ok(rom_dirs)
 }
 
-impl BiosFileReader {
-    pub fn new(path: &str) -> Result<BiosFileReader, Box<dyn Error>> {
-        let mut file = File::open(path)?;
-    
-        let rom_dirs = read_all_rom_dirs(&mut file)?;
+fn read_all_ext_info<R: Read + Seek>(file: &mut R, rom_dirs: &mut BTreeMap<String, RomDirLocation>) -> Result<(), ImageError> {
+    let ext_info_data = match rom_dirs.get("EXTINFO") {
+        Some(ext_info_location) => read_bytes(file, ext_info_location.file_offset, ext_info_location.file_length)?,
+        //Very early BIOS dumps don't have any ext info at all
+        None => return Ok(()),
+    };
+
+    for rom_dir in rom_dirs.values_mut() {
+        let start = rom_dir.ext_info_offset as usize;
+        let end = start + rom_dir.ext_info_length;
+
+        if end <= ext_info_data.len() {
+            rom_dir.ext_info = parse_ext_info(&ext_info_data[start..end]);
+        }
+    }
+
+    Ok(())
+}
+
+impl BiosFileReader<File> {
+    pub fn new(path: &str) -> Result<BiosFileReader<File>, ImageError> {
+        BiosFileReader::from_reader(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> BiosFileReader<R> {
+    pub fn from_reader(mut file: R) -> Result<BiosFileReader<R>, ImageError> {
+        let mut rom_dirs = read_all_rom_dirs(&mut file)?;
+        read_all_ext_info(&mut file, &mut rom_dirs)?;
 
         Ok(BiosFileReader {
             file,
@@ -147,40 +348,221 @@ impl BiosFileReader {
         self.rom_dirs.keys().collect()
     }
 
-    pub fn read_rom_dir_data(&mut self, rom_dir_identifier: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        let rom_dir_opt = self.rom_dirs.get(&rom_dir_identifier.to_string());
+    //Entries are returned in the order they appear in the rom
+    pub fn get_rom_dir_catalog(&self) -> Vec<RomDirCatalogEntry> {
+        let mut catalog: Vec<RomDirCatalogEntry> = self.rom_dirs.iter().map(|(name, rom_dir)| RomDirCatalogEntry {
+            name: name.clone(),
+            entry_offset: rom_dir.offset,
+            file_offset: rom_dir.file_offset,
+            file_length: rom_dir.file_length,
+            ext_info: rom_dir.ext_info.clone(),
+        }).collect();
+
+        catalog.sort_by_key(|entry| entry.entry_offset);
+
+        catalog
+    }
+
+    pub fn read_image(&mut self) -> Result<Vec<u8>, ImageError> {
+        let size = self.file.seek(io::SeekFrom::End(0))?;
+
+        read_bytes(&mut self.file, 0, size as usize)
+    }
+
+    pub fn hash_image(&mut self) -> Result<BiosHashes, ImageError> {
+        let data = self.read_image()?;
+        let size = data.len() as u64;
+
+        let mut crc32 = crc32fast::Hasher::new();
+        crc32.update(&data);
+
+        Ok(BiosHashes {
+            size,
+            crc32: crc32.finalize(),
+            md5: format!("{:x}", md5::compute(&data)),
+        })
+    }
+
+    pub fn identify(&mut self, hash_list: &BiosHashList) -> Result<BiosIdentification, ImageError> {
+        let version = self.get_bios_version()?;
+        let hashes = self.hash_image()?;
+        let problems = self.check_image_structure()?;
+
+        Ok(identify_bios(version, hashes, problems, hash_list))
+    }
+
+    //Sanity checks that don't need a known dump to compare against
+    pub fn check_image_structure(&mut self) -> Result<Vec<String>, ImageError> {
+        let mut problems: Vec<String> = vec![];
+
+        let size = self.file.seek(io::SeekFrom::End(0))?;
+
+        if size != BIOS_IMAGE_SIZE {
+            problems.push(format!("image is {} bytes, expected {}", size, BIOS_IMAGE_SIZE));
+        }
+
+        for (name, rom_dir) in self.rom_dirs.iter() {
+            if rom_dir.file_offset + rom_dir.file_length as u64 > size {
+                problems.push(format!("{} extends past the end of the image", name));
+            }
+        }
+
+        //The ROMDIR table is a file in itself and starts right where RESET ends
+        match (self.rom_dirs.get("RESET"), self.rom_dirs.get("ROMDIR")) {
+            (Some(reset), Some(rom_dir)) if rom_dir.file_offset != reset.offset => {
+                problems.push(format!("ROMDIR is at 0x{:x} but the table was found at 0x{:x}", rom_dir.file_offset, reset.offset));
+            },
+            (_, None) => problems.push("missing ROMDIR entry".to_string()),
+            _ => {},
+        }
+
+        if !self.rom_dirs.contains_key("ROMVER") {
+            problems.push("missing ROMVER entry".to_string());
+        }
+
+        Ok(problems)
+    }
+
+    pub fn read_rom_dir_data(&mut self, rom_dir_identifier: &str) -> Result<Vec<u8>, ImageError> {
+        let rom_dir_opt = self.rom_dirs.get(rom_dir_identifier);
 
         match rom_dir_opt {
             Some(rom_dir) => Ok(read_bytes(&mut self.file, rom_dir.file_offset, rom_dir.file_length)?),
-            None => Err(create_io_error(io::ErrorKind::NotFound, "could not find the specified file in the bios!"))
+            None => Err(ImageError::FileNotFound {
+                path: rom_dir_identifier.to_string(),
+                near_matches: vec![],
+            })
         }
     }
 
-    pub fn get_bios_version(&mut self) -> Result<BiosVersion, Box<dyn Error>> {
+    pub fn get_bios_version(&mut self) -> Result<BiosVersion, ImageError> {
         let bios_version_data = self.read_rom_dir_data("ROMVER")?;
-        
+
+        if bios_version_data.len() < ROMVER_LENGTH || !bios_version_data[..ROMVER_LENGTH].is_ascii() {
+            return Err(ImageError::BadRomver);
+        }
+
+        let romver = String::from_utf8_lossy(&bios_version_data[..ROMVER_LENGTH]).to_string();
+
         Ok(BiosVersion {
             zone: match bios_version_data[4] as char {
                 'T' => BiosZone::T10K,
                 'X' => BiosZone::Test,
                 'J' => BiosZone::Japan,
-                'A' => BiosZone::USA,
+                'A' => BiosZone::Usa,
                 'E' => BiosZone::Europe,
                 'H' => BiosZone::HK,
                 'P' => BiosZone::Free,
                 'C' => BiosZone::China,
                 _ => BiosZone::Unknown(bios_version_data[4] as char),
             },
-            major_version: std::str::from_utf8(&[bios_version_data[0], bios_version_data[1]])?.to_string(),
-            minor_version: std::str::from_utf8(&[bios_version_data[2], bios_version_data[3]])?.to_string(),
-            day: std::str::from_utf8(&[bios_version_data[12], bios_version_data[13]])?.to_string(),
-            month: std::str::from_utf8(&[bios_version_data[10], bios_version_data[11]])?.to_string(),
-            year: std::str::from_utf8(&[bios_version_data[6], bios_version_data[7], bios_version_data[8], bios_version_data[9]])?.to_string(),
+            major_version: romver[0..2].to_string(),
+            minor_version: romver[2..4].to_string(),
+            day: romver[12..14].to_string(),
+            month: romver[10..12].to_string(),
+            year: romver[6..10].to_string(),
             bios_type: match bios_version_data[5] as char {
                 'C' => BiosType::Console,
                 'D' => BiosType::Devel,
                 _ => BiosType::Unknown,
             },
+            romver,
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{parse_ext_info, BiosFileReader, RomDirDate};
+    use super::super::bios_database::{BiosDumpStatus, BiosHashList};
+    use super::super::image_error::ImageError;
+
+    fn rom_dir_entry(name: &str, ext_info_size: u16, file_size: u32) -> Vec<u8> {
+        let mut entry = vec![0u8; 16];
+
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        entry[10..12].copy_from_slice(&ext_info_size.to_le_bytes());
+        entry[12..16].copy_from_slice(&file_size.to_le_bytes());
+
+        entry
+    }
+
+    //RESET is 0x20 bytes, followed by the ROMDIR table which is followed by ROMVER then EXTINFO
+    //Only RESET has any ext info, a date, a version and a comment
+    fn create_mock_bios() -> Vec<u8> {
+        let ext_info: Vec<u8> = vec![
+            0x00, 0x00, 0x04, 0x01, 0x14, 0x06, 0x04, 0x20,
+            0x00, 0x02, 0x00, 0x02,
+            0x00, 0x00, 0x08, 0x03, b'P', b'S', b'2', b' ', b'B', b'I', b'O', b'S',
+        ];
+        let mut image = vec![0u8; 0x20];
+
+        image.extend(rom_dir_entry("RESET", ext_info.len() as u16, 0x20));
+        image.extend(rom_dir_entry("ROMDIR", 0, 0x50));
+        image.extend(rom_dir_entry("ROMVER", 0, 0x10));
+        image.extend(rom_dir_entry("EXTINFO", 0, ext_info.len() as u32));
+        image.extend(rom_dir_entry("", 0, 0));
+        image.extend(b"0200EC20040614\0\0");
+        image.extend(ext_info);
+
+        image
+    }
+
+    #[test]
+    fn test_read_bios_from_memory() {
+        let mut bios_file = BiosFileReader::from_reader(Cursor::new(create_mock_bios())).unwrap();
+
+        assert_eq!(bios_file.get_all_rom_dir_identifiers(), vec!["EXTINFO", "RESET", "ROMDIR", "ROMVER"]);
+        assert_eq!(bios_file.get_bios_version().unwrap().to_string(), "Europe v02.00(14/06/2004) Console");
+        assert!(bios_file.check_image_structure().unwrap().iter().all(|problem| problem.starts_with("image is")));
+
+        let catalog = bios_file.get_rom_dir_catalog();
+        let names: Vec<&str> = catalog.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["RESET", "ROMDIR", "ROMVER", "EXTINFO"]);
+        assert_eq!(catalog[0].ext_info.version_string(), Some("2.0".to_string()));
+        assert_eq!(catalog[0].ext_info.date, Some(RomDirDate(0x20040614)));
+        assert_eq!(catalog[0].ext_info.comment, Some("PS2 BIOS".to_string()));
+        assert_eq!(catalog[0].to_string(), "RESET at 0x0, 32 bytes v2.0 2004/06/14 \"PS2 BIOS\"");
+        assert_eq!(catalog[3].file_offset, 0x80);
+        assert!(catalog[2].ext_info.comment.is_none());
+
+        let missing = bios_file.read_rom_dir_data("OSDSYS").unwrap_err();
+        assert!(matches!(&missing, ImageError::FileNotFound { path, .. } if path == "OSDSYS"));
+
+        let not_a_bios = BiosFileReader::from_reader(Cursor::new(vec![0u8; 64])).err().unwrap();
+        assert_eq!(not_a_bios, ImageError::NotABios);
+    }
+
+    #[test]
+    fn test_identify_against_hash_list() {
+        let mut bios_file = BiosFileReader::from_reader(Cursor::new(create_mock_bios())).unwrap();
+        let md5 = bios_file.hash_image().unwrap().md5;
+
+        let identification = bios_file.identify(&BiosHashList::parse(&format!("# SCPH-70004\n{} 0200EC20040614\n", md5.to_uppercase()))).unwrap();
+        assert_eq!(identification.status, BiosDumpStatus::Verified);
+        assert_eq!(identification.known_bios.unwrap().models[2], "SCPH-70004");
+
+        //Same ROMVER but a different image
+        let mut hash_list = BiosHashList::new();
+        hash_list.add("00000000000000000000000000000000", "0200EC20040614");
+        assert_eq!(bios_file.identify(&hash_list).unwrap().status, BiosDumpStatus::HashMismatch);
+        assert_eq!(bios_file.identify(&BiosHashList::new()).unwrap().status, BiosDumpStatus::NoReferenceHash);
+    }
+
+    #[test]
+    fn test_parse_ext_info() {
+        let data: Vec<u8> = vec![
+            0x00, 0x00, 0x04, 0x01, 0x14, 0x06, 0x04, 0x20,
+            0x01, 0x01, 0x00, 0x02,
+            0x00, 0x00, 0x08, 0x03, b'S', b'I', b'O', b'2', b'M', b'A', b'N', 0x00,
+        ];
+
+        let ext_info = parse_ext_info(&data);
+
+        assert_eq!(ext_info.date, Some(RomDirDate(0x20040614)));
+        assert_eq!(ext_info.date.unwrap().to_string(), "2004/06/14");
+        assert_eq!(ext_info.version_string(), Some("1.1".to_string()));
+        assert_eq!(ext_info.comment, Some("SIO2MAN".to_string()));
+        assert!(!ext_info.fixed);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Seek};
+use super::image_error::ImageError;
+use super::iso_file_reader::SECTOR_SIZE;
+
+pub const RAW_SECTOR_SIZE: usize = 2352;
+pub const USER_DATA_SIZE: usize = SECTOR_SIZE as usize;
+//Mode 2 Form 2 trades the ECC for more data, it's used for XA audio and video streams
+pub const FORM2_DATA_SIZE: usize = 2324;
+//Submode byte in the mode 2 subheader, the subheader is written twice and this is its third byte
+const SUBMODE_FORM2: u8 = 0x20;
+
+const FRAMES_PER_SECOND: u64 = 75;
+const SECONDS_PER_MINUTE: u64 = 60;
+
+pub const SYNC_PATTERN: [u8; 12] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrackType {
+    Mode1_2048,
+    Mode1_2352,
+    //Just the user data of mode 2 form 1 sectors, CHD calls these MODE2_FORM1
+    Mode2_2048,
+    Mode2_2336,
+    Mode2_2352,
+    Audio,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Msf {
+    pub minute: u8,
+    pub second: u8,
+    pub frame: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub number: u8,
+    pub track_type: TrackType,
+    //LSN of INDEX 01
+    pub start_lsn: u64,
+    //Sectors before INDEX 01 that belong to this track
+    pub pregap_length: u64,
+    pub sector_count: u64,
+}
+
+//Track type of a sector and its bytes as stored in the image, None for pregap silence that isn't stored
+pub type StoredSector = (TrackType, Option<Vec<u8>>);
+
+//Anything that can hand out disc sectors along with the track layout. User data is 2048 bytes
+//except for mode 2 form 2 sectors which have 2324
+pub trait BlockDevice {
+    fn sector_count(&self) -> u64;
+
+    fn read_sector(&mut self, lsn: u64) -> Result<Vec<u8>, ImageError>;
+
+    fn tracks(&self) -> Vec<Track>;
+}
+
+impl TrackType {
+    pub fn sector_size(&self) -> usize {
+        match self {
+            TrackType::Mode1_2048 | TrackType::Mode2_2048 => USER_DATA_SIZE,
+            TrackType::Mode2_2336 => 2336,
+            TrackType::Mode1_2352 | TrackType::Mode2_2352 | TrackType::Audio => RAW_SECTOR_SIZE,
+        }
+    }
+}
+
+//Written the way cue sheets spell them
+impl fmt::Display for TrackType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackType::Mode1_2048 => write!(f, "MODE1/2048"),
+            TrackType::Mode1_2352 => write!(f, "MODE1/2352"),
+            TrackType::Mode2_2048 => write!(f, "MODE2/2048"),
+            TrackType::Mode2_2336 => write!(f, "MODE2/2336"),
+            TrackType::Mode2_2352 => write!(f, "MODE2/2352"),
+            TrackType::Audio => write!(f, "AUDIO"),
+        }
+    }
+}
+
+impl Msf {
+    pub fn to_frames(self) -> u64 {
+        (self.minute as u64 * SECONDS_PER_MINUTE + self.second as u64) * FRAMES_PER_SECOND + self.frame as u64
+    }
+}
+
+//Pulls the user data out of a sector stored in the track's format
+pub fn cook_sector(track_type: TrackType, sector: &[u8]) -> Vec<u8> {
+    //Where the subheader is for mode 2 sectors, 2336 byte sectors start with it
+    let subheader = match track_type {
+        TrackType::Mode2_2336 => Some(0),
+        TrackType::Mode2_2352 => Some(16),
+        _ => None,
+    };
+
+    let offset = match subheader {
+        Some(subheader) => subheader + 8,
+        None if track_type == TrackType::Mode1_2352 => 16,
+        None => 0,
+    };
+
+    let form2 = subheader.is_some_and(|subheader| sector.get(subheader + 2).is_some_and(|submode| submode & SUBMODE_FORM2 != 0));
+    let size = if form2 { FORM2_DATA_SIZE } else { USER_DATA_SIZE };
+
+    let mut data = vec![0u8; size];
+    let available = sector.len().saturating_sub(offset).min(size);
+    data[..available].copy_from_slice(&sector[offset..offset + available]);
+
+    data
+}
+
+//Compressed images decompress a whole block at a time and the filesystem tends to hit the same
+//few blocks over and over, so keep the most recently used ones around
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    blocks: HashMap<u64, Vec<u8>>,
+    //Least recently used at the front
+    order: VecDeque<u64>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> BlockCache {
+        BlockCache {
+            capacity: capacity.max(1),
+            blocks: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        self.blocks.contains_key(&index)
+    }
+
+    pub fn get(&mut self, index: u64) -> Option<&[u8]> {
+        if let Some(position) = self.order.iter().position(|cached| *cached == index) {
+            self.order.remove(position);
+            self.order.push_back(index);
+        }
+
+        self.blocks.get(&index).map(|block| block.as_slice())
+    }
+
+    pub fn insert(&mut self, index: u64, block: Vec<u8>) {
+        if self.blocks.insert(index, block).is_some() {
+            return;
+        }
+
+        self.order.push_back(index);
+
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.blocks.remove(&evicted);
+            }
+        }
+    }
+}
+
+//Presents the cooked sectors of a block device as one continuous stream so ISOFileReader can sit on top of it
+pub struct BlockDeviceReader<D: BlockDevice> {
+    device: D,
+    position: u64,
+    cached_lsn: Option<u64>,
+    cached_sector: Vec<u8>,
+}
+
+impl<D: BlockDevice> BlockDeviceReader<D> {
+    pub fn new(device: D) -> BlockDeviceReader<D> {
+        BlockDeviceReader {
+            device,
+            position: 0,
+            cached_lsn: None,
+            cached_sector: vec![],
+        }
+    }
+
+    fn length(&self) -> u64 {
+        self.device.sector_count() * SECTOR_SIZE
+    }
+}
+
+impl<D: BlockDevice> std::fmt::Debug for BlockDeviceReader<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BlockDeviceReader {{ sectors: {}, position: {} }}", self.device.sector_count(), self.position)
+    }
+}
+
+impl<D: BlockDevice> Read for BlockDeviceReader<D> {
+    //Keeps going across sector boundaries so callers get everything they asked for in one go
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut bytes_read: usize = 0;
+
+        while bytes_read < buf.len() && self.position < self.length() {
+            let lsn = self.position / SECTOR_SIZE;
+
+            //Image errors ride inside the io::Error and come back out when converted to an ImageError
+            if self.cached_lsn != Some(lsn) {
+                self.cached_sector = self.device.read_sector(lsn)?;
+                self.cached_lsn = Some(lsn);
+            }
+
+            //Form 2 sectors have more than this but the stream is addressed 2048 bytes a sector
+            let sector_offset = (self.position % SECTOR_SIZE) as usize;
+            let length = (buf.len() - bytes_read).min(USER_DATA_SIZE - sector_offset);
+
+            buf[bytes_read..bytes_read + length].copy_from_slice(&self.cached_sector[sector_offset..sector_offset + length]);
+            self.position += length as u64;
+            bytes_read += length;
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+impl<D: BlockDevice> Seek for BlockDeviceReader<D> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => offset as i64,
+            io::SeekFrom::End(offset) => self.length() as i64 + offset,
+            io::SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"));
+        }
+
+        self.position = position as u64;
+
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,1145 @@
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
+use super::utils::{read_bytes, ByteReader};
+use super::image_error::ImageError;
+use super::iso_file_reader::SECTOR_SIZE;
+use super::block_device::{cook_sector, BlockCache, BlockDevice, StoredSector, Track, TrackType, RAW_SECTOR_SIZE, SYNC_PATTERN, USER_DATA_SIZE};
+
+const CHD_MAGIC: &[u8] = b"MComprHD";
+const V5_HEADER_SIZE: usize = 124;
+const MAP_HEADER_SIZE: usize = 16;
+const METADATA_HEADER_SIZE: usize = 16;
+const CACHED_HUNKS: usize = 16;
+
+//CD frames are the 2352 byte sector followed by 96 bytes of subchannel data
+const CD_FRAME_SIZE: usize = 2448;
+const CD_SUBCODE_SIZE: usize = 96;
+//Every track is padded out to a multiple of 4 frames
+const CD_TRACK_PADDING: u64 = 4;
+
+const fn fourcc(tag: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*tag)
+}
+
+const CODEC_ZLIB: u32 = fourcc(b"zlib");
+const CODEC_LZMA: u32 = fourcc(b"lzma");
+const CODEC_FLAC: u32 = fourcc(b"flac");
+const CODEC_CD_ZLIB: u32 = fourcc(b"cdzl");
+const CODEC_CD_LZMA: u32 = fourcc(b"cdlz");
+const CODEC_CD_FLAC: u32 = fourcc(b"cdfl");
+
+const CD_TRACK_METADATA: u32 = fourcc(b"CHTR");
+const CD_TRACK_METADATA2: u32 = fourcc(b"CHT2");
+
+//Hunk types in the compressed v5 map, 0-3 are the 4 codecs from the header
+const COMPRESSION_TYPE_3: u8 = 3;
+const COMPRESSION_NONE: u8 = 4;
+const COMPRESSION_SELF: u8 = 5;
+const COMPRESSION_PARENT: u8 = 6;
+const COMPRESSION_RLE_SMALL: u8 = 7;
+const COMPRESSION_RLE_LARGE: u8 = 8;
+const COMPRESSION_SELF_0: u8 = 9;
+const COMPRESSION_SELF_1: u8 = 10;
+const COMPRESSION_PARENT_SELF: u8 = 11;
+const COMPRESSION_PARENT_0: u8 = 12;
+const COMPRESSION_PARENT_1: u8 = 13;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Hunk {
+    Compressed { codec: usize, offset: u64, length: u32, crc: u16 },
+    Uncompressed { offset: u64, crc: Option<u16> },
+    //Same data as an earlier hunk
+    Duplicate(u64),
+    Parent,
+    Zero,
+}
+
+#[derive(Clone, Debug)]
+struct ChdTrack {
+    track: Track,
+    //CHD frame holding the first stored sector of the track, which is the pregap if it's stored
+    chd_frame: u64,
+    stored_pregap: u64,
+}
+
+#[derive(Debug)]
+pub struct ChdImage<R: Read + Seek = File> {
+    file: R,
+    compressors: [u32; 4],
+    logical_bytes: u64,
+    hunk_bytes: u32,
+    unit_bytes: u32,
+    map: Vec<Hunk>,
+    //Only CD images have tracks, DVD images are plain 2048 byte sectors
+    cd_tracks: Vec<ChdTrack>,
+    cache: BlockCache,
+}
+
+//MSB first, reading past the end gives zeros
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+        }
+    }
+
+    fn peek(&self, bits: u32) -> u32 {
+        let mut value: u32 = 0;
+
+        for bit in 0..bits as usize {
+            let position = self.position + bit;
+            let byte = self.data.get(position / 8).copied().unwrap_or(0);
+
+            value = (value << 1) | ((byte >> (7 - position % 8)) & 1) as u32;
+        }
+
+        value
+    }
+
+    fn read(&mut self, bits: u32) -> u32 {
+        let value = self.peek(bits);
+        self.position += bits as usize;
+
+        value
+    }
+
+    fn overflowed(&self) -> bool {
+        self.position > self.data.len() * 8
+    }
+}
+
+//Canonical huffman decoder used for the hunk map
+struct HuffmanDecoder {
+    max_bits: u32,
+    //(symbol, code length) for every max_bits wide bit pattern
+    lookup: Vec<(u32, u32)>,
+}
+
+impl HuffmanDecoder {
+    //Code lengths are stored as small fields with a run length escape for repeats
+    fn import_tree_rle(reader: &mut BitReader, symbol_count: usize, max_bits: u32) -> Result<HuffmanDecoder, ImageError> {
+        let field_bits = if max_bits >= 16 {
+            5
+        } else if max_bits >= 8 {
+            4
+        } else {
+            3
+        };
+
+        let mut code_lengths: Vec<u32> = Vec::with_capacity(symbol_count);
+
+        while code_lengths.len() < symbol_count {
+            let length = reader.read(field_bits);
+
+            if length != 1 {
+                code_lengths.push(length);
+                continue;
+            }
+
+            let length = reader.read(field_bits);
+
+            if length == 1 {
+                code_lengths.push(length);
+                continue;
+            }
+
+            let repeat = reader.read(field_bits) as usize + 3;
+
+            if code_lengths.len() + repeat > symbol_count {
+                return Err(ImageError::CorruptImage { detail: "huffman tree has too many codes" });
+            }
+
+            code_lengths.extend(std::iter::repeat_n(length, repeat));
+        }
+
+        HuffmanDecoder::from_code_lengths(&code_lengths, max_bits)
+    }
+
+    fn from_code_lengths(code_lengths: &[u32], max_bits: u32) -> Result<HuffmanDecoder, ImageError> {
+        let mut histogram = [0u32; 33];
+
+        for length in code_lengths {
+            if *length > max_bits {
+                return Err(ImageError::CorruptImage { detail: "huffman code is too long" });
+            }
+
+            histogram[*length as usize] += 1;
+        }
+
+        //Work out the first code of each length, longest codes first
+        let mut current_start: u32 = 0;
+
+        for length in (1..=32).rev() {
+            let next_start = (current_start + histogram[length]) >> 1;
+
+            if length != 1 && next_start * 2 != current_start + histogram[length] {
+                return Err(ImageError::CorruptImage { detail: "huffman tree is incomplete" });
+            }
+
+            histogram[length] = current_start;
+            current_start = next_start;
+        }
+
+        let mut lookup = vec![(0, 0); 1 << max_bits];
+
+        for (symbol, length) in code_lengths.iter().enumerate() {
+            if *length == 0 {
+                continue;
+            }
+
+            let code = histogram[*length as usize];
+            histogram[*length as usize] += 1;
+
+            let shift = max_bits - length;
+            let start = (code << shift) as usize;
+            let end = ((code + 1) << shift) as usize;
+
+            if end > lookup.len() {
+                return Err(ImageError::CorruptImage { detail: "huffman tree is oversubscribed" });
+            }
+
+            for entry in lookup[start..end].iter_mut() {
+                *entry = (symbol as u32, *length);
+            }
+        }
+
+        Ok(HuffmanDecoder {
+            max_bits,
+            lookup,
+        })
+    }
+
+    fn decode_one(&self, reader: &mut BitReader) -> u32 {
+        let (symbol, length) = self.lookup[reader.peek(self.max_bits) as usize];
+        reader.position += length as usize;
+
+        symbol
+    }
+}
+
+//CRC-16/CCITT-FALSE as used for the map and hunk checksums
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+fn read_u48_be(data: &[u8]) -> u64 {
+    data[..6].iter().fold(0u64, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn decompress_error(codec: &'static str, error: impl std::fmt::Display) -> ImageError {
+    ImageError::DecompressFailed { codec, reason: error.to_string() }
+}
+
+fn inflate(data: &[u8], length: usize) -> Result<Vec<u8>, ImageError> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(data, length).map_err(|error| decompress_error("zlib", error))
+}
+
+//Raw LZMA without a header, CHD always compresses with lc=3 lp=0 pb=2 and the output size is known so
+//a dictionary the size of the output is enough
+fn lzma_decompress(data: &[u8], length: usize) -> Result<Vec<u8>, ImageError> {
+    let properties = LzmaProperties {
+        lc: 3,
+        lp: 0,
+        pb: 2,
+    };
+    let params = LzmaParams::new(properties, (length as u32).max(4096), Some(length as u64));
+
+    let mut decoder = LzmaDecoder::new(params, None).map_err(|error| decompress_error("lzma", error))?;
+    let mut output = Vec::with_capacity(length);
+    decoder.decompress(&mut &data[..], &mut output).map_err(|error| decompress_error("lzma", error))?;
+
+    Ok(output)
+}
+
+//Bare FLAC frames of 16 bit stereo samples, returns the samples as bytes and how much input was used
+fn flac_decompress(data: &[u8], length: usize, big_endian: bool) -> Result<(Vec<u8>, usize), ImageError> {
+    let mut reader = claxon::frame::FrameReader::new(io::Cursor::new(data));
+    let mut output = Vec::with_capacity(length);
+    let mut buffer = vec![];
+
+    while output.len() < length {
+        let block = match reader.read_next_or_eof(buffer).map_err(|error| decompress_error("flac", error))? {
+            Some(block) => block,
+            None => return Err(ImageError::CorruptImage { detail: "FLAC hunk ended early" }),
+        };
+
+        for sample in 0..block.duration() {
+            for channel in 0..block.channels() {
+                let value = block.sample(channel, sample) as i16;
+
+                if big_endian {
+                    output.extend_from_slice(&value.to_be_bytes());
+                } else {
+                    output.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        buffer = block.into_buffer();
+    }
+
+    output.truncate(length);
+
+    Ok((output, reader.into_inner().position() as usize))
+}
+
+fn parse_chd_track_type(track_type: &str) -> Result<TrackType, ImageError> {
+    match track_type {
+        "MODE1" => Ok(TrackType::Mode1_2048),
+        "MODE2_FORM1" => Ok(TrackType::Mode2_2048),
+        "MODE1_RAW" => Ok(TrackType::Mode1_2352),
+        "MODE2" | "MODE2_FORM_MIX" => Ok(TrackType::Mode2_2336),
+        "MODE2_RAW" => Ok(TrackType::Mode2_2352),
+        "AUDIO" => Ok(TrackType::Audio),
+        _ => Err(ImageError::UnsupportedImage { feature: format!("CHD track type {}", track_type) }),
+    }
+}
+
+fn parse_metadata_number<T: std::str::FromStr>(value: &str) -> Result<T, ImageError> {
+    value.parse().map_err(|_| ImageError::CorruptImage { detail: "CD track metadata has a malformed number" })
+}
+
+//TRACK:1 TYPE:MODE2_RAW SUBTYPE:NONE FRAMES:1234 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0
+//Returns the track along with its pregap type and postgap
+fn parse_track_metadata(text: &str) -> Result<(Track, String, u64), ImageError> {
+    let mut track = Track {
+        number: 0,
+        track_type: TrackType::Mode1_2048,
+        start_lsn: 0,
+        pregap_length: 0,
+        sector_count: 0,
+    };
+    let mut pregap_type = String::new();
+    let mut postgap: u64 = 0;
+
+    for field in text.split_whitespace() {
+        let mut parts = field.splitn(2, ':');
+        let key = parts.next().unwrap_or_default();
+        let value = parts.next().unwrap_or_default();
+
+        match key {
+            "TRACK" => track.number = parse_metadata_number(value)?,
+            "TYPE" => track.track_type = parse_chd_track_type(value)?,
+            "FRAMES" => track.sector_count = parse_metadata_number(value)?,
+            "PREGAP" => track.pregap_length = parse_metadata_number(value)?,
+            "PGTYPE" => pregap_type = value.to_string(),
+            "POSTGAP" => postgap = parse_metadata_number(value)?,
+            _ => {},
+        }
+    }
+
+    Ok((track, pregap_type, postgap))
+}
+
+impl ChdImage<File> {
+    pub fn open(path: &str) -> Result<ChdImage<File>, ImageError> {
+        ChdImage::from_reader(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ChdImage<R> {
+    pub fn from_reader(mut file: R) -> Result<ChdImage<R>, ImageError> {
+        let header = read_bytes(&mut file, 0, 16)?;
+
+        if &header[0..8] != CHD_MAGIC {
+            return Err(ImageError::NotAnImage { format: "CHD" });
+        }
+
+        let version = ByteReader::new("ChdHeader", &header[12..], 12).u32_be("version")?;
+
+        if version != 5 {
+            return Err(ImageError::UnsupportedImage { feature: format!("CHD version {}", version) });
+        }
+
+        let header = read_bytes(&mut file, 0, V5_HEADER_SIZE)?;
+        let mut reader = ByteReader::new("ChdHeader", &header[16..], 16);
+        let mut compressors = [0u32; 4];
+
+        for compressor in compressors.iter_mut() {
+            *compressor = reader.u32_be("compressors")?;
+        }
+
+        let logical_bytes = reader.u64_be("logical_bytes")?;
+        let map_offset = reader.u64_be("map_offset")?;
+        let metadata_offset = reader.u64_be("metadata_offset")?;
+        let hunk_bytes = reader.u32_be("hunk_bytes")?;
+        let unit_bytes = reader.u32_be("unit_bytes")?;
+
+        if hunk_bytes == 0 || unit_bytes == 0 {
+            return Err(ImageError::CorruptImage { detail: "CHD header is bad" });
+        }
+
+        let hunk_count = logical_bytes.div_ceil(hunk_bytes as u64);
+
+        let mut chd = ChdImage {
+            file,
+            compressors,
+            logical_bytes,
+            hunk_bytes,
+            unit_bytes,
+            map: vec![],
+            cd_tracks: vec![],
+            cache: BlockCache::new(CACHED_HUNKS),
+        };
+
+        chd.map = if compressors[0] == 0 {
+            chd.read_uncompressed_map(map_offset, hunk_count)?
+        } else {
+            chd.read_compressed_map(map_offset, hunk_count)?
+        };
+
+        chd.cd_tracks = chd.read_cd_tracks(metadata_offset)?;
+
+        Ok(chd)
+    }
+
+    pub fn is_cd(&self) -> bool {
+        !self.cd_tracks.is_empty()
+    }
+
+    fn read_uncompressed_map(&mut self, map_offset: u64, hunk_count: u64) -> Result<Vec<Hunk>, ImageError> {
+        let map_data = read_bytes(&mut self.file, map_offset, (hunk_count as usize).saturating_mul(4))?;
+
+        Ok(map_data.chunks_exact(4).map(|entry| {
+            match u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) {
+                0 => Hunk::Zero,
+                block => Hunk::Uncompressed {
+                    offset: block as u64 * self.hunk_bytes as u64,
+                    crc: None,
+                },
+            }
+        }).collect())
+    }
+
+    //The map is huffman coded hunk types followed by bit packed lengths, offsets and CRCs
+    fn read_compressed_map(&mut self, map_offset: u64, hunk_count: u64) -> Result<Vec<Hunk>, ImageError> {
+        let map_header = read_bytes(&mut self.file, map_offset, MAP_HEADER_SIZE)?;
+        let mut header_reader = ByteReader::new("ChdMapHeader", &map_header, map_offset);
+        let map_bytes = header_reader.u32_be("map_bytes")? as usize;
+        let first_offset = read_u48_be(header_reader.bytes("first_offset", 6)?);
+        let map_crc = header_reader.u16_be("map_crc")?;
+        let length_bits = header_reader.u8("length_bits")? as u32;
+        let self_bits = header_reader.u8("self_bits")? as u32;
+        let parent_bits = header_reader.u8("parent_bits")? as u32;
+
+        let map_data = read_bytes(&mut self.file, map_offset + MAP_HEADER_SIZE as u64, map_bytes)?;
+        let mut reader = BitReader::new(&map_data);
+        let decoder = HuffmanDecoder::import_tree_rle(&mut reader, 16, 8)?;
+
+        //hunk_count comes from the header so the map has to be read before anything is sized by it
+        let mut hunk_types: Vec<u8> = vec![];
+        let mut last_type: u8 = 0;
+        let mut repeat: u32 = 0;
+
+        while (hunk_types.len() as u64) < hunk_count {
+            if reader.overflowed() {
+                return Err(ImageError::CorruptImage { detail: "CHD map has fewer hunks than the header" });
+            }
+
+            if repeat > 0 {
+                hunk_types.push(last_type);
+                repeat -= 1;
+                continue;
+            }
+
+            match decoder.decode_one(&mut reader) as u8 {
+                COMPRESSION_RLE_SMALL => {
+                    hunk_types.push(last_type);
+                    repeat = 2 + decoder.decode_one(&mut reader);
+                },
+                COMPRESSION_RLE_LARGE => {
+                    hunk_types.push(last_type);
+                    repeat = 2 + 16 + (decoder.decode_one(&mut reader) << 4);
+                    repeat += decoder.decode_one(&mut reader);
+                },
+                hunk_type => {
+                    hunk_types.push(hunk_type);
+                    last_type = hunk_type;
+                },
+            }
+        }
+
+        //Rebuild the raw 12 byte map entries as well since that's what the CRC covers
+        let mut raw_map: Vec<u8> = Vec::with_capacity(hunk_types.len() * 12);
+        let mut map: Vec<Hunk> = Vec::with_capacity(hunk_types.len());
+        let mut current_offset = first_offset;
+        let mut last_self: u64 = 0;
+        let mut last_parent: u64 = 0;
+        let units_per_hunk = (self.hunk_bytes / self.unit_bytes) as u64;
+
+        for (hunk_number, hunk_type) in hunk_types.into_iter().enumerate() {
+            let mut raw_type = hunk_type;
+            let mut length: u32 = 0;
+            let mut offset: u64 = current_offset;
+            let mut crc: u16 = 0;
+
+            let hunk = match hunk_type {
+                0..=COMPRESSION_TYPE_3 => {
+                    length = reader.read(length_bits);
+                    current_offset += length as u64;
+                    crc = reader.read(16) as u16;
+
+                    Hunk::Compressed { codec: hunk_type as usize, offset, length, crc }
+                },
+                COMPRESSION_NONE => {
+                    length = self.hunk_bytes;
+                    current_offset += length as u64;
+                    crc = reader.read(16) as u16;
+
+                    Hunk::Uncompressed { offset, crc: Some(crc) }
+                },
+                COMPRESSION_SELF | COMPRESSION_SELF_0 | COMPRESSION_SELF_1 => {
+                    match hunk_type {
+                        COMPRESSION_SELF => last_self = reader.read(self_bits) as u64,
+                        COMPRESSION_SELF_1 => last_self += 1,
+                        _ => {},
+                    }
+
+                    raw_type = COMPRESSION_SELF;
+                    offset = last_self;
+
+                    Hunk::Duplicate(last_self)
+                },
+                COMPRESSION_PARENT | COMPRESSION_PARENT_SELF | COMPRESSION_PARENT_0 | COMPRESSION_PARENT_1 => {
+                    match hunk_type {
+                        COMPRESSION_PARENT => last_parent = reader.read(parent_bits) as u64,
+                        COMPRESSION_PARENT_SELF => last_parent = hunk_number as u64 * units_per_hunk,
+                        COMPRESSION_PARENT_1 => last_parent += units_per_hunk,
+                        _ => {},
+                    }
+
+                    raw_type = COMPRESSION_PARENT;
+                    offset = last_parent;
+
+                    Hunk::Parent
+                },
+                _ => return Err(ImageError::CorruptImage { detail: "CHD map has an unknown hunk type" }),
+            };
+
+            raw_map.push(raw_type);
+            raw_map.extend_from_slice(&length.to_be_bytes()[1..]);
+            raw_map.extend_from_slice(&offset.to_be_bytes()[2..]);
+            raw_map.extend_from_slice(&crc.to_be_bytes());
+            map.push(hunk);
+        }
+
+        if reader.overflowed() || crc16(&raw_map) != map_crc {
+            return Err(ImageError::CorruptImage { detail: "CHD map failed its CRC check" });
+        }
+
+        Ok(map)
+    }
+
+    //Lays the tracks out the same way MAME does, LSN 0 being INDEX 01 of the first track
+    fn read_cd_tracks(&mut self, mut metadata_offset: u64) -> Result<Vec<ChdTrack>, ImageError> {
+        let mut tracks: Vec<(Track, String, u64)> = vec![];
+
+        while metadata_offset != 0 {
+            let header = read_bytes(&mut self.file, metadata_offset, METADATA_HEADER_SIZE)?;
+            let mut reader = ByteReader::new("ChdMetadataHeader", &header, metadata_offset);
+            let tag = reader.u32_be("tag")?;
+            //The top byte is the flags
+            let length = reader.u32_be("length")? & 0x00ffffff;
+            let next_offset = reader.u64_be("next")?;
+
+            if tag == CD_TRACK_METADATA || tag == CD_TRACK_METADATA2 {
+                let data = read_bytes(&mut self.file, metadata_offset + METADATA_HEADER_SIZE as u64, length as usize)?;
+                let text = String::from_utf8_lossy(&data);
+
+                tracks.push(parse_track_metadata(text.trim_end_matches('\u{0}'))?);
+            }
+
+            metadata_offset = next_offset;
+        }
+
+        tracks.sort_by_key(|(track, _, _)| track.number);
+
+        let mut cd_tracks: Vec<ChdTrack> = vec![];
+        let mut logical_frame: u64 = 0;
+        let mut chd_frame: u64 = 0;
+
+        for (mut track, pregap_type, postgap) in tracks {
+            //A V prefix on the pregap type means the pregap is stored in the image
+            let frames = track.sector_count;
+            let stored_pregap = if pregap_type.starts_with('V') { track.pregap_length.min(frames) } else { 0 };
+
+            if stored_pregap == 0 {
+                logical_frame += track.pregap_length;
+            }
+
+            track.start_lsn = logical_frame + stored_pregap;
+            track.sector_count = frames - stored_pregap;
+
+            cd_tracks.push(ChdTrack {
+                track,
+                chd_frame,
+                stored_pregap,
+            });
+
+            logical_frame += frames + postgap;
+            chd_frame += frames + (CD_TRACK_PADDING - frames % CD_TRACK_PADDING) % CD_TRACK_PADDING;
+        }
+
+        if let Some(first_start) = cd_tracks.first().map(|cd_track| cd_track.track.start_lsn) {
+            for cd_track in cd_tracks.iter_mut() {
+                cd_track.track.start_lsn -= first_start;
+            }
+
+            //The first track's pregap is the lead in
+            cd_tracks[0].track.pregap_length = 0;
+            cd_tracks[0].stored_pregap = 0;
+        }
+
+        Ok(cd_tracks)
+    }
+
+    fn decompress(&self, codec: u32, data: &[u8]) -> Result<Vec<u8>, ImageError> {
+        let length = self.hunk_bytes as usize;
+
+        match codec {
+            CODEC_ZLIB => inflate(data, length),
+            CODEC_LZMA => lzma_decompress(data, length),
+            //First byte says which endianness the samples were in
+            CODEC_FLAC => match data.first() {
+                Some(b'L') => Ok(flac_decompress(&data[1..], length, false)?.0),
+                Some(b'B') => Ok(flac_decompress(&data[1..], length, true)?.0),
+                _ => Err(ImageError::CorruptImage { detail: "FLAC hunk has a bad endianness marker" }),
+            },
+            CODEC_CD_ZLIB | CODEC_CD_LZMA | CODEC_CD_FLAC => self.decompress_cd(codec, data),
+            _ => Err(ImageError::UnsupportedImage { feature: format!("CHD codec {:?}", String::from_utf8_lossy(&codec.to_be_bytes())) }),
+        }
+    }
+
+    //CD codecs compress the sector data and the subchannel data separately
+    fn decompress_cd(&self, codec: u32, data: &[u8]) -> Result<Vec<u8>, ImageError> {
+        let frames = self.hunk_bytes as usize / CD_FRAME_SIZE;
+        let sector_bytes = frames * RAW_SECTOR_SIZE;
+        let subcode_bytes = frames * CD_SUBCODE_SIZE;
+
+        let (sectors, subcode, ecc_flags) = if codec == CODEC_CD_FLAC {
+            let (sectors, used) = flac_decompress(data, sector_bytes, true)?;
+
+            (sectors, inflate(&data[used..], subcode_bytes)?, &[][..])
+        } else {
+            //Header is a bitmap of frames that had their sync and ECC stripped then the length of the sector data
+            let ecc_bytes = frames.div_ceil(8);
+            let length_bytes = if self.hunk_bytes < 65536 { 2 } else { 3 };
+            let header_bytes = ecc_bytes + length_bytes;
+
+            if data.len() < header_bytes {
+                return Err(ImageError::CorruptImage { detail: "CD hunk is too short" });
+            }
+
+            let base_length = data[ecc_bytes..header_bytes].iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+            let base = match data.get(header_bytes..header_bytes + base_length) {
+                Some(base) => base,
+                None => return Err(ImageError::CorruptImage { detail: "CD hunk is too short" }),
+            };
+
+            let sectors = if codec == CODEC_CD_LZMA {
+                lzma_decompress(base, sector_bytes)?
+            } else {
+                inflate(base, sector_bytes)?
+            };
+
+            (sectors, inflate(&data[header_bytes + base_length..], subcode_bytes)?, &data[..ecc_bytes])
+        };
+
+        if sectors.len() < sector_bytes || subcode.len() < subcode_bytes {
+            return Err(ImageError::CorruptImage { detail: "CD hunk decompressed to the wrong size" });
+        }
+
+        let mut hunk = vec![0u8; self.hunk_bytes as usize];
+
+        for frame in 0..frames {
+            let output = &mut hunk[frame * CD_FRAME_SIZE..(frame + 1) * CD_FRAME_SIZE];
+            output[..RAW_SECTOR_SIZE].copy_from_slice(&sectors[frame * RAW_SECTOR_SIZE..(frame + 1) * RAW_SECTOR_SIZE]);
+            output[RAW_SECTOR_SIZE..].copy_from_slice(&subcode[frame * CD_SUBCODE_SIZE..(frame + 1) * CD_SUBCODE_SIZE]);
+
+            //Only the sync is put back, ECC is left zeroed since nothing checks it
+            if ecc_flags.get(frame / 8).is_some_and(|flags| flags & (1 << (frame % 8)) != 0) {
+                output[..SYNC_PATTERN.len()].copy_from_slice(&SYNC_PATTERN);
+            }
+        }
+
+        Ok(hunk)
+    }
+
+    fn read_hunk(&mut self, hunk_number: u64) -> Result<Vec<u8>, ImageError> {
+        let hunk = match self.map.get(hunk_number as usize) {
+            Some(hunk) => *hunk,
+            None => return Err(ImageError::CorruptImage { detail: "hunk is past the end of the map" }),
+        };
+
+        let (data, crc) = match hunk {
+            Hunk::Compressed { codec, offset, length, crc } => {
+                let compressed = read_bytes(&mut self.file, offset, length as usize)?;
+
+                (self.decompress(self.compressors[codec], &compressed)?, Some(crc))
+            },
+            Hunk::Uncompressed { offset, crc } => (read_bytes(&mut self.file, offset, self.hunk_bytes as usize)?, crc),
+            //Only ever points backwards so this can't loop
+            Hunk::Duplicate(other) if other < hunk_number => (self.read_hunk(other)?, None),
+            Hunk::Duplicate(_) => return Err(ImageError::CorruptImage { detail: "hunk refers to a later hunk" }),
+            Hunk::Parent => return Err(ImageError::UnsupportedImage { feature: "a parent CHD".to_string() }),
+            Hunk::Zero => (vec![0u8; self.hunk_bytes as usize], None),
+        };
+
+        if data.len() != self.hunk_bytes as usize || crc.is_some_and(|crc| crc16(&data) != crc) {
+            return Err(ImageError::ChecksumMismatch { hunk: hunk_number });
+        }
+
+        Ok(data)
+    }
+
+    fn read_data(&mut self, mut offset: u64, length: usize) -> Result<Vec<u8>, ImageError> {
+        let start = offset;
+        let mut data = Vec::with_capacity(length);
+
+        while data.len() < length {
+            let hunk_number = offset / self.hunk_bytes as u64;
+
+            if !self.cache.contains(hunk_number) {
+                let hunk = self.read_hunk(hunk_number)?;
+                self.cache.insert(hunk_number, hunk);
+            }
+
+            let hunk = match self.cache.get(hunk_number) {
+                Some(hunk) => hunk,
+                None => break,
+            };
+
+            let hunk_offset = (offset % self.hunk_bytes as u64) as usize;
+            let count = (length - data.len()).min(hunk.len() - hunk_offset);
+            data.extend_from_slice(&hunk[hunk_offset..hunk_offset + count]);
+            offset += count as u64;
+        }
+
+        //Only short if the last block or hunk doesn't cover the read
+        if data.len() < length {
+            return Err(ImageError::Truncated { offset: start, len: length });
+        }
+
+        Ok(data)
+    }
+
+    //Sector as it is stored in the frame, or None for pregap silence that isn't in the image
+    fn read_cd_sector(&mut self, lsn: u64) -> Result<StoredSector, ImageError> {
+        let cd_track = match self.cd_tracks.iter().find(|cd_track| {
+            let track = &cd_track.track;
+
+            lsn + track.pregap_length >= track.start_lsn && lsn < track.start_lsn + track.sector_count
+        }) {
+            Some(cd_track) => cd_track.clone(),
+            None => return Err(ImageError::SectorOutOfRange { lsn }),
+        };
+
+        let track_type = cd_track.track.track_type;
+        let first_stored = cd_track.track.start_lsn - cd_track.stored_pregap;
+
+        if lsn < first_stored {
+            return Ok((track_type, None));
+        }
+
+        let frame = cd_track.chd_frame + (lsn - first_stored);
+        let mut data = self.read_data(frame * self.unit_bytes as u64, CD_FRAME_SIZE)?;
+        data.truncate(track_type.sector_size());
+
+        Ok((track_type, Some(data)))
+    }
+}
+
+impl<R: Read + Seek> BlockDevice for ChdImage<R> {
+    fn sector_count(&self) -> u64 {
+        match self.cd_tracks.last() {
+            Some(cd_track) => cd_track.track.start_lsn + cd_track.track.sector_count,
+            None => self.logical_bytes / SECTOR_SIZE,
+        }
+    }
+
+    fn read_sector(&mut self, lsn: u64) -> Result<Vec<u8>, ImageError> {
+        if !self.is_cd() {
+            if lsn >= self.sector_count() {
+                return Err(ImageError::SectorOutOfRange { lsn });
+            }
+
+            return self.read_data(lsn * SECTOR_SIZE, USER_DATA_SIZE);
+        }
+
+        match self.read_cd_sector(lsn)? {
+            (TrackType::Audio, _) => Err(ImageError::AudioSector { lsn }),
+            (track_type, data) => Ok(cook_sector(track_type, &data.unwrap_or_default())),
+        }
+    }
+
+    fn tracks(&self) -> Vec<Track> {
+        if self.is_cd() {
+            return self.cd_tracks.iter().map(|cd_track| cd_track.track.clone()).collect();
+        }
+
+        vec![Track {
+            number: 1,
+            track_type: TrackType::Mode1_2048,
+            start_lsn: 0,
+            pregap_length: 0,
+            sector_count: self.sector_count(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use lzma_rs::compress::{Options, UnpackedSize};
+    use super::{crc16, ChdImage, CD_FRAME_SIZE};
+    use super::super::image_error::ImageError;
+    use super::super::block_device::{BlockDevice, TrackType, RAW_SECTOR_SIZE, SYNC_PATTERN};
+
+    struct BitWriter {
+        data: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u32) {
+            for bit in (0..bits).rev() {
+                if self.bits.is_multiple_of(8) {
+                    self.data.push(0);
+                }
+
+                if value & (1 << bit) != 0 {
+                    *self.data.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+                }
+
+                self.bits += 1;
+            }
+        }
+    }
+
+    fn create_chd_header(codec: &[u8; 4], logical_bytes: u64, metadata_offset: u64, hunk_bytes: u32, unit_bytes: u32) -> Vec<u8> {
+        let mut header = vec![0u8; 124];
+        header[0..8].copy_from_slice(b"MComprHD");
+        header[8..12].copy_from_slice(&124u32.to_be_bytes());
+        header[12..16].copy_from_slice(&5u32.to_be_bytes());
+        header[16..20].copy_from_slice(codec);
+        header[32..40].copy_from_slice(&logical_bytes.to_be_bytes());
+        //The map always goes straight after the header
+        header[40..48].copy_from_slice(&124u64.to_be_bytes());
+        header[48..56].copy_from_slice(&metadata_offset.to_be_bytes());
+        header[56..60].copy_from_slice(&hunk_bytes.to_be_bytes());
+        header[60..64].copy_from_slice(&unit_bytes.to_be_bytes());
+
+        header
+    }
+
+    //Code lengths for the map's huffman tree: type 0 gets 1 bit, NONE and SELF get 2 bits
+    fn write_map_tree(map: &mut BitWriter) {
+        for (value, bits) in [(1, 4), (1, 4), (1, 4), (0, 4), (0, 4), (2, 4), (2, 4), (1, 4), (0, 4), (7, 4)] {
+            map.write(value, bits);
+        }
+    }
+
+    //Every hunk goes through the header's first codec, the metadata entries are CHT2 track descriptions
+    fn create_compressed_chd(codec: &[u8; 4], hunk_bytes: u32, unit_bytes: u32, hunks: &[Vec<u8>], compress: impl Fn(&[u8]) -> Vec<u8>, tracks: &[&str]) -> Vec<u8> {
+        let compressed: Vec<Vec<u8>> = hunks.iter().map(|hunk| compress(hunk)).collect();
+
+        let mut map = BitWriter { data: vec![], bits: 0 };
+        write_map_tree(&mut map);
+
+        for _ in hunks {
+            map.write(1, 1);
+        }
+
+        for (hunk, data) in hunks.iter().zip(compressed.iter()) {
+            map.write(data.len() as u32, 24);
+            map.write(crc16(hunk) as u32, 16);
+        }
+
+        let first_offset = (124 + 16 + map.data.len()) as u64;
+        let mut raw_map: Vec<u8> = vec![];
+        let mut offset = first_offset;
+
+        for (hunk, data) in hunks.iter().zip(compressed.iter()) {
+            raw_map.push(0);
+            raw_map.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            raw_map.extend_from_slice(&offset.to_be_bytes()[2..]);
+            raw_map.extend_from_slice(&crc16(hunk).to_be_bytes());
+            offset += data.len() as u64;
+        }
+
+        let metadata_offset = if tracks.is_empty() { 0 } else { offset };
+        let mut image = create_chd_header(codec, hunks.len() as u64 * hunk_bytes as u64, metadata_offset, hunk_bytes, unit_bytes);
+
+        image.extend_from_slice(&(map.data.len() as u32).to_be_bytes());
+        image.extend_from_slice(&first_offset.to_be_bytes()[2..]);
+        image.extend_from_slice(&crc16(&raw_map).to_be_bytes());
+        image.extend_from_slice(&[24, 8, 0, 0]);
+        image.extend_from_slice(&map.data);
+        image.extend(compressed.concat());
+
+        //Each entry is the tag, the length, the next entry then the null terminated text
+        for (index, track) in tracks.iter().enumerate() {
+            let next = if index + 1 < tracks.len() { image.len() as u64 + 16 + track.len() as u64 + 1 } else { 0 };
+
+            image.extend_from_slice(b"CHT2");
+            image.extend_from_slice(&(track.len() as u32 + 1).to_be_bytes());
+            image.extend_from_slice(&next.to_be_bytes());
+            image.extend_from_slice(track.as_bytes());
+            image.push(0);
+        }
+
+        image
+    }
+
+    type Compressor = fn(&[u8]) -> Vec<u8>;
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        miniz_oxide::deflate::compress_to_vec(data, 6)
+    }
+
+    //CHD keeps the properties to itself so only the raw stream after the 5 byte header is stored
+    fn lzma(data: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        lzma_rs::lzma_compress_with_options(&mut &data[..], &mut output, &Options { unpacked_size: UnpackedSize::SkipWritingToHeader }).unwrap();
+
+        output[5..].to_vec()
+    }
+
+    fn crc8(data: &[u8]) -> u8 {
+        data.iter().fold(0u8, |crc, byte| (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 }))
+    }
+
+    fn crc16_flac(data: &[u8]) -> u16 {
+        data.iter().fold(0u16, |crc, byte| (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 }))
+    }
+
+    //Bare 44.1kHz 16 bit stereo FLAC frames with verbatim subframes, 588 samples (one CD sector) a frame
+    fn flac(data: &[u8], big_endian: bool) -> Vec<u8> {
+        let samples: Vec<i16> = data.chunks_exact(2).map(|bytes| {
+            if big_endian { i16::from_be_bytes([bytes[0], bytes[1]]) } else { i16::from_le_bytes([bytes[0], bytes[1]]) }
+        }).collect();
+        let mut output = vec![];
+
+        for (number, block) in samples.chunks(588 * 2).enumerate() {
+            let mut frame = vec![0xff, 0xf8, 0x79, 0x18, number as u8];
+            frame.extend_from_slice(&(block.len() as u16 / 2 - 1).to_be_bytes());
+            frame.push(crc8(&frame));
+
+            for channel in 0..2 {
+                frame.push(0x02);
+
+                for sample in block.iter().skip(channel).step_by(2) {
+                    frame.extend_from_slice(&sample.to_be_bytes());
+                }
+            }
+
+            frame.extend_from_slice(&crc16_flac(&frame).to_be_bytes());
+            output.extend(frame);
+        }
+
+        output
+    }
+
+    fn split_cd_hunk(hunk: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let sectors = hunk.chunks_exact(CD_FRAME_SIZE).flat_map(|frame| frame[..RAW_SECTOR_SIZE].to_vec()).collect();
+        let subcode = hunk.chunks_exact(CD_FRAME_SIZE).flat_map(|frame| frame[RAW_SECTOR_SIZE..].to_vec()).collect();
+
+        (sectors, subcode)
+    }
+
+    //cdzl and cdlz strip the sync from frames that have one and flag them in the bitmap at the front
+    fn compress_cd(hunk: &[u8], compress: Compressor) -> Vec<u8> {
+        let (mut sectors, subcode) = split_cd_hunk(hunk);
+        let mut ecc_flags = vec![0u8; (hunk.len() / CD_FRAME_SIZE).div_ceil(8)];
+
+        for (frame, sector) in sectors.chunks_exact_mut(RAW_SECTOR_SIZE).enumerate() {
+            if sector[..12] == SYNC_PATTERN {
+                sector[..12].fill(0);
+                ecc_flags[frame / 8] |= 1 << (frame % 8);
+            }
+        }
+
+        let base = compress(&sectors);
+        [ecc_flags, (base.len() as u16).to_be_bytes().to_vec(), base, deflate(&subcode)].concat()
+    }
+
+    fn compress_cd_flac(hunk: &[u8]) -> Vec<u8> {
+        let (sectors, subcode) = split_cd_hunk(hunk);
+
+        [flac(&sectors, true), deflate(&subcode)].concat()
+    }
+
+    //Mode 1 frame with a sync, its data filled with value and a subchannel that's all 0xff
+    fn create_cd_frame(value: u8) -> Vec<u8> {
+        let mut frame = vec![0xffu8; CD_FRAME_SIZE];
+        frame[..12].copy_from_slice(&SYNC_PATTERN);
+        frame[12..16].copy_from_slice(&[0, 2, 0, 1]);
+        frame[16..RAW_SECTOR_SIZE].fill(value);
+
+        frame
+    }
+
+    //3 hunks of 2 sectors each, zlib compressed, stored uncompressed and a copy of the first
+    fn create_mock_chd() -> Vec<u8> {
+        let hunks: Vec<Vec<u8>> = (0..2u8).map(|hunk| [vec![hunk * 2 + 1; 2048], vec![hunk * 2 + 2; 2048]].concat()).collect();
+        let compressed = miniz_oxide::deflate::compress_to_vec(&hunks[0], 6);
+
+        let mut map = BitWriter { data: vec![], bits: 0 };
+        write_map_tree(&mut map);
+
+        //Type 0, NONE then SELF
+        map.write(1, 1);
+        map.write(0, 2);
+        map.write(1, 2);
+
+        //Length and CRC of the compressed hunk, CRC of the uncompressed hunk then the hunk SELF points at
+        map.write(compressed.len() as u32, 24);
+        map.write(crc16(&hunks[0]) as u32, 16);
+        map.write(crc16(&hunks[1]) as u32, 16);
+        map.write(0, 8);
+
+        let first_offset = (124 + 16 + map.data.len()) as u64;
+        let raw_map = [
+            [&[0u8][..], &(compressed.len() as u32).to_be_bytes()[1..], &first_offset.to_be_bytes()[2..], &crc16(&hunks[0]).to_be_bytes()].concat(),
+            [&[4u8][..], &4096u32.to_be_bytes()[1..], &(first_offset + compressed.len() as u64).to_be_bytes()[2..], &crc16(&hunks[1]).to_be_bytes()].concat(),
+            [&[5u8][..], &[0u8; 3], &[0u8; 6], &[0u8; 2]].concat(),
+        ].concat();
+
+        let mut image = create_chd_header(b"zlib", 3 * 4096, 0, 4096, 2048);
+
+        image.extend_from_slice(&(map.data.len() as u32).to_be_bytes());
+        image.extend_from_slice(&first_offset.to_be_bytes()[2..]);
+        image.extend_from_slice(&crc16(&raw_map).to_be_bytes());
+        image.extend_from_slice(&[24, 8, 0, 0]);
+        image.extend_from_slice(&map.data);
+        image.extend_from_slice(&compressed);
+        image.extend_from_slice(&hunks[1]);
+
+        image
+    }
+
+    #[test]
+    fn test_read_chd() {
+        let mut image = ChdImage::from_reader(Cursor::new(create_mock_chd())).unwrap();
+
+        assert!(!image.is_cd());
+        assert_eq!(image.sector_count(), 6);
+
+        for (lsn, value) in [(0, 1), (1, 2), (2, 3), (3, 4), (4, 1), (5, 2)] {
+            assert_eq!(image.read_sector(lsn).unwrap(), vec![value; 2048]);
+        }
+
+        assert_eq!(image.read_sector(6).unwrap_err(), ImageError::SectorOutOfRange { lsn: 6 });
+    }
+    #[test]
+    fn test_read_chd_codecs() {
+        //Anything that isn't a run of one value so the compressors have to do some work
+        let hunks: Vec<Vec<u8>> = (0..2u32).map(|hunk| (0..4096u32).map(|i| (i * 7 + hunk * 13 + i / 256) as u8).collect()).collect();
+        let codecs: [(&[u8; 4], Compressor); 3] = [
+            (b"zlib", deflate),
+            (b"lzma", lzma),
+            (b"flac", |data| [&b"L"[..], &flac(data, false)].concat()),
+        ];
+
+        for (codec, compress) in codecs {
+            let mut image = ChdImage::from_reader(Cursor::new(create_compressed_chd(codec, 4096, 2048, &hunks, compress, &[]))).unwrap();
+
+            assert_eq!(image.sector_count(), 4);
+
+            for lsn in 0..4 {
+                let hunk = &hunks[lsn as usize / 2];
+                let offset = (lsn as usize % 2) * 2048;
+                assert_eq!(image.read_sector(lsn).unwrap(), hunk[offset..offset + 2048], "{:?}", String::from_utf8_lossy(codec));
+            }
+        }
+    }
+
+    #[test]
+    fn test_reject_chd_bigger_than_its_map() {
+        let hunks = vec![vec![1u8; 4096]];
+        let mut image = create_compressed_chd(b"zlib", 4096, 2048, &hunks, deflate, &[]);
+        image[32..40].copy_from_slice(&(1u64 << 60).to_be_bytes());
+
+        assert_eq!(ChdImage::from_reader(Cursor::new(image)).unwrap_err(), ImageError::CorruptImage { detail: "CHD map has fewer hunks than the header" });
+    }
+
+    #[test]
+    fn test_read_cd_chd_codecs() {
+        let hunk: Vec<u8> = (1..=4).flat_map(create_cd_frame).collect();
+        let track = "TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0";
+        let codecs: [(&[u8; 4], Compressor); 3] = [
+            (b"cdzl", |hunk| compress_cd(hunk, deflate)),
+            (b"cdlz", |hunk| compress_cd(hunk, lzma)),
+            (b"cdfl", compress_cd_flac),
+        ];
+
+        for (codec, compress) in codecs {
+            let chd = create_compressed_chd(codec, 4 * CD_FRAME_SIZE as u32, CD_FRAME_SIZE as u32, std::slice::from_ref(&hunk), compress, &[track]);
+            let mut image = ChdImage::from_reader(Cursor::new(chd)).unwrap();
+
+            assert!(image.is_cd());
+            assert_eq!(image.sector_count(), 4);
+
+            for lsn in 0..4 {
+                assert_eq!(image.read_sector(lsn).unwrap(), vec![lsn as u8 + 1; 2048]);
+            }
+
+            //The stripped sync is put back and the subchannel is left off the sector
+            let (_, data) = image.read_cd_sector(2).unwrap();
+            assert_eq!(data.unwrap(), create_cd_frame(3)[..RAW_SECTOR_SIZE]);
+        }
+    }
+
+    #[test]
+    fn test_read_multi_track_cd_chd() {
+        //Track 1 is 5 frames so 3 frames of padding come before track 2, whose 2 sector pregap isn't stored
+        let frames: Vec<Vec<u8>> = (0..12u8).map(|frame| match frame {
+            0..=4 => create_cd_frame(frame + 1),
+            8..=10 => [vec![frame + 1; 2048], vec![0; CD_FRAME_SIZE - 2048]].concat(),
+            _ => vec![0; CD_FRAME_SIZE],
+        }).collect();
+        let hunks: Vec<Vec<u8>> = frames.chunks(4).map(|frames| frames.concat()).collect();
+        let tracks = [
+            "TRACK:2 TYPE:MODE2_FORM1 SUBTYPE:NONE FRAMES:3 PREGAP:2 PGTYPE:MODE2_FORM1 PGSUB:NONE POSTGAP:0",
+            "TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:5 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0",
+        ];
+
+        let chd = create_compressed_chd(b"cdzl", 4 * CD_FRAME_SIZE as u32, CD_FRAME_SIZE as u32, &hunks, |hunk| compress_cd(hunk, deflate), &tracks);
+        let mut image = ChdImage::from_reader(Cursor::new(chd)).unwrap();
+
+        let layout: Vec<(u8, TrackType, u64, u64, u64)> = image.tracks().iter()
+            .map(|track| (track.number, track.track_type, track.start_lsn, track.pregap_length, track.sector_count)).collect();
+        assert_eq!(layout, [(1, TrackType::Mode1_2352, 0, 0, 5), (2, TrackType::Mode2_2048, 7, 2, 3)]);
+        assert_eq!(image.sector_count(), 10);
+
+        assert_eq!(image.read_sector(4).unwrap(), vec![5; 2048]);
+        assert_eq!(image.read_sector(5).unwrap(), vec![0; 2048]);
+
+        for lsn in 7..10 {
+            assert_eq!(image.read_sector(lsn).unwrap(), vec![lsn as u8 + 2; 2048]);
+        }
+
+        assert_eq!(image.read_sector(10).unwrap_err(), ImageError::SectorOutOfRange { lsn: 10 });
+    }
+}
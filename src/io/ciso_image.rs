@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use super::utils::{read_bytes, ByteReader};
+use super::image_error::ImageError;
+use super::iso_file_reader::SECTOR_SIZE;
+use super::block_device::{BlockCache, BlockDevice, Track, TrackType, USER_DATA_SIZE};
+
+const HEADER_SIZE: u64 = 24;
+const INDEX_OFFSET_MASK: u32 = 0x7fffffff;
+const INDEX_FLAG: u32 = 0x80000000;
+const CACHED_BLOCKS: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CisoFormat {
+    //CSO, deflate compressed (version 2 can also use LZ4 per block)
+    Cso,
+    //ZSO, LZ4 compressed
+    Zso,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum BlockCompression {
+    Plain,
+    Deflate,
+    Lz4,
+}
+
+//Header is 24 bytes: magic, header size, total bytes, block size, version, index alignment and 2 reserved bytes
+//followed by one u32 per block plus one for the end of the last block
+#[derive(Debug)]
+pub struct CisoImage<R: Read + Seek = File> {
+    file: R,
+    format: CisoFormat,
+    version: u8,
+    total_bytes: u64,
+    block_size: u32,
+    index_alignment: u8,
+    block_index: Vec<u32>,
+    cache: BlockCache,
+}
+
+impl CisoImage<File> {
+    pub fn open(path: &str) -> Result<CisoImage<File>, ImageError> {
+        CisoImage::from_reader(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> CisoImage<R> {
+    pub fn from_reader(mut file: R) -> Result<CisoImage<R>, ImageError> {
+        let header = read_bytes(&mut file, 0, HEADER_SIZE as usize)?;
+
+        let format = match &header[0..4] {
+            b"CISO" => CisoFormat::Cso,
+            b"ZISO" => CisoFormat::Zso,
+            _ => return Err(ImageError::NotAnImage { format: "CSO or ZSO" }),
+        };
+
+        let mut reader = ByteReader::new("CisoHeader", &header[8..], 8);
+        let total_bytes = reader.u64_le("total_bytes")?;
+        let block_size = reader.u32_le("block_size")?;
+        let version = reader.u8("version")?;
+        let index_alignment = reader.u8("index_alignment")?;
+
+        if block_size == 0 || index_alignment >= 32 {
+            return Err(ImageError::CorruptImage { detail: "compressed image header is bad" });
+        }
+
+        let block_count = total_bytes.div_ceil(block_size as u64);
+        let index_data = read_bytes(&mut file, HEADER_SIZE, (block_count as usize).saturating_add(1).saturating_mul(4))?;
+        let block_index = index_data.chunks_exact(4).map(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]])).collect();
+
+        Ok(CisoImage {
+            file,
+            format,
+            version,
+            total_bytes,
+            block_size,
+            index_alignment,
+            block_index,
+            cache: BlockCache::new(CACHED_BLOCKS),
+        })
+    }
+
+    fn block_compression(&self, index_entry: u32, stored_size: u64) -> BlockCompression {
+        let flagged = index_entry & INDEX_FLAG != 0;
+
+        match self.format {
+            //Version 2 marks uncompressed blocks by size and uses the flag to pick LZ4
+            CisoFormat::Cso if self.version >= 2 => {
+                if stored_size >= self.block_size as u64 {
+                    BlockCompression::Plain
+                } else if flagged {
+                    BlockCompression::Lz4
+                } else {
+                    BlockCompression::Deflate
+                }
+            },
+            _ if flagged => BlockCompression::Plain,
+            CisoFormat::Cso => BlockCompression::Deflate,
+            CisoFormat::Zso => BlockCompression::Lz4,
+        }
+    }
+
+    fn decompress_block(&mut self, block: u64) -> Result<Vec<u8>, ImageError> {
+        let block = block as usize;
+
+        if block + 1 >= self.block_index.len() {
+            return Err(ImageError::CorruptImage { detail: "block is past the end of the block index" });
+        }
+
+        let start = ((self.block_index[block] & INDEX_OFFSET_MASK) as u64) << self.index_alignment;
+        let end = ((self.block_index[block + 1] & INDEX_OFFSET_MASK) as u64) << self.index_alignment;
+
+        //The last block can be short
+        let block_length = (self.total_bytes - block as u64 * self.block_size as u64).min(self.block_size as u64) as usize;
+        let stored_size = end.saturating_sub(start);
+        let compression = self.block_compression(self.block_index[block], stored_size);
+
+        let data = match compression {
+            BlockCompression::Plain => read_bytes(&mut self.file, start, block_length)?,
+            BlockCompression::Deflate => {
+                let compressed = read_bytes(&mut self.file, start, stored_size as usize)?;
+
+                miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, block_length)
+                    .map_err(|error| ImageError::DecompressFailed { codec: "deflate", reason: format!("block {}: {}", block, error) })?
+            },
+            BlockCompression::Lz4 => {
+                let compressed = read_bytes(&mut self.file, start, stored_size as usize)?;
+                let mut data = vec![0u8; block_length];
+
+                lz4_flex::block::decompress_into(&compressed, &mut data)
+                    .map_err(|error| ImageError::DecompressFailed { codec: "lz4", reason: format!("block {}: {}", block, error) })?;
+
+                data
+            },
+        };
+
+        if data.len() < block_length {
+            return Err(ImageError::CorruptImage { detail: "compressed block is shorter than the block size" });
+        }
+
+        Ok(data)
+    }
+
+    fn read_data(&mut self, mut offset: u64, length: usize) -> Result<Vec<u8>, ImageError> {
+        let start = offset;
+        let mut data = Vec::with_capacity(length);
+
+        while data.len() < length {
+            let block = offset / self.block_size as u64;
+
+            if !self.cache.contains(block) {
+                let block_data = self.decompress_block(block)?;
+                self.cache.insert(block, block_data);
+            }
+
+            let block_data = match self.cache.get(block) {
+                Some(block_data) => block_data,
+                None => break,
+            };
+
+            let block_offset = (offset % self.block_size as u64) as usize;
+
+            if block_offset >= block_data.len() {
+                break;
+            }
+
+            let count = (length - data.len()).min(block_data.len() - block_offset);
+            data.extend_from_slice(&block_data[block_offset..block_offset + count]);
+            offset += count as u64;
+        }
+
+        //Only short if the last block or hunk doesn't cover the read
+        if data.len() < length {
+            return Err(ImageError::Truncated { offset: start, len: length });
+        }
+
+        Ok(data)
+    }
+}
+
+impl<R: Read + Seek> BlockDevice for CisoImage<R> {
+    fn sector_count(&self) -> u64 {
+        self.total_bytes / SECTOR_SIZE
+    }
+
+    fn read_sector(&mut self, lsn: u64) -> Result<Vec<u8>, ImageError> {
+        if lsn >= self.sector_count() {
+            return Err(ImageError::SectorOutOfRange { lsn });
+        }
+
+        self.read_data(lsn * SECTOR_SIZE, USER_DATA_SIZE)
+    }
+
+    fn tracks(&self) -> Vec<Track> {
+        vec![Track {
+            number: 1,
+            track_type: TrackType::Mode1_2048,
+            start_lsn: 0,
+            pregap_length: 0,
+            sector_count: self.sector_count(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{CisoFormat, CisoImage};
+    use super::super::image_error::ImageError;
+    use super::super::block_device::BlockDevice;
+
+    //Block 1 is stored uncompressed, the rest go through the format's compressor
+    fn create_mock_ciso(magic: &[u8; 4], compress: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        let blocks: Vec<Vec<u8>> = (0..3u8).map(|block| vec![block + 1; 2048]).collect();
+
+        let mut image = vec![0u8; 24 + 4 * 4];
+        image[0..4].copy_from_slice(magic);
+        image[4..8].copy_from_slice(&24u32.to_le_bytes());
+        image[8..16].copy_from_slice(&(3u64 * 2048).to_le_bytes());
+        image[16..20].copy_from_slice(&2048u32.to_le_bytes());
+        image[20] = 1;
+
+        for (index, block) in blocks.iter().enumerate() {
+            let mut entry = image.len() as u32;
+
+            if index == 1 {
+                entry |= 0x80000000;
+                image.extend_from_slice(block);
+            } else {
+                image.extend_from_slice(&compress(block));
+            }
+
+            image[24 + index * 4..28 + index * 4].copy_from_slice(&entry.to_le_bytes());
+        }
+
+        let end = image.len() as u32;
+        image[36..40].copy_from_slice(&end.to_le_bytes());
+
+        image
+    }
+
+    #[test]
+    fn test_read_cso_and_zso() {
+        let cso = create_mock_ciso(b"CISO", |block| miniz_oxide::deflate::compress_to_vec(block, 6));
+        let zso = create_mock_ciso(b"ZISO", lz4_flex::block::compress);
+
+        for (image, format) in [(cso, CisoFormat::Cso), (zso, CisoFormat::Zso)] {
+            let mut image = CisoImage::from_reader(Cursor::new(image)).unwrap();
+
+            assert_eq!(image.format, format);
+            assert_eq!(image.sector_count(), 3);
+
+            for lsn in [2, 1, 0, 2] {
+                assert_eq!(image.read_sector(lsn).unwrap(), vec![lsn as u8 + 1; 2048]);
+            }
+
+            assert_eq!(image.read_sector(3).unwrap_err(), ImageError::SectorOutOfRange { lsn: 3 });
+        }
+    }
+}
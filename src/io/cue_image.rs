@@ -0,0 +1,358 @@
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use super::utils::read_bytes;
+use super::image_error::ImageError;
+use super::block_device::{cook_sector, BlockDevice, Msf, StoredSector, Track, TrackType};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueTrack {
+    pub number: u8,
+    pub track_type: TrackType,
+    //Silence from a PREGAP command, this isn't stored in the bin
+    pub pregap: u64,
+    //(index number, frames from the start of the file)
+    pub indexes: Vec<(u8, u64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueFile {
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Clone, Debug)]
+struct BinTrack {
+    track: Track,
+    file: usize,
+    //Byte offset of INDEX 01 in the bin
+    file_offset: u64,
+    //Part of the pregap that is actually stored in the bin (INDEX 00 to INDEX 01)
+    stored_pregap: u64,
+}
+
+#[derive(Debug)]
+pub struct BinCueImage<R: Read + Seek = File> {
+    files: Vec<R>,
+    tracks: Vec<BinTrack>,
+    sector_count: u64,
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, ImageError> {
+    value.parse().map_err(|_| ImageError::BadCueSheet { detail: "has a malformed number" })
+}
+
+fn parse_msf(msf: &str) -> Result<u64, ImageError> {
+    let parts: Vec<&str> = msf.split(':').collect();
+
+    if parts.len() != 3 {
+        return Err(ImageError::BadCueSheet { detail: "has a malformed mm:ss:ff time" });
+    }
+
+    Ok(Msf {
+        minute: parse_number(parts[0])?,
+        second: parse_number(parts[1])?,
+        frame: parse_number(parts[2])?,
+    }.to_frames())
+}
+
+fn parse_track_type(track_type: &str) -> Result<TrackType, ImageError> {
+    match track_type.to_uppercase().as_str() {
+        "MODE1/2048" => Ok(TrackType::Mode1_2048),
+        "MODE1/2352" => Ok(TrackType::Mode1_2352),
+        "MODE2/2336" => Ok(TrackType::Mode2_2336),
+        "MODE2/2352" => Ok(TrackType::Mode2_2352),
+        "AUDIO" => Ok(TrackType::Audio),
+        _ => Err(ImageError::UnsupportedImage { feature: format!("cue track type {}", track_type) }),
+    }
+}
+
+//FILE "name with spaces.bin" BINARY -> name with spaces.bin
+fn parse_file_name(arguments: &str) -> String {
+    let arguments = arguments.trim();
+
+    if let Some(stripped) = arguments.strip_prefix('"') {
+        stripped.split('"').next().unwrap_or_default().to_string()
+    } else {
+        arguments.split_whitespace().next().unwrap_or_default().to_string()
+    }
+}
+
+impl CueSheet {
+    pub fn parse(text: &str) -> Result<CueSheet, ImageError> {
+        let mut cue_sheet = CueSheet::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let command = parts.next().unwrap_or_default().to_uppercase();
+            let arguments = parts.next().unwrap_or_default().trim();
+            let values: Vec<&str> = arguments.split_whitespace().collect();
+
+            match command.as_str() {
+                "FILE" => cue_sheet.files.push(CueFile {
+                    name: parse_file_name(arguments),
+                    tracks: vec![],
+                }),
+                "TRACK" if values.len() >= 2 => {
+                    let track = CueTrack {
+                        number: parse_number(values[0])?,
+                        track_type: parse_track_type(values[1])?,
+                        pregap: 0,
+                        indexes: vec![],
+                    };
+
+                    match cue_sheet.files.last_mut() {
+                        Some(file) => file.tracks.push(track),
+                        None => return Err(ImageError::BadCueSheet { detail: "has a TRACK before any FILE" }),
+                    }
+                },
+                "INDEX" | "PREGAP" => {
+                    let track = match cue_sheet.files.last_mut().and_then(|file| file.tracks.last_mut()) {
+                        Some(track) => track,
+                        None => return Err(ImageError::BadCueSheet { detail: "has an INDEX or PREGAP outside of a TRACK" }),
+                    };
+
+                    if command == "PREGAP" && !values.is_empty() {
+                        track.pregap = parse_msf(values[0])?;
+                    } else if values.len() >= 2 {
+                        track.indexes.push((parse_number(values[0])?, parse_msf(values[1])?));
+                    }
+                },
+                //REM, TITLE, FLAGS, POSTGAP etc don't matter for reading sectors
+                _ => {},
+            }
+        }
+
+        if cue_sheet.files.iter().all(|file| file.tracks.is_empty()) {
+            return Err(ImageError::BadCueSheet { detail: "has no tracks" });
+        }
+
+        Ok(cue_sheet)
+    }
+}
+
+impl CueTrack {
+    fn index(&self, number: u8) -> Option<u64> {
+        self.indexes.iter().find(|(index, _)| *index == number).map(|(_, frames)| *frames)
+    }
+}
+
+//Lays every track out on one continuous LSN range, LSN 0 being INDEX 01 of the first track
+//Tracks within one bin are assumed to share a sector size, which is true of every dump I've seen
+fn layout_tracks<R: Read + Seek>(cue_sheet: &CueSheet, files: &mut [R]) -> Result<(Vec<BinTrack>, u64), ImageError> {
+    let mut bin_tracks: Vec<BinTrack> = vec![];
+    let mut disc_lsn: u64 = 0;
+
+    for (file_index, (cue_file, file)) in cue_sheet.files.iter().zip(files.iter_mut()).enumerate() {
+        let sector_size = match cue_file.tracks.first() {
+            Some(track) => track.track_type.sector_size() as u64,
+            None => continue,
+        };
+        let file_sectors = file.seek(io::SeekFrom::End(0))? / sector_size;
+        let mut silence: u64 = 0;
+
+        for (track_index, cue_track) in cue_file.tracks.iter().enumerate() {
+            let index1 = match cue_track.index(1) {
+                Some(index1) => index1,
+                None => return Err(ImageError::BadCueSheet { detail: "track is missing INDEX 01" }),
+            };
+            let index0 = cue_track.index(0).unwrap_or(index1).min(index1);
+
+            let track_end = match cue_file.tracks.get(track_index + 1) {
+                Some(next_track) => next_track.index(0).or_else(|| next_track.index(1)).unwrap_or(file_sectors),
+                None => file_sectors,
+            };
+
+            //The first track's pregap is the lead in which is never addressable
+            let (first_track, stored_pregap) = if bin_tracks.is_empty() {
+                (true, 0)
+            } else {
+                (false, index1 - index0)
+            };
+
+            if !first_track {
+                silence += cue_track.pregap;
+            }
+
+            let start_lsn = if first_track {
+                0
+            } else {
+                disc_lsn.wrapping_add(silence + index1)
+            };
+
+            bin_tracks.push(BinTrack {
+                track: Track {
+                    number: cue_track.number,
+                    track_type: cue_track.track_type,
+                    start_lsn,
+                    pregap_length: stored_pregap + if first_track { 0 } else { cue_track.pregap },
+                    sector_count: track_end.saturating_sub(index1),
+                },
+                file: file_index,
+                file_offset: index1 * sector_size,
+                stored_pregap,
+            });
+
+            //Make the rest of this file line up with the first track starting at 0
+            if first_track {
+                disc_lsn = 0u64.wrapping_sub(index1);
+            }
+        }
+
+        disc_lsn = disc_lsn.wrapping_add(silence + file_sectors);
+    }
+
+    Ok((bin_tracks, disc_lsn))
+}
+
+impl BinCueImage<File> {
+    //Bins are looked up relative to the cue sheet
+    pub fn open(cue_path: &str) -> Result<BinCueImage<File>, ImageError> {
+        let cue_sheet = CueSheet::parse(&std::fs::read_to_string(cue_path)?)?;
+        let directory = Path::new(cue_path).parent().unwrap_or_else(|| Path::new(""));
+
+        let mut files: Vec<File> = vec![];
+
+        for cue_file in cue_sheet.files.iter() {
+            files.push(File::open(directory.join(&cue_file.name))?);
+        }
+
+        BinCueImage::from_readers(&cue_sheet, files)
+    }
+}
+
+impl<R: Read + Seek> BinCueImage<R> {
+    pub fn from_readers(cue_sheet: &CueSheet, mut files: Vec<R>) -> Result<BinCueImage<R>, ImageError> {
+        if files.len() != cue_sheet.files.len() {
+            return Err(ImageError::MissingTrackFiles { expected: cue_sheet.files.len(), found: files.len() });
+        }
+
+        let (tracks, sector_count) = layout_tracks(cue_sheet, &mut files)?;
+
+        Ok(BinCueImage {
+            files,
+            tracks,
+            sector_count,
+        })
+    }
+
+    //A lone bin without a cue sheet, treated as a single track
+    pub fn from_raw_image(file: R, track_type: TrackType) -> Result<BinCueImage<R>, ImageError> {
+        let cue_sheet = CueSheet {
+            files: vec![CueFile {
+                name: String::new(),
+                tracks: vec![CueTrack {
+                    number: 1,
+                    track_type,
+                    pregap: 0,
+                    indexes: vec![(1, 0)],
+                }],
+            }],
+        };
+
+        BinCueImage::from_readers(&cue_sheet, vec![file])
+    }
+
+    fn find_track(&self, lsn: u64) -> Option<&BinTrack> {
+        self.tracks.iter().find(|bin_track| {
+            let track = &bin_track.track;
+
+            lsn + track.pregap_length >= track.start_lsn && lsn < track.start_lsn + track.sector_count
+        })
+    }
+
+    fn read_stored_sector(&mut self, lsn: u64) -> Result<StoredSector, ImageError> {
+        let bin_track = match self.find_track(lsn) {
+            Some(bin_track) => bin_track.clone(),
+            None => return Err(ImageError::SectorOutOfRange { lsn }),
+        };
+
+        let track_type = bin_track.track.track_type;
+        let sector_size = track_type.sector_size() as u64;
+
+        if lsn + bin_track.stored_pregap < bin_track.track.start_lsn {
+            return Ok((track_type, None));
+        }
+
+        let offset = (bin_track.file_offset + lsn * sector_size) - bin_track.track.start_lsn * sector_size;
+        let data = read_bytes(&mut self.files[bin_track.file], offset, sector_size as usize)?;
+
+        Ok((track_type, Some(data)))
+    }
+}
+
+impl<R: Read + Seek> BlockDevice for BinCueImage<R> {
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sector(&mut self, lsn: u64) -> Result<Vec<u8>, ImageError> {
+        match self.read_stored_sector(lsn)? {
+            (TrackType::Audio, _) => Err(ImageError::AudioSector { lsn }),
+            (track_type, Some(data)) => Ok(cook_sector(track_type, &data)),
+            (track_type, None) => Ok(cook_sector(track_type, &[])),
+        }
+    }
+
+    fn tracks(&self) -> Vec<Track> {
+        self.tracks.iter().map(|bin_track| bin_track.track.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{BinCueImage, CueSheet};
+    use super::super::image_error::ImageError;
+    use super::super::block_device::{BlockDevice, BlockDeviceReader, TrackType, RAW_SECTOR_SIZE};
+    use super::super::iso_file_reader::SECTOR_SIZE;
+    use super::super::utils::read_bytes;
+
+    const CUE: &str = "FILE \"game.bin\" BINARY\r\n  TRACK 01 MODE2/2352\r\n    INDEX 01 00:00:00\r\n  TRACK 02 AUDIO\r\n    INDEX 00 00:00:04\r\n    INDEX 01 00:00:06\r\n";
+
+    //4 data sectors, 2 sectors of pregap and 4 sectors of audio, the last data sector is form 2
+    fn create_mock_bin() -> Vec<u8> {
+        let mut bin = vec![0u8; 10 * RAW_SECTOR_SIZE];
+
+        for sector in 0..4 {
+            bin[sector * RAW_SECTOR_SIZE + 24] = sector as u8 + 1;
+        }
+
+        bin[3 * RAW_SECTOR_SIZE + 18] = 0x20;
+        bin[3 * RAW_SECTOR_SIZE + 24 + 2323] = 0xff;
+
+        bin
+    }
+
+    #[test]
+    fn test_read_bin_cue() {
+        let cue_sheet = CueSheet::parse(CUE).unwrap();
+        assert_eq!(cue_sheet.files[0].name, "game.bin");
+        assert_eq!(cue_sheet.files[0].tracks[1].track_type, TrackType::Audio);
+
+        let mut image = BinCueImage::from_readers(&cue_sheet, vec![Cursor::new(create_mock_bin())]).unwrap();
+        let tracks = image.tracks();
+
+        assert_eq!(image.sector_count(), 10);
+        assert_eq!((tracks[0].start_lsn, tracks[0].sector_count), (0, 4));
+        assert_eq!((tracks[1].start_lsn, tracks[1].pregap_length, tracks[1].sector_count), (6, 2, 4));
+
+        assert_eq!(image.read_sector(2).unwrap()[0], 3);
+        assert_eq!(image.read_sector(2).unwrap().len(), 2048);
+
+        let form2 = image.read_sector(3).unwrap();
+        assert_eq!((form2.len(), form2[0], form2[2323]), (2324, 4, 0xff));
+        assert_eq!(image.read_sector(7).unwrap_err(), ImageError::AudioSector { lsn: 7 });
+
+        //The same error comes back out of the stream the filesystem reads through
+        let mut reader = BlockDeviceReader::new(image);
+        let error = read_bytes(&mut reader, 7 * SECTOR_SIZE, 16).unwrap_err();
+        assert_eq!(error, ImageError::AudioSector { lsn: 7 });
+    }
+}
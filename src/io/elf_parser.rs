@@ -0,0 +1,167 @@
+use std::error::Error;
+use std::io;
+use super::utils::{create_io_error, ByteReader};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+pub const ELF_MACHINE_MIPS: u16 = 8;
+
+pub const SHT_REL: u32 = 9;
+pub const PT_LOAD: u32 = 1;
+
+//Only what's needed to load 32 bit little endian MIPS files, both the EE and IOP use them
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElfHeader {
+    pub file_type: u16,
+    pub machine: u16,
+    pub entry: u32,
+    pub program_header_offset: u32,
+    pub section_header_offset: u32,
+    pub flags: u32,
+    pub program_header_size: u16,
+    pub program_header_count: u16,
+    pub section_header_size: u16,
+    pub section_header_count: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProgramHeader {
+    pub segment_type: u32,
+    pub offset: u32,
+    pub virtual_address: u32,
+    pub file_size: u32,
+    pub memory_size: u32,
+    pub flags: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SectionHeader {
+    pub section_type: u32,
+    pub address: u32,
+    pub offset: u32,
+    pub size: u32,
+    pub link: u32,
+    //For relocation sections this is the section the relocations apply to
+    pub info: u32,
+    pub entry_size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ElfFile<'a> {
+    pub header: ElfHeader,
+    pub program_headers: Vec<ProgramHeader>,
+    pub section_headers: Vec<SectionHeader>,
+    data: &'a [u8],
+}
+
+fn read_header(data: &[u8]) -> Result<ElfHeader, Box<dyn Error>> {
+    let mut reader = ByteReader::new("ElfHeader", data, 0);
+
+    let ident: [u8; 16] = reader.array("e_ident")?;
+
+    if ident[..4] != ELF_MAGIC {
+        return Err(create_io_error(io::ErrorKind::InvalidData, "file is not an ELF!"));
+    }
+
+    if ident[4] != ELF_CLASS_32 || ident[5] != ELF_DATA_LITTLE_ENDIAN {
+        return Err(create_io_error(io::ErrorKind::InvalidData, "only 32 bit little endian ELFs are supported!"));
+    }
+
+    let file_type = reader.u16_le("e_type")?;
+    let machine = reader.u16_le("e_machine")?;
+    reader.u32_le("e_version")?;
+    let entry = reader.u32_le("e_entry")?;
+    let program_header_offset = reader.u32_le("e_phoff")?;
+    let section_header_offset = reader.u32_le("e_shoff")?;
+    let flags = reader.u32_le("e_flags")?;
+    reader.u16_le("e_ehsize")?;
+
+    Ok(ElfHeader {
+        file_type,
+        machine,
+        entry,
+        program_header_offset,
+        section_header_offset,
+        flags,
+        program_header_size: reader.u16_le("e_phentsize")?,
+        program_header_count: reader.u16_le("e_phnum")?,
+        section_header_size: reader.u16_le("e_shentsize")?,
+        section_header_count: reader.u16_le("e_shnum")?,
+    })
+}
+
+fn table<'a>(structure: &'static str, data: &'a [u8], offset: u32, entry_size: u16, index: u16) -> Result<ByteReader<'a>, Box<dyn Error>> {
+    let start = offset as usize + entry_size as usize * index as usize;
+
+    match data.get(start..) {
+        Some(entry) => Ok(ByteReader::new(structure, entry, start as u64)),
+        None => Err(create_io_error(io::ErrorKind::InvalidData, &format!("{} {} is past the end of the file!", structure, index))),
+    }
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<ElfFile<'a>, Box<dyn Error>> {
+        let header = read_header(data)?;
+        let mut program_headers = vec![];
+        let mut section_headers = vec![];
+
+        for index in 0..header.program_header_count {
+            let mut reader = table("ProgramHeader", data, header.program_header_offset, header.program_header_size, index)?;
+
+            program_headers.push(ProgramHeader {
+                segment_type: reader.u32_le("p_type")?,
+                offset: reader.u32_le("p_offset")?,
+                virtual_address: reader.u32_le("p_vaddr")?,
+                file_size: {
+                    reader.u32_le("p_paddr")?;
+                    reader.u32_le("p_filesz")?
+                },
+                memory_size: reader.u32_le("p_memsz")?,
+                flags: reader.u32_le("p_flags")?,
+            });
+        }
+
+        for index in 0..header.section_header_count {
+            let mut reader = table("SectionHeader", data, header.section_header_offset, header.section_header_size, index)?;
+            reader.u32_le("sh_name")?;
+
+            let section_type = reader.u32_le("sh_type")?;
+            reader.u32_le("sh_flags")?;
+
+            section_headers.push(SectionHeader {
+                section_type,
+                address: reader.u32_le("sh_addr")?,
+                offset: reader.u32_le("sh_offset")?,
+                size: reader.u32_le("sh_size")?,
+                link: reader.u32_le("sh_link")?,
+                info: reader.u32_le("sh_info")?,
+                entry_size: {
+                    reader.u32_le("sh_addralign")?;
+                    reader.u32_le("sh_entsize")?
+                },
+            });
+        }
+
+        Ok(ElfFile {
+            header,
+            program_headers,
+            section_headers,
+            data,
+        })
+    }
+
+    pub fn segment_data(&self, segment: &ProgramHeader) -> Result<&'a [u8], Box<dyn Error>> {
+        let mut reader = ByteReader::new("Segment", self.data, 0);
+        reader.bytes("offset", segment.offset as usize)?;
+
+        Ok(reader.bytes("data", segment.file_size as usize)?)
+    }
+
+    pub fn section_data(&self, section: &SectionHeader) -> Result<&'a [u8], Box<dyn Error>> {
+        let mut reader = ByteReader::new("Section", self.data, 0);
+        reader.bytes("offset", section.offset as usize)?;
+
+        Ok(reader.bytes("data", section.size as usize)?)
+    }
+}
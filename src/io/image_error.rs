@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use super::utils::ParseError;
+
+//io::Error can't be cloned or compared so it's shared, two are equal when their kind and message match
+#[derive(Clone, Debug)]
+pub struct IoError(Arc<io::Error>);
+
+impl IoError {
+    pub fn kind(&self) -> io::ErrorKind {
+        self.0.kind()
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &IoError) -> bool {
+        self.kind() == other.kind() && self.0.to_string() == other.0.to_string()
+    }
+}
+
+//Everything the image readers can fail with, reading the host file itself failing is Io
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageError {
+    Io(IoError),
+    NotABios,
+    //ROMVER has to be 14 ASCII characters, VVvvZTYYYYMMDD
+    BadRomver,
+    CorruptRomDir {
+        offset: u64,
+    },
+    BadVolumeDescriptor {
+        sector: u64,
+    },
+    FileNotFound {
+        path: String,
+        //Similar paths that do exist, best match first
+        near_matches: Vec<String>,
+    },
+    Truncated {
+        offset: u64,
+        len: usize,
+    },
+    WrongRomSize {
+        rom: &'static str,
+        size: usize,
+        expected: usize,
+    },
+    //A structure in the image ran past the end of the data it was read from
+    Malformed(ParseError),
+    NotAnImage {
+        format: &'static str,
+    },
+    UnsupportedImage {
+        feature: String,
+    },
+    CorruptImage {
+        detail: &'static str,
+    },
+    DecompressFailed {
+        codec: &'static str,
+        reason: String,
+    },
+    ChecksumMismatch {
+        hunk: u64,
+    },
+    BadCueSheet {
+        detail: &'static str,
+    },
+    //The cue sheet names a different number of FILEs than there are readers for
+    MissingTrackFiles {
+        expected: usize,
+        found: usize,
+    },
+    SectorOutOfRange {
+        lsn: u64,
+    },
+    AudioSector {
+        lsn: u64,
+    },
+    IsADirectory {
+        path: String,
+    },
+    NotADirectory {
+        path: String,
+    },
+    NoBootExecutable,
+    BootPathNotOnCdrom {
+        path: String,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "{}", error.0),
+            ImageError::NotABios => write!(f, "file is not a PS2 BIOS image, no RESET rom dir was found"),
+            ImageError::BadRomver => write!(f, "ROMVER is not a valid version string"),
+            ImageError::CorruptRomDir { offset } => write!(f, "rom dir entry at offset {:#x} is corrupted", offset),
+            ImageError::BadVolumeDescriptor { sector } => write!(f, "volume descriptor at sector {} is missing or invalid", sector),
+            ImageError::FileNotFound { path, near_matches } if near_matches.is_empty() => write!(f, "could not find {}", path),
+            ImageError::FileNotFound { path, near_matches } => write!(f, "could not find {}, did you mean {}?", path, near_matches.join(", ")),
+            ImageError::Truncated { offset, len } => write!(f, "image is truncated, could not read {} bytes at offset {}", len, offset),
+            ImageError::WrongRomSize { rom, size, expected } => write!(f, "{} is {} bytes but should be {}", rom, size, expected),
+            ImageError::Malformed(error) => write!(f, "image is malformed, {}", error),
+            ImageError::NotAnImage { format } => write!(f, "image is not a {}", format),
+            ImageError::UnsupportedImage { feature } => write!(f, "image uses {} which is not supported", feature),
+            ImageError::CorruptImage { detail } => write!(f, "image is corrupt, {}", detail),
+            ImageError::DecompressFailed { codec, reason } => write!(f, "failed to decompress {} data: {}", codec, reason),
+            ImageError::ChecksumMismatch { hunk } => write!(f, "hunk {} failed its CRC check", hunk),
+            ImageError::BadCueSheet { detail } => write!(f, "cue sheet {}", detail),
+            ImageError::MissingTrackFiles { expected, found } => write!(f, "cue sheet has {} FILEs but {} were given", expected, found),
+            ImageError::SectorOutOfRange { lsn } => write!(f, "sector {} is outside of the image", lsn),
+            ImageError::AudioSector { lsn } => write!(f, "sector {} is CD audio and has no user data", lsn),
+            ImageError::IsADirectory { path } => write!(f, "{} is a directory", path),
+            ImageError::NotADirectory { path } => write!(f, "{} is not a directory", path),
+            ImageError::NoBootExecutable => write!(f, "SYSTEM.CNF has no boot executable"),
+            ImageError::BootPathNotOnCdrom { path } => write!(f, "boot path {} is not on the cdrom device", path),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io(error) => Some(&*error.0),
+            _ => None,
+        }
+    }
+}
+
+//Block devices hand their image errors back inside an io::Error so those come back out as they were
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> ImageError {
+        match error.get_ref().and_then(|inner| inner.downcast_ref::<ImageError>()) {
+            Some(image_error) => image_error.clone(),
+            None => ImageError::Io(IoError(Arc::new(error))),
+        }
+    }
+}
+
+//For things that have to hand back an io::Error like Read impls and the IOP file devices,
+//the ImageError stays inside so it can be taken back out
+impl From<ImageError> for io::Error {
+    fn from(error: ImageError) -> io::Error {
+        let kind = match &error {
+            ImageError::Io(inner) => inner.kind(),
+            ImageError::FileNotFound { .. } => io::ErrorKind::NotFound,
+            ImageError::IsADirectory { .. } => io::ErrorKind::IsADirectory,
+            ImageError::NotADirectory { .. } => io::ErrorKind::NotADirectory,
+            _ => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, error)
+    }
+}
+
+impl From<ParseError> for ImageError {
+    fn from(error: ParseError) -> ImageError {
+        ImageError::Malformed(error)
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use super::elf_parser::{ElfFile, ELF_MACHINE_MIPS, PT_LOAD, SHT_REL};
+use super::utils::{create_io_error, ByteReader};
+
+//IRX files are ELF relocatable executables with their own file type and a program header for .iopmod
+const ET_SCE_IOPRELEXEC: u16 = 0xff80;
+const ET_SCE_IOPRELEXEC2: u16 = 0xff81;
+const PT_SCE_IOPMOD: u32 = 0x70000080;
+
+const R_MIPS_32: u8 = 2;
+const R_MIPS_26: u8 = 4;
+const R_MIPS_HI16: u8 = 5;
+const R_MIPS_LO16: u8 = 6;
+
+//The first word of the tables loadcore walks, they're found by scanning the image for them
+pub const IRX_EXPORT_MAGIC: u32 = 0x41c00000;
+pub const IRX_IMPORT_MAGIC: u32 = 0x41e00000;
+//magic, next, version, mode and an 8 byte name
+const LIBRARY_HEADER_SIZE: usize = 20;
+const LIBRARY_NAME_SIZE: usize = 8;
+
+//Unresolved import stubs are jr $ra with addiu $zero, $zero, <function index> in the delay slot
+const STUB_JR_RA: u32 = 0x03e00008;
+const STUB_ADDIU_ZERO: u32 = 0x24000000;
+//Put in the delay slot of stubs nothing resolves, addiu $v0, $zero, 0 so calling one returns 0
+const STUB_RETURN_ZERO: u32 = 0x24020000;
+const J_OPCODE: u32 = 0x08000000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RelocationType {
+    Word32,
+    Jump26,
+    Hi16,
+    Lo16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Relocation {
+    //Offset into the module image
+    pub offset: u32,
+    pub relocation_type: RelocationType,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImportStub {
+    pub address: u32,
+    pub index: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrxImport {
+    pub library: String,
+    pub version: u16,
+    //Where the import table is, stubs follow the header
+    pub address: u32,
+    pub stubs: Vec<ImportStub>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrxExport {
+    pub library: String,
+    pub version: u16,
+    pub address: u32,
+    //Indexed by the number in the import stubs
+    pub functions: Vec<u32>,
+}
+
+//Libraries modules can import from, filled in as modules register their exports
+#[derive(Clone, Debug, Default)]
+pub struct IopLibraryRegistry {
+    libraries: HashMap<String, IrxExport>,
+}
+
+//A parsed IRX, the image is linked at address 0 and has to be relocated before it can run
+#[derive(Clone, Debug)]
+pub struct IrxModule {
+    pub name: String,
+    pub version: u16,
+    pub entry: u32,
+    pub gp: u32,
+    pub text_size: u32,
+    pub data_size: u32,
+    pub bss_size: u32,
+    image: Vec<u8>,
+    memory_size: u32,
+    pub relocations: Vec<Relocation>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedModule {
+    pub name: String,
+    pub version: u16,
+    pub base: u32,
+    pub size: u32,
+    pub entry: u32,
+    pub gp: u32,
+    pub imports: Vec<IrxImport>,
+    pub exports: Vec<IrxExport>,
+    //Library and function index of every stub nothing was registered for, they're patched to return 0
+    pub unresolved: Vec<(String, u16)>,
+}
+
+fn read_word(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn write_word(data: &mut [u8], offset: usize, value: u32) -> Result<(), Box<dyn Error>> {
+    match data.get_mut(offset..offset + 4) {
+        Some(bytes) => {
+            bytes.copy_from_slice(&value.to_le_bytes());
+            Ok(())
+        },
+        None => Err(create_io_error(io::ErrorKind::InvalidData, &format!("relocation at {:#x} is outside the module!", offset))),
+    }
+}
+
+//Names are padded with zeros and only count if they look like a name, which keeps data that happens
+//to contain a magic word from being taken for a table
+fn library_name(data: &[u8], offset: usize) -> Option<String> {
+    let name = data.get(offset + 12..offset + 12 + LIBRARY_NAME_SIZE)?;
+    let length = name.iter().position(|byte| *byte == 0).unwrap_or(LIBRARY_NAME_SIZE);
+
+    if length == 0 || !name[..length].iter().all(|byte| byte.is_ascii_graphic()) {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&name[..length]).to_string())
+}
+
+//Every aligned word matching magic with a zero next pointer and a sensible name, as (offset, name, version)
+fn find_tables(data: &[u8], magic: u32) -> Vec<(usize, String, u16)> {
+    (0..data.len().saturating_sub(LIBRARY_HEADER_SIZE - 1)).step_by(4)
+        .filter(|offset| read_word(data, *offset) == Some(magic) && read_word(data, offset + 4) == Some(0))
+        .filter_map(|offset| {
+            let version = read_word(data, offset + 8)? as u16;
+            library_name(data, offset).map(|name| (offset, name, version))
+        })
+        .collect()
+}
+
+//The .iopmod section, everything in it is relative to where the module ends up
+struct IopMod {
+    name: String,
+    version: u16,
+    entry: u32,
+    gp: u32,
+    text_size: u32,
+    data_size: u32,
+    bss_size: u32,
+}
+
+fn read_iopmod(data: &[u8], offset: u64) -> Result<IopMod, Box<dyn Error>> {
+    let mut reader = ByteReader::new("IopMod", data, offset);
+    reader.u32_le("moduleinfo")?;
+
+    let entry = reader.u32_le("entry")?;
+    let gp = reader.u32_le("gp_value")?;
+    let text_size = reader.u32_le("text_size")?;
+    let data_size = reader.u32_le("data_size")?;
+    let bss_size = reader.u32_le("bss_size")?;
+    let version = reader.u16_le("version")?;
+    let name = reader.bytes("name", reader.remaining())?;
+    let length = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+
+    Ok(IopMod {
+        name: String::from_utf8_lossy(&name[..length]).to_string(),
+        version,
+        entry,
+        gp,
+        text_size,
+        data_size,
+        bss_size,
+    })
+}
+
+impl IopLibraryRegistry {
+    pub fn new() -> IopLibraryRegistry {
+        IopLibraryRegistry::default()
+    }
+
+    //A library registered again under the same name replaces the old one
+    pub fn register(&mut self, export: IrxExport) {
+        self.libraries.insert(export.library.clone(), export);
+    }
+
+    pub fn library(&self, name: &str) -> Option<&IrxExport> {
+        self.libraries.get(name)
+    }
+
+    pub fn resolve(&self, library: &str, index: u16) -> Option<u32> {
+        self.libraries.get(library)?.functions.get(index as usize).copied().filter(|address| *address != 0)
+    }
+}
+
+impl IrxModule {
+    pub fn parse(data: &[u8]) -> Result<IrxModule, Box<dyn Error>> {
+        let elf = ElfFile::parse(data)?;
+
+        if elf.header.machine != ELF_MACHINE_MIPS || (elf.header.file_type != ET_SCE_IOPRELEXEC && elf.header.file_type != ET_SCE_IOPRELEXEC2) {
+            return Err(create_io_error(io::ErrorKind::InvalidData, "file is not an IRX module!"));
+        }
+
+        let iopmod = match elf.program_headers.iter().find(|segment| segment.segment_type == PT_SCE_IOPMOD) {
+            Some(segment) => segment,
+            None => return Err(create_io_error(io::ErrorKind::InvalidData, "IRX module has no .iopmod!")),
+        };
+
+        let iopmod = read_iopmod(elf.segment_data(iopmod)?, iopmod.offset as u64)?;
+
+        let load = match elf.program_headers.iter().find(|segment| segment.segment_type == PT_LOAD) {
+            Some(segment) => *segment,
+            None => return Err(create_io_error(io::ErrorKind::InvalidData, "IRX module has nothing to load!")),
+        };
+
+        let mut relocations = vec![];
+
+        for section in elf.section_headers.iter().filter(|section| section.section_type == SHT_REL) {
+            let target = match elf.section_headers.get(section.info as usize) {
+                Some(target) => target,
+                None => return Err(create_io_error(io::ErrorKind::InvalidData, "relocations for a section that doesn't exist!")),
+            };
+
+            //Offsets are addresses, turned into positions in the image through the section they're in
+            let image_offset = target.offset.wrapping_sub(load.offset).wrapping_sub(target.address);
+            let mut reader = ByteReader::new("Relocation", elf.section_data(section)?, section.offset as u64);
+
+            while reader.remaining() >= 8 {
+                let offset = reader.u32_le("r_offset")?.wrapping_add(image_offset);
+
+                let relocation_type = match reader.u32_le("r_info")? as u8 {
+                    R_MIPS_32 => RelocationType::Word32,
+                    R_MIPS_26 => RelocationType::Jump26,
+                    R_MIPS_HI16 => RelocationType::Hi16,
+                    R_MIPS_LO16 => RelocationType::Lo16,
+                    other => return Err(create_io_error(io::ErrorKind::InvalidData, &format!("unsupported relocation type {}!", other))),
+                };
+
+                relocations.push(Relocation {
+                    offset,
+                    relocation_type,
+                });
+            }
+        }
+
+        Ok(IrxModule {
+            name: iopmod.name,
+            version: iopmod.version,
+            entry: iopmod.entry,
+            gp: iopmod.gp,
+            text_size: iopmod.text_size,
+            data_size: iopmod.data_size,
+            bss_size: iopmod.bss_size,
+            image: elf.segment_data(&load)?.to_vec(),
+            memory_size: load.memory_size.max(load.file_size),
+            relocations,
+        })
+    }
+
+    //Bytes of IOP memory the module takes up, bss included
+    pub fn size(&self) -> u32 {
+        self.memory_size.max(self.text_size + self.data_size + self.bss_size)
+    }
+
+    //The image as it would be at base, a HI16 is only fixed up once the LO16 after it says what the low half is
+    pub fn relocated_image(&self, base: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut image = self.image.clone();
+        image.resize(self.size() as usize, 0);
+
+        let mut pending_hi16: Vec<usize> = vec![];
+
+        for relocation in self.relocations.iter() {
+            let offset = relocation.offset as usize;
+            let value = match read_word(&image, offset) {
+                Some(value) => value,
+                None => return Err(create_io_error(io::ErrorKind::InvalidData, &format!("relocation at {:#x} is outside the module!", offset))),
+            };
+
+            match relocation.relocation_type {
+                RelocationType::Word32 => write_word(&mut image, offset, value.wrapping_add(base))?,
+                RelocationType::Jump26 => {
+                    let target = ((value & 0x03ffffff) << 2).wrapping_add(base);
+                    write_word(&mut image, offset, (value & 0xfc000000) | ((target >> 2) & 0x03ffffff))?;
+                },
+                RelocationType::Hi16 => pending_hi16.push(offset),
+                RelocationType::Lo16 => {
+                    let low = value as u16 as i16 as i32 as u32;
+
+                    for hi_offset in pending_hi16.drain(..) {
+                        let hi = read_word(&image, hi_offset).unwrap_or(0);
+                        let address = (hi << 16).wrapping_add(low).wrapping_add(base);
+                        //The low half is sign extended when it's added so carry into the high half
+                        write_word(&mut image, hi_offset, (hi & 0xffff0000) | (address.wrapping_add(0x8000) >> 16))?;
+                    }
+
+                    write_word(&mut image, offset, (value & 0xffff0000) | (low.wrapping_add(base) & 0xffff))?;
+                },
+            }
+        }
+
+        //A HI16 with no LO16 after it has a low half of 0
+        for hi_offset in pending_hi16 {
+            let hi = read_word(&image, hi_offset).unwrap_or(0);
+            let address = (hi << 16).wrapping_add(base);
+            write_word(&mut image, hi_offset, (hi & 0xffff0000) | (address.wrapping_add(0x8000) >> 16))?;
+        }
+
+        Ok(image)
+    }
+
+    pub fn imports(&self) -> Vec<IrxImport> {
+        IrxModule::find_imports(&self.image, 0)
+    }
+
+    pub fn exports(&self) -> Vec<IrxExport> {
+        IrxModule::find_exports(&self.image, 0)
+    }
+
+    fn find_imports(image: &[u8], base: u32) -> Vec<IrxImport> {
+        find_tables(image, IRX_IMPORT_MAGIC).into_iter().map(|(offset, library, version)| {
+            let stubs = (offset + LIBRARY_HEADER_SIZE..image.len()).step_by(8)
+                .map(|stub| (stub, read_word(image, stub), read_word(image, stub + 4)))
+                .take_while(|(_, jump, delay_slot)| *jump == Some(STUB_JR_RA) && delay_slot.is_some_and(|word| word & 0xffff0000 == STUB_ADDIU_ZERO))
+                .map(|(stub, _, delay_slot)| ImportStub {
+                    address: base + stub as u32,
+                    index: delay_slot.unwrap_or(0) as u16,
+                })
+                .collect();
+
+            IrxImport {
+                library,
+                version,
+                address: base + offset as u32,
+                stubs,
+            }
+        }).collect()
+    }
+
+    fn find_exports(image: &[u8], base: u32) -> Vec<IrxExport> {
+        find_tables(image, IRX_EXPORT_MAGIC).into_iter().map(|(offset, library, version)| IrxExport {
+            library,
+            version,
+            address: base + offset as u32,
+            functions: (offset + LIBRARY_HEADER_SIZE..image.len()).step_by(4)
+                .map_while(|function| read_word(image, function))
+                .take_while(|function| *function != 0)
+                .collect(),
+        }).collect()
+    }
+
+    //Relocates the module to base in IOP memory, registers its exports and points every import stub it
+    //has a library for at the function with a j, the delay slot is left alone. Stubs without a library
+    //get their delay slot set to clear v0 instead. Libraries only stay compatible within a major version
+    //so a mismatch is warned about but still linked, the same as loadcore does
+    pub fn load(&self, memory: &mut [u8], base: u32, registry: &mut IopLibraryRegistry) -> Result<LoadedModule, Box<dyn Error>> {
+        let mut image = self.relocated_image(base)?;
+        let exports = IrxModule::find_exports(&image, base);
+        let imports = IrxModule::find_imports(&image, base);
+
+        for export in exports.iter() {
+            registry.register(export.clone());
+        }
+
+        let mut unresolved = vec![];
+
+        for import in imports.iter() {
+            if let Some(export) = registry.library(&import.library) {
+                if export.version >> 8 != import.version >> 8 {
+                    println!("[IRX] {} imports {} version {:#06x} but version {:#06x} is registered", self.name, import.library, import.version, export.version);
+                }
+            }
+
+            for stub in import.stubs.iter() {
+                let offset = (stub.address - base) as usize;
+
+                match registry.resolve(&import.library, stub.index) {
+                    Some(function) => write_word(&mut image, offset, J_OPCODE | ((function >> 2) & 0x03ffffff))?,
+                    None => {
+                        write_word(&mut image, offset + 4, STUB_RETURN_ZERO)?;
+                        unresolved.push((import.library.clone(), stub.index));
+                    },
+                }
+            }
+        }
+
+        let start = base as usize;
+        match memory.get_mut(start..start + image.len()) {
+            Some(destination) => destination.copy_from_slice(&image),
+            None => return Err(create_io_error(io::ErrorKind::InvalidInput, &format!("{} doesn't fit in IOP memory at {:#x}!", self.name, base))),
+        }
+
+        Ok(LoadedModule {
+            name: self.name.clone(),
+            version: self.version,
+            base,
+            size: image.len() as u32,
+            entry: base.wrapping_add(self.entry),
+            gp: base.wrapping_add(self.gp),
+            imports,
+            exports,
+            unresolved,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ImportStub, IopLibraryRegistry, IrxExport, IrxModule, RelocationType};
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    //ELF header, .iopmod and PT_LOAD program headers, .iopmod, the image, then .rel.text and the section headers
+    fn create_test_irx() -> Vec<u8> {
+        let image = words(&[
+            0x3c020000, //lui v0, 0 (HI16)
+            0x24427ff0, //addiu v0, v0, 0x7ff0 (LO16)
+            0x0c000008, //jal 0x20 (26)
+            0x00000000,
+            //Imports sysclib function 4
+            0x41e00000, 0, 0x0101, u32::from_le_bytes(*b"sysc"), u32::from_le_bytes(*b"lib\0"),
+            0x03e00008, 0x24000004,
+            0, 0,
+            //Exports test with one function at 0x20 (32)
+            0x41c00000, 0, 0x0100, u32::from_le_bytes(*b"test"), 0,
+            0x00000020, 0,
+            //.data, a pointer to 0x44 (32)
+            0x00000044,
+        ]);
+        let relocations = words(&[0x00, 5, 0x04, 6, 0x08, 4, 0x48, 2, 0x50, 2]);
+
+        let (iopmod_offset, image_offset) = (0x74u32, 0xa0u32);
+        let relocation_offset = image_offset + image.len() as u32;
+        let section_offset = relocation_offset + relocations.len() as u32;
+
+        let mut data = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend(&0xff80u16.to_le_bytes());
+        data.extend(&8u16.to_le_bytes());
+        data.extend(words(&[1, 0, 0x34, section_offset, 0]));
+        data.extend(&[52, 0, 32, 0, 2, 0, 40, 0, 3, 0, 0, 0]);
+
+        data.extend(words(&[0x70000080, iopmod_offset, 0, 0, 38, 38, 4, 0]));
+        data.extend(words(&[1, image_offset, 0, 0, image.len() as u32, image.len() as u32 + 0x10, 7, 0x10]));
+
+        data.extend(words(&[0xffffffff, 0x00, 0x8000, 0x50, 0x04, 0x10]));
+        data.extend(&0x0102u16.to_le_bytes());
+        data.extend(b"Test_Module\0");
+        data.resize(image_offset as usize, 0);
+
+        data.extend(&image);
+        data.extend(&relocations);
+
+        data.extend(vec![0; 40]);
+        data.extend(words(&[0, 1, 6, 0, image_offset, image.len() as u32, 0, 0, 4, 0]));
+        data.extend(words(&[0, 9, 0, 0, relocation_offset, relocations.len() as u32, 0, 1, 4, 8]));
+        data
+    }
+
+    #[test]
+    fn test_load_with_relocations_and_imports() {
+        let module = IrxModule::parse(&create_test_irx()).unwrap();
+
+        assert_eq!((module.name.as_str(), module.version, module.entry, module.bss_size), ("Test_Module", 0x0102, 0, 0x10));
+        assert_eq!(module.relocations[1].relocation_type, RelocationType::Lo16);
+        assert_eq!(module.imports()[0].stubs, vec![ImportStub { address: 0x24, index: 4 }]);
+        assert_eq!(module.exports()[0].functions, vec![0x20]);
+
+        let mut registry = IopLibraryRegistry::new();
+        registry.register(IrxExport {
+            library: "sysclib".to_string(),
+            version: 0x0101,
+            address: 0,
+            functions: vec![0, 0, 0, 0, 0x1234],
+        });
+
+        let mut memory = vec![0; 0x20000];
+        let loaded = module.load(&mut memory, 0x10010, &mut registry).unwrap();
+        let word = |address: usize| u32::from_le_bytes([memory[address], memory[address + 1], memory[address + 2], memory[address + 3]]);
+
+        //0x10010 + 0x7ff0 = 0x18000, the low half goes negative so the high half is 2
+        assert_eq!(word(0x10010), 0x3c020002);
+        assert_eq!(word(0x10014), 0x24428000);
+        assert_eq!(word(0x10018), 0x0c00400c);
+        assert_eq!(word(0x10034), 0x0800048d);
+        assert_eq!(word(0x10038), 0x24000004);
+        assert_eq!(word(0x10060), 0x10054);
+
+        assert_eq!((loaded.entry, loaded.gp, loaded.size), (0x10010, 0x18010, 0x64));
+        assert!(loaded.unresolved.is_empty());
+        assert_eq!(registry.resolve("test", 0), Some(0x10030));
+        assert_eq!(registry.resolve("sysclib", 5), None);
+
+        //Without sysclib the stub stays a jr ra but now clears v0 in its delay slot
+        let mut memory = vec![0; 0x20000];
+        let loaded = module.load(&mut memory, 0x10010, &mut IopLibraryRegistry::new()).unwrap();
+        let stub: Vec<u32> = memory[0x10034..0x1003c].chunks(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+        assert_eq!(loaded.unresolved, vec![("sysclib".to_string(), 4)]);
+        assert_eq!(stub, vec![0x03e00008, 0x24020000]);
+    }
+}
@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::utils::create_io_error;
+use super::iso_file::RecordingDate;
+use super::iso_file_reader::{normalize_path, bi_u16, bi_u32, BaseDirectoryRecord, DirectoryRecord, PrimaryVolume, BASE_DIRECTORY_RECORD_SIZE, FILE_FLAG_DIRECTORY, SECTOR_SIZE, STANDARD_IDENTIFIER};
+use super::system_cnf::{SystemCnf, VideoMode};
+
+const PRIMARY_VOLUME_SECTOR: u32 = 16;
+const TERMINATOR_SECTOR: u32 = 17;
+//L path table goes straight after the terminator with the M path table after it
+const PATH_TABLE_SECTOR: u32 = 18;
+
+#[derive(Clone, Debug)]
+enum IsoNode {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, IsoNode>),
+}
+
+//Builds small ISO9660 images in memory, mostly so tests can boot things from a "disc"
+#[derive(Clone, Debug)]
+pub struct IsoBuilder {
+    volume_identifier: String,
+    recording_date: RecordingDate,
+    root: BTreeMap<String, IsoNode>,
+}
+
+//Directory record dates are years since 1900 then the GMT offset in 15 minute steps
+fn recording_date_bytes(date: RecordingDate) -> [u8; 7] {
+    [(date.year.saturating_sub(1900)) as u8, date.month, date.day, date.hour, date.minute, date.second, date.gmt_offset as u8]
+}
+
+//Text fields are d-characters padded out with spaces
+fn padded_identifier<const N: usize>(identifier: &str) -> [u8; N] {
+    let mut data = [b' '; N];
+    let length = identifier.len().min(N);
+    data[..length].copy_from_slice(&identifier.as_bytes()[..length]);
+
+    data
+}
+
+//Volume dates are YYYYMMDDHHMMSScc as text then the GMT offset
+fn volume_datetime(date: &RecordingDate) -> [u8; 17] {
+    let mut data = [0u8; 17];
+    let text = format!("{:04}{:02}{:02}{:02}{:02}{:02}00", date.year, date.month, date.day, date.hour, date.minute, date.second);
+    data[..16].copy_from_slice(&text.as_bytes()[..16]);
+    data[16] = date.gmt_offset as u8;
+
+    data
+}
+
+impl bi_u16 {
+    fn new(value: u16) -> bi_u16 {
+        bi_u16 {
+            le: value,
+            be: value,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.le.to_le_bytes());
+        data.extend_from_slice(&self.be.to_be_bytes());
+    }
+}
+
+impl bi_u32 {
+    fn new(value: u32) -> bi_u32 {
+        bi_u32 {
+            le: value,
+            be: value,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.le.to_le_bytes());
+        data.extend_from_slice(&self.be.to_be_bytes());
+    }
+}
+
+impl PrimaryVolume {
+    fn new(volume_identifier: &str, volume_space_size: u32, path_table_size: u32, l_path_table: u32, m_path_table: u32, root_directory: BaseDirectoryRecord, date: RecordingDate) -> PrimaryVolume {
+        let datetime = volume_datetime(&date);
+
+        PrimaryVolume {
+            vtype: 1,
+            identifier: *STANDARD_IDENTIFIER,
+            version: 1,
+            _unused1: 0,
+            system_identifier: padded_identifier("PLAYSTATION"),
+            volume_identifier: padded_identifier(volume_identifier),
+            _unused2: [0; 8],
+            volume_space_size: bi_u32::new(volume_space_size),
+            escape_sequences: [0; 32],
+            volume_set_size: bi_u16::new(1),
+            volume_sequence_number: bi_u16::new(1),
+            logical_block_size: bi_u16::new(SECTOR_SIZE as u16),
+            path_table_size: bi_u32::new(path_table_size),
+            type_l_path_table_location: l_path_table,
+            opt_type_l_path_table_location: 0,
+            type_m_path_table_location: m_path_table,
+            opt_type_m_path_table_location: 0,
+            root_directory,
+            _unused4: 0,
+            volume_set_identifier: padded_identifier(volume_identifier),
+            publisher_identifier: padded_identifier(""),
+            data_preparer_identifier: padded_identifier(""),
+            application_identifier: padded_identifier("PLAYSTATION"),
+            copyright_file_identifier: padded_identifier(""),
+            abstract_file_identifier: padded_identifier(""),
+            bibliographic_file_identifier: padded_identifier(""),
+            volume_created_datetime: datetime,
+            volume_modification_datetime: datetime,
+            volume_expiration_datetime: volume_datetime(&RecordingDate::default()),
+            volume_effective_datetime: datetime,
+            file_structure_version: 1,
+            _unused5: 0,
+            application_data: [0; 512],
+            reserved_data: [0; 653],
+        }
+    }
+
+    //Same field order as PrimaryVolume::read
+    fn to_bytes(self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::with_capacity(SECTOR_SIZE as usize);
+
+        data.push(self.vtype);
+        data.extend_from_slice(&self.identifier);
+        data.push(self.version);
+        data.push(self._unused1);
+        data.extend_from_slice(&self.system_identifier);
+        data.extend_from_slice(&self.volume_identifier);
+        data.extend_from_slice(&self._unused2);
+        self.volume_space_size.write(&mut data);
+        data.extend_from_slice(&self.escape_sequences);
+        self.volume_set_size.write(&mut data);
+        self.volume_sequence_number.write(&mut data);
+        self.logical_block_size.write(&mut data);
+        self.path_table_size.write(&mut data);
+        data.extend_from_slice(&self.type_l_path_table_location.to_le_bytes());
+        data.extend_from_slice(&self.opt_type_l_path_table_location.to_le_bytes());
+        data.extend_from_slice(&self.type_m_path_table_location.to_be_bytes());
+        data.extend_from_slice(&self.opt_type_m_path_table_location.to_be_bytes());
+        self.root_directory.write(&mut data);
+        data.push(self._unused4);
+        data.extend_from_slice(&self.volume_set_identifier);
+        data.extend_from_slice(&self.publisher_identifier);
+        data.extend_from_slice(&self.data_preparer_identifier);
+        data.extend_from_slice(&self.application_identifier);
+        data.extend_from_slice(&self.copyright_file_identifier);
+        data.extend_from_slice(&self.abstract_file_identifier);
+        data.extend_from_slice(&self.bibliographic_file_identifier);
+        data.extend_from_slice(&self.volume_created_datetime);
+        data.extend_from_slice(&self.volume_modification_datetime);
+        data.extend_from_slice(&self.volume_expiration_datetime);
+        data.extend_from_slice(&self.volume_effective_datetime);
+        data.push(self.file_structure_version);
+        data.push(self._unused5);
+        data.extend_from_slice(&self.application_data);
+        data.extend_from_slice(&self.reserved_data);
+
+        data
+    }
+}
+
+impl BaseDirectoryRecord {
+    fn new(extent_location: u32, extent_length: u32, file_flags: u8, recording_date: RecordingDate, file_identifier_length: u8) -> BaseDirectoryRecord {
+        let length = BASE_DIRECTORY_RECORD_SIZE + file_identifier_length as usize + (file_identifier_length as usize + 1) % 2;
+
+        BaseDirectoryRecord {
+            length: length as u8,
+            extended_attribute_length: 0,
+            extent_location: bi_u32::new(extent_location),
+            extent_length: bi_u32::new(extent_length),
+            recording_date: recording_date_bytes(recording_date),
+            file_flags,
+            file_unit_size_int: 0,
+            int_gap_size: 0,
+            volume_seq_number: bi_u16::new(1),
+            file_identifier_length,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.push(self.length);
+        data.push(self.extended_attribute_length);
+        self.extent_location.write(data);
+        self.extent_length.write(data);
+        data.extend_from_slice(&self.recording_date);
+        data.push(self.file_flags);
+        data.push(self.file_unit_size_int);
+        data.push(self.int_gap_size);
+        self.volume_seq_number.write(data);
+        data.push(self.file_identifier_length);
+    }
+}
+
+impl DirectoryRecord {
+    fn new(extent_location: u32, extent_length: u32, file_flags: u8, recording_date: RecordingDate, file_identifier: &[u8]) -> DirectoryRecord {
+        DirectoryRecord {
+            base: BaseDirectoryRecord::new(extent_location, extent_length, file_flags, recording_date, file_identifier.len() as u8),
+            file_identifier: file_identifier.to_vec(),
+            system_use: vec![],
+        }
+    }
+
+    //Identifier is padded to an even length, the system use area goes after that
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::with_capacity(self.base.length as usize);
+
+        self.base.write(&mut data);
+        data.extend_from_slice(&self.file_identifier);
+
+        if self.file_identifier.len().is_multiple_of(2) {
+            data.push(0);
+        }
+
+        data.extend_from_slice(&self.system_use);
+        data
+    }
+}
+
+//Where a directory ends up, kept in path table order
+#[derive(Clone, Debug)]
+struct DirectoryLayout {
+    path: Vec<String>,
+    parent: usize,
+    sector: u32,
+    size: u32,
+}
+
+fn sector_count(length: u64) -> u32 {
+    length.div_ceil(SECTOR_SIZE) as u32
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '.')
+}
+
+fn file_identifier(name: &str, node: &IsoNode) -> Vec<u8> {
+    match node {
+        //ISO9660 wants the separator dot even when there's no extension
+        IsoNode::File(_) if name.contains('.') => format!("{};1", name).into_bytes(),
+        IsoNode::File(_) => format!("{}.;1", name).into_bytes(),
+        IsoNode::Directory(_) => name.as_bytes().to_vec(),
+    }
+}
+
+//Records aren't allowed to cross a sector boundary so they get pushed to the next sector instead
+fn layout_records(record_lengths: impl Iterator<Item = usize>) -> Vec<usize> {
+    let sector = SECTOR_SIZE as usize;
+    let mut offsets: Vec<usize> = vec![];
+    let mut offset: usize = 0;
+
+    for length in record_lengths {
+        if offset % sector + length > sector {
+            offset = offset.next_multiple_of(sector);
+        }
+
+        offsets.push(offset);
+        offset += length;
+    }
+
+    offsets.push(offset);
+    offsets
+}
+
+fn path_table_record(identifier: &[u8], sector: u32, parent: u16, big_endian: bool) -> Vec<u8> {
+    let mut record: Vec<u8> = vec![identifier.len() as u8, 0];
+
+    if big_endian {
+        record.extend_from_slice(&sector.to_be_bytes());
+        record.extend_from_slice(&parent.to_be_bytes());
+    } else {
+        record.extend_from_slice(&sector.to_le_bytes());
+        record.extend_from_slice(&parent.to_le_bytes());
+    }
+
+    record.extend_from_slice(identifier);
+
+    if !identifier.len().is_multiple_of(2) {
+        record.push(0);
+    }
+
+    record
+}
+
+impl IsoBuilder {
+    pub fn new(volume_identifier: &str) -> IsoBuilder {
+        IsoBuilder {
+            volume_identifier: volume_identifier.to_uppercase(),
+            recording_date: RecordingDate {
+                year: 2000,
+                month: 1,
+                day: 1,
+                ..RecordingDate::default()
+            },
+            root: BTreeMap::new(),
+        }
+    }
+
+    //Everything under the host directory is added with upper case names
+    pub fn from_host_directory(host_path: &Path, volume_identifier: &str) -> Result<IsoBuilder, Box<dyn Error>> {
+        let mut builder = IsoBuilder::new(volume_identifier);
+        builder.add_host_directory(host_path, "")?;
+
+        Ok(builder)
+    }
+
+    pub fn set_recording_date(&mut self, recording_date: RecordingDate) {
+        self.recording_date = recording_date;
+    }
+
+    fn get_directory_mut(&mut self, path: &[String]) -> Result<&mut BTreeMap<String, IsoNode>, Box<dyn Error>> {
+        let mut directory = &mut self.root;
+
+        for name in path {
+            if !is_valid_name(name) {
+                return Err(create_io_error(io::ErrorKind::InvalidInput, &format!("{} is not a valid ISO9660 name!", name)));
+            }
+
+            let node = directory.entry(name.clone()).or_insert_with(|| IsoNode::Directory(BTreeMap::new()));
+
+            directory = match node {
+                IsoNode::Directory(children) => children,
+                IsoNode::File(_) => return Err(create_io_error(io::ErrorKind::InvalidInput, &format!("{} is a file!", name))),
+            };
+        }
+
+        Ok(directory)
+    }
+
+    fn get_directory(&self, path: &[String]) -> Option<&BTreeMap<String, IsoNode>> {
+        let mut directory = &self.root;
+
+        for name in path {
+            directory = match directory.get(name)? {
+                IsoNode::Directory(children) => children,
+                IsoNode::File(_) => return None,
+            };
+        }
+
+        Some(directory)
+    }
+
+    pub fn add_directory(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.get_directory_mut(&normalize_path(path))?;
+
+        Ok(())
+    }
+
+    //Parent directories are created as needed
+    pub fn add_file(&mut self, path: &str, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let mut dirs = normalize_path(path);
+
+        let name = match dirs.pop() {
+            Some(name) if is_valid_name(&name) => name,
+            _ => return Err(create_io_error(io::ErrorKind::InvalidInput, &format!("{} is not a valid ISO9660 file path!", path))),
+        };
+
+        if data.len() as u64 > u32::MAX as u64 {
+            return Err(create_io_error(io::ErrorKind::InvalidInput, "files over 4GiB are not supported!"));
+        }
+
+        let directory = self.get_directory_mut(&dirs)?;
+
+        if let Some(IsoNode::Directory(_)) = directory.get(&name) {
+            return Err(create_io_error(io::ErrorKind::InvalidInput, &format!("{} is a directory!", path)));
+        }
+
+        directory.insert(name, IsoNode::File(data));
+
+        Ok(())
+    }
+
+    pub fn add_host_directory(&mut self, host_path: &Path, disc_path: &str) -> Result<(), Box<dyn Error>> {
+        self.add_directory(disc_path)?;
+
+        for entry in fs::read_dir(host_path)? {
+            let entry = entry?;
+            let disc_entry_path = format!("{}/{}", disc_path, entry.file_name().to_string_lossy());
+
+            if entry.file_type()?.is_dir() {
+                self.add_host_directory(&entry.path(), &disc_entry_path)?;
+            } else {
+                self.add_file(&disc_entry_path, fs::read(entry.path())?)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_system_cnf(&mut self, system_cnf: &SystemCnf) -> Result<(), Box<dyn Error>> {
+        self.add_file("SYSTEM.CNF", system_cnf.to_bytes())
+    }
+
+    //Writes a SYSTEM.CNF that boots an ELF already added to the image
+    pub fn set_boot_executable(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let path = normalize_path(path);
+
+        if path.is_empty() {
+            return Err(create_io_error(io::ErrorKind::InvalidInput, "the boot executable needs a path!"));
+        }
+
+        self.set_system_cnf(&SystemCnf {
+            boot2: Some(format!("cdrom0:\\{};1", path.join("\\"))),
+            version: Some("1.00".to_string()),
+            video_mode: Some(VideoMode::Ntsc),
+            ..SystemCnf::default()
+        })
+    }
+
+    fn directory_records(&self, directory: &DirectoryLayout, parent: &DirectoryLayout, locations: &HashMap<Vec<String>, (u32, u32)>) -> Result<Vec<DirectoryRecord>, Box<dyn Error>> {
+        let children = match self.get_directory(&directory.path) {
+            Some(children) => children,
+            None => return Err(create_io_error(io::ErrorKind::NotFound, "directory went missing while building the image!")),
+        };
+
+        let mut records = vec![
+            DirectoryRecord::new(directory.sector, directory.size, FILE_FLAG_DIRECTORY, self.recording_date, &[0]),
+            DirectoryRecord::new(parent.sector, parent.size, FILE_FLAG_DIRECTORY, self.recording_date, &[1]),
+        ];
+
+        for (name, node) in children.iter() {
+            let mut path = directory.path.clone();
+            path.push(name.clone());
+
+            let (sector, length) = locations.get(&path).copied().unwrap_or_default();
+            let flags = match node {
+                IsoNode::Directory(_) => FILE_FLAG_DIRECTORY,
+                IsoNode::File(_) => 0,
+            };
+
+            records.push(DirectoryRecord::new(sector, length, flags, self.recording_date, &file_identifier(name, node)));
+        }
+
+        Ok(records)
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        //Breadth first with each level sorted by parent then name is the order path tables want
+        let mut directories = vec![DirectoryLayout {
+            path: vec![],
+            parent: 0,
+            sector: 0,
+            size: 0,
+        }];
+        let mut index = 0;
+
+        while index < directories.len() {
+            let path = directories[index].path.clone();
+
+            if let Some(children) = self.get_directory(&path) {
+                for (name, node) in children.iter() {
+                    if let IsoNode::Directory(_) = node {
+                        let mut child_path = path.clone();
+                        child_path.push(name.clone());
+
+                        directories.push(DirectoryLayout {
+                            path: child_path,
+                            parent: index,
+                            sector: 0,
+                            size: 0,
+                        });
+                    }
+                }
+            }
+
+            index += 1;
+        }
+
+        let path_table_size: usize = directories.iter().map(|directory| {
+            8 + directory.path.last().map_or(2, |name| name.len() + name.len() % 2)
+        }).sum();
+
+        let m_path_table_sector = PATH_TABLE_SECTOR + sector_count(path_table_size as u64);
+        let mut next_sector = m_path_table_sector + sector_count(path_table_size as u64);
+
+        //Directory sizes only depend on the names of their children so they can be laid out first
+        for directory in directories.iter_mut() {
+            let children = self.get_directory(&directory.path).cloned().unwrap_or_default();
+            let record_lengths = [1usize, 1].iter().copied().chain(children.iter().map(|(name, node)| file_identifier(name, node).len()))
+                .map(|identifier_length| 33 + identifier_length + (identifier_length + 1) % 2);
+
+            let size = (*layout_records(record_lengths).last().unwrap_or(&0)).max(1) as u64;
+
+            directory.sector = next_sector;
+            directory.size = sector_count(size) * SECTOR_SIZE as u32;
+            next_sector += sector_count(size);
+        }
+
+        let mut locations: HashMap<Vec<String>, (u32, u32)> = HashMap::new();
+        let mut files: Vec<(u32, &Vec<u8>)> = vec![];
+
+        for directory in directories.iter() {
+            locations.insert(directory.path.clone(), (directory.sector, directory.size));
+
+            for (name, node) in self.get_directory(&directory.path).into_iter().flatten() {
+                if let IsoNode::File(data) = node {
+                    let mut path = directory.path.clone();
+                    path.push(name.clone());
+
+                    locations.insert(path, (next_sector, data.len() as u32));
+                    files.push((next_sector, data));
+                    next_sector += sector_count(data.len() as u64);
+                }
+            }
+        }
+
+        let mut image = vec![0u8; next_sector as usize * SECTOR_SIZE as usize];
+        let sector_offset = |sector: u32| sector as usize * SECTOR_SIZE as usize;
+
+        let root = &directories[0];
+        let root_record = BaseDirectoryRecord::new(root.sector, root.size, FILE_FLAG_DIRECTORY, self.recording_date, 1);
+        let primary_volume = PrimaryVolume::new(&self.volume_identifier, next_sector, path_table_size as u32, PATH_TABLE_SECTOR, m_path_table_sector, root_record, self.recording_date);
+        let primary_volume_offset = sector_offset(PRIMARY_VOLUME_SECTOR);
+        image[primary_volume_offset..primary_volume_offset + SECTOR_SIZE as usize].copy_from_slice(&primary_volume.to_bytes());
+
+        let terminator_offset = sector_offset(TERMINATOR_SECTOR);
+        image[terminator_offset..terminator_offset + 7].copy_from_slice(&[255, b'C', b'D', b'0', b'0', b'1', 1]);
+
+        let mut l_path_table: Vec<u8> = vec![];
+        let mut m_path_table: Vec<u8> = vec![];
+
+        //Directory numbers in the path table start at 1
+        for directory in directories.iter() {
+            let identifier = directory.path.last().map_or(vec![0], |name| name.as_bytes().to_vec());
+            let parent = directory.parent as u16 + 1;
+
+            l_path_table.extend(path_table_record(&identifier, directory.sector, parent, false));
+            m_path_table.extend(path_table_record(&identifier, directory.sector, parent, true));
+        }
+
+        image[sector_offset(PATH_TABLE_SECTOR)..sector_offset(PATH_TABLE_SECTOR) + path_table_size].copy_from_slice(&l_path_table);
+        image[sector_offset(m_path_table_sector)..sector_offset(m_path_table_sector) + path_table_size].copy_from_slice(&m_path_table);
+
+        for directory in directories.iter() {
+            let records: Vec<Vec<u8>> = self.directory_records(directory, &directories[directory.parent], &locations)?
+                .iter().map(|record| record.to_bytes()).collect();
+            let offsets = layout_records(records.iter().map(|record| record.len()));
+            let directory_offset = sector_offset(directory.sector);
+
+            for (record, offset) in records.iter().zip(offsets) {
+                image[directory_offset + offset..directory_offset + offset + record.len()].copy_from_slice(record);
+            }
+        }
+
+        for (sector, data) in files {
+            image[sector_offset(sector)..sector_offset(sector) + data.len()].copy_from_slice(data);
+        }
+
+        Ok(image)
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.build()?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Cursor;
+    use crate::test_utils::TempPath;
+    use super::IsoBuilder;
+    use super::super::iso_file::RecordingDate;
+    use super::super::iso_file_reader::{ISOFileReader, SECTOR_SIZE};
+    use super::super::system_cnf::{SystemCnf, VideoMode};
+
+    #[test]
+    fn test_build_bootable_image() {
+        let elf = b"\x7fELF homebrew".to_vec();
+        let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+
+        let mut builder = IsoBuilder::new("test_disc");
+        builder.add_file("BOOT.ELF", elf.clone()).unwrap();
+        builder.add_file("cdrom0:\\DATA\\SUB\\file.bin;1", data.clone()).unwrap();
+        builder.add_directory("EMPTY").unwrap();
+        builder.set_system_cnf(&SystemCnf {
+            boot2: Some("cdrom0:\\BOOT.ELF;1".to_string()),
+            version: Some("1.00".to_string()),
+            video_mode: Some(VideoMode::Ntsc),
+            ..SystemCnf::default()
+        }).unwrap();
+
+        assert!(builder.add_file("lower case.bin", vec![]).is_err());
+
+        let image = builder.build().unwrap();
+
+        //Root is the first path table entry and is its own parent
+        let path_table = &image[18 * SECTOR_SIZE as usize..];
+        assert_eq!(path_table[..2], [1, 0]);
+        assert_eq!(path_table[6..8], 1u16.to_le_bytes());
+
+        let mut iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+        let boot_executable = iso_file.read_boot_executable().unwrap();
+
+        assert_eq!(boot_executable.elf, elf);
+        assert_eq!(boot_executable.video_mode, Some(VideoMode::Ntsc));
+        assert_eq!(iso_file.read_file("DATA/SUB/FILE.BIN").unwrap(), data);
+
+        let names: Vec<String> = iso_file.read_dir("").unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["BOOT.ELF", "DATA", "EMPTY", "SYSTEM.CNF"]);
+        assert!(iso_file.read_dir("EMPTY").unwrap().is_empty());
+    }
+    #[test]
+    fn test_build_from_host_directory() {
+        let host = TempPath::new("iso_host");
+        fs::create_dir_all(host.path().join("data").join("sub")).unwrap();
+        fs::write(host.path().join("boot.elf"), b"\x7fELF host").unwrap();
+        fs::write(host.path().join("README"), b"no extension").unwrap();
+        fs::write(host.path().join("data").join("sub").join("file.bin"), vec![0x5a; 3000]).unwrap();
+
+        let recording_date = RecordingDate::from_unix_timestamp(1_000_000_000);
+        let mut builder = IsoBuilder::from_host_directory(host.path(), "host_disc").unwrap();
+        builder.set_recording_date(recording_date);
+        builder.set_boot_executable("boot.elf").unwrap();
+
+        let image_path = TempPath::new("iso_image");
+        builder.write_to_file(image_path.path()).unwrap();
+
+        //Names without an extension still get the separator dot
+        let image = fs::read(image_path.path()).unwrap();
+        assert!(image.windows(9).any(|window| window == b"README.;1"));
+
+        let mut iso_file = ISOFileReader::new(&image_path.path().to_string_lossy()).unwrap();
+        assert_eq!(iso_file.read_boot_executable().unwrap().elf, b"\x7fELF host");
+        assert_eq!(iso_file.read_file("README").unwrap(), b"no extension");
+        assert_eq!(iso_file.read_file("cdrom0:\\DATA\\SUB\\FILE.BIN;1").unwrap(), vec![0x5a; 3000]);
+
+        let entries = iso_file.read_dir("").unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["BOOT.ELF", "DATA", "README", "SYSTEM.CNF"]);
+        assert_eq!(entries[0].metadata.recording_date, recording_date);
+        assert_eq!(recording_date.to_string(), "2001/09/09 01:46:40");
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::io::{self, Read, Seek};
+use super::iso_file_reader::FileLocation;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+//GMT offset is stored in 15 minute steps
+const GMT_OFFSET_SECONDS: i64 = 15 * 60;
+
+//The 7 byte date from a directory record
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RecordingDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub gmt_offset: i8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FileMetadata {
+    pub size: u64,
+    //Sector the first extent starts at
+    pub lsn: u64,
+    pub is_directory: bool,
+    pub is_hidden: bool,
+    pub recording_date: RecordingDate,
+}
+
+#[derive(Clone, Debug)]
+pub struct DirectoryEntry {
+    //Rock Ridge or Joliet name if there is one, otherwise the ISO9660 name without the version
+    pub name: String,
+    pub metadata: FileMetadata,
+}
+
+//Days since 1970/01/01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+//Inverse of days_from_civil
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+impl RecordingDate {
+    pub fn from_bytes(data: &[u8; 7]) -> RecordingDate {
+        RecordingDate {
+            year: 1900 + data[0] as u16,
+            month: data[1],
+            day: data[2],
+            hour: data[3],
+            minute: data[4],
+            second: data[5],
+            gmt_offset: data[6] as i8,
+        }
+    }
+
+    //Mastering tools leave the date zeroed sometimes
+    pub fn is_set(&self) -> bool {
+        self.month != 0 && self.day != 0
+    }
+
+    pub fn to_unix_timestamp(self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month.max(1) as i64, self.day.max(1) as i64);
+        let seconds = days * SECONDS_PER_DAY + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+
+        seconds - self.gmt_offset as i64 * GMT_OFFSET_SECONDS
+    }
+
+    //Dates made here are always in GMT
+    pub fn from_unix_timestamp(timestamp: i64) -> RecordingDate {
+        let (year, month, day) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+        let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+
+        RecordingDate {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            gmt_offset: 0,
+        }
+    }
+}
+
+impl fmt::Display for RecordingDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}/{:02}/{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+//Streams a file straight off the image instead of loading it all, movie files can be over 1GiB
+#[derive(Debug)]
+pub struct IsoFile<'a, R: Read + Seek> {
+    file: &'a mut R,
+    location: FileLocation,
+    position: u64,
+}
+
+impl<'a, R: Read + Seek> IsoFile<'a, R> {
+    pub fn new(file: &'a mut R, location: FileLocation) -> IsoFile<'a, R> {
+        IsoFile {
+            file,
+            location,
+            position: 0,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.location.length
+    }
+}
+
+impl<'a, R: Read + Seek> Read for IsoFile<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut extent_start: u64 = 0;
+
+        for extent in self.location.extents.iter() {
+            if self.position < extent_start + extent.length {
+                let extent_offset = self.position - extent_start;
+                let length = (buf.len() as u64).min(extent.length - extent_offset) as usize;
+
+                self.file.seek(io::SeekFrom::Start(extent.offset + extent_offset))?;
+                let bytes_read = self.file.read(&mut buf[..length])?;
+                self.position += bytes_read as u64;
+
+                return Ok(bytes_read);
+            }
+
+            extent_start += extent.length;
+        }
+
+        Ok(0)
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for IsoFile<'a, R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => offset as i64,
+            io::SeekFrom::End(offset) => self.location.length as i64 + offset,
+            io::SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"));
+        }
+
+        self.position = position as u64;
+
+        Ok(self.position)
+    }
+}
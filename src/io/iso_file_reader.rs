@@ -1,138 +1,400 @@
-use std::io;
+use std::io::{self, Read, Seek};
 use std::fs::File;
 use std::collections::BTreeMap;
-use std::error::Error;
-use super::utils::{read_bytes, create_io_error};
+use super::utils::{read_bytes, ByteReader};
+use super::image_error::ImageError;
+use super::block_device::{BlockDevice, BlockDeviceReader};
+use super::iso_file::{DirectoryEntry, FileMetadata, IsoFile, RecordingDate};
+use super::system_cnf::{resolve_cdrom_path, serial_from_boot_path, BootExecutable, SystemCnf};
 
 pub const SECTOR_SIZE: u64 = 2 * 1024;
 
 #[derive(Copy, Clone, Debug)]
-#[repr(C, packed)]
 pub struct bi_u16 {
-    le: u16,
-    be: u16
+    pub(super) le: u16,
+    pub(super) be: u16
 }
 
 #[derive(Copy, Clone, Debug)]
-#[repr(C, packed)]
 pub struct bi_u32 {
-    le: u32,
-    be: u32
+    pub(super) le: u32,
+    pub(super) be: u32
 }
 
 #[derive(Copy, Clone, Debug)]
-#[repr(C, packed)]
 pub struct PrimaryVolume {
-    vtype: u8,
-    identifier: [u8; 5],
-    version: u8,
-    _unused1: u8,
-    system_identifier: [u8; 32],
-    volume_identifier: [u8; 32],
-    _unused2: [u8; 8],
-    volume_space_size: bi_u32,
-    _unused3: [u8; 32],
-    volume_set_size: bi_u16,
-    volume_sequence_number: bi_u16,
-    logical_block_size: bi_u16,
-    path_table_size: bi_u32,
-    type_l_path_table_location: u32,
-    opt_type_l_path_table_location: u32,
-    type_m_path_table_location: u32,
-    opt_type_m_path_table_location: u32,
-    root_directory: BaseDirectoryRecord,
-    _unused4: u8,
+    pub(super) vtype: u8,
+    pub(super) identifier: [u8; 5],
+    pub(super) version: u8,
+    pub(super) _unused1: u8,
+    pub(super) system_identifier: [u8; 32],
+    pub(super) volume_identifier: [u8; 32],
+    pub(super) _unused2: [u8; 8],
+    pub(super) volume_space_size: bi_u32,
+    //Only used by supplementary descriptors
+    pub(super) escape_sequences: [u8; 32],
+    pub(super) volume_set_size: bi_u16,
+    pub(super) volume_sequence_number: bi_u16,
+    pub(super) logical_block_size: bi_u16,
+    pub(super) path_table_size: bi_u32,
+    pub(super) type_l_path_table_location: u32,
+    pub(super) opt_type_l_path_table_location: u32,
+    pub(super) type_m_path_table_location: u32,
+    pub(super) opt_type_m_path_table_location: u32,
+    pub(super) root_directory: BaseDirectoryRecord,
+    pub(super) _unused4: u8,
     pub volume_set_identifier: [u8; 128],
     pub publisher_identifier: [u8; 128],
     pub data_preparer_identifier: [u8; 128],
     pub application_identifier: [u8; 128],
-    copyright_file_identifier: [u8; 37],
-    abstract_file_identifier: [u8; 37],
-    bibliographic_file_identifier: [u8; 37],
-    volume_created_datetime: [u8; 17],
-    volume_modification_datetime: [u8; 17],
-    volume_expiration_datetime: [u8; 17],
-    volume_effective_datetime: [u8; 17],
-    file_structure_version: u8,
-    _unused5: u8,
-    application_data: [u8; 512],
-    reserved_data: [u8; 653],
+    pub(super) copyright_file_identifier: [u8; 37],
+    pub(super) abstract_file_identifier: [u8; 37],
+    pub(super) bibliographic_file_identifier: [u8; 37],
+    pub(super) volume_created_datetime: [u8; 17],
+    pub(super) volume_modification_datetime: [u8; 17],
+    pub(super) volume_expiration_datetime: [u8; 17],
+    pub(super) volume_effective_datetime: [u8; 17],
+    pub(super) file_structure_version: u8,
+    pub(super) _unused5: u8,
+    pub(super) application_data: [u8; 512],
+    pub(super) reserved_data: [u8; 653],
 }
 
 #[derive(Copy, Clone, Debug)]
-#[repr(C, packed)]
 pub struct BaseDirectoryRecord {
-    length: u8,
-    extended_attribute_length: u8,
-    extent_location: bi_u32,
-    extent_length: bi_u32,
-    recording_date: [u8; 7],
-    file_flags: u8,
-    file_unit_size_int: u8,
-    int_gap_size: u8,
-    volume_seq_number: bi_u16,
-    file_identifier_length: u8,
+    pub(super) length: u8,
+    pub(super) extended_attribute_length: u8,
+    pub(super) extent_location: bi_u32,
+    pub(super) extent_length: bi_u32,
+    pub(super) recording_date: [u8; 7],
+    pub(super) file_flags: u8,
+    pub(super) file_unit_size_int: u8,
+    pub(super) int_gap_size: u8,
+    pub(super) volume_seq_number: bi_u16,
+    pub(super) file_identifier_length: u8,
 }
 
 #[derive(Clone, Debug)]
 pub struct DirectoryRecord {
-    base: BaseDirectoryRecord,
-    file_identifier: Vec<u8>
+    pub(super) base: BaseDirectoryRecord,
+    pub(super) file_identifier: Vec<u8>,
+    pub(super) system_use: Vec<u8>,
 }
 
 #[derive(Copy, Clone, Debug)]
+pub struct FileExtent {
+    pub offset: u64,
+    pub length: u64,
+}
+
+//Files over 4GiB don't fit in one directory record so they are split over several extents
+#[derive(Clone, Debug)]
 pub struct FileLocation {
-    offset: u64,
-    length: u64,
+    pub extents: Vec<FileExtent>,
+    pub length: u64,
+    pub recording_date: RecordingDate,
+    pub file_flags: u8,
+    pub rock_ridge_name: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VolumeDescriptorType {
+    BootRecord,
+    Primary,
+    Supplementary,
+    Partition,
+    Terminator,
+    Unknown(u8),
+}
+
+//Each layer of a DVD9 is its own ISO9660 volume, layer 1 starts right where layer 0's volume space ends
+#[derive(Copy, Clone, Debug)]
+pub struct DiscLayer {
+    pub start_sector: u64,
+    pub sector_count: u64,
 }
 
 type PathLocationFinder = BTreeMap<Vec<String>, FileLocation>;
+//Normalized path -> key in path_locations or long_path_locations
+type PathIndex = BTreeMap<Vec<String>, Vec<String>>;
+
+const MAX_NEAR_MATCHES: usize = 5;
 
 #[derive(Debug)]
-pub struct ISOFileReader {
-    file: File,
+pub struct ISOFileReader<R: Read + Seek = File> {
+    file: R,
     pub primary_volume: PrimaryVolume,
+    pub layers: Vec<DiscLayer>,
     pub path_locations: PathLocationFinder,
+    //Long names from Rock Ridge or Joliet if the image has them, these point at the same files as path_locations
+    pub long_path_locations: PathLocationFinder,
+    path_index: PathIndex,
 }
 
+const VOLUME_DESCRIPTOR_START_SECTOR: u64 = 16;
+//Give up if the set never ends with a terminator
+const MAX_VOLUME_DESCRIPTORS: u64 = 64;
+pub(super) const STANDARD_IDENTIFIER: &[u8; 5] = b"CD001";
 
+//How far either side of layer 0's volume space size to look for the layer 1 descriptor
+const LAYER_BREAK_SEARCH_SECTORS: u64 = 16;
 
-fn read_primary_volume(file: &mut File, offset: u64) -> Result<PrimaryVolume, Box<dyn Error>> {
-    let length: usize = std::mem::size_of::<PrimaryVolume>();
+pub(super) const BASE_DIRECTORY_RECORD_SIZE: usize = 33;
 
-    let data = read_bytes(file, offset, length)?;
-    
-    Ok(
-        unsafe {
-            //Struct should be aligned in memory even if attributes arent
-            //The function will fail if the buffer was not entirely filled
-            std::ptr::read(data.as_ptr() as *const PrimaryVolume)
+pub const FILE_FLAG_HIDDEN: u8 = 0x01;
+pub const FILE_FLAG_DIRECTORY: u8 = 0x02;
+const FILE_FLAG_MULTI_EXTENT: u8 = 0x80;
+
+//Escape sequences in a supplementary descriptor that mark it as Joliet (UCS-2 level 1, 2 and 3)
+const JOLIET_ESCAPE_SEQUENCES: [&[u8; 3]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+impl bi_u16 {
+    fn read(reader: &mut ByteReader, field: &'static str) -> Result<bi_u16, ImageError> {
+        let (le, be) = reader.bi_u16(field)?;
+
+        Ok(bi_u16 { le, be })
+    }
+}
+
+impl bi_u32 {
+    fn read(reader: &mut ByteReader, field: &'static str) -> Result<bi_u32, ImageError> {
+        let (le, be) = reader.bi_u32(field)?;
+
+        Ok(bi_u32 { le, be })
+    }
+}
+
+impl PrimaryVolume {
+    //Field by field in disc order, the M path table locations are the only big endian only fields
+    fn read(reader: &mut ByteReader) -> Result<PrimaryVolume, ImageError> {
+        Ok(PrimaryVolume {
+            vtype: reader.u8("vtype")?,
+            identifier: reader.array("identifier")?,
+            version: reader.u8("version")?,
+            _unused1: reader.u8("_unused1")?,
+            system_identifier: reader.array("system_identifier")?,
+            volume_identifier: reader.array("volume_identifier")?,
+            _unused2: reader.array("_unused2")?,
+            volume_space_size: bi_u32::read(reader, "volume_space_size")?,
+            escape_sequences: reader.array("escape_sequences")?,
+            volume_set_size: bi_u16::read(reader, "volume_set_size")?,
+            volume_sequence_number: bi_u16::read(reader, "volume_sequence_number")?,
+            logical_block_size: bi_u16::read(reader, "logical_block_size")?,
+            path_table_size: bi_u32::read(reader, "path_table_size")?,
+            type_l_path_table_location: reader.u32_le("type_l_path_table_location")?,
+            opt_type_l_path_table_location: reader.u32_le("opt_type_l_path_table_location")?,
+            type_m_path_table_location: reader.u32_be("type_m_path_table_location")?,
+            opt_type_m_path_table_location: reader.u32_be("opt_type_m_path_table_location")?,
+            root_directory: BaseDirectoryRecord::read(reader)?,
+            _unused4: reader.u8("_unused4")?,
+            volume_set_identifier: reader.array("volume_set_identifier")?,
+            publisher_identifier: reader.array("publisher_identifier")?,
+            data_preparer_identifier: reader.array("data_preparer_identifier")?,
+            application_identifier: reader.array("application_identifier")?,
+            copyright_file_identifier: reader.array("copyright_file_identifier")?,
+            abstract_file_identifier: reader.array("abstract_file_identifier")?,
+            bibliographic_file_identifier: reader.array("bibliographic_file_identifier")?,
+            volume_created_datetime: reader.array("volume_created_datetime")?,
+            volume_modification_datetime: reader.array("volume_modification_datetime")?,
+            volume_expiration_datetime: reader.array("volume_expiration_datetime")?,
+            volume_effective_datetime: reader.array("volume_effective_datetime")?,
+            file_structure_version: reader.u8("file_structure_version")?,
+            _unused5: reader.u8("_unused5")?,
+            application_data: reader.array("application_data")?,
+            reserved_data: reader.array("reserved_data")?,
+        })
+    }
+}
+
+impl BaseDirectoryRecord {
+    fn read(reader: &mut ByteReader) -> Result<BaseDirectoryRecord, ImageError> {
+        Ok(BaseDirectoryRecord {
+            length: reader.u8("length")?,
+            extended_attribute_length: reader.u8("extended_attribute_length")?,
+            extent_location: bi_u32::read(reader, "extent_location")?,
+            extent_length: bi_u32::read(reader, "extent_length")?,
+            recording_date: reader.array("recording_date")?,
+            file_flags: reader.u8("file_flags")?,
+            file_unit_size_int: reader.u8("file_unit_size_int")?,
+            int_gap_size: reader.u8("int_gap_size")?,
+            volume_seq_number: bi_u16::read(reader, "volume_seq_number")?,
+            file_identifier_length: reader.u8("file_identifier_length")?,
+        })
+    }
+}
+
+impl FileLocation {
+    fn new(extents: Vec<FileExtent>, record: &BaseDirectoryRecord) -> FileLocation {
+        FileLocation {
+            length: extents.iter().map(|extent| extent.length).sum(),
+            extents,
+            recording_date: RecordingDate::from_bytes(&record.recording_date),
+            file_flags: record.file_flags,
+            rock_ridge_name: None,
         }
-    )
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.file_flags & FILE_FLAG_DIRECTORY != 0
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.file_flags & FILE_FLAG_HIDDEN != 0
+    }
+
+    pub fn metadata(&self) -> FileMetadata {
+        FileMetadata {
+            size: self.length,
+            lsn: self.extents.first().map_or(0, |extent| extent.offset / SECTOR_SIZE),
+            is_directory: self.is_directory(),
+            is_hidden: self.is_hidden(),
+            recording_date: self.recording_date,
+        }
+    }
+}
+
+impl From<u8> for VolumeDescriptorType {
+    fn from(vtype: u8) -> VolumeDescriptorType {
+        match vtype {
+            0 => VolumeDescriptorType::BootRecord,
+            1 => VolumeDescriptorType::Primary,
+            2 => VolumeDescriptorType::Supplementary,
+            3 => VolumeDescriptorType::Partition,
+            255 => VolumeDescriptorType::Terminator,
+            _ => VolumeDescriptorType::Unknown(vtype),
+        }
+    }
+}
+
+fn read_primary_volume<R: Read + Seek>(file: &mut R, offset: u64) -> Result<PrimaryVolume, ImageError> {
+    let data = read_bytes(file, offset, SECTOR_SIZE as usize)?;
+
+    PrimaryVolume::read(&mut ByteReader::new("PrimaryVolume", &data, offset))
+}
+
+fn is_joliet_volume(volume: &PrimaryVolume) -> bool {
+    JOLIET_ESCAPE_SEQUENCES.iter().any(|escape_sequence| volume.escape_sequences.starts_with(*escape_sequence))
+}
+
+struct VolumeDescriptorSet {
+    primary_volume: PrimaryVolume,
+    joliet_volume: Option<PrimaryVolume>,
+}
+
+fn read_volume_descriptors<R: Read + Seek>(file: &mut R) -> Result<VolumeDescriptorSet, ImageError> {
+    let mut primary_volume: Option<PrimaryVolume> = None;
+    let mut joliet_volume: Option<PrimaryVolume> = None;
+
+    for sector in VOLUME_DESCRIPTOR_START_SECTOR..VOLUME_DESCRIPTOR_START_SECTOR + MAX_VOLUME_DESCRIPTORS {
+        let header = read_bytes(file, sector * SECTOR_SIZE, 1 + STANDARD_IDENTIFIER.len())?;
+
+        if &header[1..] != STANDARD_IDENTIFIER {
+            return Err(ImageError::BadVolumeDescriptor { sector });
+        }
+
+        match VolumeDescriptorType::from(header[0]) {
+            VolumeDescriptorType::Primary if primary_volume.is_none() => {
+                primary_volume = Some(read_primary_volume(file, sector * SECTOR_SIZE)?);
+            },
+            //Supplementary descriptors share the primary layout
+            VolumeDescriptorType::Supplementary if joliet_volume.is_none() => {
+                let volume = read_primary_volume(file, sector * SECTOR_SIZE)?;
+
+                if is_joliet_volume(&volume) {
+                    joliet_volume = Some(volume);
+                }
+            },
+            VolumeDescriptorType::Terminator => break,
+            _ => {},
+        }
+    }
+
+    match primary_volume {
+        Some(primary_volume) => Ok(VolumeDescriptorSet {
+            primary_volume,
+            joliet_volume,
+        }),
+        //Blame the first descriptor, that's where the primary volume should have been
+        None => Err(ImageError::BadVolumeDescriptor { sector: VOLUME_DESCRIPTOR_START_SECTOR }),
+    }
+}
+
+fn has_volume_descriptor<R: Read + Seek>(file: &mut R, sector: u64) -> bool {
+    match read_bytes(file, sector * SECTOR_SIZE, 1 + STANDARD_IDENTIFIER.len()) {
+        Ok(header) => header[0] == 1 && &header[1..] == STANDARD_IDENTIFIER,
+        Err(_) => false,
+    }
+}
+
+//Some dumps don't put layer 1 exactly at the end of layer 0's volume space so look around a bit like PCSX2 does
+fn find_layer1_start<R: Read + Seek>(file: &mut R, layer0_sectors: u64, image_sectors: u64) -> Option<u64> {
+    if image_sectors <= layer0_sectors + VOLUME_DESCRIPTOR_START_SECTOR {
+        return None;
+    }
+
+    let search_start = layer0_sectors.saturating_sub(LAYER_BREAK_SEARCH_SECTORS);
+    let search_end = layer0_sectors + LAYER_BREAK_SEARCH_SECTORS;
+
+    //Closest candidates first
+    let mut candidates: Vec<u64> = (search_start..=search_end).collect();
+    candidates.sort_by_key(|candidate| (*candidate as i64 - layer0_sectors as i64).abs());
+
+    candidates.into_iter()
+        .filter(|candidate| *candidate > VOLUME_DESCRIPTOR_START_SECTOR && candidate + VOLUME_DESCRIPTOR_START_SECTOR < image_sectors)
+        .find(|candidate| has_volume_descriptor(file, candidate + VOLUME_DESCRIPTOR_START_SECTOR))
 }
 
-fn read_directory_record(file: &mut File, offset: u64) -> Result<DirectoryRecord, Box<dyn Error>> {
-    let base_record_length: usize = std::mem::size_of::<BaseDirectoryRecord>();
+fn read_disc_layers<R: Read + Seek>(file: &mut R, primary_volume: &PrimaryVolume) -> Result<Vec<DiscLayer>, ImageError> {
+    let image_sectors = file.seek(io::SeekFrom::End(0))? / SECTOR_SIZE;
+    let layer0_sectors = primary_volume.volume_space_size.le as u64;
+
+    let mut layers = vec![DiscLayer {
+        start_sector: 0,
+        sector_count: layer0_sectors,
+    }];
+
+    if let Some(layer1_start) = find_layer1_start(file, layer0_sectors, image_sectors) {
+        let layer1_volume = read_primary_volume(file, (layer1_start + VOLUME_DESCRIPTOR_START_SECTOR) * SECTOR_SIZE)?;
+
+        layers[0].sector_count = layer1_start;
+        layers.push(DiscLayer {
+            start_sector: layer1_start,
+            sector_count: (layer1_volume.volume_space_size.le as u64).min(image_sectors - layer1_start),
+        });
+    }
+
+    Ok(layers)
+}
+
+fn read_directory_record<R: Read + Seek>(file: &mut R, offset: u64) -> Result<DirectoryRecord, ImageError> {
+    let base_record_length = BASE_DIRECTORY_RECORD_SIZE;
 
     let data = read_bytes(file, offset, base_record_length)?;
 
-    let base = unsafe {
-        std::ptr::read(data.as_ptr() as *const BaseDirectoryRecord)
-    };
+    let base = BaseDirectoryRecord::read(&mut ByteReader::new("DirectoryRecord", &data, offset))?;
+
+    let identifier_length = base.file_identifier_length as usize;
 
-    let identifier_length = base.file_identifier_length;
+    let file_identifier = read_bytes(file, offset + base_record_length as u64, identifier_length)?;
 
-    let file_identifier = read_bytes(file, offset + base_record_length as u64, identifier_length as usize)?;
+    //System use area comes after the identifier and a padding byte if the identifier length is even
+    let system_use_offset = base_record_length + identifier_length + (identifier_length + 1) % 2;
+    let system_use = if (base.length as usize) > system_use_offset {
+        read_bytes(file, offset + system_use_offset as u64, base.length as usize - system_use_offset)?
+    } else {
+        vec![]
+    };
     
     Ok(
         DirectoryRecord {
             base,
             file_identifier,
+            system_use,
         }
     )
 }
 
-fn read_directory_children(file: &mut File, dir: &BaseDirectoryRecord) -> Result<Vec<DirectoryRecord>, Box<dyn Error>> {
+fn read_directory_children<R: Read + Seek>(file: &mut R, dir: &BaseDirectoryRecord) -> Result<Vec<DirectoryRecord>, ImageError> {
     let mut final_records: Vec<DirectoryRecord> = vec![];
     
     let children_offset = dir.extent_location.le;
@@ -141,7 +403,8 @@ fn read_directory_children(file: &mut File, dir: &BaseDirectoryRecord) -> Result
     let mut bytes_read: u64 = 0;
     let mut wasted_bytes: u64 = 0;
 
-    while bytes_read < children_length as u64 {
+    //Padding at the end of a sector still counts towards the extent length
+    while bytes_read + wasted_bytes < children_length as u64 {
         let new_dir = read_directory_record(file, children_offset as u64 * SECTOR_SIZE + bytes_read + wasted_bytes)?;
 
         //Spec will waste bytes if a directory record crosses a sector boundary
@@ -158,80 +421,539 @@ fn read_directory_children(file: &mut File, dir: &BaseDirectoryRecord) -> Result
     Ok(final_records)
 }
 
-fn is_file_string(file_identifier: &String) -> bool {
-    file_identifier.contains(";")
+fn is_directory(record: &DirectoryRecord) -> bool {
+    record.base.file_flags & FILE_FLAG_DIRECTORY != 0
 }
 
-fn is_special_file_string(file_identifier: &String) -> bool {
+fn is_special_file_string(file_identifier: &str) -> bool {
     file_identifier.eq("\u{0}") || file_identifier.eq("\u{1}")
 }
 
-fn parse_identifier(identifier: &Vec<u8>) -> Result<String, Box<dyn Error>> {
-    Ok(std::str::from_utf8(identifier)?.to_string())
+fn parse_identifier(identifier: &[u8], joliet: bool) -> Result<String, ImageError> {
+    //Joliet names are big endian UCS-2, except for the single byte . and .. entries
+    if joliet && identifier.len() > 1 {
+        let characters: Vec<u16> = identifier.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+
+        Ok(String::from_utf16_lossy(&characters))
+    } else {
+        match std::str::from_utf8(identifier) {
+            Ok(identifier) => Ok(identifier.to_string()),
+            Err(_) => Err(ImageError::CorruptImage { detail: "file identifier is not valid UTF-8" }),
+        }
+    }
+}
+
+//Rock Ridge stores the real name in NM entries in the system use area, long names can be split over several entries
+fn read_rock_ridge_name(system_use: &[u8]) -> Option<String> {
+    let mut name: Vec<u8> = vec![];
+    let mut found = false;
+    let mut offset: usize = 0;
+
+    while offset + 4 <= system_use.len() {
+        let signature = &system_use[offset..offset + 2];
+        let length = system_use[offset + 2] as usize;
+
+        if length < 4 || offset + length > system_use.len() {
+            break;
+        }
+
+        if signature == b"NM" && length >= 5 {
+            let flags = system_use[offset + 4];
+
+            //Current and parent directory flags, these don't carry a name
+            if flags & 0x06 == 0 {
+                name.extend_from_slice(&system_use[offset + 5..offset + length]);
+                found = true;
+            }
+        }
+
+        offset += length;
+    }
+
+    if found {
+        Some(String::from_utf8_lossy(&name).to_string())
+    } else {
+        None
+    }
 }
 
-fn generate_path_location_finder(file: &mut File, root_dir: &BaseDirectoryRecord) -> Result<PathLocationFinder, Box<dyn Error>> {
+//Returns the ISO9660 paths along with the Rock Ridge paths for any files that have them
+fn generate_path_location_finder<R: Read + Seek>(file: &mut R, root_dir: &BaseDirectoryRecord, joliet: bool) -> Result<(PathLocationFinder, PathLocationFinder), ImageError> {
     let mut path_location_finder: PathLocationFinder = BTreeMap::new();
-    
-    let init_dir = DirectoryRecord {
-        base: *root_dir,
-        file_identifier: vec![],
-    };
-    let mut search_dirs: Vec<(DirectoryRecord, Vec<String>)> = vec![(init_dir, vec![])];
+    let mut rock_ridge_location_finder: PathLocationFinder = BTreeMap::new();
 
-    while !search_dirs.is_empty() {
-        let (search_dir, path) = search_dirs.pop().unwrap();
+    let mut search_dirs: Vec<(BaseDirectoryRecord, Vec<String>, Vec<String>)> = vec![(*root_dir, vec![], vec![])];
+    //Guards against a corrupt image with a directory pointing back at one of its parents
+    let mut visited_dirs: Vec<u32> = vec![root_dir.extent_location.le];
 
-        let children_dirs = read_directory_children(file, &search_dir.base)?;
+    while let Some((search_dir, path, rock_ridge_path)) = search_dirs.pop() {
+        let children_dirs = read_directory_children(file, &search_dir)?;
+        let mut extents: Vec<FileExtent> = vec![];
         
         for child_dir in children_dirs {
-            if !is_special_file_string(&parse_identifier(&child_dir.file_identifier)?) {
-                let mut new_path = path.clone();
-                new_path.append(&mut vec![parse_identifier(&child_dir.file_identifier)?]);
+            let identifier = parse_identifier(&child_dir.file_identifier, joliet)?;
 
-                if is_file_string(&parse_identifier(&child_dir.file_identifier)?) {
+            if is_special_file_string(&identifier) {
+                continue;
+            }
 
+            let mut new_path = path.clone();
+            new_path.push(identifier.clone());
 
-                    path_location_finder.insert(new_path, FileLocation {
-                        offset: child_dir.base.extent_location.le as u64 * SECTOR_SIZE,
-                        length: child_dir.base.extent_length.le as u64,
-                    });
-                } else {
+            let mut new_rock_ridge_path = rock_ridge_path.clone();
+            let rock_ridge_name = read_rock_ridge_name(&child_dir.system_use);
+            new_rock_ridge_path.push(rock_ridge_name.clone().unwrap_or(identifier));
 
-                    search_dirs.push((child_dir, new_path));
+            if is_directory(&child_dir) {
+                let extent_location = child_dir.base.extent_location.le;
+
+                //Directories go in too so they can be listed, they are never multi extent
+                let mut directory_location = FileLocation::new(vec![FileExtent {
+                    offset: extent_location as u64 * SECTOR_SIZE,
+                    length: child_dir.base.extent_length.le as u64,
+                }], &child_dir.base);
+                directory_location.rock_ridge_name = rock_ridge_name;
+
+                if new_rock_ridge_path != new_path {
+                    rock_ridge_location_finder.insert(new_rock_ridge_path.clone(), directory_location.clone());
                 }
+
+                path_location_finder.insert(new_path.clone(), directory_location);
+
+                if !visited_dirs.contains(&extent_location) {
+                    visited_dirs.push(extent_location);
+                    search_dirs.push((child_dir.base, new_path, new_rock_ridge_path));
+                }
+
+                continue;
+            }
+
+            extents.push(FileExtent {
+                offset: child_dir.base.extent_location.le as u64 * SECTOR_SIZE,
+                length: child_dir.base.extent_length.le as u64,
+            });
+
+            //Every extent but the last one has the multi extent flag set
+            if child_dir.base.file_flags & FILE_FLAG_MULTI_EXTENT != 0 {
+                continue;
             }
+
+            let mut file_location = FileLocation::new(std::mem::take(&mut extents), &child_dir.base);
+            file_location.rock_ridge_name = rock_ridge_name;
+
+            if new_rock_ridge_path != new_path {
+                rock_ridge_location_finder.insert(new_rock_ridge_path, file_location.clone());
+            }
+
+            path_location_finder.insert(new_path, file_location);
         }
     }
     
-    Ok(path_location_finder)
+    Ok((path_location_finder, rock_ridge_location_finder))
 }
 
 fn path_to_dirs(path: &str) -> Vec<String> {
-    path.to_string().split("/").map(|s| s.to_string()).collect()
+    path.split(['/', '\\']).map(|s| s.to_string()).collect()
+}
+
+//Drops the version and the trailing dot ISO9660 puts on names without an extension
+fn display_name(identifier: &str) -> String {
+    let name = identifier.split(';').next().unwrap_or_default();
+
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+fn normalize_path_component(name: &str) -> String {
+    display_name(name).to_uppercase()
+}
+
+//cdrom0:\DIR\file.elf;1, /DIR/FILE.ELF and DIR/FILE.ELF;1 all become ["DIR", "FILE.ELF"]
+pub fn normalize_path(path: &str) -> Vec<String> {
+    let path = match path.find(':') {
+        Some(index) => &path[index + 1..],
+        None => path,
+    };
+
+    path.split(['/', '\\']).filter(|component| !component.is_empty()).map(normalize_path_component).collect()
+}
+
+//FILE.ELF;10 is 10 and anything dotted like 1.10 is compared a part at a time, so it's newer than
+//1.9 even though it sorts before it as a string
+fn file_version(path: &[String]) -> Vec<u32> {
+    let version = path.last().and_then(|name| name.split_once(';')).map_or("", |(_, version)| version);
+
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+//ISO9660 names win over long names, and the highest version of a file wins
+fn build_path_index(path_locations: &PathLocationFinder, long_path_locations: &PathLocationFinder) -> PathIndex {
+    let mut path_index: PathIndex = BTreeMap::new();
+
+    for path in path_locations.keys() {
+        let normalized = normalize_path(&path.join("/"));
+
+        if path_index.get(&normalized).is_none_or(|existing| file_version(path) > file_version(existing)) {
+            path_index.insert(normalized, path.clone());
+        }
+    }
+
+    for path in long_path_locations.keys() {
+        path_index.entry(normalize_path(&path.join("/"))).or_insert_with(|| path.clone());
+    }
+
+    path_index
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+impl ISOFileReader<File> {
+    pub fn new(path: &str) -> Result<ISOFileReader<File>, ImageError> {
+        ISOFileReader::from_reader(File::open(path)?)
+    }
+}
+
+impl<D: BlockDevice> ISOFileReader<BlockDeviceReader<D>> {
+    //For BIN/CUE and other images that don't store plain 2048 byte sectors
+    pub fn from_block_device(device: D) -> Result<ISOFileReader<BlockDeviceReader<D>>, ImageError> {
+        ISOFileReader::from_reader(BlockDeviceReader::new(device))
+    }
 }
 
-impl ISOFileReader {
-    pub fn new(path: &str) -> Result<ISOFileReader, Box<dyn Error>> {
-        let mut file = File::open(path).unwrap();
-        let primary_volume = read_primary_volume(&mut file, 16 * SECTOR_SIZE)?;
-        let path_locations = generate_path_location_finder(&mut file, &primary_volume.root_directory)?;
+impl<R: Read + Seek> ISOFileReader<R> {
+    pub fn from_reader(mut file: R) -> Result<ISOFileReader<R>, ImageError> {
+        let VolumeDescriptorSet { primary_volume, joliet_volume } = read_volume_descriptors(&mut file)?;
+        let layers = read_disc_layers(&mut file, &primary_volume)?;
+        let (path_locations, rock_ridge_path_locations) = generate_path_location_finder(&mut file, &primary_volume.root_directory, false)?;
+
+        //Rock Ridge names are preferred since they come from the same tree as path_locations
+        let long_path_locations = match &joliet_volume {
+            Some(joliet_volume) if rock_ridge_path_locations.is_empty() => {
+                generate_path_location_finder(&mut file, &joliet_volume.root_directory, true)?.0
+            },
+            _ => rock_ridge_path_locations,
+        };
+
+        let path_index = build_path_index(&path_locations, &long_path_locations);
 
         Ok(ISOFileReader {
             file,
             primary_volume,
+            layers,
             path_locations,
+            long_path_locations,
+            path_index,
         })
     }
 
-    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    //This is the layer 1 start the CDVD reports for DVD9 discs
+    pub fn layer_break(&self) -> Option<u64> {
+        self.layers.get(1).map(|layer| layer.start_sector)
+    }
+
+    //Tries an exact match first then falls back to ignoring case, separators, the device and the version
+    pub fn find_file(&self, path: &str) -> Result<FileLocation, ImageError> {
+        match self.resolve_path(path).and_then(|dirs| self.get_location(&dirs)) {
+            Some(file_location) => Ok(file_location.clone()),
+            None => Err(ImageError::FileNotFound {
+                path: path.to_string(),
+                near_matches: self.near_matches(&normalize_path(path)),
+            }),
+        }
+    }
+
+    //Key into path_locations or long_path_locations for the path
+    fn resolve_path(&self, path: &str) -> Option<Vec<String>> {
         let dirs = path_to_dirs(path);
 
-        match self.path_locations.get(&dirs) {
-            Some(file_location) => {
-                Ok(read_bytes(&mut self.file, file_location.offset, file_location.length as usize)?)
-            },
-            None => Err(create_io_error(io::ErrorKind::NotFound, "could not find file in iso!"))
+        if self.get_location(&dirs).is_some() {
+            return Some(dirs);
+        }
+
+        self.path_index.get(&normalize_path(path)).cloned()
+    }
+
+    fn get_location(&self, dirs: &[String]) -> Option<&FileLocation> {
+        self.path_locations.get(dirs).or(self.long_path_locations.get(dirs))
+    }
+
+    //Paths a small typo away from the one asked for, or with the same file name in another directory
+    fn near_matches(&self, normalized_path: &[String]) -> Vec<String> {
+        let wanted = normalized_path.join("/");
+        let max_distance = (wanted.len() / 4).max(2);
+
+        let mut near_matches: Vec<(usize, String)> = self.path_index.iter().filter_map(|(candidate, path)| {
+            let distance = edit_distance(&wanted, &candidate.join("/"));
+
+            if distance <= max_distance || candidate.last() == normalized_path.last() {
+                Some((distance, path.join("/")))
+            } else {
+                None
+            }
+        }).collect();
+
+        near_matches.sort();
+        near_matches.truncate(MAX_NEAR_MATCHES);
+
+        near_matches.into_iter().map(|(_, path)| path).collect()
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, ImageError> {
+        let file_location = self.find_file(path)?;
+
+        if file_location.is_directory() {
+            return Err(ImageError::IsADirectory { path: path.to_string() });
+        }
+
+        let mut data: Vec<u8> = vec![];
+
+        for extent in file_location.extents.iter() {
+            data.append(&mut read_bytes(&mut self.file, extent.offset, extent.length as usize)?);
+        }
+
+        Ok(data)
+    }
+
+    pub fn open(&mut self, path: &str) -> Result<IsoFile<'_, R>, ImageError> {
+        let file_location = self.find_file(path)?;
+
+        if file_location.is_directory() {
+            return Err(ImageError::IsADirectory { path: path.to_string() });
+        }
+
+        Ok(IsoFile::new(&mut self.file, file_location))
+    }
+
+    fn root_location(&self) -> FileLocation {
+        let root_directory = &self.primary_volume.root_directory;
+
+        FileLocation::new(vec![FileExtent {
+            offset: root_directory.extent_location.le as u64 * SECTOR_SIZE,
+            length: root_directory.extent_length.le as u64,
+        }], root_directory)
+    }
+
+    pub fn metadata(&self, path: &str) -> Result<FileMetadata, ImageError> {
+        if normalize_path(path).is_empty() {
+            return Ok(self.root_location().metadata());
+        }
+
+        Ok(self.find_file(path)?.metadata())
+    }
+
+    //Lists the ISO9660 tree using Rock Ridge names where there are any, directories only found
+    //under their Joliet names are listed from the Joliet tree instead
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirectoryEntry>, ImageError> {
+        let directory = if normalize_path(path).is_empty() {
+            vec![]
+        } else {
+            if !self.find_file(path)?.is_directory() {
+                return Err(ImageError::NotADirectory { path: path.to_string() });
+            }
+
+            self.resolve_path(path).unwrap_or_default()
+        };
+
+        let locations = if directory.is_empty() || self.path_locations.contains_key(&directory) {
+            &self.path_locations
+        } else {
+            &self.long_path_locations
+        };
+
+        let entries = locations.range(directory.clone()..)
+            .take_while(|(entry_path, _)| entry_path.starts_with(&directory))
+            .filter(|(entry_path, _)| entry_path.len() == directory.len() + 1)
+            .map(|(entry_path, file_location)| DirectoryEntry {
+                name: file_location.rock_ridge_name.clone().unwrap_or_else(|| display_name(&entry_path[directory.len()])),
+                metadata: file_location.metadata(),
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    pub fn read_system_cnf(&mut self) -> Result<SystemCnf, ImageError> {
+        SystemCnf::parse(&self.read_file("SYSTEM.CNF")?)
+    }
+
+    pub fn read_boot_executable(&mut self) -> Result<BootExecutable, ImageError> {
+        let system_cnf = self.read_system_cnf()?;
+
+        let boot_path = match system_cnf.boot_path() {
+            Some(boot_path) => boot_path,
+            None => return Err(ImageError::NoBootExecutable),
+        };
+
+        let path = resolve_cdrom_path(boot_path)?;
+        let elf = self.read_file(&path)?;
+
+        Ok(BootExecutable {
+            serial: serial_from_boot_path(&path),
+            video_mode: system_cnf.video_mode,
+            path,
+            elf,
+        })
+    }
+}
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use super::{ISOFileReader, SECTOR_SIZE};
+    use super::super::image_error::ImageError;
+
+    fn directory_record(extent: u32, length: u32, flags: u8, name: &[u8]) -> Vec<u8> {
+        let record_length = 33 + name.len() + (name.len() + 1) % 2;
+        let mut record = vec![0u8; record_length];
+
+        record[0] = record_length as u8;
+        record[2..6].copy_from_slice(&extent.to_le_bytes());
+        record[6..10].copy_from_slice(&extent.to_be_bytes());
+        record[10..14].copy_from_slice(&length.to_le_bytes());
+        record[14..18].copy_from_slice(&length.to_be_bytes());
+        //2024/03/15 12:30:00 GMT
+        record[18..25].copy_from_slice(&[124, 3, 15, 12, 30, 0, 0]);
+        record[25] = flags;
+        record[28..30].copy_from_slice(&1u16.to_le_bytes());
+        record[30..32].copy_from_slice(&1u16.to_be_bytes());
+        record[32] = name.len() as u8;
+        record[33..33 + name.len()].copy_from_slice(name);
+
+        record
+    }
+
+    //Smallest image the reader will accept, a PVD, a terminator and a root directory
+    //Files are (name, flags, data) and each one gets its own sector starting at 19
+    fn create_mock_iso(files: &[(&[u8], u8, &[u8])]) -> Vec<u8> {
+        let sector = SECTOR_SIZE as usize;
+        let mut image = vec![0u8; (19 + files.len()) * sector];
+
+        let root_length = sector as u32;
+        let pvd = &mut image[16 * sector..17 * sector];
+        pvd[0] = 1;
+        pvd[1..6].copy_from_slice(b"CD001");
+        pvd[6] = 1;
+        pvd[80..84].copy_from_slice(&((19 + files.len()) as u32).to_le_bytes());
+        pvd[84..88].copy_from_slice(&((19 + files.len()) as u32).to_be_bytes());
+        pvd[156..190].copy_from_slice(&directory_record(18, root_length, 0x02, &[0]));
+
+        let terminator = &mut image[17 * sector..18 * sector];
+        terminator[0] = 255;
+        terminator[1..6].copy_from_slice(b"CD001");
+        terminator[6] = 1;
+
+        let mut root: Vec<u8> = vec![];
+        root.extend(directory_record(18, root_length, 0x02, &[0]));
+        root.extend(directory_record(18, root_length, 0x02, &[1]));
+
+        for (index, (name, flags, data)) in files.iter().enumerate() {
+            let extent = 19 + index;
+
+            root.extend(directory_record(extent as u32, data.len() as u32, *flags, name));
+            image[extent * sector..extent * sector + data.len()].copy_from_slice(data);
         }
+
+        image[18 * sector..18 * sector + root.len()].copy_from_slice(&root);
+
+        image
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_file_from_memory() {
+        let data = b"BOOT2 = cdrom0:\\SLUS_123.45;1\r\n";
+        let image = create_mock_iso(&[(b"SYSTEM.CNF;1", 0x00, data)]);
+
+        let mut iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+
+        assert_eq!(iso_file.read_file("SYSTEM.CNF;1").unwrap(), data.to_vec());
+        assert!(iso_file.read_file("MISSING.BIN;1").is_err());
+    }
+
+    #[test]
+    fn test_path_lookup() {
+        let image = create_mock_iso(&[(b"SLUS_123.45;1", 0x00, b"ELF"), (b"README.;1", 0x00, b"TEXT"), (b"GAME.BIN;10", 0x00, b"NEW"), (b"GAME.BIN;9", 0x00, b"OLD")]);
+
+        let mut iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+
+        assert_eq!(iso_file.read_file("cdrom0:\\slus_123.45;1").unwrap(), b"ELF".to_vec());
+        assert_eq!(iso_file.read_file("/SLUS_123.45").unwrap(), b"ELF".to_vec());
+        assert_eq!(iso_file.read_file("readme").unwrap(), b"TEXT".to_vec());
+        //;10 is newer even though it sorts before ;9
+        assert_eq!(iso_file.read_file("cdrom0:\\GAME.BIN").unwrap(), b"NEW".to_vec());
+
+        let error = iso_file.read_file("cdrom0:\\SLUS_123.46;1").unwrap_err();
+        assert!(error.to_string().contains("did you mean SLUS_123.45;1"));
+        assert!(matches!(&error, ImageError::FileNotFound { path, .. } if path == "cdrom0:\\SLUS_123.46;1"));
+
+        let not_an_iso = ISOFileReader::from_reader(Cursor::new(vec![0u8; 20 * SECTOR_SIZE as usize])).err().unwrap();
+        assert_eq!(not_an_iso, ImageError::BadVolumeDescriptor { sector: 16 });
+    }
+
+    #[test]
+    fn test_open_and_read_dir() {
+        let data: Vec<u8> = (0..200).collect();
+        let image = create_mock_iso(&[(b"MOVIE.PSS;1", 0x00, &data), (b"DATA", 0x02, &[])]);
+
+        let mut iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+
+        let mut movie = iso_file.open("cdrom0:\\MOVIE.PSS").unwrap();
+        let mut buffer = [0u8; 4];
+        movie.seek(SeekFrom::Start(100)).unwrap();
+        movie.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [100, 101, 102, 103]);
+        assert_eq!(movie.seek(SeekFrom::End(0)).unwrap(), 200);
+        assert_eq!(movie.read(&mut buffer).unwrap(), 0);
+
+        let entries = iso_file.read_dir("cdrom0:\\").unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["DATA", "MOVIE.PSS"]);
+        assert!(entries[0].metadata.is_directory);
+        assert_eq!(entries[1].metadata.size, 200);
+        assert_eq!(entries[1].metadata.lsn, 19);
+        assert_eq!(entries[1].metadata.recording_date.to_unix_timestamp(), 1710505800);
+
+        assert!(iso_file.read_dir("DATA").unwrap().is_empty());
+        assert_eq!(iso_file.read_dir("MOVIE.PSS").unwrap_err(), ImageError::NotADirectory { path: "MOVIE.PSS".to_string() });
+        assert_eq!(iso_file.open("DATA").err().unwrap(), ImageError::IsADirectory { path: "DATA".to_string() });
+    }
+
+    #[test]
+    fn test_read_multi_extent_file() {
+        let image = create_mock_iso(&[
+            (b"MOVIE.PSS;1", 0x80, b"first extent "),
+            (b"MOVIE.PSS;1", 0x00, b"second extent"),
+            (b"NOVERSION", 0x00, b"flags decide this is a file"),
+        ]);
+
+        let mut iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+
+        assert_eq!(iso_file.read_file("MOVIE.PSS;1").unwrap(), b"first extent second extent".to_vec());
+        assert_eq!(iso_file.read_file("NOVERSION").unwrap(), b"flags decide this is a file".to_vec());
+    }
+
+    #[test]
+    fn test_dual_layer_detection() {
+        let mut image = create_mock_iso(&[(b"LAYER0.BIN;1", 0x00, b"layer 0")]);
+        let layer1_start = image.len() as u64 / SECTOR_SIZE;
+        image.extend(create_mock_iso(&[(b"LAYER1.BIN;1", 0x00, b"layer 1")]));
+
+        let iso_file = ISOFileReader::from_reader(Cursor::new(image)).unwrap();
+
+        assert_eq!(iso_file.layer_break(), Some(layer1_start));
+        assert_eq!(iso_file.layers.iter().map(|layer| layer.sector_count).collect::<Vec<u64>>(), [layer1_start, layer1_start]);
+    }
+}
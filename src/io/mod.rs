@@ -1,6 +1,27 @@
 mod iso_file_reader;
+mod iso_file;
+mod iso_builder;
 mod bios_file_reader;
+mod bios_database;
+mod system_cnf;
+mod block_device;
+mod cue_image;
+mod ciso_image;
+mod chd_image;
+mod elf_parser;
+mod irx_module;
 mod utils;
+mod image_error;
 
 pub use iso_file_reader::ISOFileReader;
-pub use bios_file_reader::BiosFileReader;
\ No newline at end of file
+pub use image_error::ImageError;
+pub use iso_builder::IsoBuilder;
+pub use iso_file::{FileMetadata, RecordingDate};
+pub use bios_file_reader::BiosFileReader;
+pub use bios_database::{BiosDumpStatus, BiosHashList};
+pub use block_device::{BlockDevice, TrackType};
+pub use cue_image::BinCueImage;
+pub use ciso_image::CisoImage;
+pub use chd_image::ChdImage;
+pub use irx_module::{IopLibraryRegistry, IrxExport, IrxImport, IrxModule, LoadedModule};
+pub use utils::create_io_error;
@@ -0,0 +1,167 @@
+use std::error::Error;
+use std::fmt;
+use super::elf_parser::ElfFile;
+use super::image_error::ImageError;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VideoMode {
+    Ntsc,
+    Pal,
+}
+
+//Written the way SYSTEM.CNF spells them
+impl fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoMode::Ntsc => write!(f, "NTSC"),
+            VideoMode::Pal => write!(f, "PAL"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemCnf {
+    //PS2 discs boot through BOOT2, PS1 discs use BOOT
+    pub boot2: Option<String>,
+    pub boot: Option<String>,
+    pub version: Option<String>,
+    pub video_mode: Option<VideoMode>,
+    pub hdd_unit_power: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BootExecutable {
+    pub path: String,
+    pub serial: Option<String>,
+    pub video_mode: Option<VideoMode>,
+    pub elf: Vec<u8>,
+}
+
+impl BootExecutable {
+    //Where the EE starts running it
+    pub fn entry(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(ElfFile::parse(&self.elf)?.header.entry)
+    }
+}
+
+impl SystemCnf {
+    pub fn parse(data: &[u8]) -> Result<SystemCnf, ImageError> {
+        let mut system_cnf = SystemCnf::default();
+
+        //Lines are KEY = VALUE, usually with \r\n endings and sometimes trailing garbage after a null
+        let text = String::from_utf8_lossy(data);
+        let text = text.split('\u{0}').next().unwrap_or_default();
+
+        for line in text.lines() {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim().to_uppercase();
+            let value = match parts.next() {
+                Some(value) => value.trim().to_string(),
+                None => continue,
+            };
+
+            match key.as_str() {
+                "BOOT2" => system_cnf.boot2 = Some(value),
+                "BOOT" => system_cnf.boot = Some(value),
+                "VER" => system_cnf.version = Some(value),
+                "VMODE" => system_cnf.video_mode = match value.to_uppercase().as_str() {
+                    "NTSC" => Some(VideoMode::Ntsc),
+                    "PAL" => Some(VideoMode::Pal),
+                    _ => None,
+                },
+                "HDDUNITPOWER" => system_cnf.hdd_unit_power = Some(value),
+                _ => {},
+            }
+        }
+
+        if system_cnf.boot2.is_none() && system_cnf.boot.is_none() {
+            return Err(ImageError::NoBootExecutable);
+        }
+
+        Ok(system_cnf)
+    }
+
+    pub fn boot_path(&self) -> Option<&String> {
+        self.boot2.as_ref().or(self.boot.as_ref())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut text = String::new();
+
+        let fields = [
+            ("BOOT2", self.boot2.clone()),
+            ("BOOT", self.boot.clone()),
+            ("VER", self.version.clone()),
+            ("VMODE", self.video_mode.map(|video_mode| video_mode.to_string())),
+            ("HDDUNITPOWER", self.hdd_unit_power.clone()),
+        ];
+
+        for (key, value) in fields.iter() {
+            if let Some(value) = value {
+                text.push_str(&format!("{} = {}\r\n", key, value));
+            }
+        }
+
+        text.into_bytes()
+    }
+}
+
+//cdrom0:\SLUS_123.45;1 -> SLUS_123.45;1
+pub fn resolve_cdrom_path(boot_path: &str) -> Result<String, ImageError> {
+    let path = match boot_path.find(':') {
+        Some(index) => {
+            let device = &boot_path[..index];
+
+            if !device.eq_ignore_ascii_case("cdrom0") && !device.eq_ignore_ascii_case("cdrom") {
+                return Err(ImageError::BootPathNotOnCdrom { path: boot_path.to_string() });
+            }
+
+            &boot_path[index + 1..]
+        },
+        None => boot_path,
+    };
+
+    let mut path = path.trim_start_matches(['\\', '/']).replace('\\', "/").to_uppercase();
+
+    if !path.contains(';') {
+        path.push_str(";1");
+    }
+
+    Ok(path)
+}
+
+//SLUS_123.45;1 -> SLUS-12345
+pub fn serial_from_boot_path(boot_path: &str) -> Option<String> {
+    let file_name = boot_path.rsplit(['\\', '/', ':']).next()?;
+    let file_name = file_name.split(';').next()?;
+
+    let mut parts = file_name.splitn(2, '_');
+    let prefix = parts.next()?;
+    let number: String = parts.next()?.chars().filter(|c| *c != '.').collect();
+
+    if prefix.len() == 4 && prefix.chars().all(|c| c.is_ascii_alphabetic()) && !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+        Some(format!("{}-{}", prefix.to_uppercase(), number))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve_cdrom_path, serial_from_boot_path, SystemCnf, VideoMode};
+
+    #[test]
+    fn test_parse_system_cnf() {
+        let data = b"BOOT2 = cdrom0:\\SLUS_123.45;1\r\nVER = 1.00\r\nVMODE = NTSC\r\n";
+
+        let system_cnf = SystemCnf::parse(data).unwrap();
+        let boot_path = system_cnf.boot_path().unwrap();
+
+        assert_eq!(boot_path, "cdrom0:\\SLUS_123.45;1");
+        assert_eq!(system_cnf.version, Some("1.00".to_string()));
+        assert_eq!(system_cnf.video_mode, Some(VideoMode::Ntsc));
+        assert_eq!(resolve_cdrom_path(boot_path).unwrap(), "SLUS_123.45;1");
+        assert_eq!(resolve_cdrom_path("cdrom0:\\DATA\\main.elf").unwrap(), "DATA/MAIN.ELF;1");
+        assert_eq!(serial_from_boot_path(boot_path), Some("SLUS-12345".to_string()));
+    }
+}
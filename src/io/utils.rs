@@ -1,21 +1,179 @@
-use std::fs::File;
 use std::error::Error;
+use std::fmt;
 use std::io::{self, Read, Seek};
+use super::image_error::ImageError;
 
 pub fn create_io_error(kind: io::ErrorKind, error: &str) -> Box<io::Error> {
     Box::new(io::Error::new(kind, error))
 }
 
-pub fn read_bytes(file: &mut File, offset: u64, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+//read can return less than asked for without being at the end of the file so keep going until it's full
+//Lengths usually come from the image itself so they're checked against its size before anything is allocated
+pub fn read_bytes<R: Read + Seek>(file: &mut R, offset: u64, length: usize) -> Result<Vec<u8>, ImageError> {
+    let size = file.seek(io::SeekFrom::End(0))?;
+
+    if offset.checked_add(length as u64).is_none_or(|end| end > size) {
+        return Err(ImageError::Truncated { offset, len: length });
+    }
+
     file.seek(io::SeekFrom::Start(offset))?;
 
     let mut data: Vec<u8> = vec![0; length];
-    
-    let bytes_read = file.read(&mut data)?;
 
-    if bytes_read == length {
-        Ok(data)
-    } else {
-        Err(create_io_error(io::ErrorKind::UnexpectedEof, "could not read full length of bytes!"))
+    match file.read_exact(&mut data) {
+        Ok(()) => Ok(data),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Err(ImageError::Truncated { offset, len: length }),
+        Err(error) => Err(ImageError::from(error)),
+    }
+}
+
+//Which field of which structure ran past the end of the data
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub structure: &'static str,
+    pub field: &'static str,
+    //Offset in the file the field starts at
+    pub offset: u64,
+    pub length: usize,
+    pub available: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} at offset {} needs {} bytes but only {} are left", self.structure, self.field, self.offset, self.length, self.available)
+    }
+}
+
+impl Error for ParseError {}
+
+//Bounds checked cursor over a byte slice, every read names the field so a bad image says what broke
+//Running off the end is reported as ImageError::Malformed
+#[derive(Clone, Debug)]
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    structure: &'static str,
+    //Where data starts in the file, only used for errors
+    base_offset: u64,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(structure: &'static str, data: &'a [u8], base_offset: u64) -> ByteReader<'a> {
+        ByteReader {
+            data,
+            position: 0,
+            structure,
+            base_offset,
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn bytes(&mut self, field: &'static str, length: usize) -> Result<&'a [u8], ImageError> {
+        if length > self.remaining() {
+            return Err(ImageError::Malformed(ParseError {
+                structure: self.structure,
+                field,
+                offset: self.base_offset + self.position as u64,
+                length,
+                available: self.remaining(),
+            }));
+        }
+
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], ImageError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(field, N)?);
+
+        Ok(array)
+    }
+
+    pub fn u8(&mut self, field: &'static str) -> Result<u8, ImageError> {
+        Ok(self.array::<1>(field)?[0])
+    }
+
+    pub fn u16_le(&mut self, field: &'static str) -> Result<u16, ImageError> {
+        Ok(u16::from_le_bytes(self.array(field)?))
     }
-}
\ No newline at end of file
+
+    pub fn u16_be(&mut self, field: &'static str) -> Result<u16, ImageError> {
+        Ok(u16::from_be_bytes(self.array(field)?))
+    }
+
+    pub fn u32_le(&mut self, field: &'static str) -> Result<u32, ImageError> {
+        Ok(u32::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn u32_be(&mut self, field: &'static str) -> Result<u32, ImageError> {
+        Ok(u32::from_be_bytes(self.array(field)?))
+    }
+
+    pub fn u64_le(&mut self, field: &'static str) -> Result<u64, ImageError> {
+        Ok(u64::from_le_bytes(self.array(field)?))
+    }
+
+    pub fn u64_be(&mut self, field: &'static str) -> Result<u64, ImageError> {
+        Ok(u64::from_be_bytes(self.array(field)?))
+    }
+
+    //ISO9660 both-endian fields store the little endian value then the big endian one, returned as (le, be)
+    pub fn bi_u16(&mut self, field: &'static str) -> Result<(u16, u16), ImageError> {
+        Ok((self.u16_le(field)?, self.u16_be(field)?))
+    }
+
+    pub fn bi_u32(&mut self, field: &'static str) -> Result<(u32, u32), ImageError> {
+        Ok((self.u32_le(field)?, self.u32_be(field)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Cursor, Read, Seek};
+    use super::{read_bytes, ByteReader, ParseError};
+    use super::super::image_error::ImageError;
+
+    //Hands out one byte per read like a pipe or a slow network file would
+    struct TrickleReader(Cursor<Vec<u8>>);
+
+    impl Read for TrickleReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf.len().min(1);
+            self.0.read(&mut buf[..length])
+        }
+    }
+
+    impl Seek for TrickleReader {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_byte_reader() {
+        let data = [0x34, 0x12, 0x12, 0x34, 0x78, 0x56, 0x34, 0x12, 0x12, 0x34, 0x56, 0x78, 0xff];
+        let mut reader = ByteReader::new("Test", &data, 100);
+
+        assert_eq!(reader.bi_u16("short").unwrap(), (0x1234, 0x1234));
+        assert_eq!(reader.bi_u32("long").unwrap(), (0x12345678, 0x12345678));
+        assert_eq!(reader.u8("byte").unwrap(), 0xff);
+        assert_eq!(reader.u16_le("past_end"), Err(ImageError::Malformed(ParseError {
+            structure: "Test",
+            field: "past_end",
+            offset: 113,
+            length: 2,
+            available: 0,
+        })));
+
+        let mut file = TrickleReader(Cursor::new(data.to_vec()));
+        assert_eq!(read_bytes(&mut file, 2, 10).unwrap(), data[2..12]);
+        assert_eq!(read_bytes(&mut file, 8, 10).unwrap_err(), ImageError::Truncated { offset: 8, len: 10 });
+        assert_eq!(read_bytes(&mut file, 4, usize::MAX).unwrap_err(), ImageError::Truncated { offset: 4, len: usize::MAX });
+    }
+}
@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::dma::IopDma;
+use super::intc::Intc;
+use super::timers::RootCounters;
+use crate::sif::{SharedSif, IOP_SIF_BASE};
+
+pub const IOP_RAM_SIZE: usize = 2 * 1024 * 1024;
+const SCRATCHPAD_SIZE: usize = 1024;
+const HARDWARE_REGISTERS_SIZE: usize = 64 * 1024;
+const BIOS_SIZE: usize = 4 * 1024 * 1024;
+
+//IOP RAM is also mapped into the EE address space at 0x1C000000 so both sides hold a handle to it
+pub type IopRam = Rc<RefCell<Box<[u8]>>>;
+
+//KUSEG and KSEG2 aren't translated, KSEG0 and KSEG1 mirror the bottom 512MiB
+const SEGMENT_MASKS: [u32; 8] = [
+    0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff,
+    0x7fffffff, 0x1fffffff,
+    0xffffffff, 0xffffffff,
+];
+
+enum IopAddressLocation {
+    Ram(usize),
+    Scratchpad(usize),
+    //Carries the physical address, devices are matched on it before falling back to the byte array
+    HardwareRegisters(u32),
+    Sif(u32),
+    Bios(usize),
+    CacheControl,
+}
+
+pub struct IopBus {
+    ram: IopRam,
+    scratchpad: Box<[u8]>,
+    //Everything in 0x1F800000-0x1F80FFFF that isn't scratchpad, read back as written until a device claims it
+    hardware_registers: Box<[u8]>,
+    bios: Box<[u8]>,
+    cache_control: u32,
+    sif: SharedSif,
+    pub intc: Intc,
+    pub dma: IopDma,
+    pub timers: RootCounters,
+}
+
+pub fn create_iop_ram() -> IopRam {
+    Rc::new(RefCell::new(vec![0; IOP_RAM_SIZE].into_boxed_slice()))
+}
+
+fn translate_address(address: u32) -> Option<IopAddressLocation> {
+    let physical = address & SEGMENT_MASKS[(address >> 29) as usize];
+
+    match physical {
+        //2MiB of RAM mirrored over the first 8MiB
+        0x00000000..=0x007FFFFF => Some(IopAddressLocation::Ram(physical as usize % IOP_RAM_SIZE)),
+        0x1F800000..=0x1F8003FF => Some(IopAddressLocation::Scratchpad((physical - 0x1F800000) as usize)),
+        0x1F801000..=0x1F80FFFF => Some(IopAddressLocation::HardwareRegisters(physical)),
+        0x1D000000..=0x1D00006F => Some(IopAddressLocation::Sif(physical - IOP_SIF_BASE)),
+        0x1FC00000..=0x1FFFFFFF => Some(IopAddressLocation::Bios((physical - 0x1FC00000) as usize)),
+        0xFFFE0130..=0xFFFE0133 => Some(IopAddressLocation::CacheControl),
+        _ => None,
+    }
+}
+
+fn read_le(memory: &[u8], offset: usize, length: usize) -> u32 {
+    memory[offset..offset + length].iter().rev().fold(0, |value, byte| value << 8 | *byte as u32)
+}
+
+fn write_le(memory: &mut [u8], offset: usize, length: usize, value: u32) {
+    for (index, byte) in memory[offset..offset + length].iter_mut().enumerate() {
+        *byte = (value >> (index * 8)) as u8;
+    }
+}
+
+impl IopBus {
+    pub fn new(bios: &[u8], ram: IopRam, sif: SharedSif) -> IopBus {
+        let mut bios_rom = vec![0; BIOS_SIZE];
+        let length = bios.len().min(BIOS_SIZE);
+        bios_rom[..length].copy_from_slice(&bios[..length]);
+
+        IopBus {
+            dma: IopDma::new(ram.clone(), sif.clone()),
+            ram,
+            scratchpad: vec![0; SCRATCHPAD_SIZE].into_boxed_slice(),
+            hardware_registers: vec![0; HARDWARE_REGISTERS_SIZE].into_boxed_slice(),
+            bios: bios_rom.into_boxed_slice(),
+            cache_control: 0,
+            sif,
+            intc: Intc::new(),
+            timers: RootCounters::new(),
+        }
+    }
+
+    pub fn ram(&self) -> IopRam {
+        self.ram.clone()
+    }
+
+    //Devices are only 32 bits wide, narrower accesses pick out or update part of the register
+    fn read_hardware(&mut self, address: u32, length: usize) -> u32 {
+        let register = address & !3;
+        let shift = (address & 3) * 8;
+
+        let value = match register {
+            0x1F801070..=0x1F80107F => self.intc.read(register),
+            0x1F801080..=0x1F8010FF | 0x1F801500..=0x1F80157F => self.dma.read(register),
+            0x1F801100..=0x1F80112F | 0x1F801480..=0x1F8014AF => self.timers.read(register),
+            _ => return read_le(&self.hardware_registers, (address - 0x1F800000) as usize, length),
+        };
+
+        value >> shift
+    }
+
+    fn write_hardware(&mut self, address: u32, length: usize, value: u32) {
+        let register = address & !3;
+        let shift = (address & 3) * 8;
+        let write_mask = (u64::MAX >> (64 - length * 8)) as u32;
+        let (value, write_mask) = (value << shift, write_mask << shift);
+
+        match register {
+            0x1F801070..=0x1F80107F => self.intc.write(register, value, write_mask),
+            0x1F801080..=0x1F8010FF | 0x1F801500..=0x1F80157F => self.dma.write(register, value, write_mask, &mut self.intc),
+            0x1F801100..=0x1F80112F | 0x1F801480..=0x1F8014AF => self.timers.write(register, value, write_mask),
+            _ => write_le(&mut self.hardware_registers, (address - 0x1F800000) as usize, length, value >> shift),
+        }
+    }
+
+    //Called once per IOP cycle to move the counters along
+    pub fn tick(&mut self, cycles: u32) {
+        self.timers.tick(cycles, &mut self.intc);
+    }
+
+    //Lets SIF transfers that were waiting on the EE carry on
+    pub fn run_dma(&mut self) {
+        self.dma.run_pending(&mut self.intc);
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.intc.pending()
+    }
+
+    //Callers are expected to have checked alignment, None means nothing is mapped there
+    fn read(&mut self, address: u32, length: usize) -> Option<u32> {
+        Some(match translate_address(address)? {
+            IopAddressLocation::Ram(offset) => read_le(&self.ram.borrow(), offset, length),
+            IopAddressLocation::Scratchpad(offset) => read_le(&self.scratchpad, offset, length),
+            IopAddressLocation::HardwareRegisters(address) => self.read_hardware(address, length),
+            IopAddressLocation::Sif(register) => self.sif.borrow().read(register & !3) >> ((register & 3) * 8),
+            IopAddressLocation::Bios(offset) => read_le(&self.bios, offset, length),
+            IopAddressLocation::CacheControl => self.cache_control,
+        })
+    }
+
+    fn write(&mut self, address: u32, length: usize, value: u32) -> Option<()> {
+        match translate_address(address)? {
+            IopAddressLocation::Ram(offset) => write_le(&mut self.ram.borrow_mut(), offset, length, value),
+            IopAddressLocation::Scratchpad(offset) => write_le(&mut self.scratchpad, offset, length, value),
+            IopAddressLocation::HardwareRegisters(address) => self.write_hardware(address, length, value),
+            IopAddressLocation::Sif(register) => self.sif.borrow_mut().write_iop(register & !3, value << ((register & 3) * 8)),
+            //Writes to ROM are dropped
+            IopAddressLocation::Bios(_) => {},
+            IopAddressLocation::CacheControl => self.cache_control = value,
+        }
+
+        Some(())
+    }
+
+    pub fn read8(&mut self, address: u32) -> Option<u8> {
+        self.read(address, 1).map(|value| value as u8)
+    }
+
+    pub fn read16(&mut self, address: u32) -> Option<u16> {
+        self.read(address, 2).map(|value| value as u16)
+    }
+
+    pub fn read32(&mut self, address: u32) -> Option<u32> {
+        self.read(address, 4)
+    }
+
+    pub fn write8(&mut self, address: u32, value: u8) -> Option<()> {
+        self.write(address, 1, value as u32)
+    }
+
+    pub fn write16(&mut self, address: u32, value: u16) -> Option<()> {
+        self.write(address, 2, value as u32)
+    }
+
+    pub fn write32(&mut self, address: u32, value: u32) -> Option<()> {
+        self.write(address, 4, value)
+    }
+}
@@ -0,0 +1,146 @@
+pub const COP0_BADVADDR: u8 = 8;
+pub const COP0_SR: u8 = 12;
+pub const COP0_CAUSE: u8 = 13;
+pub const COP0_EPC: u8 = 14;
+pub const COP0_PRID: u8 = 15;
+
+//Same revision PCSX2 reports for the IOP
+const PRID_VALUE: u32 = 0x1f;
+
+const SR_IEC: u32 = 1 << 0;
+const SR_ISC: u32 = 1 << 16;
+const SR_BEV: u32 = 1 << 22;
+const CAUSE_BD: u32 = 1 << 31;
+//Only the two software interrupt bits in Cause can be written
+const CAUSE_WRITE_MASK: u32 = 0x300;
+//INTC is wired to hardware interrupt 0 which is bit 10 of Cause
+const CAUSE_INTC_PENDING: u32 = 1 << 10;
+
+const GENERAL_VECTOR: u32 = 0x80000080;
+const BOOT_GENERAL_VECTOR: u32 = 0xBFC00180;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IopException {
+    Interrupt,
+    AddressErrorLoad,
+    AddressErrorStore,
+    BusErrorInstruction,
+    BusErrorData,
+    Syscall,
+    Breakpoint,
+    ReservedInstruction,
+    CoprocessorUnusable(u8),
+    Overflow,
+}
+
+impl IopException {
+    pub fn code(&self) -> u32 {
+        match self {
+            IopException::Interrupt => 0,
+            IopException::AddressErrorLoad => 4,
+            IopException::AddressErrorStore => 5,
+            IopException::BusErrorInstruction => 6,
+            IopException::BusErrorData => 7,
+            IopException::Syscall => 8,
+            IopException::Breakpoint => 9,
+            IopException::ReservedInstruction => 10,
+            IopException::CoprocessorUnusable(_) => 11,
+            IopException::Overflow => 12,
+        }
+    }
+}
+
+//The R3000A only has the exception side of COP0, there is no TLB on the IOP
+pub struct Cop0 {
+    registers: [u32; 32],
+}
+
+impl Cop0 {
+    pub fn new() -> Cop0 {
+        let mut registers = [0; 32];
+        registers[COP0_SR as usize] = SR_BEV;
+        registers[COP0_PRID as usize] = PRID_VALUE;
+
+        Cop0 {
+            registers,
+        }
+    }
+
+    pub fn read(&self, register: u8) -> u32 {
+        self.registers[register as usize]
+    }
+
+    pub fn write(&mut self, register: u8, value: u32) {
+        match register {
+            COP0_CAUSE => {
+                let cause = &mut self.registers[COP0_CAUSE as usize];
+                *cause = (*cause & !CAUSE_WRITE_MASK) | (value & CAUSE_WRITE_MASK);
+            },
+            COP0_PRID | COP0_BADVADDR => {},
+            _ => self.registers[register as usize] = value,
+        }
+    }
+
+    pub fn sr(&self) -> u32 {
+        self.registers[COP0_SR as usize]
+    }
+
+    pub fn cause(&self) -> u32 {
+        self.registers[COP0_CAUSE as usize]
+    }
+
+    pub fn epc(&self) -> u32 {
+        self.registers[COP0_EPC as usize]
+    }
+
+    pub fn set_bad_vaddr(&mut self, address: u32) {
+        self.registers[COP0_BADVADDR as usize] = address;
+    }
+
+    //The BIOS isolates the cache to clear it, stores while it's set never reach memory
+    pub fn is_cache_isolated(&self) -> bool {
+        self.sr() & SR_ISC != 0
+    }
+
+    pub fn set_intc_pending(&mut self, pending: bool) {
+        let cause = &mut self.registers[COP0_CAUSE as usize];
+
+        if pending {
+            *cause |= CAUSE_INTC_PENDING;
+        } else {
+            *cause &= !CAUSE_INTC_PENDING;
+        }
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.sr() & SR_IEC != 0 && self.sr() & self.cause() & 0xff00 != 0
+    }
+
+    //Pushes the interrupt enable/kernel mode stack and returns the vector to jump to
+    pub fn enter_exception(&mut self, exception: IopException, pc: u32, in_delay_slot: bool) -> u32 {
+        let sr = self.sr();
+        self.registers[COP0_SR as usize] = (sr & !0x3f) | ((sr << 2) & 0x3f);
+
+        let coprocessor = match exception {
+            IopException::CoprocessorUnusable(coprocessor) => coprocessor as u32,
+            _ => 0,
+        };
+
+        let cause = self.cause() & (0xff00 | CAUSE_WRITE_MASK);
+        self.registers[COP0_CAUSE as usize] = cause | exception.code() << 2 | coprocessor << 28 | if in_delay_slot { CAUSE_BD } else { 0 };
+
+        //EPC points at the branch so the whole thing is run again
+        self.registers[COP0_EPC as usize] = if in_delay_slot { pc.wrapping_sub(4) } else { pc };
+
+        if sr & SR_BEV != 0 {
+            BOOT_GENERAL_VECTOR
+        } else {
+            GENERAL_VECTOR
+        }
+    }
+
+    pub fn return_from_exception(&mut self) {
+        let sr = self.sr();
+        self.registers[COP0_SR as usize] = (sr & !0xf) | ((sr >> 2) & 0xf);
+    }
+}
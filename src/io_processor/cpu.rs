@@ -0,0 +1,458 @@
+use super::bus::{IopBus, IopRam};
+use super::cop0::{Cop0, IopException};
+use super::decoder::decode_instruction;
+use crate::mips::Instruction;
+use crate::sif::SharedSif;
+
+//Same reset vector as the EE, both start out running the BIOS
+pub const IOP_RESET_VECTOR: u32 = 0xBFC00000;
+
+pub const V0_REG: u8 = 2;
+pub const A0_REG: u8 = 4;
+pub const GP_REG: u8 = 28;
+pub const SP_REG: u8 = 29;
+pub const FP_REG: u8 = 30;
+pub const RA_REG: u8 = 31;
+
+//SYSCALL codes from here up are calls into the HLE kernel rather than exceptions
+pub const HLE_SYSCALL_BASE: u32 = 0x80000;
+
+pub struct IopCpu {
+    registers: [u32; 32],
+    //Instructions write here so a load landing this cycle can still be overwritten, copied back after each step
+    out_registers: [u32; 32],
+    //Loads only show up after the next instruction has run
+    pending_load: (u8, u32),
+    pub pc: u32,
+    next_pc: u32,
+    //Address of the instruction being executed, used for EPC
+    current_pc: u32,
+    is_branch: bool,
+    in_delay_slot: bool,
+    pub hi: u32,
+    pub lo: u32,
+    pub cop0: Cop0,
+    pub bus: IopBus,
+    pub cycles: u64,
+    //Set by an HLE syscall, whoever is running the IOP handles it and puts pc back at $ra
+    pub hle_call: Option<u32>,
+}
+
+//Offsets and immediates are sign extended unless the instruction says otherwise
+#[inline(always)]
+fn sign_extend(value: u16) -> u32 {
+    value as i16 as u32
+}
+
+
+impl IopCpu {
+    pub fn new(bios: &[u8], ram: IopRam, sif: SharedSif) -> IopCpu {
+        IopCpu {
+            registers: [0; 32],
+            out_registers: [0; 32],
+            pending_load: (0, 0),
+            pc: IOP_RESET_VECTOR,
+            next_pc: IOP_RESET_VECTOR.wrapping_add(4),
+            current_pc: IOP_RESET_VECTOR,
+            is_branch: false,
+            in_delay_slot: false,
+            hi: 0,
+            lo: 0,
+            cop0: Cop0::new(),
+            bus: IopBus::new(bios, ram, sif),
+            cycles: 0,
+            hle_call: None,
+        }
+    }
+
+    pub fn read_register(&self, register: u8) -> u32 {
+        self.registers[register as usize]
+    }
+
+    pub fn write_register(&mut self, register: u8, value: u32) {
+        if register != 0 {
+            self.registers[register as usize] = value;
+            self.out_registers[register as usize] = value;
+        }
+    }
+
+    //Moves execution somewhere else, dropping any branch that was in flight
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+        self.next_pc = pc.wrapping_add(4);
+        self.is_branch = false;
+    }
+
+    //Nothing half done, a branch or load in flight would be lost if execution moved somewhere else now
+    pub fn at_instruction_boundary(&self) -> bool {
+        !self.is_branch && self.pending_load.0 == 0
+    }
+
+    //Time passes with nothing to run, devices still tick
+    pub fn idle(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.bus.tick(cycles as u32);
+    }
+
+    #[inline(always)]
+    fn reg(&self, register: u8) -> u32 {
+        self.registers[register as usize]
+    }
+
+    #[inline(always)]
+    fn set_reg(&mut self, register: u8, value: u32) {
+        self.out_registers[register as usize] = value;
+        self.out_registers[0] = 0;
+    }
+
+    #[inline(always)]
+    fn delayed_load(&mut self, register: u8, value: u32) {
+        self.pending_load = (register, value);
+    }
+
+    fn exception(&mut self, exception: IopException) {
+        let vector = self.cop0.enter_exception(exception, self.current_pc, self.in_delay_slot);
+
+        self.pc = vector;
+        self.next_pc = vector.wrapping_add(4);
+    }
+
+    fn branch(&mut self, condition: bool, offset: u16) {
+        self.is_branch = true;
+
+        //pc already points at the delay slot
+        if condition {
+            self.next_pc = self.pc.wrapping_add(sign_extend(offset) << 2);
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.is_branch = true;
+        self.next_pc = target;
+    }
+
+    pub fn run(&mut self, cycles: u64) {
+        let target = self.cycles + cycles;
+
+        while self.cycles < target {
+            self.step();
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.current_pc = self.pc;
+        self.in_delay_slot = self.is_branch;
+        self.is_branch = false;
+
+        let (load_register, load_value) = self.pending_load;
+        self.pending_load = (0, 0);
+        self.out_registers = self.registers;
+        self.set_reg(load_register, load_value);
+
+        self.cycles += 1;
+        self.bus.tick(1);
+        self.cop0.set_intc_pending(self.bus.interrupt_pending());
+
+        if self.cop0.interrupt_pending() {
+            self.exception(IopException::Interrupt);
+        } else if !self.current_pc.is_multiple_of(4) {
+            self.cop0.set_bad_vaddr(self.current_pc);
+            self.exception(IopException::AddressErrorLoad);
+        } else {
+            match self.bus.read32(self.current_pc) {
+                Some(word) => {
+                    self.pc = self.next_pc;
+                    self.next_pc = self.pc.wrapping_add(4);
+
+                    match decode_instruction(word) {
+                        Ok(instruction) => self.execute(instruction),
+                        Err(exception) => self.exception(exception),
+                    }
+                },
+                None => self.exception(IopException::BusErrorInstruction),
+            }
+        }
+
+        self.registers = self.out_registers;
+    }
+
+    fn execute(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::SLL { rt, rd, sa } => self.set_reg(rd, self.reg(rt) << sa),
+            Instruction::SRL { rt, rd, sa } => self.set_reg(rd, self.reg(rt) >> sa),
+            Instruction::SRA { rt, rd, sa } => self.set_reg(rd, ((self.reg(rt) as i32) >> sa) as u32),
+            Instruction::SLLV { rd, rt, rs } => self.set_reg(rd, self.reg(rt) << (self.reg(rs) & 0x1f)),
+            Instruction::SRLV { rd, rt, rs } => self.set_reg(rd, self.reg(rt) >> (self.reg(rs) & 0x1f)),
+            Instruction::SRAV { rd, rt, rs } => self.set_reg(rd, ((self.reg(rt) as i32) >> (self.reg(rs) & 0x1f)) as u32),
+            Instruction::JR { rs } => self.jump(self.reg(rs)),
+            Instruction::JALR { rd, rs } => {
+                let target = self.reg(rs);
+                self.set_reg(rd, self.next_pc);
+                self.jump(target);
+            },
+            Instruction::SYSCALL { code } if code >= HLE_SYSCALL_BASE => self.hle_call = Some(code - HLE_SYSCALL_BASE),
+            Instruction::SYSCALL { .. } => self.exception(IopException::Syscall),
+            Instruction::BREAK { .. } => self.exception(IopException::Breakpoint),
+            Instruction::MFHI { rd } => self.set_reg(rd, self.hi),
+            Instruction::MTHI { rs } => self.hi = self.reg(rs),
+            Instruction::MFLO { rd } => self.set_reg(rd, self.lo),
+            Instruction::MTLO { rs } => self.lo = self.reg(rs),
+            Instruction::MULT { rs, rt, .. } => {
+                let result = (self.reg(rs) as i32 as i64) * (self.reg(rt) as i32 as i64);
+                self.hi = (result >> 32) as u32;
+                self.lo = result as u32;
+            },
+            Instruction::MULTU { rs, rt, .. } => {
+                let result = (self.reg(rs) as u64) * (self.reg(rt) as u64);
+                self.hi = (result >> 32) as u32;
+                self.lo = result as u32;
+            },
+            Instruction::DIV { rs, rt } => {
+                let (numerator, denominator) = (self.reg(rs) as i32, self.reg(rt) as i32);
+
+                //Division by zero and overflow don't trap, they leave these values behind
+                if denominator == 0 {
+                    self.hi = numerator as u32;
+                    self.lo = if numerator >= 0 { 0xffffffff } else { 1 };
+                } else if numerator == i32::MIN && denominator == -1 {
+                    self.hi = 0;
+                    self.lo = i32::MIN as u32;
+                } else {
+                    self.hi = (numerator % denominator) as u32;
+                    self.lo = (numerator / denominator) as u32;
+                }
+            },
+            Instruction::DIVU { rs, rt } => {
+                let (numerator, denominator) = (self.reg(rs), self.reg(rt));
+
+                if denominator == 0 {
+                    self.hi = numerator;
+                    self.lo = 0xffffffff;
+                } else {
+                    self.hi = numerator % denominator;
+                    self.lo = numerator / denominator;
+                }
+            },
+            Instruction::ADD { rd, rs, rt } => match (self.reg(rs) as i32).checked_add(self.reg(rt) as i32) {
+                Some(value) => self.set_reg(rd, value as u32),
+                None => self.exception(IopException::Overflow),
+            },
+            Instruction::ADDU { rd, rs, rt } => self.set_reg(rd, self.reg(rs).wrapping_add(self.reg(rt))),
+            Instruction::SUB { rd, rt, rs } => match (self.reg(rs) as i32).checked_sub(self.reg(rt) as i32) {
+                Some(value) => self.set_reg(rd, value as u32),
+                None => self.exception(IopException::Overflow),
+            },
+            Instruction::SUBU { rd, rt, rs } => self.set_reg(rd, self.reg(rs).wrapping_sub(self.reg(rt))),
+            Instruction::AND { rd, rs, rt } => self.set_reg(rd, self.reg(rs) & self.reg(rt)),
+            Instruction::OR { rd, rs, rt } => self.set_reg(rd, self.reg(rs) | self.reg(rt)),
+            Instruction::XOR { rd, rs, rt } => self.set_reg(rd, self.reg(rs) ^ self.reg(rt)),
+            Instruction::NOR { rd, rs, rt } => self.set_reg(rd, !(self.reg(rs) | self.reg(rt))),
+            Instruction::SLT { rd, rt, rs } => self.set_reg(rd, ((self.reg(rs) as i32) < self.reg(rt) as i32) as u32),
+            Instruction::SLTU { rd, rt, rs } => self.set_reg(rd, (self.reg(rs) < self.reg(rt)) as u32),
+            Instruction::BLTZ { rs, off } => self.branch((self.reg(rs) as i32) < 0, off),
+            Instruction::BGEZ { rs, off } => self.branch(self.reg(rs) as i32 >= 0, off),
+            //Linking happens even if the branch isn't taken
+            Instruction::BLTZAL { rs, off } => {
+                let condition = (self.reg(rs) as i32) < 0;
+                self.set_reg(RA_REG, self.next_pc);
+                self.branch(condition, off);
+            },
+            Instruction::BGEZAL { rs, off } => {
+                let condition = self.reg(rs) as i32 >= 0;
+                self.set_reg(RA_REG, self.next_pc);
+                self.branch(condition, off);
+            },
+            Instruction::J { off } => self.jump((self.pc & 0xf0000000) | off << 2),
+            Instruction::JAL { off } => {
+                self.set_reg(RA_REG, self.next_pc);
+                self.jump((self.pc & 0xf0000000) | off << 2);
+            },
+            Instruction::BEQ { rs, rt, off } => self.branch(self.reg(rs) == self.reg(rt), off),
+            Instruction::BNE { rs, rt, off } => self.branch(self.reg(rs) != self.reg(rt), off),
+            Instruction::BLEZ { rs, off } => self.branch(self.reg(rs) as i32 <= 0, off),
+            Instruction::BGTZ { rs, off } => self.branch(self.reg(rs) as i32 > 0, off),
+            Instruction::ADDI { rt, rs, imm } => match (self.reg(rs) as i32).checked_add(sign_extend(imm) as i32) {
+                Some(value) => self.set_reg(rt, value as u32),
+                None => self.exception(IopException::Overflow),
+            },
+            Instruction::ADDIU { rt, rs, imm } => self.set_reg(rt, self.reg(rs).wrapping_add(imm as u32)),
+            Instruction::SLTI { rt, rs, imm } => self.set_reg(rt, ((self.reg(rs) as i32) < imm as i32) as u32),
+            Instruction::SLTIU { rt, rs, imm } => self.set_reg(rt, (self.reg(rs) < sign_extend(imm)) as u32),
+            Instruction::ANDI { rt, rs, imm } => self.set_reg(rt, self.reg(rs) & imm as u32),
+            Instruction::ORI { rt, rs, imm } => self.set_reg(rt, self.reg(rs) | imm as u32),
+            Instruction::XORI { rt, rs, imm } => self.set_reg(rt, self.reg(rs) ^ imm as u32),
+            Instruction::LUI { rt, imm } => self.set_reg(rt, (imm as u32) << 16),
+            //MFC0 has a load delay just like a memory load
+            Instruction::MFC0 { rt, rd } => self.delayed_load(rt, self.cop0.read(rd)),
+            Instruction::MTC0 { rt, rd } => self.cop0.write(rd, self.reg(rt)),
+            Instruction::RFE => self.cop0.return_from_exception(),
+            Instruction::LB { rt, off, base } => self.load(rt, base, off, 1, |bus, address| bus.read8(address).map(|value| value as i8 as u32)),
+            Instruction::LH { rt, off, base } => self.load(rt, base, off, 2, |bus, address| bus.read16(address).map(|value| value as i16 as u32)),
+            Instruction::LW { rt, off, base } => self.load(rt, base, off, 4, |bus, address| bus.read32(address)),
+            Instruction::LBU { rt, off, base } => self.load(rt, base, off, 1, |bus, address| bus.read8(address).map(|value| value as u32)),
+            Instruction::LHU { rt, off, base } => self.load(rt, base, off, 2, |bus, address| bus.read16(address).map(|value| value as u32)),
+            //LWL/LWR merge with whatever is in rt including a load still in the delay slot
+            Instruction::LWL { rt, off, base } => {
+                let current = self.out_registers[rt as usize];
+
+                self.load(rt, base, off, 1, |bus, address| bus.read32(address & !3).map(|word| match address & 3 {
+                    0 => (current & 0x00ffffff) | (word << 24),
+                    1 => (current & 0x0000ffff) | (word << 16),
+                    2 => (current & 0x000000ff) | (word << 8),
+                    _ => word,
+                }));
+            },
+            Instruction::LWR { rt, off, base } => {
+                let current = self.out_registers[rt as usize];
+
+                self.load(rt, base, off, 1, |bus, address| bus.read32(address & !3).map(|word| match address & 3 {
+                    0 => word,
+                    1 => (current & 0xff000000) | (word >> 8),
+                    2 => (current & 0xffff0000) | (word >> 16),
+                    _ => (current & 0xffffff00) | (word >> 24),
+                }));
+            },
+            Instruction::SB { rt, off, base } => {
+                let value = self.reg(rt);
+                self.store(base, off, 1, |bus, address| bus.write8(address, value as u8));
+            },
+            Instruction::SH { rt, off, base } => {
+                let value = self.reg(rt);
+                self.store(base, off, 2, |bus, address| bus.write16(address, value as u16));
+            },
+            Instruction::SW { rt, off, base } => {
+                let value = self.reg(rt);
+                self.store(base, off, 4, |bus, address| bus.write32(address, value));
+            },
+            Instruction::SWL { rt, off, base } => {
+                let value = self.reg(rt);
+
+                self.store(base, off, 1, |bus, address| {
+                    let word = bus.read32(address & !3)?;
+
+                    bus.write32(address & !3, match address & 3 {
+                        0 => (word & 0xffffff00) | (value >> 24),
+                        1 => (word & 0xffff0000) | (value >> 16),
+                        2 => (word & 0xff000000) | (value >> 8),
+                        _ => value,
+                    })
+                });
+            },
+            Instruction::SWR { rt, off, base } => {
+                let value = self.reg(rt);
+
+                self.store(base, off, 1, |bus, address| {
+                    let word = bus.read32(address & !3)?;
+
+                    bus.write32(address & !3, match address & 3 {
+                        0 => value,
+                        1 => (word & 0x000000ff) | (value << 8),
+                        2 => (word & 0x0000ffff) | (value << 16),
+                        _ => (word & 0x00ffffff) | (value << 24),
+                    })
+                });
+            },
+            //The R3000A layer never hands out anything else
+            _ => self.exception(IopException::ReservedInstruction),
+        }
+    }
+
+    fn address_error(&mut self, address: u32, exception: IopException) {
+        self.cop0.set_bad_vaddr(address);
+        self.exception(exception);
+    }
+
+    fn load<F: FnOnce(&mut IopBus, u32) -> Option<u32>>(&mut self, rt: u8, base: u8, off: u16, alignment: u32, read: F) {
+        let address = self.reg(base).wrapping_add(sign_extend(off));
+
+        if !address.is_multiple_of(alignment) {
+            return self.address_error(address, IopException::AddressErrorLoad);
+        }
+
+        match read(&mut self.bus, address) {
+            Some(value) => self.delayed_load(rt, value),
+            None => self.exception(IopException::BusErrorData),
+        }
+    }
+
+    fn store<F: FnOnce(&mut IopBus, u32) -> Option<()>>(&mut self, base: u8, off: u16, alignment: u32, write: F) {
+        let address = self.reg(base).wrapping_add(sign_extend(off));
+
+        if !address.is_multiple_of(alignment) {
+            return self.address_error(address, IopException::AddressErrorStore);
+        }
+
+        //The BIOS isolates the cache to clear it, stores while it's set never reach memory
+        if self.cop0.is_cache_isolated() {
+            return;
+        }
+
+        if write(&mut self.bus, address).is_none() {
+            self.exception(IopException::BusErrorData);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IopCpu;
+    use super::super::bus::create_iop_ram;
+    use super::super::cop0::COP0_SR;
+    use crate::sif::create_sif;
+
+    fn i_type(opcode: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+        opcode << 26 | rs << 21 | rt << 16 | imm as u32
+    }
+
+    fn r_type(funct: u32, rs: u32, rt: u32, rd: u32) -> u32 {
+        rs << 21 | rt << 16 | rd << 11 | funct
+    }
+
+    fn create_cpu(program: &[u32]) -> IopCpu {
+        let ram = create_iop_ram();
+
+        for (index, instruction) in program.iter().enumerate() {
+            ram.borrow_mut()[0x1000 + index * 4..0x1004 + index * 4].copy_from_slice(&instruction.to_le_bytes());
+        }
+
+        let mut cpu = IopCpu::new(&[], ram, create_sif());
+        cpu.set_pc(0x80001000);
+        cpu
+    }
+
+    #[test]
+    fn test_load_delay_and_exceptions() {
+        let mut cpu = create_cpu(&[
+            i_type(0x0d, 0, 1, 5),          //ori $1, $0, 5
+            i_type(0x2b, 0, 1, 0x100),      //sw $1, 0x100($0)
+            i_type(0x23, 0, 2, 0x100),      //lw $2, 0x100($0)
+            r_type(0x21, 2, 0, 3),          //addu $3, $2, $0 sees the old $2
+            r_type(0x21, 2, 0, 4),          //addu $4, $2, $0 sees the loaded $2
+            i_type(0x05, 1, 0, 1),          //bne $1, $0, +1
+            0x0000000c,                     //syscall in the delay slot
+            i_type(0x0d, 0, 5, 1),          //ori $5, $0, 1 is never reached
+        ]);
+
+        //Boot vectors off so exceptions go to 0x80000080
+        cpu.cop0.write(COP0_SR, 0);
+        cpu.run(7);
+
+        assert_eq!(cpu.read_register(2), 5);
+        assert_eq!(cpu.read_register(3), 0);
+        assert_eq!(cpu.read_register(4), 5);
+        assert_eq!(cpu.read_register(5), 0);
+        assert_eq!(cpu.pc, 0x80000080);
+        assert_eq!(cpu.cop0.epc(), 0x80001014);
+        assert_eq!(cpu.cop0.cause(), 0x80000000 | 8 << 2);
+
+        //A load overwritten by the instruction in its delay slot loses
+        let mut cpu = create_cpu(&[
+            i_type(0x23, 0, 1, 0x1000),     //lw $1, 0x1000($0)
+            i_type(0x0d, 0, 1, 7),          //ori $1, $0, 7
+            0,
+        ]);
+
+        cpu.run(3);
+        assert_eq!(cpu.read_register(1), 7);
+    }
+}
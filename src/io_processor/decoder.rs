@@ -0,0 +1,45 @@
+use crate::mips::Instruction;
+use crate::mips::decoder::{decode_mips_i, funct, imm, opcode, rs, rt};
+use super::cop0::IopException;
+
+//R3000A layer, MIPS I only with no 64 bit ops, no branch likely and no GTE on the PS2's IOP
+//Anything it can't run comes back as the exception the CPU should raise
+pub fn decode_instruction(word: u32) -> Result<Instruction, IopException> {
+    let opcode = opcode(word);
+
+    match opcode {
+        //Only bit 16 and whether rt is 0x10/0x11 are looked at so every rt value decodes to something
+        0x01 => {
+            let (rs, rt, off) = (rs(word), rt(word), imm(word));
+
+            Ok(match (rt & 0x1e == 0x10, rt & 1 != 0) {
+                (false, false) => Instruction::BLTZ { rs, off },
+                (false, true) => Instruction::BGEZ { rs, off },
+                (true, false) => Instruction::BLTZAL { rs, off },
+                (true, true) => Instruction::BGEZAL { rs, off },
+            })
+        },
+        0x10 if rs(word) == 0x10 && funct(word) == 0x10 => Ok(Instruction::RFE),
+        0x11..=0x13 | 0x30..=0x33 | 0x38..=0x3b => Err(IopException::CoprocessorUnusable(opcode & 3)),
+        _ => decode_mips_i(word).ok_or(IopException::ReservedInstruction),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode_instruction;
+    use crate::mips::Instruction;
+    use super::super::cop0::IopException;
+
+    #[test]
+    fn test_decode_r3000a_layer() {
+        assert_eq!(decode_instruction(0x42000010), Ok(Instruction::RFE));
+        //rt of 0x02 is BLTZL on MIPS II but the R3000A only sees a BLTZ
+        assert_eq!(decode_instruction(0x04820003), Ok(Instruction::BLTZ { rs: 4, off: 3 }));
+        assert_eq!(decode_instruction(0x04900003), Ok(Instruction::BLTZAL { rs: 4, off: 3 }));
+        //MIPS II and R5900 encodings aren't there
+        assert_eq!(decode_instruction(0x50800004), Err(IopException::ReservedInstruction));
+        assert_eq!(decode_instruction(0x0085102d), Err(IopException::ReservedInstruction));
+        assert_eq!(decode_instruction(0x4a000000), Err(IopException::CoprocessorUnusable(2)));
+    }
+}
@@ -0,0 +1,431 @@
+use super::bus::IopRam;
+use super::intc::{Intc, IopInterrupt};
+use crate::sif::{SharedSif, SIF_TAG_WORDS};
+
+//The PS1 channels sit at 0x1F801080 and the ones the PS2 added at 0x1F801500, each has MADR, BCR, CHCR and TADR
+const PS1_CHANNELS_BASE: u32 = 0x1F801080;
+const PS2_CHANNELS_BASE: u32 = 0x1F801500;
+const CHANNEL_COUNT: usize = 13;
+const PS1_CHANNEL_COUNT: usize = 7;
+
+pub const DPCR: u32 = 0x1F8010F0;
+pub const DICR: u32 = 0x1F8010F4;
+pub const DPCR2: u32 = 0x1F801570;
+pub const DICR2: u32 = 0x1F801574;
+pub const DMACEN: u32 = 0x1F801578;
+pub const DMACINTEN: u32 = 0x1F80157C;
+
+const CHCR_STEP_BACK: u32 = 1 << 1;
+const CHCR_TAG_TRANSFER: u32 = 1 << 8;
+const CHCR_START: u32 = 1 << 24;
+const CHCR_TRIGGER: u32 = 1 << 28;
+
+const DICR_FORCE: u32 = 1 << 15;
+const DICR_MASTER_ENABLE: u32 = 1 << 23;
+const DICR_MASTER_FLAG: u32 = 1 << 31;
+const DICR_WRITABLE: u32 = 0x00ff803f;
+const DICR_FLAGS: u32 = 0x7f000000;
+const DICR2_WRITABLE: u32 = 0x003f0000;
+const DICR2_FLAGS: u32 = 0x3f000000;
+
+//SIF tags put these on the address word, either one ends the transfer after its data
+const SIF_TAG_IRQ: u32 = 1 << 31;
+const SIF_TAG_END: u32 = 1 << 30;
+
+//Channel 2 was the GPU on the PS1, the PS2 reuses it for SIF2
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IopDmaChannel {
+    MdecIn = 0,
+    MdecOut = 1,
+    Sif2 = 2,
+    Cdvd = 3,
+    Spu2Core0 = 4,
+    Pio = 5,
+    Otc = 6,
+    Spu2Core1 = 7,
+    Dev9 = 8,
+    Sif0 = 9,
+    Sif1 = 10,
+    Sio2In = 11,
+    Sio2Out = 12,
+}
+
+const CHANNELS: [IopDmaChannel; CHANNEL_COUNT] = [
+    IopDmaChannel::MdecIn, IopDmaChannel::MdecOut, IopDmaChannel::Sif2, IopDmaChannel::Cdvd,
+    IopDmaChannel::Spu2Core0, IopDmaChannel::Pio, IopDmaChannel::Otc, IopDmaChannel::Spu2Core1,
+    IopDmaChannel::Dev9, IopDmaChannel::Sif0, IopDmaChannel::Sif1, IopDmaChannel::Sio2In,
+    IopDmaChannel::Sio2Out,
+];
+
+#[derive(Copy, Clone, Debug, Default)]
+struct ChannelRegisters {
+    madr: u32,
+    bcr: u32,
+    chcr: u32,
+    tadr: u32,
+    //SIF transfers are walked a tag at a time and may have to wait on the EE in the middle of one
+    words_left: u32,
+    last_tag: bool,
+}
+
+impl ChannelRegisters {
+    fn sync_mode(&self) -> u32 {
+        self.chcr >> 9 & 3
+    }
+
+    //Burst transfers wait for the trigger bit as well, the others only need start
+    fn wants_to_start(&self) -> bool {
+        self.chcr & CHCR_START != 0 && (self.sync_mode() != 0 || self.chcr & CHCR_TRIGGER != 0)
+    }
+
+    fn word_count(&self) -> u32 {
+        let block_size = self.bcr & 0xffff;
+
+        match self.sync_mode() {
+            0 if block_size == 0 => 0x10000,
+            0 => block_size,
+            1 => block_size * (self.bcr >> 16),
+            //Linked list and chain transfers are walked by the device that uses them
+            _ => 0,
+        }
+    }
+}
+
+pub struct IopDma {
+    channels: [ChannelRegisters; CHANNEL_COUNT],
+    dpcr: u32,
+    dicr: u32,
+    dpcr2: u32,
+    dicr2: u32,
+    dmacen: u32,
+    dmacinten: u32,
+    ram: IopRam,
+    sif: SharedSif,
+}
+
+fn channel_for_address(address: u32) -> Option<(usize, u32)> {
+    match address {
+        0x1F801080..=0x1F8010EF => Some((((address - PS1_CHANNELS_BASE) >> 4) as usize, address & 0xf)),
+        0x1F801500..=0x1F80155F => Some((((address - PS2_CHANNELS_BASE) >> 4) as usize + PS1_CHANNEL_COUNT, address & 0xf)),
+        _ => None,
+    }
+}
+
+fn read_word(ram: &[u8], address: usize) -> u32 {
+    let address = address % ram.len();
+    u32::from_le_bytes([ram[address], ram[address + 1], ram[address + 2], ram[address + 3]])
+}
+
+fn write_word(ram: &mut [u8], address: usize, value: u32) {
+    let address = address % ram.len();
+    ram[address..address + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn merge(current: u32, value: u32, write_mask: u32) -> u32 {
+    (current & !write_mask) | (value & write_mask)
+}
+
+//Flag bits are acknowledged by writing a 1, everything else in writable is plain read/write
+fn write_interrupt_register(current: u32, value: u32, write_mask: u32, writable: u32, flags: u32) -> u32 {
+    let acknowledged = value & write_mask & flags;
+
+    (merge(current, value, write_mask) & writable) | (current & flags & !acknowledged)
+}
+
+impl IopDma {
+    pub fn new(ram: IopRam, sif: SharedSif) -> IopDma {
+        IopDma {
+            channels: [ChannelRegisters::default(); CHANNEL_COUNT],
+            //Reset value on a PS1, every channel disabled with its default priority
+            dpcr: 0x07654321,
+            dicr: 0,
+            dpcr2: 0,
+            dicr2: 0,
+            dmacen: 0,
+            dmacinten: 0,
+            ram,
+            sif,
+        }
+    }
+
+    fn is_enabled(&self, channel: usize) -> bool {
+        let (dpcr, index) = if channel < PS1_CHANNEL_COUNT {
+            (self.dpcr, channel)
+        } else {
+            (self.dpcr2, channel - PS1_CHANNEL_COUNT)
+        };
+
+        dpcr & (8 << (index * 4)) != 0
+    }
+
+    pub fn is_busy(&self, channel: IopDmaChannel) -> bool {
+        self.channels[channel as usize].chcr & CHCR_START != 0
+    }
+
+    pub fn read(&self, address: u32) -> u32 {
+        if let Some((channel, register)) = channel_for_address(address) {
+            let channel = &self.channels[channel];
+
+            return match register {
+                0x0 => channel.madr,
+                0x4 => channel.bcr,
+                0x8 => channel.chcr,
+                _ => channel.tadr,
+            };
+        }
+
+        match address {
+            DPCR => self.dpcr,
+            DICR => self.dicr,
+            DPCR2 => self.dpcr2,
+            DICR2 => self.dicr2,
+            DMACEN => self.dmacen,
+            DMACINTEN => self.dmacinten,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, address: u32, value: u32, write_mask: u32, intc: &mut Intc) {
+        if let Some((channel, register)) = channel_for_address(address) {
+            let registers = &mut self.channels[channel];
+
+            match register {
+                0x0 => registers.madr = merge(registers.madr, value, write_mask) & 0x00ffffff,
+                0x4 => registers.bcr = merge(registers.bcr, value, write_mask),
+                0x8 => {
+                    let chcr = merge(registers.chcr, value, write_mask);
+
+                    //A new transfer starts from a fresh tag
+                    if registers.chcr & CHCR_START == 0 && chcr & CHCR_START != 0 {
+                        registers.words_left = 0;
+                        registers.last_tag = false;
+                    }
+
+                    registers.chcr = chcr;
+                },
+                _ => registers.tadr = merge(registers.tadr, value, write_mask) & 0x00ffffff,
+            }
+        } else {
+            match address {
+                DPCR => self.dpcr = merge(self.dpcr, value, write_mask),
+                DICR => self.dicr = write_interrupt_register(self.dicr, value, write_mask, DICR_WRITABLE, DICR_FLAGS),
+                DPCR2 => self.dpcr2 = merge(self.dpcr2, value, write_mask),
+                DICR2 => self.dicr2 = write_interrupt_register(self.dicr2, value, write_mask, DICR2_WRITABLE, DICR2_FLAGS),
+                DMACEN => self.dmacen = merge(self.dmacen, value, write_mask),
+                DMACINTEN => self.dmacinten = merge(self.dmacinten, value, write_mask),
+                _ => {},
+            }
+        }
+
+        //Starting a channel or enabling one that was already started both kick off a transfer
+        self.run_pending(intc);
+    }
+
+    //Also called whenever the EE has had a chance to run so SIF transfers waiting on it can carry on
+    pub fn run_pending(&mut self, intc: &mut Intc) {
+        for (index, channel) in CHANNELS.iter().copied().enumerate() {
+            if self.is_enabled(index) && self.channels[index].wants_to_start() && self.transfer(channel) {
+                self.complete(index);
+            }
+        }
+
+        self.update_interrupt(intc);
+    }
+
+    //Returns false while the channel is waiting on the other end
+    fn transfer(&mut self, channel: IopDmaChannel) -> bool {
+        match channel {
+            IopDmaChannel::Sif0 => self.send_sif0(),
+            IopDmaChannel::Sif1 => self.receive_sif1(),
+            _ => {
+                self.transfer_to_unemulated_device(channel);
+                true
+            },
+        }
+    }
+
+    //SIF0 tags sit in IOP RAM at TADR, the address and size of the data then an EE tag if TTE is set
+    //Everything gets pushed at once as the EE side takes it whenever it's ready
+    fn send_sif0(&mut self) -> bool {
+        let ram = self.ram.borrow();
+        let mut sif = self.sif.borrow_mut();
+        let registers = &mut self.channels[IopDmaChannel::Sif0 as usize];
+
+        while !registers.last_tag {
+            let tag_address = registers.tadr as usize;
+            let address = read_word(&ram, tag_address);
+
+            registers.madr = address & 0x00ffffff;
+            registers.words_left = read_word(&ram, tag_address + 4);
+            registers.last_tag = address & (SIF_TAG_IRQ | SIF_TAG_END) != 0;
+
+            if registers.chcr & CHCR_TAG_TRANSFER != 0 {
+                sif.sif0_fifo.extend(&[read_word(&ram, tag_address + 8), read_word(&ram, tag_address + 12), 0, 0]);
+                registers.tadr = registers.tadr.wrapping_add(16) & 0x00ffffff;
+            } else {
+                registers.tadr = registers.tadr.wrapping_add(8) & 0x00ffffff;
+            }
+
+            //Data is padded out to whole quadwords
+            let padded_words = (registers.words_left + 3) & !3;
+
+            for index in 0..padded_words {
+                let word = if index < registers.words_left { read_word(&ram, (registers.madr + index * 4) as usize) } else { 0 };
+                sif.sif0_fifo.push_back(word);
+            }
+
+            registers.madr = registers.madr.wrapping_add(registers.words_left * 4) & 0x00ffffff;
+            registers.words_left = 0;
+        }
+
+        true
+    }
+
+    //SIF1 tags come out of the FIFO from the EE in front of the data they describe
+    fn receive_sif1(&mut self) -> bool {
+        let mut ram = self.ram.borrow_mut();
+        let mut sif = self.sif.borrow_mut();
+        let registers = &mut self.channels[IopDmaChannel::Sif1 as usize];
+
+        loop {
+            //Whole quadwords are taken at a time, any padding after the last word is thrown away
+            while registers.words_left > 0 && sif.sif1_fifo.len() >= 4 {
+                for _ in 0..4 {
+                    let word = sif.sif1_fifo.pop_front().unwrap_or(0);
+
+                    if registers.words_left > 0 {
+                        write_word(&mut ram, registers.madr as usize, word);
+                        registers.madr = registers.madr.wrapping_add(4) & 0x00ffffff;
+                        registers.words_left -= 1;
+                    }
+                }
+            }
+
+            if registers.words_left > 0 {
+                return false;
+            }
+
+            if registers.last_tag {
+                return true;
+            }
+
+            if sif.sif1_fifo.len() < SIF_TAG_WORDS {
+                return false;
+            }
+
+            let tag: Vec<u32> = sif.sif1_fifo.drain(..SIF_TAG_WORDS).collect();
+            registers.madr = tag[0] & 0x00ffffff;
+            registers.words_left = tag[1];
+            registers.last_tag = tag[0] & (SIF_TAG_IRQ | SIF_TAG_END) != 0;
+        }
+    }
+
+    //None of the other devices are emulated yet so transfers finish straight away,
+    //data sent to them is dropped and RAM is left as it was for data coming from them
+    fn transfer_to_unemulated_device(&mut self, channel: IopDmaChannel) {
+        let registers = &mut self.channels[channel as usize];
+        let words = registers.word_count();
+
+        //Slice mode leaves MADR after the last word and BCR with no blocks left
+        if registers.sync_mode() == 1 {
+            let step: u32 = if registers.chcr & CHCR_STEP_BACK != 0 { 0u32.wrapping_sub(4) } else { 4 };
+
+            registers.madr = registers.madr.wrapping_add(step.wrapping_mul(words)) & 0x00ffffff;
+            registers.bcr &= 0xffff;
+        }
+    }
+
+    fn complete(&mut self, channel: usize) {
+        self.channels[channel].chcr &= !(CHCR_START | CHCR_TRIGGER);
+
+        //Flags are only set for channels with their interrupt enabled
+        if channel < PS1_CHANNEL_COUNT {
+            if self.dicr & (1 << (16 + channel)) != 0 {
+                self.dicr |= 1 << (24 + channel);
+            }
+        } else {
+            let index = channel - PS1_CHANNEL_COUNT;
+
+            if self.dicr2 & (1 << (16 + index)) != 0 {
+                self.dicr2 |= 1 << (24 + index);
+            }
+        }
+    }
+
+    //The DMA interrupt fires when the master flag goes from clear to set
+    fn update_interrupt(&mut self, intc: &mut Intc) {
+        let channel_flags = (self.dicr >> 16 & self.dicr >> 24 & 0x7f) != 0 || (self.dicr2 >> 16 & self.dicr2 >> 24 & 0x3f) != 0;
+        let master_flag = self.dicr & DICR_FORCE != 0 || (self.dicr & DICR_MASTER_ENABLE != 0 && channel_flags);
+
+        if master_flag && self.dicr & DICR_MASTER_FLAG == 0 {
+            intc.raise(IopInterrupt::Dma);
+        }
+
+        self.dicr = if master_flag { self.dicr | DICR_MASTER_FLAG } else { self.dicr & !DICR_MASTER_FLAG };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IopDma, IopDmaChannel, DICR, DICR2, DPCR2};
+    use crate::io_processor::bus::create_iop_ram;
+    use crate::io_processor::intc::{Intc, I_CTRL, I_MASK, I_STAT};
+    use crate::sif::create_sif;
+
+    #[test]
+    fn test_transfer_completion_raises_interrupt() {
+        let mut intc = Intc::new();
+        let mut dma = IopDma::new(create_iop_ram(), create_sif());
+        intc.write(I_CTRL, 1, 0xffffffff);
+        intc.write(I_MASK, 1 << 3, 0xffffffff);
+
+        //DEV9 is channel 8, the second PS2 channel
+        dma.write(DPCR2, 8 << 4, 0xffffffff, &mut intc);
+        dma.write(DICR, 1 << 23, 0xffffffff, &mut intc);
+        dma.write(DICR2, 1 << 17, 0xffffffff, &mut intc);
+
+        //Two blocks of four words in slice mode
+        dma.write(0x1F801510, 0x100, 0xffffffff, &mut intc);
+        dma.write(0x1F801514, 0x00020004, 0xffffffff, &mut intc);
+        dma.write(0x1F801518, 0x01000201, 0xffffffff, &mut intc);
+
+        assert!(!dma.is_busy(IopDmaChannel::Dev9));
+        assert_eq!(dma.read(0x1F801510), 0x120);
+        assert_eq!(dma.read(DICR2) >> 24, 1 << 1);
+        assert_eq!(dma.read(DICR) >> 31, 1);
+        assert!(intc.pending());
+
+        //Acknowledging the channel flag drops the master flag again
+        dma.write(DICR2, 1 << 25, 0xffffffff, &mut intc);
+        intc.write(I_STAT, 0, 0xffffffff);
+        assert_eq!(dma.read(DICR) >> 31, 0);
+        assert!(!intc.pending());
+    }
+
+    #[test]
+    fn test_sif_transfers() {
+        let (ram, sif) = (create_iop_ram(), create_sif());
+        let mut intc = Intc::new();
+        let mut dma = IopDma::new(ram.clone(), sif.clone());
+        dma.write(DPCR2, 8 << 8 | 8 << 12, 0xffffffff, &mut intc);
+
+        //SIF1 waits until the EE has sent a tag and all the data it describes
+        dma.write(0x1F801538, 0x41000300, 0xffffffff, &mut intc);
+        sif.borrow_mut().sif1_fifo.extend(&[0x80001000, 2, 0, 0]);
+        dma.run_pending(&mut intc);
+        assert!(dma.is_busy(IopDmaChannel::Sif1));
+
+        sif.borrow_mut().sif1_fifo.extend(&[0x11, 0x22, 0, 0]);
+        dma.run_pending(&mut intc);
+        assert!(!dma.is_busy(IopDmaChannel::Sif1));
+        assert_eq!(&ram.borrow()[0x1000..0x1009], &[0x11, 0, 0, 0, 0x22, 0, 0, 0, 0]);
+        assert!(sif.borrow().sif1_fifo.is_empty());
+
+        //SIF0 sends the EE tag from the IOP tag followed by the data padded to a quadword
+        ram.borrow_mut()[0x2000..0x2010].copy_from_slice(&[0x00, 0x10, 0x00, 0xc0, 1, 0, 0, 0, 1, 0, 0, 0x70, 0, 0x30, 0, 0]);
+        dma.write(0x1F80152C, 0x2000, 0xffffffff, &mut intc);
+        dma.write(0x1F801528, 0x01000701, 0xffffffff, &mut intc);
+        assert!(!dma.is_busy(IopDmaChannel::Sif0));
+        assert_eq!(sif.borrow().sif0_fifo.iter().copied().collect::<Vec<u32>>(), vec![0x70000001, 0x3000, 0, 0, 0x11, 0, 0, 0]);
+    }
+}
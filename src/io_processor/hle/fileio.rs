@@ -0,0 +1,722 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use std::rc::Rc;
+use super::sifcmd::{word, words_to_bytes};
+use super::{EeAccess, RpcServer};
+use crate::io::{FileMetadata, ISOFileReader, RecordingDate};
+
+pub const FILEIO_SID: u32 = 0x80000001;
+
+const FIO_OPEN: u32 = 0;
+const FIO_CLOSE: u32 = 1;
+const FIO_READ: u32 = 2;
+const FIO_WRITE: u32 = 3;
+const FIO_LSEEK: u32 = 4;
+const FIO_REMOVE: u32 = 6;
+const FIO_MKDIR: u32 = 7;
+const FIO_RMDIR: u32 = 8;
+const FIO_DOPEN: u32 = 9;
+const FIO_DCLOSE: u32 = 10;
+const FIO_DREAD: u32 = 11;
+const FIO_GETSTAT: u32 = 12;
+
+//Open flags from the EE's fileio.h
+pub const FIO_O_RDONLY: u32 = 0x0001;
+pub const FIO_O_WRONLY: u32 = 0x0002;
+pub const FIO_O_APPEND: u32 = 0x0100;
+pub const FIO_O_CREAT: u32 = 0x0200;
+pub const FIO_O_TRUNC: u32 = 0x0400;
+
+//Mode bits in fio_stat_t
+const FIO_SO_IFREG: u32 = 0x0010;
+const FIO_SO_IFDIR: u32 = 0x0020;
+const FIO_SO_IROTH: u32 = 0x0004;
+const FIO_SO_IWOTH: u32 = 0x0002;
+
+const EIO: i32 = 5;
+const ENOENT: i32 = 2;
+const EBADF: i32 = 9;
+const EACCES: i32 = 13;
+const EEXIST: i32 = 17;
+const ENODEV: i32 = 19;
+const ENOTDIR: i32 = 20;
+const EISDIR: i32 = 21;
+const EINVAL: i32 = 22;
+const EMFILE: i32 = 24;
+
+const PATH_MAX: usize = 256;
+//Reads and writes can't move more than fits in EE RAM, the guest's size isn't trusted any further
+const MAX_TRANSFER_SIZE: usize = 32 * 1024 * 1024;
+const MAX_FILES: usize = 32;
+//fio_stat_t is 40 bytes and io_dirent_t adds a 256 byte name and a pointer
+const STAT_SIZE: usize = 40;
+//Stat times are in JST like the PS2's clock
+const JST_OFFSET_SECONDS: i64 = 9 * 60 * 60;
+const DIRENT_SIZE: usize = STAT_SIZE + PATH_MAX + 4;
+
+pub trait FileHandle: Read + Write + Seek {}
+
+impl<T: Read + Write + Seek> FileHandle for T {}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FileStat {
+    pub size: u64,
+    pub is_directory: bool,
+    pub read_only: bool,
+    //Unix timestamp, images with zeroed dates don't have one
+    pub modified: Option<i64>,
+}
+
+//A device prefix like cdrom0: or host:, paths are handed over with the prefix already taken off
+pub trait FileDevice {
+    fn open(&mut self, path: &str, flags: u32) -> io::Result<Box<dyn FileHandle>>;
+
+    fn stat(&mut self, path: &str) -> io::Result<FileStat>;
+
+    fn read_dir(&mut self, path: &str) -> io::Result<Vec<(String, FileStat)>>;
+
+    fn remove(&mut self, _path: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "device is read only!"))
+    }
+
+    fn mkdir(&mut self, _path: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "device is read only!"))
+    }
+
+    fn rmdir(&mut self, _path: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "device is read only!"))
+    }
+}
+
+//cdrom0: straight out of an ISO, every handle opens the file again since IsoFile borrows the reader
+pub struct IsoDevice<R: Read + Seek> {
+    reader: Rc<RefCell<ISOFileReader<R>>>,
+}
+
+struct IsoHandle<R: Read + Seek> {
+    reader: Rc<RefCell<ISOFileReader<R>>>,
+    path: String,
+    position: u64,
+    size: u64,
+}
+
+impl<R: Read + Seek> IsoDevice<R> {
+    pub fn new(reader: ISOFileReader<R>) -> IsoDevice<R> {
+        IsoDevice {
+            reader: Rc::new(RefCell::new(reader)),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for IsoHandle<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut reader = self.reader.borrow_mut();
+        let mut file = reader.open(&self.path)?;
+
+        file.seek(SeekFrom::Start(self.position))?;
+        let length = file.read(buffer)?;
+        self.position += length as u64;
+
+        Ok(length)
+    }
+}
+
+impl<R: Read + Seek> Write for IsoHandle<R> {
+    fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "cdrom0 is read only!"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Seek for IsoHandle<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file!"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+fn iso_stat(metadata: &FileMetadata) -> FileStat {
+    FileStat {
+        size: metadata.size,
+        is_directory: metadata.is_directory,
+        read_only: true,
+        modified: metadata.recording_date.is_set().then(|| metadata.recording_date.to_unix_timestamp()),
+    }
+}
+
+impl<R: Read + Seek + 'static> FileDevice for IsoDevice<R> {
+    fn open(&mut self, path: &str, flags: u32) -> io::Result<Box<dyn FileHandle>> {
+        if flags & FIO_O_WRONLY != 0 {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "cdrom0 is read only!"));
+        }
+
+        let size = self.reader.borrow_mut().open(path)?.len();
+
+        Ok(Box::new(IsoHandle {
+            reader: self.reader.clone(),
+            path: path.to_string(),
+            position: 0,
+            size,
+        }))
+    }
+
+    fn stat(&mut self, path: &str) -> io::Result<FileStat> {
+        let metadata = self.reader.borrow().metadata(path)?;
+
+        Ok(iso_stat(&metadata))
+    }
+
+    fn read_dir(&mut self, path: &str) -> io::Result<Vec<(String, FileStat)>> {
+        Ok(self.reader.borrow().read_dir(path)?.into_iter().map(|entry| (entry.name, iso_stat(&entry.metadata))).collect())
+    }
+}
+
+//host: for homebrew, everything is kept inside the root directory
+pub struct HostDevice {
+    root: PathBuf,
+}
+
+impl HostDevice {
+    pub fn new(root: &str) -> HostDevice {
+        HostDevice {
+            root: PathBuf::from(root),
+        }
+    }
+
+    //Paths are always relative to the root whether or not they start with a slash, and .. can't go above it
+    fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let mut components: Vec<&str> = vec![];
+
+        for component in path.split(['/', '\\']) {
+            match component {
+                "" | "." => {},
+                ".." => {
+                    if components.pop().is_none() {
+                        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path is outside of the host directory!"));
+                    }
+                },
+                //Drive letters and the like would replace the root when joined
+                component if component.contains(':') => return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path is outside of the host directory!")),
+                component => components.push(component),
+            }
+        }
+
+        let joined = components.iter().fold(self.root.clone(), |path, component| path.join(component));
+
+        //A symlink inside the root can still lead out of it so the real path has to be checked as well,
+        //anything that doesn't exist yet is checked through its parent
+        let root = fs::canonicalize(&self.root)?;
+        let real = match fs::canonicalize(&joined) {
+            Ok(real) => real,
+            Err(error) => match (joined.parent(), joined.file_name()) {
+                (Some(parent), Some(name)) => fs::canonicalize(parent)?.join(name),
+                _ => return Err(error),
+            },
+        };
+
+        if !real.starts_with(&root) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path is outside of the host directory!"));
+        }
+
+        Ok(real)
+    }
+}
+
+fn host_stat(metadata: &fs::Metadata) -> FileStat {
+    FileStat {
+        size: metadata.len(),
+        is_directory: metadata.is_dir(),
+        read_only: metadata.permissions().readonly(),
+        modified: metadata.modified().ok().and_then(|modified| modified.duration_since(UNIX_EPOCH).ok()).map(|modified| modified.as_secs() as i64),
+    }
+}
+
+impl FileDevice for HostDevice {
+    fn open(&mut self, path: &str, flags: u32) -> io::Result<Box<dyn FileHandle>> {
+        let file = fs::OpenOptions::new()
+            .read(flags & FIO_O_RDONLY != 0)
+            .write(flags & FIO_O_WRONLY != 0)
+            .append(flags & FIO_O_APPEND != 0)
+            .create(flags & FIO_O_CREAT != 0)
+            .truncate(flags & FIO_O_TRUNC != 0)
+            .open(self.resolve(path)?)?;
+
+        Ok(Box::new(file))
+    }
+
+    fn stat(&mut self, path: &str) -> io::Result<FileStat> {
+        Ok(host_stat(&fs::metadata(self.resolve(path)?)?))
+    }
+
+    fn read_dir(&mut self, path: &str) -> io::Result<Vec<(String, FileStat)>> {
+        let mut entries = vec![];
+
+        for entry in fs::read_dir(self.resolve(path)?)? {
+            let entry = entry?;
+            entries.push((entry.file_name().to_string_lossy().to_string(), host_stat(&entry.metadata()?)));
+        }
+
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries)
+    }
+
+    fn remove(&mut self, path: &str) -> io::Result<()> {
+        fs::remove_file(self.resolve(path)?)
+    }
+
+    fn mkdir(&mut self, path: &str) -> io::Result<()> {
+        fs::create_dir(self.resolve(path)?)
+    }
+
+    fn rmdir(&mut self, path: &str) -> io::Result<()> {
+        fs::remove_dir(self.resolve(path)?)
+    }
+}
+
+enum OpenFile {
+    File(Box<dyn FileHandle>),
+    //Directory listings are read up front and handed out one entry at a time
+    Directory(Vec<(String, FileStat)>),
+}
+
+//The rom0:FILEIO server, open files are numbered from 0 like the IOP's iomanX descriptors
+pub struct FileioServer {
+    devices: HashMap<String, Box<dyn FileDevice>>,
+    files: Vec<Option<OpenFile>>,
+}
+
+//Anything that isn't obviously a missing file or a permission problem is an I/O error
+//Image errors come through with the kind From<ImageError> gave them
+fn error_code(error: io::Error) -> i32 {
+    match error.kind() {
+        io::ErrorKind::NotFound => -ENOENT,
+        io::ErrorKind::NotADirectory => -ENOTDIR,
+        io::ErrorKind::IsADirectory => -EISDIR,
+        io::ErrorKind::PermissionDenied => -EACCES,
+        io::ErrorKind::AlreadyExists => -EEXIST,
+        io::ErrorKind::InvalidInput => -EINVAL,
+        _ => -EIO,
+    }
+}
+
+fn result(value: i32) -> Vec<u8> {
+    (value as u32).to_le_bytes().to_vec()
+}
+
+fn read_path(data: &[u8]) -> String {
+    let data = &data[..data.len().min(PATH_MAX)];
+    let length = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+
+    String::from_utf8_lossy(&data[..length]).to_string()
+}
+
+fn stat_bytes(stat: &FileStat) -> Vec<u8> {
+    let mut mode = FIO_SO_IROTH | if stat.is_directory { FIO_SO_IFDIR } else { FIO_SO_IFREG };
+
+    if !stat.read_only {
+        mode |= FIO_SO_IWOTH;
+    }
+
+    let mut bytes = words_to_bytes(&[mode, 0, stat.size as u32]);
+
+    //ctime, atime and mtime are a reserved byte, seconds, minutes, hours, day, month then a 16 bit year.
+    //There's only the one time to give so all three get it
+    let time = match stat.modified {
+        Some(modified) => {
+            let date = RecordingDate::from_unix_timestamp(modified + JST_OFFSET_SECONDS);
+            let year = date.year.to_le_bytes();
+            [0, date.second, date.minute, date.hour, date.day, date.month, year[0], year[1]]
+        },
+        None => [0; 8],
+    };
+
+    for _ in 0..3 {
+        bytes.extend_from_slice(&time);
+    }
+
+    bytes.extend_from_slice(&((stat.size >> 32) as u32).to_le_bytes());
+    bytes
+}
+
+impl FileioServer {
+    pub fn new() -> FileioServer {
+        FileioServer {
+            devices: HashMap::new(),
+            files: vec![],
+        }
+    }
+
+    //name is without the colon, eg. "cdrom0" or "host"
+    pub fn mount(&mut self, name: &str, device: Box<dyn FileDevice>) {
+        self.devices.insert(name.to_string(), device);
+    }
+
+    pub fn close_all(&mut self) {
+        self.files.clear();
+    }
+
+    //cdrom0:\SYSTEM.CNF;1 -> ("cdrom0", "\SYSTEM.CNF;1"), a device number on host (host0:) is ignored
+    fn device(&mut self, path: &str) -> Result<(&mut Box<dyn FileDevice>, String), i32> {
+        let index = path.find(':').ok_or(-ENODEV)?;
+        let name = &path[..index];
+        let relative = path[index + 1..].to_string();
+
+        let name = if self.devices.contains_key(name) { name } else { name.trim_end_matches(|c: char| c.is_ascii_digit()) };
+
+        match self.devices.get_mut(name) {
+            Some(device) => Ok((device, relative)),
+            None => Err(-ENODEV),
+        }
+    }
+
+    fn add_file(&mut self, file: OpenFile) -> i32 {
+        match self.files.iter().position(|file| file.is_none()) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                fd as i32
+            },
+            None if self.files.len() < MAX_FILES => {
+                self.files.push(Some(file));
+                self.files.len() as i32 - 1
+            },
+            None => -EMFILE,
+        }
+    }
+
+    fn file(&mut self, fd: u32) -> Option<&mut OpenFile> {
+        self.files.get_mut(fd as usize).and_then(|file| file.as_mut())
+    }
+
+    fn open(&mut self, path: &str, flags: u32) -> i32 {
+        let file = match self.device(path) {
+            Ok((device, relative)) => device.open(&relative, flags),
+            Err(error) => return error,
+        };
+
+        match file {
+            Ok(file) => self.add_file(OpenFile::File(file)),
+            Err(error) => error_code(error),
+        }
+    }
+
+    fn dopen(&mut self, path: &str) -> i32 {
+        let entries = match self.device(path) {
+            Ok((device, relative)) => device.read_dir(&relative),
+            Err(error) => return error,
+        };
+
+        match entries {
+            Ok(mut entries) => {
+                //Handed out from the back
+                entries.reverse();
+                self.add_file(OpenFile::Directory(entries))
+            },
+            Err(error) => error_code(error),
+        }
+    }
+
+    fn close(&mut self, fd: u32) -> i32 {
+        match self.files.get_mut(fd as usize) {
+            Some(file @ Some(_)) => {
+                *file = None;
+                0
+            },
+            _ => -EBADF,
+        }
+    }
+
+    //Reads go to the EE in three parts, the unaligned start and end are copied into read_data for the
+    //EE to put in place itself and only the aligned middle is sent by DMA
+    fn read(&mut self, arguments: &[u8], ee: &mut EeAccess) -> i32 {
+        let (fd, address, size, read_data) = (word(arguments, 0), word(arguments, 1), (word(arguments, 2) as usize).min(MAX_TRANSFER_SIZE), word(arguments, 3));
+
+        let mut data = vec![0; size];
+        let length = match self.file(fd) {
+            Some(OpenFile::File(file)) => match file.read(&mut data) {
+                Ok(length) => length,
+                Err(_) => return -EIO,
+            },
+            _ => return -EBADF,
+        };
+        data.truncate(length);
+
+        let head = ((16 - (address & 15) as usize) & 15).min(length);
+        let tail = (length - head) & 15;
+        let middle = &data[head..length - tail];
+
+        let mut read_data_bytes = words_to_bytes(&[head as u32, tail as u32, address, address.wrapping_add((length - tail) as u32)]);
+        read_data_bytes.extend_from_slice(&data[..head]);
+        read_data_bytes.resize(32, 0);
+        read_data_bytes.extend_from_slice(&data[length - tail..]);
+        read_data_bytes.resize(48, 0);
+
+        if !middle.is_empty() {
+            ee.write(address.wrapping_add(head as u32), middle.to_vec());
+        }
+
+        if read_data != 0 {
+            ee.write(read_data, read_data_bytes);
+        }
+
+        length as i32
+    }
+
+    //The first few unaligned bytes come in the arguments, the rest are fetched from EE memory
+    fn write(&mut self, arguments: &[u8], ee: &mut EeAccess) -> i32 {
+        let (fd, address, size, misaligned) = (word(arguments, 0), word(arguments, 1), (word(arguments, 2) as usize).min(MAX_TRANSFER_SIZE), word(arguments, 3) as usize);
+        let misaligned = misaligned.min(16).min(size);
+
+        let mut data = arguments.get(16..16 + misaligned).unwrap_or_default().to_vec();
+        data.extend(ee.read(address.wrapping_add(misaligned as u32), size.saturating_sub(misaligned)));
+
+        match self.file(fd) {
+            Some(OpenFile::File(file)) => match file.write(&data) {
+                Ok(length) => length as i32,
+                Err(_) => -EIO,
+            },
+            _ => -EBADF,
+        }
+    }
+
+    fn lseek(&mut self, fd: u32, offset: i32, whence: u32) -> i32 {
+        let position = match whence {
+            0 => SeekFrom::Start(offset.max(0) as u64),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return -EINVAL,
+        };
+
+        match self.file(fd) {
+            Some(OpenFile::File(file)) => match file.seek(position) {
+                Ok(position) => position as i32,
+                Err(_) => -EINVAL,
+            },
+            _ => -EBADF,
+        }
+    }
+
+    //Fills in an io_dirent_t on the EE, 1 for an entry and 0 once there are none left
+    fn dread(&mut self, fd: u32, address: u32, ee: &mut EeAccess) -> i32 {
+        let entry = match self.file(fd) {
+            Some(OpenFile::Directory(entries)) => entries.pop(),
+            _ => return -EBADF,
+        };
+
+        match entry {
+            Some((name, stat)) => {
+                let mut dirent = stat_bytes(&stat);
+                dirent.extend(name.bytes().take(PATH_MAX - 1));
+                dirent.resize(DIRENT_SIZE, 0);
+
+                ee.write(address, dirent);
+                1
+            },
+            None => 0,
+        }
+    }
+
+    fn getstat(&mut self, address: u32, path: &str, ee: &mut EeAccess) -> i32 {
+        let stat = match self.device(path) {
+            Ok((device, relative)) => device.stat(&relative),
+            Err(error) => return error,
+        };
+
+        match stat {
+            Ok(stat) => {
+                ee.write(address, stat_bytes(&stat));
+                0
+            },
+            Err(error) => error_code(error),
+        }
+    }
+
+    fn device_call<F: FnOnce(&mut Box<dyn FileDevice>, &str) -> io::Result<()>>(&mut self, path: &str, call: F) -> i32 {
+        match self.device(path) {
+            Ok((device, relative)) => match call(device, &relative) {
+                Ok(()) => 0,
+                Err(error) => error_code(error),
+            },
+            Err(error) => error,
+        }
+    }
+}
+
+impl RpcServer for FileioServer {
+    fn call(&mut self, function: u32, arguments: &[u8], ee: &mut EeAccess) -> Vec<u8> {
+        let path_at = |offset: usize| read_path(arguments.get(offset..).unwrap_or_default());
+
+        result(match function {
+            FIO_OPEN => self.open(&path_at(4), word(arguments, 0)),
+            FIO_CLOSE | FIO_DCLOSE => self.close(word(arguments, 0)),
+            FIO_READ => self.read(arguments, ee),
+            FIO_WRITE => self.write(arguments, ee),
+            FIO_LSEEK => self.lseek(word(arguments, 0), word(arguments, 1) as i32, word(arguments, 2)),
+            FIO_REMOVE => self.device_call(&path_at(0), |device, path| device.remove(path)),
+            FIO_MKDIR => self.device_call(&path_at(4), |device, path| device.mkdir(path)),
+            FIO_RMDIR => self.device_call(&path_at(0), |device, path| device.rmdir(path)),
+            FIO_DOPEN => self.dopen(&path_at(0)),
+            FIO_DREAD => self.dread(word(arguments, 0), word(arguments, 1), ee),
+            FIO_GETSTAT => self.getstat(word(arguments, 0), &path_at(4), ee),
+            _ => -EINVAL,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileioServer, HostDevice, IsoDevice, FIO_O_CREAT, FIO_O_RDONLY, FIO_O_WRONLY};
+    use super::super::sifcmd::{word, words_to_bytes};
+    use super::super::{EeAccess, RpcServer};
+    use crate::emotion_engine::cpu::test::create_mock_memory;
+    use crate::io::{ISOFileReader, IsoBuilder};
+    use crate::test_utils::TempPath;
+    use std::fs;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_open_read_and_stat_on_cdrom0() {
+        let mut builder = IsoBuilder::new("TEST");
+        builder.add_file("DATA/FILE.BIN", (0..40u8).collect()).unwrap();
+        let reader = ISOFileReader::from_reader(Cursor::new(builder.build().unwrap())).unwrap();
+
+        let mut fileio = FileioServer::new();
+        fileio.mount("cdrom", Box::new(IsoDevice::new(reader)));
+
+        let memory = create_mock_memory();
+        let mut ee = EeAccess {
+            memory: &memory,
+            writes: vec![],
+        };
+
+        let mut open = words_to_bytes(&[FIO_O_RDONLY]);
+        open.extend_from_slice(b"cdrom0:\\DATA\\FILE.BIN;1\0");
+        let fd = word(&fileio.call(0, &open, &mut ee), 0);
+        assert_eq!(fd, 0);
+
+        //Read 36 bytes into 0x1004, 12 come back through read_data, 16 by DMA and 8 through read_data again
+        assert_eq!(word(&fileio.call(4, &words_to_bytes(&[fd, 2, 0]), &mut ee), 0), 2);
+        assert_eq!(word(&fileio.call(2, &words_to_bytes(&[fd, 0x1004, 36, 0x2000]), &mut ee), 0), 36);
+        assert_eq!(ee.writes[0], (0x1010, (14..30).collect::<Vec<u8>>()));
+
+        let (address, read_data) = &ee.writes[1];
+        assert_eq!(*address, 0x2000);
+        assert_eq!(&read_data[..16], &words_to_bytes(&[12, 8, 0x1004, 0x1020])[..]);
+        assert_eq!(&read_data[16..28], &(2..14).collect::<Vec<u8>>()[..]);
+        assert_eq!(&read_data[32..40], &(30..38).collect::<Vec<u8>>()[..]);
+
+        //getstat writes the size and the recording date into fio_stat_t, midnight GMT is 9am in JST
+        let mut getstat = words_to_bytes(&[0x3000]);
+        getstat.extend_from_slice(b"cdrom0:\\DATA\\FILE.BIN;1\0");
+        assert_eq!(word(&fileio.call(12, &getstat, &mut ee), 0), 0);
+        assert_eq!(word(&ee.writes[2].1, 2), 40);
+        assert_eq!(ee.writes[2].1[12..20], [0, 0, 0, 9, 1, 1, 0xd0, 0x07]);
+        assert_eq!(ee.writes[2].1[28..36], ee.writes[2].1[12..20]);
+
+        assert_eq!(word(&fileio.call(1, &words_to_bytes(&[fd]), &mut ee), 0), 0);
+        assert_eq!(word(&fileio.call(1, &words_to_bytes(&[fd]), &mut ee), 0) as i32, -9);
+        assert_eq!(word(&fileio.call(0, &open[..4], &mut ee), 0) as i32, -19);
+    }
+
+    #[test]
+    fn test_host_files_stay_in_the_root() {
+        let temp_root = TempPath::new("host_root");
+        let root = temp_root.path();
+        fs::create_dir_all(root).unwrap();
+
+        let mut fileio = FileioServer::new();
+        fileio.mount("host", Box::new(HostDevice::new(root.to_str().unwrap())));
+
+        let memory = create_mock_memory();
+        let mut ee = EeAccess {
+            memory: &memory,
+            writes: vec![],
+        };
+
+        let mut mkdir = words_to_bytes(&[0]);
+        mkdir.extend_from_slice(b"host0:results\0");
+        assert_eq!(word(&fileio.call(7, &mkdir, &mut ee), 0), 0);
+
+        //The five bytes fit in the misaligned part of the write arguments so EE memory isn't needed
+        let mut open = words_to_bytes(&[FIO_O_RDONLY | FIO_O_WRONLY | FIO_O_CREAT]);
+        open.extend_from_slice(b"host:/results/../results/out.txt\0");
+        let fd = word(&fileio.call(0, &open, &mut ee), 0);
+        assert_eq!(fd, 0);
+
+        let mut write = words_to_bytes(&[fd, 0x1000, 5, 5]);
+        write.extend_from_slice(b"hello");
+        assert_eq!(word(&fileio.call(3, &write, &mut ee), 0), 5);
+        assert_eq!(word(&fileio.call(4, &words_to_bytes(&[fd, 1, 0]), &mut ee), 0), 1);
+        assert_eq!(word(&fileio.call(2, &words_to_bytes(&[fd, 0x2000, 16, 0x3000]), &mut ee), 0), 4);
+        //Too short for DMA so it all comes back as the tail of read_data
+        assert_eq!(&ee.writes[0].1[32..36], b"ello");
+        assert_eq!(word(&fileio.call(1, &words_to_bytes(&[fd]), &mut ee), 0), 0);
+        assert_eq!(fs::read(root.join("results").join("out.txt")).unwrap(), b"hello");
+
+        let fd = word(&fileio.call(9, b"host:results\0", &mut ee), 0);
+        assert_eq!(word(&fileio.call(11, &words_to_bytes(&[fd, 0x4000]), &mut ee), 0), 1);
+        assert_eq!(&ee.writes[1].1[40..48], b"out.txt\0");
+        assert_eq!(word(&fileio.call(11, &words_to_bytes(&[fd, 0x4000]), &mut ee), 0), 0);
+
+        let mut getstat = words_to_bytes(&[0x5000]);
+        getstat.extend_from_slice(b"host:results/out.txt\0");
+        assert_eq!(word(&fileio.call(12, &getstat, &mut ee), 0), 0);
+        assert_eq!(word(&ee.writes[2].1, 2), 5);
+
+        //Climbing out of the root or naming another drive is refused
+        let mut escape = words_to_bytes(&[FIO_O_RDONLY]);
+        escape.extend_from_slice(b"host:results/../../outside.txt\0");
+        assert_eq!(word(&fileio.call(0, &escape, &mut ee), 0) as i32, -13);
+        let mut drive = words_to_bytes(&[FIO_O_RDONLY]);
+        drive.extend_from_slice(b"host:C:\\outside.txt\0");
+        assert_eq!(word(&fileio.call(0, &drive, &mut ee), 0) as i32, -13);
+
+        assert_eq!(word(&fileio.call(6, b"host:results/out.txt\0", &mut ee), 0), 0);
+        assert!(!root.join("results").join("out.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_host_symlinks_cant_leave_the_root() {
+        let (temp_root, outside) = (TempPath::new("host_root"), TempPath::new("host_outside"));
+        let root = temp_root.path();
+        fs::create_dir_all(root).unwrap();
+        fs::create_dir_all(outside.path()).unwrap();
+        fs::write(outside.path().join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("link")).unwrap();
+
+        let mut fileio = FileioServer::new();
+        fileio.mount("host", Box::new(HostDevice::new(root.to_str().unwrap())));
+
+        let memory = create_mock_memory();
+        let mut ee = EeAccess {
+            memory: &memory,
+            writes: vec![],
+        };
+
+        //Neither an existing file nor a new one can be reached through the link
+        for (flags, name) in [(FIO_O_RDONLY, "secret.txt"), (FIO_O_RDONLY | FIO_O_WRONLY | FIO_O_CREAT, "new.txt")] {
+            let mut open = words_to_bytes(&[flags]);
+            open.extend_from_slice(format!("host:link/{}\0", name).as_bytes());
+            assert_eq!(word(&fileio.call(0, &open, &mut ee), 0) as i32, -13);
+        }
+
+        assert!(!outside.path().join("new.txt").exists());
+    }
+}
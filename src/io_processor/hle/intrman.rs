@@ -0,0 +1,133 @@
+use super::kernel::IopKernel;
+use super::threadman::Context;
+use super::super::cpu::{IopCpu, GP_REG};
+use super::super::intc::I_MASK;
+
+const KE_CPUDI: i32 = -102;
+const KE_FOUND_HANDLER: i32 = -104;
+const KE_NOTFOUND_HANDLER: i32 = -105;
+
+//Interrupt numbers past the I_STAT bits are DMA channels, they aren't routed anywhere yet
+const INTERRUPT_COUNT: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InterruptHandler {
+    pub function: u32,
+    pub argument: u32,
+    //The gp of whoever registered it, handlers run with it
+    pub gp: u32,
+}
+
+//What was running when the handler was called, None when the IOP was idle
+struct ActiveInterrupt {
+    interrupt: u32,
+    context: Option<Context>,
+}
+
+pub struct IntrManager {
+    handlers: [Option<InterruptHandler>; INTERRUPT_COUNT],
+    //CpuSuspendIntr and CpuDisableIntr turn this off
+    pub enabled: bool,
+    active: Option<ActiveInterrupt>,
+}
+
+impl IntrManager {
+    pub fn new() -> IntrManager {
+        IntrManager {
+            handlers: [None; INTERRUPT_COUNT],
+            enabled: true,
+            active: None,
+        }
+    }
+
+    pub fn handler(&self, interrupt: u32) -> Option<InterruptHandler> {
+        self.handlers.get(interrupt as usize).copied().flatten()
+    }
+
+    pub fn in_handler(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn enter(&mut self, interrupt: u32, context: Option<Context>) {
+        self.active = Some(ActiveInterrupt {
+            interrupt,
+            context,
+        });
+    }
+
+    pub fn leave(&mut self) -> Option<(u32, Option<Context>)> {
+        self.active.take().map(|active| (active.interrupt, active.context))
+    }
+}
+
+fn result(value: i32) -> Option<u32> {
+    Some(value as u32)
+}
+
+pub fn call(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1, a2, a3) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1), kernel.argument(cpu, 2), kernel.argument(cpu, 3));
+    let mask = 1u32.checked_shl(a0).unwrap_or(0);
+
+    match function {
+        //RegisterIntrHandler(interrupt, mode, handler, argument)
+        4 => match kernel.intrman.handlers.get_mut(a0 as usize) {
+            Some(Some(_)) => result(KE_FOUND_HANDLER),
+            Some(handler) => {
+                *handler = Some(InterruptHandler {
+                    function: a2,
+                    argument: a3,
+                    gp: cpu.read_register(GP_REG),
+                });
+
+                result(0)
+            },
+            None => result(KE_NOTFOUND_HANDLER),
+        },
+        5 => match kernel.intrman.handlers.get_mut(a0 as usize) {
+            Some(handler @ Some(_)) => {
+                *handler = None;
+                result(0)
+            },
+            _ => result(KE_NOTFOUND_HANDLER),
+        },
+        //EnableIntr and DisableIntr(interrupt, int *result) go straight to I_MASK
+        6 => {
+            cpu.bus.intc.write(I_MASK, mask, mask);
+            result(0)
+        },
+        7 => {
+            cpu.bus.intc.write(I_MASK, 0, mask);
+
+            if a1 != 0 {
+                kernel.write_u32(a1, a0);
+            }
+
+            result(0)
+        },
+        8 => {
+            kernel.intrman.enabled = false;
+            result(0)
+        },
+        9 => {
+            kernel.intrman.enabled = true;
+            result(0)
+        },
+        //CpuSuspendIntr(int *state) and CpuResumeIntr(state)
+        17 => {
+            let was_enabled = kernel.intrman.enabled;
+            kernel.intrman.enabled = false;
+
+            if a0 != 0 {
+                kernel.write_u32(a0, was_enabled as u32);
+            }
+
+            result(if was_enabled { 0 } else { KE_CPUDI })
+        },
+        18 => {
+            kernel.intrman.enabled = a0 != 0;
+            result(0)
+        },
+        23 => Some(kernel.intrman.in_handler() as u32),
+        _ => kernel.unimplemented("intrman", function),
+    }
+}
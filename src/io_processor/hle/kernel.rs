@@ -0,0 +1,441 @@
+use std::collections::HashSet;
+use std::error::Error;
+use super::intrman::{self, IntrManager};
+use super::printf::format;
+use super::{stdio, sysclib};
+use super::sysmem::{self, SysMem, ALLOC_FIRST, ALLOC_LAST};
+use super::threadman::{self, Context, ThreadManager};
+use super::super::bus::{IopRam, IOP_RAM_SIZE};
+use super::super::cpu::{IopCpu, HLE_SYSCALL_BASE, A0_REG, GP_REG, RA_REG, SP_REG, V0_REG};
+use super::super::intc::{I_MASK, I_STAT};
+use crate::io::{create_io_error, IopLibraryRegistry, IrxExport, IrxModule, LoadedModule};
+use crate::scheduler::{EE_CLOCK_HZ, IOP_CLOCK_DIVIDER};
+use crate::tty::Tty;
+
+pub const IOP_CYCLES_PER_SECOND: u64 = EE_CLOCK_HZ / IOP_CLOCK_DIVIDER;
+
+//Low IOP RAM where the BIOS kernel would be, each HLE function is a single SYSCALL here
+const HLE_STUB_BASE: u32 = 0x1000;
+const INTERRUPT_STACK_TOP: u32 = 0x3ff0;
+//Module and thread memory comes from the rest
+const USER_MEMORY_START: u32 = 0x10000;
+
+const LIBRARY_VERSION: u16 = 0x0101;
+const MODULE_THREAD_PRIORITY: u32 = 8;
+const MODULE_STACK_SIZE: u32 = 0x1000;
+//How long to sit idle at a time while nothing's ready, interrupts are only noticed in between
+const IDLE_CYCLES: u64 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum HleLibrary {
+    Sysmem,
+    Loadcore,
+    Thbase,
+    Thsemap,
+    Thevent,
+    Intrman,
+    Sysclib,
+    Stdio,
+    //Where threads and interrupt handlers return to
+    Kernel,
+}
+
+//Name, library and how many functions to make stubs for, the unimplemented ones return 0
+const LIBRARIES: [(&str, HleLibrary, u16); 9] = [
+    ("sysmem", HleLibrary::Sysmem, 16),
+    ("loadcore", HleLibrary::Loadcore, 16),
+    ("thbase", HleLibrary::Thbase, 42),
+    ("thsemap", HleLibrary::Thsemap, 13),
+    ("thevent", HleLibrary::Thevent, 15),
+    ("intrman", HleLibrary::Intrman, 32),
+    ("sysclib", HleLibrary::Sysclib, 43),
+    ("stdio", HleLibrary::Stdio, 15),
+    ("", HleLibrary::Kernel, 2),
+];
+
+const KERNEL_THREAD_EXIT: u16 = 0;
+const KERNEL_INTERRUPT_RETURN: u16 = 1;
+
+fn syscall(code: u32) -> u32 {
+    (code << 6) | 0x0c
+}
+
+//Stands in for the IOP kernel modules, their exports are HLE stubs so IRX modules can be loaded
+//and run without a BIOS. Whoever drives the IOP calls run instead of IopCpu::run
+pub struct IopKernel {
+    ram: IopRam,
+    pub registry: IopLibraryRegistry,
+    pub sysmem: SysMem,
+    pub threads: ThreadManager,
+    pub intrman: IntrManager,
+    pub modules: Vec<LoadedModule>,
+    //printf and Kprintf output
+    pub tty: Tty,
+    //Stub number to library and function
+    calls: Vec<(HleLibrary, u16)>,
+    //Missing functions that have already been reported
+    unimplemented_calls: HashSet<(&'static str, u16)>,
+}
+
+impl IopKernel {
+    pub fn new(ram: IopRam) -> IopKernel {
+        let mut kernel = IopKernel {
+            ram,
+            registry: IopLibraryRegistry::new(),
+            sysmem: SysMem::new(USER_MEMORY_START, IOP_RAM_SIZE as u32),
+            threads: ThreadManager::new(),
+            intrman: IntrManager::new(),
+            modules: vec![],
+            tty: Tty::new("IOP TTY"),
+            calls: vec![],
+            unimplemented_calls: HashSet::new(),
+        };
+
+        for (name, library, count) in LIBRARIES.iter().copied() {
+            let mut functions = vec![];
+
+            for function in 0..count {
+                let address = HLE_STUB_BASE + kernel.calls.len() as u32 * 4;
+                kernel.write_u32(address, syscall(HLE_SYSCALL_BASE + kernel.calls.len() as u32));
+                kernel.calls.push((library, function));
+                functions.push(address);
+            }
+
+            if library != HleLibrary::Kernel {
+                kernel.registry.register(IrxExport {
+                    library: name.to_string(),
+                    version: LIBRARY_VERSION,
+                    address: 0,
+                    functions,
+                });
+            }
+        }
+
+        kernel
+    }
+
+    fn kernel_stub(&self, function: u16) -> u32 {
+        let index = self.calls.iter().position(|call| *call == (HleLibrary::Kernel, function)).unwrap_or(0);
+        HLE_STUB_BASE + index as u32 * 4
+    }
+
+    //Threads return here when their entry function does
+    pub fn thread_exit(&self) -> u32 {
+        self.kernel_stub(KERNEL_THREAD_EXIT)
+    }
+
+    fn physical(address: u32) -> usize {
+        address as usize & (IOP_RAM_SIZE - 1)
+    }
+
+    //Lengths from modules are cut off at the end of RAM rather than wrapping round or allocating
+    //whatever a bad pointer asks for
+    pub fn clamp_length(address: u32, length: u32) -> usize {
+        (length as usize).min(IOP_RAM_SIZE - IopKernel::physical(address))
+    }
+
+    pub fn read_u32(&self, address: u32) -> u32 {
+        let bytes = self.read_bytes(address, 4);
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    pub fn write_u32(&self, address: u32, value: u32) {
+        self.write_bytes(address, &value.to_le_bytes());
+    }
+
+    pub fn read_bytes(&self, address: u32, length: usize) -> Vec<u8> {
+        let ram = self.ram.borrow();
+        (0..length).map(|offset| ram[IopKernel::physical(address.wrapping_add(offset as u32))]).collect()
+    }
+
+    pub fn write_bytes(&self, address: u32, data: &[u8]) {
+        let mut ram = self.ram.borrow_mut();
+
+        for (offset, byte) in data.iter().copied().enumerate() {
+            ram[IopKernel::physical(address.wrapping_add(offset as u32))] = byte;
+        }
+    }
+
+    //Up to the terminator, cut off if there isn't one in a sensible distance
+    pub fn read_string(&self, address: u32) -> Vec<u8> {
+        let ram = self.ram.borrow();
+
+        (0..4096).map(|offset| ram[IopKernel::physical(address.wrapping_add(offset))]).take_while(|byte| *byte != 0).collect()
+    }
+
+    //Standard MIPS calling convention, a0-a3 then the stack past the space reserved for them
+    pub fn argument(&self, cpu: &IopCpu, index: usize) -> u32 {
+        if index < 4 {
+            cpu.read_register(A0_REG + index as u8)
+        } else {
+            self.read_u32(cpu.read_register(SP_REG).wrapping_add(16 + (index as u32 - 4) * 4))
+        }
+    }
+
+    //printf style output with the format at the given argument and the values after it
+    pub fn printf(&self, cpu: &IopCpu, format_argument: usize) -> Vec<u8> {
+        let mut index = format_argument;
+
+        format(&self.read_string(self.argument(cpu, format_argument)), || {
+            index += 1;
+            self.argument(cpu, index)
+        }, |address| self.read_string(address))
+    }
+
+    //Reported once each through the TTY, modules tend to call the same thing over and over
+    pub fn unimplemented(&mut self, library: &'static str, function: u16) -> Option<u32> {
+        if self.unimplemented_calls.insert((library, function)) {
+            self.tty.write(format!("HLE kernel doesn't implement {} function {}\n", library, function).as_bytes());
+        }
+
+        Some(0)
+    }
+
+    //Relocates the module into freshly allocated memory, links it against everything registered so
+    //far and starts a thread at its entry point with argc and argv like loadcore would
+    pub fn load_module(&mut self, data: &[u8], arguments: &[&str]) -> Result<LoadedModule, Box<dyn Error>> {
+        let module = IrxModule::parse(data)?;
+
+        let base = match self.sysmem.alloc(ALLOC_FIRST, module.size(), 0) {
+            Some(base) => base,
+            None => return Err(create_io_error(std::io::ErrorKind::OutOfMemory, &format!("no room in IOP memory for {}!", module.name))),
+        };
+
+        let loaded = {
+            let mut ram = self.ram.borrow_mut();
+            module.load(&mut ram[..], base, &mut self.registry)?
+        };
+
+        //argv[0] is the module name, the strings go right after the pointers
+        let mut argv: Vec<&str> = vec![&module.name];
+        argv.extend_from_slice(arguments);
+
+        let pointers_size = (argv.len() as u32 + 1) * 4;
+        let strings_size: u32 = argv.iter().map(|argument| argument.len() as u32 + 1).sum();
+        let argv_address = self.sysmem.alloc(ALLOC_LAST, pointers_size + strings_size, 0).unwrap_or(0);
+        let mut string_address = argv_address + pointers_size;
+
+        for (index, argument) in argv.iter().enumerate() {
+            self.write_u32(argv_address + index as u32 * 4, string_address);
+            self.write_bytes(string_address, &[argument.as_bytes(), &[0]].concat());
+            string_address += argument.len() as u32 + 1;
+        }
+
+        self.write_u32(argv_address + argv.len() as u32 * 4, 0);
+
+        let stack = match self.sysmem.alloc(ALLOC_LAST, MODULE_STACK_SIZE, 0) {
+            Some(stack) => stack,
+            None => return Err(create_io_error(std::io::ErrorKind::OutOfMemory, &format!("no room for {}'s stack!", module.name))),
+        };
+
+        let thread = self.threads.create_thread(loaded.entry, stack, MODULE_STACK_SIZE, MODULE_THREAD_PRIORITY, loaded.gp);
+        self.threads.start_thread(thread, (argv.len() as u32, argv_address), self.thread_exit());
+
+        self.modules.push(loaded.clone());
+        Ok(loaded)
+    }
+
+    //Runs the current thread, switching threads and calling interrupt handlers as the HLE calls ask for it
+    pub fn run(&mut self, cpu: &mut IopCpu, cycles: u64) {
+        let target = cpu.cycles + cycles;
+
+        while cpu.cycles < target {
+            self.threads.wake_delayed(cpu.cycles);
+            self.dispatch_interrupt(cpu);
+
+            if self.threads.current().is_none() && !self.intrman.in_handler() {
+                self.threads.reschedule(cpu);
+
+                if self.threads.current().is_none() {
+                    let until = self.threads.next_wakeup().unwrap_or(target).min(target);
+                    cpu.idle(until.saturating_sub(cpu.cycles).clamp(1, IDLE_CYCLES));
+                    continue;
+                }
+            }
+
+            cpu.step();
+
+            if let Some(call) = cpu.hle_call.take() {
+                self.call(cpu, call);
+            }
+        }
+    }
+
+    //The lowest pending interrupt with a handler gets called on the interrupt stack
+    fn dispatch_interrupt(&mut self, cpu: &mut IopCpu) {
+        if self.intrman.in_handler() || !self.intrman.enabled || !cpu.at_instruction_boundary() {
+            return;
+        }
+
+        let pending = cpu.bus.intc.read(I_STAT) & cpu.bus.intc.read(I_MASK);
+
+        let (interrupt, handler) = match (0..32).filter(|interrupt| pending & (1 << interrupt) != 0).find_map(|interrupt| self.intrman.handler(interrupt).map(|handler| (interrupt, handler))) {
+            Some(found) => found,
+            None => return,
+        };
+
+        let context = self.threads.current().map(|_| Context::save(cpu));
+        self.intrman.enter(interrupt, context);
+
+        cpu.write_register(A0_REG, handler.argument);
+        cpu.write_register(GP_REG, handler.gp);
+        cpu.write_register(SP_REG, INTERRUPT_STACK_TOP);
+        cpu.write_register(RA_REG, self.kernel_stub(KERNEL_INTERRUPT_RETURN));
+        cpu.set_pc(handler.function);
+    }
+
+    fn call(&mut self, cpu: &mut IopCpu, call: u32) {
+        let (library, function) = match self.calls.get(call as usize) {
+            Some(call) => *call,
+            None => return,
+        };
+
+        //Everything returns to the caller unless it says otherwise
+        cpu.set_pc(cpu.read_register(RA_REG));
+
+        let result = match library {
+            HleLibrary::Sysmem => sysmem::call(self, cpu, function),
+            HleLibrary::Loadcore => self.call_loadcore(cpu, function),
+            HleLibrary::Thbase => threadman::call_thbase(self, cpu, function),
+            HleLibrary::Thsemap => threadman::call_thsemap(self, cpu, function),
+            HleLibrary::Thevent => threadman::call_thevent(self, cpu, function),
+            HleLibrary::Intrman => intrman::call(self, cpu, function),
+            HleLibrary::Sysclib => sysclib::call(self, cpu, function),
+            HleLibrary::Stdio => stdio::call(self, cpu, function),
+            HleLibrary::Kernel => self.call_kernel(cpu, function),
+        };
+
+        if let Some(value) = result {
+            cpu.write_register(V0_REG, value);
+        }
+
+        //Threads can't be switched until the handler is done
+        if !self.intrman.in_handler() {
+            self.threads.reschedule(cpu);
+        }
+    }
+
+    fn call_kernel(&mut self, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+        match function {
+            KERNEL_THREAD_EXIT => self.threads.exit_current(),
+            //A handler returning 0 leaves its interrupt disabled
+            KERNEL_INTERRUPT_RETURN => {
+                if let Some((interrupt, context)) = self.intrman.leave() {
+                    cpu.bus.intc.write(I_STAT, 0, 1 << interrupt);
+
+                    if cpu.read_register(V0_REG) == 0 {
+                        cpu.bus.intc.write(I_MASK, 0, 1 << interrupt);
+                    }
+
+                    if let Some(context) = context {
+                        context.restore(cpu);
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        None
+    }
+
+    fn call_loadcore(&mut self, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+        let a0 = self.argument(cpu, 0);
+
+        match function {
+            //FlushIcache and FlushDcache, there are no caches here
+            4 | 5 => Some(0),
+            //RegisterLibraryEntries(export table), the table is the same one the IRX loader finds
+            6 => {
+                let name = self.read_bytes(a0 + 12, 8);
+                let length = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+                let functions = (0..).map(|index| self.read_u32(a0 + 20 + index * 4)).take_while(|function| *function != 0).collect();
+
+                self.registry.register(IrxExport {
+                    library: String::from_utf8_lossy(&name[..length]).to_string(),
+                    version: self.read_u32(a0 + 8) as u16,
+                    address: a0,
+                    functions,
+                });
+
+                Some(0)
+            },
+            7 => Some(0),
+            _ => self.unimplemented("loadcore", function),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IopKernel;
+    use super::super::threadman::call_thsemap;
+    use crate::io_processor::{create_iop_ram, IopCpu};
+    use crate::sif::create_sif;
+
+    fn jal(target: u32) -> u32 {
+        0x0c000000 | ((target >> 2) & 0x03ffffff)
+    }
+
+    fn write_program(kernel: &IopKernel, address: u32, program: &[u32]) {
+        for (index, word) in program.iter().copied().enumerate() {
+            kernel.write_u32(address + index as u32 * 4, word);
+        }
+    }
+
+    #[test]
+    fn test_threads_and_semaphores() {
+        let ram = create_iop_ram();
+        let mut cpu = IopCpu::new(&[], ram.clone(), create_sif());
+        let mut kernel = IopKernel::new(ram);
+
+        let stub = |library: &str, function: u16| kernel.registry.resolve(library, function).unwrap();
+        let (wait_sema, signal_sema, sleep) = (stub("thsemap", 8), stub("thsemap", 6), stub("thbase", 24));
+        let semaphore = kernel.threads.create_semaphore(0, 0, 0, 1);
+
+        //The high priority thread blocks on the semaphore then stores 1 to 0x7000
+        write_program(&kernel, 0x20000, &[
+            0x24040000 | semaphore, //addiu a0, zero, semaphore
+            jal(wait_sema),
+            0,
+            0x24080001, //addiu t0, zero, 1
+            0xac087000, //sw t0, 0x7000(zero)
+            jal(sleep),
+            0,
+        ]);
+
+        //The low priority one signals it and stores 2 to 0x7004
+        write_program(&kernel, 0x21000, &[
+            0x24040000 | semaphore,
+            jal(signal_sema),
+            0,
+            0x24080002,
+            0xac087004,
+            jal(sleep),
+            0,
+        ]);
+
+        let exit = kernel.thread_exit();
+        let high = kernel.threads.create_thread(0x20000, 0x30000, 0x1000, 10, 0);
+        let low = kernel.threads.create_thread(0x21000, 0x31000, 0x1000, 20, 0);
+        kernel.threads.start_thread(low, (0, 0), exit);
+        kernel.threads.start_thread(high, (0, 0), exit);
+
+        //Four instructions in, the high priority thread is waiting and the other one is running
+        kernel.run(&mut cpu, 4);
+        assert_eq!(kernel.threads.current(), Some(low));
+        assert_eq!(kernel.read_u32(0x7000), 0);
+
+        //ReferSemaStatus sees the waiting thread, the low priority thread sets a0 to the semaphore anyway
+        cpu.write_register(4, semaphore);
+        cpu.write_register(5, 0x7100);
+        assert_eq!(call_thsemap(&mut kernel, &mut cpu, 11), Some(0));
+        assert_eq!((0..6).map(|index| kernel.read_u32(0x7100 + index * 4)).collect::<Vec<u32>>(), vec![0, 0, 0, 1, 0, 1]);
+
+        //Signalling wakes the high priority thread straight away so it stores first
+        kernel.run(&mut cpu, 200);
+        assert_eq!(kernel.read_u32(0x7000), 1);
+        assert_eq!(kernel.read_u32(0x7004), 2);
+        assert_eq!(kernel.threads.semaphore(semaphore).unwrap().count, 0);
+        assert_eq!(kernel.threads.current(), None);
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use super::sifcmd::{word, words_to_bytes};
+use super::{EeAccess, RpcServer};
+
+pub const LIBSD_SID: u32 = 0x80000701;
+
+const SD_INIT: u32 = 0x8000;
+const SD_SET_PARAM: u32 = 0x8010;
+const SD_GET_PARAM: u32 = 0x8020;
+const SD_SET_SWITCH: u32 = 0x8030;
+const SD_GET_SWITCH: u32 = 0x8040;
+const SD_SET_ADDR: u32 = 0x8050;
+const SD_GET_ADDR: u32 = 0x8060;
+const SD_SET_CORE_ATTR: u32 = 0x8070;
+const SD_GET_CORE_ATTR: u32 = 0x8080;
+
+//The sdrpc server on top of LIBSD, nothing reaches the SPU2 yet so it just remembers what was set
+//and hands it back, the kind of setting is kept alongside the entry since they overlap
+pub struct LibsdServer {
+    values: HashMap<(u32, u32), u32>,
+}
+
+impl LibsdServer {
+    pub fn new() -> LibsdServer {
+        LibsdServer {
+            values: HashMap::new(),
+        }
+    }
+
+    fn get(&self, kind: u32, entry: u32) -> u32 {
+        self.values.get(&(kind, entry)).copied().unwrap_or(0)
+    }
+}
+
+impl RpcServer for LibsdServer {
+    fn call(&mut self, function: u32, arguments: &[u8], _ee: &mut EeAccess) -> Vec<u8> {
+        let (entry, value) = (word(arguments, 0), word(arguments, 1));
+
+        let result = match function {
+            SD_INIT => {
+                self.values.clear();
+                0
+            },
+            SD_SET_PARAM | SD_SET_SWITCH | SD_SET_ADDR | SD_SET_CORE_ATTR => {
+                self.values.insert((function, entry), value);
+                0
+            },
+            //Each getter is 0x10 after its setter
+            SD_GET_PARAM | SD_GET_SWITCH | SD_GET_ADDR | SD_GET_CORE_ATTR => self.get(function - 0x10, entry),
+            _ => 0,
+        };
+
+        words_to_bytes(&[result])
+    }
+}
@@ -0,0 +1,56 @@
+use super::sifcmd::words_to_bytes;
+use super::{EeAccess, RpcServer};
+
+pub const MCSERV_SID: u32 = 0x80000400;
+
+const MC_INIT: u32 = 0x70;
+const MC_INIT_NEW: u32 = 0xfe;
+
+//What libmc gets back for a slot with nothing in it
+const MC_RESULT_NO_CARD: i32 = -10;
+
+//Both memory card slots are always empty, games handle that and carry on without saving
+pub struct McservServer;
+
+impl McservServer {
+    pub fn new() -> McservServer {
+        McservServer
+    }
+}
+
+impl RpcServer for McservServer {
+    fn call(&mut self, function: u32, _arguments: &[u8], _ee: &mut EeAccess) -> Vec<u8> {
+        let result = match function {
+            MC_INIT | MC_INIT_NEW => 0,
+            _ => MC_RESULT_NO_CARD,
+        };
+
+        //Get info reads type, free space and format after the result, all 0 for no card
+        words_to_bytes(&[result as u32, 0, 0, 0])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MCSERV_SID, MC_INIT, MC_RESULT_NO_CARD};
+    use super::super::test::{bind, call, ee_word};
+    use super::super::IopHle;
+    use crate::emotion_engine::cpu::test::create_mock_memory;
+
+    #[test]
+    fn test_slots_are_empty() {
+        let mut memory = create_mock_memory();
+        let sif = memory.sif();
+        let mut hle = IopHle::new(memory.iop_ram(), sif.clone());
+
+        let server = bind(&mut hle, &sif, &mut memory, MCSERV_SID);
+        assert_ne!(server.0, 0);
+
+        call(&mut hle, &sif, &mut memory, server, MC_INIT, &[0], (0x20000, 16));
+        assert_eq!(ee_word(&memory, 0x20000), 0);
+
+        //mcGetInfo on port 0, everything after the result is 0 for no card
+        call(&mut hle, &sif, &mut memory, server, 1, &[0, 0, 0, 0], (0x20000, 16));
+        assert_eq!((0..4).map(|index| ee_word(&memory, 0x20000 + index * 4)).collect::<Vec<u32>>(), vec![MC_RESULT_NO_CARD as u32, 0, 0, 0]);
+    }
+}
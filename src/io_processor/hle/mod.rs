@@ -0,0 +1,472 @@
+mod fileio;
+mod intrman;
+mod kernel;
+mod libsd;
+mod mcserv;
+mod padman;
+mod printf;
+mod sifcmd;
+mod stdio;
+mod sysclib;
+mod sysmem;
+mod threadman;
+
+pub use fileio::{FileDevice, FileioServer, HostDevice, IsoDevice};
+pub use kernel::IopKernel;
+pub use libsd::LibsdServer;
+pub use mcserv::McservServer;
+pub use padman::PadmanServer;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use super::bus::IopRam;
+use crate::emotion_engine::Memory;
+use crate::io::{BinCueImage, BlockDevice, ChdImage, CisoImage, ISOFileReader, TrackType};
+use crate::sif::{SharedSif, SIF_SMCOM, SIF_SMFLG, SIF_TAG_WORDS};
+use sifcmd::*;
+
+//A corner of IOP RAM the modules would normally allocate, the EE is told about these addresses
+//so they have to point somewhere real
+const COMMAND_BUFFER: u32 = 0x1C0000;
+const SERVER_TABLE: u32 = 0x1C1000;
+const SERVER_SIZE: u32 = 0x40;
+const SERVER_BUFFERS: u32 = 0x1C2000;
+const SERVER_BUFFER_SIZE: u32 = 0x4000;
+const SERVER_COUNT: usize = 4;
+
+const SIF_TAG_IRQ: u32 = 1 << 31;
+const EE_TAG_CNT: u32 = 1 << 28;
+const EE_TAG_END: u32 = 7 << 28;
+const EE_TAG_IRQ: u32 = 1 << 31;
+
+//The RPC servers the BIOS modules would register, PADMAN answers on both of its ids
+const SERVERS: [(u32, usize); 5] = [
+    (fileio::FILEIO_SID, 0),
+    (padman::PADMAN_SID, 1),
+    (padman::PADMAN_EXT_SID, 1),
+    (mcserv::MCSERV_SID, 2),
+    (libsd::LIBSD_SID, 3),
+];
+
+//What a server can do on the EE side, reads go straight to EE memory and writes are sent over SIF0
+//in front of the RPC reply like the real modules would DMA them
+pub struct EeAccess<'a> {
+    memory: &'a Memory,
+    writes: Vec<(u32, Vec<u8>)>,
+}
+
+impl<'a> EeAccess<'a> {
+    pub fn read(&self, address: u32, length: usize) -> Vec<u8> {
+        (0..length).map(|offset| self.memory.try_read_address(address as usize + offset).unwrap_or(0)).collect()
+    }
+
+    //SIF0 only moves whole quadwords so the address needs to be 16 byte aligned
+    pub fn write(&mut self, address: u32, data: Vec<u8>) {
+        self.writes.push((address, data));
+    }
+}
+
+pub trait RpcServer {
+    fn call(&mut self, function: u32, arguments: &[u8], ee: &mut EeAccess) -> Vec<u8>;
+
+    //Called every time the HLE runs, for servers that push data to the EE on their own
+    fn update(&mut self, _ee: &mut EeAccess) {}
+}
+
+//Stands in for SIFMAN, SIFCMD and the RPC servers so the EE can use SIF without any IOP code running
+//Packets from the EE are taken straight out of the SIF1 FIFO and replies are pushed into SIF0
+pub struct IopHle {
+    ram: IopRam,
+    sif: SharedSif,
+    ee_command_buffer: u32,
+    software_registers: [u32; SIF_SREG_COUNT],
+    pub fileio: FileioServer,
+    pub padman: PadmanServer,
+    pub mcserv: McservServer,
+    pub libsd: LibsdServer,
+}
+
+struct SifPacket {
+    address: u32,
+    flags: u32,
+    data: Vec<u8>,
+}
+
+//Tags and data are both padded out to quadwords, nothing is taken until the whole packet is there
+fn take_packet(fifo: &mut VecDeque<u32>) -> Option<SifPacket> {
+    if fifo.len() < SIF_TAG_WORDS {
+        return None;
+    }
+
+    let (tag, words) = (fifo[0], fifo[1] as usize);
+    let padded_words = (words + 3) & !3;
+
+    if fifo.len() < SIF_TAG_WORDS + padded_words {
+        return None;
+    }
+
+    let data: Vec<u32> = fifo.drain(..SIF_TAG_WORDS + padded_words).skip(SIF_TAG_WORDS).take(words).collect();
+
+    Some(SifPacket {
+        address: tag & 0x00ffffff,
+        flags: tag & 0xff000000,
+        data: words_to_bytes(&data),
+    })
+}
+
+impl IopHle {
+    pub fn new(ram: IopRam, sif: SharedSif) -> IopHle {
+        let mut hle = IopHle {
+            ram,
+            sif,
+            ee_command_buffer: 0,
+            software_registers: [0; SIF_SREG_COUNT],
+            fileio: FileioServer::new(),
+            padman: PadmanServer::new(),
+            mcserv: McservServer::new(),
+            libsd: LibsdServer::new(),
+        };
+
+        hle.reset();
+        hle
+    }
+
+    //Homebrew reads and writes host: files in this directory, nothing outside it can be reached
+    pub fn mount_host(&mut self, root: &str) {
+        self.fileio.mount("host", Box::new(HostDevice::new(root)));
+    }
+
+    //cdrom0: from a disc image, the format is picked from the extension and anything unknown is read as a plain ISO
+    pub fn mount_disc(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let extension = Path::new(path).extension().map_or(String::new(), |extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_str() {
+            "cue" => self.mount_block_device(BinCueImage::open(path)?),
+            //A bin without its cue sheet, PS2 CDs are all mode 2
+            "bin" => self.mount_block_device(BinCueImage::from_raw_image(File::open(path)?, TrackType::Mode2_2352)?),
+            "chd" => self.mount_block_device(ChdImage::open(path)?),
+            "cso" | "zso" => self.mount_block_device(CisoImage::open(path)?),
+            _ => {
+                self.mount_iso(ISOFileReader::new(path)?);
+                Ok(())
+            },
+        }
+    }
+
+    //The filesystem only sees the first data track so list them all for mixed mode discs
+    fn mount_block_device<D: BlockDevice + 'static>(&mut self, device: D) -> Result<(), Box<dyn Error>> {
+        for track in device.tracks() {
+            println!("[IOP HLE] cdrom0 track {} {} at sector {}, {} sectors", track.number, track.track_type, track.start_lsn, track.sector_count);
+        }
+
+        self.mount_iso(ISOFileReader::from_block_device(device)?);
+
+        Ok(())
+    }
+
+    fn mount_iso<R: Read + Seek + 'static>(&mut self, mut reader: ISOFileReader<R>) {
+        match reader.read_boot_executable() {
+            Ok(boot) => {
+                let video_mode = boot.video_mode.map_or("no video mode".to_string(), |video_mode| video_mode.to_string());
+                println!("[IOP HLE] cdrom0 boots {} ({}, {})", boot.path, boot.serial.as_deref().unwrap_or("no serial"), video_mode);
+
+                match boot.entry() {
+                    Ok(entry) => println!("[IOP HLE] {} is {} bytes starting at {:#x}", boot.path, boot.elf.len(), entry),
+                    Err(error) => println!("[IOP HLE] {} isn't an ELF: {}", boot.path, error),
+                }
+            },
+            Err(error) => println!("[IOP HLE] cdrom0 has no boot executable: {}", error),
+        }
+
+        if let Some(layer_break) = reader.layer_break() {
+            println!("[IOP HLE] cdrom0 is dual layer, layer 1 starts at sector {}", layer_break);
+        }
+
+        self.fileio.mount("cdrom0", Box::new(IsoDevice::new(reader)));
+    }
+
+    //What the IOP does at the end of booting, tell the EE where to send commands and that SIF is up
+    fn reset(&mut self) {
+        self.ee_command_buffer = 0;
+        self.software_registers = [0; SIF_SREG_COUNT];
+        self.fileio.close_all();
+
+        let mut sif = self.sif.borrow_mut();
+        sif.write_iop(SIF_SMCOM, COMMAND_BUFFER);
+        sif.write_iop(SIF_SMFLG, SIF_STAT_SIFINIT | SIF_STAT_CMDINIT | SIF_STAT_BOOTEND);
+    }
+
+    fn server(&mut self, index: usize) -> &mut dyn RpcServer {
+        match index {
+            0 => &mut self.fileio,
+            1 => &mut self.padman,
+            2 => &mut self.mcserv,
+            _ => &mut self.libsd,
+        }
+    }
+
+    fn server_address(index: usize) -> u32 {
+        SERVER_TABLE + index as u32 * SERVER_SIZE
+    }
+
+    fn server_buffer(index: usize) -> u32 {
+        SERVER_BUFFERS + index as u32 * SERVER_BUFFER_SIZE
+    }
+
+    //Only addresses handed out by a bind name a server
+    fn server_index(server: u32) -> Option<usize> {
+        let offset = server.checked_sub(SERVER_TABLE)?;
+        let index = (offset / SERVER_SIZE) as usize;
+
+        if offset % SERVER_SIZE == 0 && index < SERVER_COUNT {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn process(&mut self, ee_memory: &Memory) {
+        let mut ee = EeAccess {
+            memory: ee_memory,
+            writes: vec![],
+        };
+
+        loop {
+            let packet = match take_packet(&mut self.sif.borrow_mut().sif1_fifo) {
+                Some(packet) => packet,
+                None => break,
+            };
+
+            {
+                let mut ram = self.ram.borrow_mut();
+                let start = packet.address as usize % ram.len();
+                let end = (start + packet.data.len()).min(ram.len());
+                ram[start..end].copy_from_slice(&packet.data[..end - start]);
+            }
+
+            //The command itself is always the last part of a transfer and asks for an interrupt
+            if packet.flags & SIF_TAG_IRQ != 0 {
+                self.handle_command(&packet.data, &mut ee);
+            }
+        }
+
+        for index in 0..SERVER_COUNT {
+            self.server(index).update(&mut ee);
+        }
+
+        for (address, data) in ee.writes.drain(..) {
+            self.send_to_ee(address, &data, true);
+        }
+    }
+
+    fn handle_command(&mut self, packet: &[u8], ee: &mut EeAccess) {
+        let header = SifCmdHeader::read(packet);
+
+        match header.cid {
+            SIF_CMD_CHANGE_SADDR | SIF_CMD_INIT_CMD => self.ee_command_buffer = word(packet, CMD_BUFFER),
+            SIF_CMD_SET_SREG => self.software_registers[word(packet, SREG_INDEX) as usize % SIF_SREG_COUNT] = word(packet, SREG_VALUE),
+            //The EE rebooting the IOP, every module is gone so start over
+            SIF_CMD_RESET_CMD => self.reset(),
+            SIF_CMD_RPC_BIND => {
+                let sid = word(packet, RPC_BIND_SID);
+
+                //Binding to a server that doesn't exist (yet) hands back 0 and the EE keeps retrying
+                let (server, buffer) = match SERVERS.iter().find(|(server_sid, _)| *server_sid == sid) {
+                    Some((_, index)) => (IopHle::server_address(*index), IopHle::server_buffer(*index)),
+                    None => (0, 0),
+                };
+
+                self.send_command(rpc_end_packet(packet, SIF_CMD_RPC_BIND, server, buffer));
+            },
+            SIF_CMD_RPC_CALL => {
+                let server = word(packet, RPC_CALL_SERVER);
+
+                //A call to something that was never bound is dropped, there's no server to answer it
+                let index = match IopHle::server_index(server) {
+                    Some(index) => index,
+                    None => {
+                        println!("[IOP HLE] RPC call to unknown server {:#x} ignored", server);
+                        return;
+                    },
+                };
+                let buffer = IopHle::server_buffer(index);
+
+                let arguments = {
+                    let ram = self.ram.borrow();
+                    let start = (buffer as usize).min(ram.len());
+                    let length = (word(packet, RPC_CALL_SEND_SIZE) as usize).min(SERVER_BUFFER_SIZE as usize);
+                    ram[start..(start + length).min(ram.len())].to_vec()
+                };
+
+                let mut reply = self.server(index).call(word(packet, RPC_CALL_NUMBER), &arguments, ee);
+
+                //Anything the server wrote to the EE has to be there before the client wakes up
+                for (address, data) in ee.writes.drain(..) {
+                    self.send_to_ee(address, &data, false);
+                }
+
+                //The reply comes out of the server's buffer so it can't be any bigger than that
+                let (receive, receive_size) = (word(packet, RPC_CALL_RECEIVE), (word(packet, RPC_CALL_RECEIVE_SIZE) as usize).min(SERVER_BUFFER_SIZE as usize));
+
+                if receive != 0 && receive_size != 0 {
+                    reply.resize(receive_size, 0);
+                    self.send_to_ee(receive, &reply, false);
+                }
+
+                self.send_command(rpc_end_packet(packet, SIF_CMD_RPC_CALL, server, buffer));
+            },
+            SIF_CMD_RPC_RDATA => {
+                let data = {
+                    let ram = self.ram.borrow();
+                    let start = word(packet, RPC_RDATA_SOURCE) as usize % ram.len();
+                    let end = (start + word(packet, RPC_RDATA_SIZE) as usize).min(ram.len());
+                    ram[start..end].to_vec()
+                };
+
+                self.send_to_ee(word(packet, RPC_RDATA_DESTINATION), &data, false);
+                self.send_command(rpc_end_packet(packet, SIF_CMD_RPC_RDATA, 0, 0));
+            },
+            _ => {},
+        }
+    }
+
+    fn send_command(&mut self, packet: Vec<u8>) {
+        //Nowhere to put it until the EE has set up its side
+        if self.ee_command_buffer != 0 {
+            self.send_to_ee(self.ee_command_buffer, &packet, true);
+        }
+    }
+
+    //Pushes an EE tag and the data padded out to quadwords, the last part of a transfer ends the EE's chain
+    fn send_to_ee(&mut self, address: u32, data: &[u8], last: bool) {
+        let quadwords = data.len().div_ceil(16) as u32;
+        let id = if last { EE_TAG_END | EE_TAG_IRQ } else { EE_TAG_CNT };
+
+        let mut padded = data.to_vec();
+        padded.resize(quadwords as usize * 16, 0);
+
+        let mut sif = self.sif.borrow_mut();
+        sif.sif0_fifo.extend(&[quadwords | id, address, 0, 0]);
+        sif.sif0_fifo.extend(padded.chunks(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])));
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::sifcmd::*;
+    use super::IopHle;
+    use crate::emotion_engine::cpu::test::create_mock_memory;
+    use crate::emotion_engine::Memory;
+    use crate::io_processor::bus::create_iop_ram;
+    use crate::sif::{create_sif, SharedSif, SIF_SMCOM, SIF_SMFLG};
+
+    //Where the tests point the EE's command buffer, RPC ends land here
+    pub const EE_COMMAND_BUFFER: u32 = 0x10000;
+
+    //What sceSifSendCmd puts in the SIF1 FIFO, the extra data first then the packet with an interrupt
+    pub fn send_command(sif: &SharedSif, words: &[u32], extra: Option<(u32, &[u32])>) {
+        let mut sif = sif.borrow_mut();
+
+        if let Some((address, data)) = extra {
+            sif.sif1_fifo.extend(&[address, data.len() as u32, 0, 0]);
+            sif.sif1_fifo.extend(data);
+            sif.sif1_fifo.extend(vec![0; ((data.len() + 3) & !3) - data.len()]);
+        }
+
+        sif.sif1_fifo.extend(&[0x80000000 | 0x1C0000, words.len() as u32, 0, 0]);
+        sif.sif1_fifo.extend(words);
+        sif.sif1_fifo.extend(vec![0; ((words.len() + 3) & !3) - words.len()]);
+    }
+
+    //Runs SIF0 chains until the FIFO is empty so the replies end up in EE memory like a real client would get them
+    pub fn receive_on_ee(sif: &SharedSif, memory: &mut Memory) {
+        memory.write_address(0x1000E000, 4, &1u32.to_le_bytes());
+
+        while !sif.borrow().sif0_fifo.is_empty() {
+            let remaining = sif.borrow().sif0_fifo.len();
+
+            memory.write_address(0x1000C000, 4, &0x104u32.to_le_bytes());
+            memory.run_dma();
+
+            assert!(sif.borrow().sif0_fifo.len() < remaining, "SIF0 chain is stuck");
+        }
+    }
+
+    pub fn ee_word(memory: &Memory, address: u32) -> u32 {
+        (0..4).rev().fold(0, |word, offset| word << 8 | memory.read_address(address as usize + offset) as u32)
+    }
+
+    //Binds sid and returns the server and buffer addresses from the RPC end
+    pub fn bind(hle: &mut IopHle, sif: &SharedSif, memory: &mut Memory, sid: u32) -> (u32, u32) {
+        send_command(sif, &[16 + 4 * 4, 0, SIF_CMD_CHANGE_SADDR, 0, EE_COMMAND_BUFFER], None);
+        send_command(sif, &[36, 0, SIF_CMD_RPC_BIND, 0, 1, 0x2000, 7, 0x3000, sid], None);
+        hle.process(memory);
+        receive_on_ee(sif, memory);
+
+        assert_eq!(ee_word(memory, EE_COMMAND_BUFFER + 8), SIF_CMD_RPC_END);
+        (ee_word(memory, EE_COMMAND_BUFFER + 36), ee_word(memory, EE_COMMAND_BUFFER + 40))
+    }
+
+    //sceSifCallRpc with the arguments sent to the server's buffer and the reply going to receive
+    pub fn call(hle: &mut IopHle, sif: &SharedSif, memory: &mut Memory, (server, buffer): (u32, u32), function: u32, arguments: &[u32], receive: (u32, u32)) {
+        let size = arguments.len() as u32 * 4;
+        send_command(sif, &[56, size << 8, SIF_CMD_RPC_CALL, 0, 2, 0x2000, 8, 0x3000, function, size, receive.0, receive.1, 0, server], Some((buffer, arguments)));
+        hle.process(memory);
+        receive_on_ee(sif, memory);
+    }
+
+    #[test]
+    fn test_rpc_bind_and_call() {
+        let (ram, sif) = (create_iop_ram(), create_sif());
+        let memory = create_mock_memory();
+        let mut hle = IopHle::new(ram, sif.clone());
+
+        assert_eq!(sif.borrow().read(SIF_SMCOM), 0x1C0000);
+        assert_eq!(sif.borrow().read(SIF_SMFLG), 0x70000);
+
+        send_command(&sif, &[16 + 4 * 4, 0, SIF_CMD_CHANGE_SADDR, 0, 0x10000], None);
+        send_command(&sif, &[16 + 4 * 2, 0, SIF_CMD_SET_SREG, 0, 3, 0x55], None);
+
+        //Binding LIBSD
+        send_command(&sif, &[36, 0, SIF_CMD_RPC_BIND, 0, 1, 0x2000, 7, 0x3000, 0x80000701], None);
+        hle.process(&memory);
+        assert_eq!(hle.software_registers[3], 0x55);
+
+        let reply: Vec<u32> = sif.borrow_mut().sif0_fifo.drain(..).collect();
+        assert_eq!(&reply[..4], &[0xf0000003, 0x10000, 0, 0]);
+        assert_eq!(&reply[4..16], &[48, 0, SIF_CMD_RPC_END, 0, 1, 0x2000, 7, 0x3000, SIF_CMD_RPC_BIND, reply[13], reply[14], 0]);
+        let (server, buffer) = (reply[13], reply[14]);
+        assert_ne!(server, 0);
+
+        //sceSdSetParam then sceSdGetParam, the arguments go to the server's buffer ahead of the call
+        send_command(&sif, &[56, 8 << 8, SIF_CMD_RPC_CALL, 0, 2, 0x2000, 8, 0x3000, 0x8010, 8, 0, 0, 0, server], Some((buffer, &[0x0301, 0x1fff])));
+        send_command(&sif, &[56, 4 << 8, SIF_CMD_RPC_CALL, 0, 3, 0x2000, 9, 0x3000, 0x8020, 4, 0x20000, 16, 0, server], Some((buffer, &[0x0301])));
+        hle.process(&memory);
+
+        let reply: Vec<u32> = sif.borrow_mut().sif0_fifo.drain(..).collect();
+        //The first call only gets an RPC end, the second sends its result to the receive buffer first
+        assert_eq!(reply[4 + 8], SIF_CMD_RPC_CALL);
+        assert_eq!(&reply[16..24], &[0x10000001, 0x20000, 0, 0, 0x1fff, 0, 0, 0]);
+        assert_eq!(reply[24 + 4 + 8], SIF_CMD_RPC_CALL);
+    }
+
+    #[test]
+    fn test_call_to_unknown_server_is_ignored() {
+        let mut memory = create_mock_memory();
+        let sif = memory.sif();
+        let mut hle = IopHle::new(memory.iop_ram(), sif.clone());
+
+        //Past the server table and far out of IOP RAM, neither can reach a server
+        for server in [0x1C1000 + 4 * 0x40, 0xfffffff0] {
+            send_command(&sif, &[16 + 4 * 4, 0, SIF_CMD_CHANGE_SADDR, 0, EE_COMMAND_BUFFER], None);
+            send_command(&sif, &[56, 0, SIF_CMD_RPC_CALL, 0, 2, 0x2000, 8, 0x3000, 0, 0xffffffff, 0x20000, 0xffffffff, 0, server], None);
+            hle.process(&memory);
+            receive_on_ee(&sif, &mut memory);
+
+            assert_eq!(ee_word(&memory, EE_COMMAND_BUFFER + 8), 0);
+        }
+    }
+}
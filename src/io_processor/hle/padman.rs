@@ -0,0 +1,144 @@
+use super::sifcmd::{word, words_to_bytes};
+use super::{EeAccess, RpcServer};
+
+pub const PADMAN_SID: u32 = 0x8000010f;
+pub const PADMAN_EXT_SID: u32 = 0x8000011f;
+
+const PAD_OPEN: u32 = 0x80000100;
+const PAD_GET_BUTTON_MASK: u32 = 0x80000108;
+const PAD_GET_PORT_MAX: u32 = 0x8000010b;
+const PAD_GET_SLOT_MAX: u32 = 0x8000010c;
+const PAD_CLOSE: u32 = 0x8000010d;
+const PAD_END: u32 = 0x8000010e;
+
+const PORTS: usize = 2;
+const REPLY_SIZE: usize = 128;
+//libpad keeps two copies of pad_data and reads whichever has the newest frame
+const PAD_DATA_SIZE: usize = 128;
+const PAD_DATA_FRAMES: usize = 2;
+
+//Offsets into libpad's pad_data
+const PAD_DATA_FRAME: usize = 88;
+const PAD_DATA_LENGTH: usize = 96;
+const PAD_DATA_READY: usize = 103;
+const PAD_DATA_STATE: usize = 112;
+
+const PAD_TYPE_DIGITAL: u8 = 0x41;
+const PAD_STATE_STABLE: u32 = 6;
+
+#[derive(Copy, Clone, Default)]
+struct Port {
+    //EE address of the pad_data area, 0 until the port is opened
+    pad_area: u32,
+    buttons: u16,
+    frame: u32,
+    dirty: bool,
+}
+
+//A digital controller in each port, the buttons are set from outside and written into the
+//EE's pad area whenever they change
+pub struct PadmanServer {
+    ports: [Port; PORTS],
+}
+
+impl PadmanServer {
+    pub fn new() -> PadmanServer {
+        PadmanServer {
+            ports: [Port::default(); PORTS],
+        }
+    }
+
+    //Bits are pressed when set, the pad reports them active low
+    pub fn set_buttons(&mut self, port: usize, buttons: u16) {
+        if let Some(port) = self.ports.get_mut(port) {
+            port.dirty |= port.buttons != buttons;
+            port.buttons = buttons;
+        }
+    }
+
+    fn pad_data(port: &Port) -> Vec<u8> {
+        let mut frame = [0u8; PAD_DATA_SIZE];
+        frame[1] = PAD_TYPE_DIGITAL;
+        frame[2..4].copy_from_slice(&(!port.buttons).to_be_bytes());
+        frame[PAD_DATA_FRAME..PAD_DATA_FRAME + 4].copy_from_slice(&port.frame.to_le_bytes());
+        frame[PAD_DATA_LENGTH..PAD_DATA_LENGTH + 4].copy_from_slice(&32u32.to_le_bytes());
+        frame[PAD_DATA_READY] = 1;
+        frame[PAD_DATA_STATE..PAD_DATA_STATE + 4].copy_from_slice(&PAD_STATE_STABLE.to_le_bytes());
+
+        frame.repeat(PAD_DATA_FRAMES)
+    }
+}
+
+impl RpcServer for PadmanServer {
+    fn call(&mut self, _function: u32, arguments: &[u8], _ee: &mut EeAccess) -> Vec<u8> {
+        //Everything goes through one function number, the command is the first word
+        let (command, port) = (word(arguments, 0), word(arguments, 1) as usize);
+
+        let result = match command {
+            PAD_OPEN if port < PORTS => {
+                self.ports[port].pad_area = word(arguments, 3);
+                self.ports[port].dirty = true;
+                1
+            },
+            PAD_CLOSE if port < PORTS => {
+                self.ports[port].pad_area = 0;
+                1
+            },
+            PAD_END => {
+                self.ports = [Port::default(); PORTS];
+                1
+            },
+            PAD_GET_BUTTON_MASK => 0xffff,
+            PAD_GET_PORT_MAX => PORTS as u32,
+            PAD_GET_SLOT_MAX => 1,
+            _ => 0,
+        };
+
+        let mut reply = words_to_bytes(&[command, port as u32, word(arguments, 2), result]);
+        reply.resize(REPLY_SIZE, 0);
+        reply
+    }
+
+    fn update(&mut self, ee: &mut EeAccess) {
+        for port in self.ports.iter_mut().filter(|port| port.dirty && port.pad_area != 0) {
+            port.frame = port.frame.wrapping_add(1);
+            port.dirty = false;
+
+            ee.write(port.pad_area, PadmanServer::pad_data(port));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PADMAN_SID, PAD_OPEN};
+    use super::super::test::{bind, call, ee_word, receive_on_ee};
+    use super::super::IopHle;
+    use crate::emotion_engine::cpu::test::create_mock_memory;
+
+    #[test]
+    fn test_open_port_and_update_buttons() {
+        let mut memory = create_mock_memory();
+        let sif = memory.sif();
+        let mut hle = IopHle::new(memory.iop_ram(), sif.clone());
+
+        let server = bind(&mut hle, &sif, &mut memory, PADMAN_SID);
+        assert_ne!(server.0, 0);
+
+        //padPortOpen(0, 0, pad_area)
+        call(&mut hle, &sif, &mut memory, server, 1, &[PAD_OPEN, 0, 0, 0x30000], (0x20000, 128));
+        assert_eq!((0..4).map(|index| ee_word(&memory, 0x20000 + index * 4)).collect::<Vec<u32>>(), vec![PAD_OPEN, 0, 0, 1]);
+
+        //Nothing is pressed and both frames are written
+        assert_eq!(memory.read_address(0x30001), 0x41);
+        assert_eq!((memory.read_address(0x30002), memory.read_address(0x30003)), (0xff, 0xff));
+        assert_eq!(memory.read_address(0x30000 + 128 + 3), 0xff);
+
+        hle.padman.set_buttons(0, 0x0010);
+        hle.process(&memory);
+        receive_on_ee(&sif, &mut memory);
+
+        assert_eq!((memory.read_address(0x30002), memory.read_address(0x30003)), (0xff, 0xef));
+        assert_eq!(ee_word(&memory, 0x30000 + 88), 2);
+    }
+}
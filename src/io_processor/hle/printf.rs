@@ -0,0 +1,152 @@
+type Characters<'a> = std::iter::Peekable<std::iter::Copied<std::slice::Iter<'a, u8>>>;
+
+//A width or precision, * takes it from the arguments
+fn number<A: FnMut() -> u32>(characters: &mut Characters, next_argument: &mut A) -> Option<usize> {
+    if characters.peek() == Some(&b'*') {
+        characters.next();
+        return Some(next_argument() as i32 as usize);
+    }
+
+    let mut value = None;
+
+    while let Some(digit) = characters.peek().copied().filter(u8::is_ascii_digit) {
+        value = Some(value.unwrap_or(0) * 10 + (digit - b'0') as usize);
+        characters.next();
+    }
+
+    value
+}
+
+//The printf family as the IOP's sysclib implements it, arguments come one 32 bit word at a time
+//from wherever the caller keeps them and strings are read out of IOP memory
+pub fn format<A: FnMut() -> u32, S: Fn(u32) -> Vec<u8>>(format: &[u8], mut next_argument: A, read_string: S) -> Vec<u8> {
+    let mut output = vec![];
+    let mut characters = format.iter().copied().peekable();
+
+    while let Some(character) = characters.next() {
+        if character != b'%' {
+            output.push(character);
+            continue;
+        }
+
+        let (mut left_align, mut zero_pad, mut plus, mut space, mut alternate) = (false, false, false, false, false);
+
+        while let Some(flag) = characters.peek().copied() {
+            match flag {
+                b'-' => left_align = true,
+                b'0' => zero_pad = true,
+                b'+' => plus = true,
+                b' ' => space = true,
+                b'#' => alternate = true,
+                _ => break,
+            }
+
+            characters.next();
+        }
+
+        let width = number(&mut characters, &mut next_argument).unwrap_or(0);
+        let precision = if characters.peek() == Some(&b'.') {
+            characters.next();
+            Some(number(&mut characters, &mut next_argument).unwrap_or(0))
+        } else {
+            None
+        };
+
+        //h and l don't change anything with 32 bit ints, ll takes two words
+        let mut long_long = false;
+
+        while let Some(length) = characters.peek().copied() {
+            match length {
+                b'h' => {},
+                b'l' if long_long => {},
+                b'l' => {
+                    characters.next();
+                    long_long = characters.peek() == Some(&b'l');
+                    continue;
+                },
+                _ => break,
+            }
+
+            characters.next();
+        }
+
+        let conversion = match characters.next() {
+            Some(conversion) => conversion,
+            None => break,
+        };
+
+        let mut argument = |long_long: bool| -> u64 {
+            if long_long {
+                let low = next_argument() as u64;
+                low | ((next_argument() as u64) << 32)
+            } else {
+                next_argument() as u64
+            }
+        };
+
+        let (prefix, mut digits): (&[u8], Vec<u8>) = match conversion {
+            b'd' | b'i' => {
+                let value = if long_long { argument(true) as i64 } else { argument(false) as u32 as i32 as i64 };
+                let sign: &[u8] = if value < 0 { b"-" } else if plus { b"+" } else if space { b" " } else { b"" };
+                (sign, value.unsigned_abs().to_string().into_bytes())
+            },
+            b'u' => (b"", argument(long_long).to_string().into_bytes()),
+            b'x' => (if alternate { b"0x" } else { b"" }, format!("{:x}", argument(long_long)).into_bytes()),
+            b'X' => (if alternate { b"0X" } else { b"" }, format!("{:X}", argument(long_long)).into_bytes()),
+            b'o' => (if alternate { b"0" } else { b"" }, format!("{:o}", argument(long_long)).into_bytes()),
+            b'p' => (b"0x", format!("{:08x}", argument(false)).into_bytes()),
+            b'c' => (b"", vec![argument(false) as u8]),
+            b's' => {
+                let address = argument(false) as u32;
+                let mut string = if address == 0 { b"(null)".to_vec() } else { read_string(address) };
+
+                if let Some(precision) = precision {
+                    string.truncate(precision);
+                }
+
+                (b"", string)
+            },
+            b'%' => (b"", vec![b'%']),
+            //Anything unknown is printed as it was written
+            other => (b"", vec![b'%', other]),
+        };
+
+        if let (Some(precision), b'd' | b'i' | b'u' | b'x' | b'X' | b'o') = (precision, conversion) {
+            if digits.len() < precision {
+                digits.splice(0..0, std::iter::repeat_n(b'0', precision - digits.len()));
+            }
+        }
+
+        let padding = width.saturating_sub(prefix.len() + digits.len());
+
+        if left_align {
+            output.extend_from_slice(prefix);
+            output.extend(digits);
+            output.extend(std::iter::repeat_n(b' ', padding));
+        } else if zero_pad && conversion != b's' && conversion != b'c' {
+            output.extend_from_slice(prefix);
+            output.extend(std::iter::repeat_n(b'0', padding));
+            output.extend(digits);
+        } else {
+            output.extend(std::iter::repeat_n(b' ', padding));
+            output.extend_from_slice(prefix);
+            output.extend(digits);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::format;
+
+    #[test]
+    fn test_format() {
+        let mut arguments = vec![42, (-7i32) as u32, 0xbeef, 0x100, 5, 0x3000, 0x2000, 0x41, 0, 1].into_iter();
+        let read_string = |address: u32| if address == 0x3000 { b"hello".to_vec() } else { b"world".to_vec() };
+
+        let output = format(b"%d|%+4d|%#x|%08X|%-*s|%.3s|%c|%llu|%%", || arguments.next().unwrap_or(0), read_string);
+        assert_eq!(String::from_utf8(output).unwrap(), "42|  -7|0xbeef|00000100|hello|wor|A|4294967296|%");
+    }
+}
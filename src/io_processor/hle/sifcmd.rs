@@ -0,0 +1,102 @@
+//SIF command packets as the EE and IOP sifcmd libraries lay them out, all fields are 32 bit words
+//System commands have the top bit set, anything else is a user command registered by a module
+
+pub const SIF_CMD_CHANGE_SADDR: u32 = 0x80000000;
+pub const SIF_CMD_SET_SREG: u32 = 0x80000001;
+pub const SIF_CMD_INIT_CMD: u32 = 0x80000002;
+pub const SIF_CMD_RESET_CMD: u32 = 0x80000003;
+pub const SIF_CMD_RPC_END: u32 = 0x80000008;
+pub const SIF_CMD_RPC_BIND: u32 = 0x80000009;
+pub const SIF_CMD_RPC_CALL: u32 = 0x8000000A;
+pub const SIF_CMD_RPC_RDATA: u32 = 0x8000000C;
+
+//Set in SMFLG by the IOP as it boots, the EE waits for all three before using SIF
+pub const SIF_STAT_SIFINIT: u32 = 0x10000;
+pub const SIF_STAT_CMDINIT: u32 = 0x20000;
+pub const SIF_STAT_BOOTEND: u32 = 0x40000;
+
+pub const SIF_CMD_HEADER_WORDS: usize = 4;
+pub const SIF_SREG_COUNT: usize = 32;
+
+//Word offsets past the header, every RPC packet starts with rec_id, pkt_addr, rpc_id and client
+pub const RPC_REC_ID: usize = 4;
+pub const RPC_PKT_ADDR: usize = 5;
+pub const RPC_ID: usize = 6;
+pub const RPC_CLIENT: usize = 7;
+pub const RPC_BIND_SID: usize = 8;
+pub const RPC_CALL_NUMBER: usize = 8;
+pub const RPC_CALL_SEND_SIZE: usize = 9;
+pub const RPC_CALL_RECEIVE: usize = 10;
+pub const RPC_CALL_RECEIVE_SIZE: usize = 11;
+pub const RPC_CALL_SERVER: usize = 13;
+pub const RPC_RDATA_SOURCE: usize = 8;
+pub const RPC_RDATA_DESTINATION: usize = 9;
+pub const RPC_RDATA_SIZE: usize = 10;
+//CHANGE_SADDR and INIT_CMD carry the EE's command buffer, SET_SREG an index and value
+pub const CMD_BUFFER: usize = 4;
+pub const SREG_INDEX: usize = 4;
+pub const SREG_VALUE: usize = 5;
+
+//psize is the size of the packet itself, dsize and dest describe extra data sent ahead of it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SifCmdHeader {
+    pub packet_size: u32,
+    pub data_size: u32,
+    pub dest: u32,
+    pub cid: u32,
+    pub opt: u32,
+}
+
+//Missing words read as 0 so a short packet can't take anything down
+pub fn word(data: &[u8], index: usize) -> u32 {
+    match data.get(index * 4..index * 4 + 4) {
+        Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        None => 0,
+    }
+}
+
+pub fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+impl SifCmdHeader {
+    pub fn read(data: &[u8]) -> SifCmdHeader {
+        let sizes = word(data, 0);
+
+        SifCmdHeader {
+            packet_size: sizes & 0xff,
+            data_size: sizes >> 8,
+            dest: word(data, 1),
+            cid: word(data, 2),
+            opt: word(data, 3),
+        }
+    }
+
+    pub fn to_words(self) -> [u32; SIF_CMD_HEADER_WORDS] {
+        [(self.data_size << 8) | (self.packet_size & 0xff), self.dest, self.cid, self.opt]
+    }
+}
+
+//Sent back to the EE when a bind, call or rdata is done, cid says which one
+pub fn rpc_end_packet(request: &[u8], cid: u32, server: u32, buffer: u32) -> Vec<u8> {
+    let mut words = SifCmdHeader {
+        packet_size: 48,
+        data_size: 0,
+        dest: 0,
+        cid: SIF_CMD_RPC_END,
+        opt: 0,
+    }.to_words().to_vec();
+
+    words.extend_from_slice(&[
+        word(request, RPC_REC_ID),
+        word(request, RPC_PKT_ADDR),
+        word(request, RPC_ID),
+        word(request, RPC_CLIENT),
+        cid,
+        server,
+        buffer,
+        0,
+    ]);
+
+    words_to_bytes(&words)
+}
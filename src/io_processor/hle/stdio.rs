@@ -0,0 +1,31 @@
+use super::kernel::IopKernel;
+use super::super::cpu::IopCpu;
+
+//There's no keyboard so reads always come back empty
+const EOF: i32 = -1;
+
+pub fn call(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let a0 = kernel.argument(cpu, 0);
+
+    match function {
+        //printf(format, ...)
+        4 => {
+            let output = kernel.printf(cpu, 0);
+            kernel.tty.write(&output);
+            Some(output.len() as u32)
+        },
+        5 => Some(EOF as u32),
+        6 => {
+            kernel.tty.write(&[a0 as u8]);
+            Some(a0 & 0xff)
+        },
+        //puts adds the newline itself
+        7 => {
+            let string = kernel.read_string(a0);
+            kernel.tty.write(&string);
+            kernel.tty.write(b"\n");
+            Some(0)
+        },
+        _ => kernel.unimplemented("stdio", function),
+    }
+}
@@ -0,0 +1,205 @@
+use super::kernel::IopKernel;
+use super::printf::format;
+use super::super::cpu::{IopCpu, FP_REG, GP_REG, RA_REG, SP_REG};
+
+//jmp_buf holds ra, sp, fp, s0-s7 and gp
+const JMP_BUF_REGISTERS: [u8; 12] = [RA_REG, SP_REG, FP_REG, 16, 17, 18, 19, 20, 21, 22, 23, GP_REG];
+
+fn compare(a: &[u8], b: &[u8]) -> u32 {
+    match a.iter().zip(b.iter()).find(|(a, b)| a != b) {
+        Some((a, b)) => (*a as i32 - *b as i32) as u32,
+        None => (a.len() as i32 - b.len() as i32).signum() as u32,
+    }
+}
+
+//strtol and strtoul, base 0 works it out from the prefix
+fn parse_integer(kernel: &IopKernel, address: u32, end: u32, base: u32) -> u32 {
+    let string = kernel.read_string(address);
+    let mut position = string.iter().take_while(|character| character.is_ascii_whitespace()).count();
+
+    let negative = string.get(position) == Some(&b'-');
+    if negative || string.get(position) == Some(&b'+') {
+        position += 1;
+    }
+
+    let hex_prefix = string.get(position) == Some(&b'0') && matches!(string.get(position + 1), Some(b'x') | Some(b'X'));
+    let base = match base {
+        0 if hex_prefix => 16,
+        0 if string.get(position) == Some(&b'0') => 8,
+        0 => 10,
+        base => base,
+    };
+
+    if !(2..=36).contains(&base) {
+        return 0;
+    }
+
+    if base == 16 && hex_prefix {
+        position += 2;
+    }
+
+    let mut value: u32 = 0;
+
+    while let Some(digit) = string.get(position).and_then(|character| (*character as char).to_digit(base)) {
+        value = value.wrapping_mul(base).wrapping_add(digit);
+        position += 1;
+    }
+
+    if end != 0 {
+        kernel.write_u32(end, address + position as u32);
+    }
+
+    if negative { value.wrapping_neg() } else { value }
+}
+
+pub fn call(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1, a2) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1), kernel.argument(cpu, 2));
+
+    match function {
+        //setjmp(jmp_buf) and longjmp(jmp_buf, value)
+        4 => {
+            for (index, register) in JMP_BUF_REGISTERS.iter().copied().enumerate() {
+                kernel.write_u32(a0 + index as u32 * 4, cpu.read_register(register));
+            }
+
+            Some(0)
+        },
+        5 => {
+            for (index, register) in JMP_BUF_REGISTERS.iter().copied().enumerate() {
+                cpu.write_register(register, kernel.read_u32(a0 + index as u32 * 4));
+            }
+
+            cpu.set_pc(cpu.read_register(RA_REG));
+            Some(if a1 == 0 { 1 } else { a1 })
+        },
+        6 => Some((a0 as u8).to_ascii_uppercase() as u32),
+        7 => Some((a0 as u8).to_ascii_lowercase() as u32),
+        //memchr(pointer, character, length)
+        10 => Some(kernel.read_bytes(a0, IopKernel::clamp_length(a0, a2)).iter().position(|byte| *byte == a1 as u8).map_or(0, |offset| a0 + offset as u32)),
+        11 | 15 => Some(compare(&kernel.read_bytes(a0, IopKernel::clamp_length(a0, a2)), &kernel.read_bytes(a1, IopKernel::clamp_length(a1, a2)))),
+        //memcpy and memmove(destination, source, length), bcopy has source first
+        12 | 13 => {
+            let data = kernel.read_bytes(a1, IopKernel::clamp_length(a1, a2).min(IopKernel::clamp_length(a0, a2)));
+            kernel.write_bytes(a0, &data);
+            Some(a0)
+        },
+        14 => {
+            kernel.write_bytes(a0, &vec![a1 as u8; IopKernel::clamp_length(a0, a2)]);
+            Some(a0)
+        },
+        16 => {
+            let data = kernel.read_bytes(a0, IopKernel::clamp_length(a0, a2).min(IopKernel::clamp_length(a1, a2)));
+            kernel.write_bytes(a1, &data);
+            Some(0)
+        },
+        17 => {
+            kernel.write_bytes(a0, &vec![0; IopKernel::clamp_length(a0, a1)]);
+            Some(0)
+        },
+        //sprintf(buffer, format, ...) and vsprintf(buffer, format, va_list)
+        19 | 42 => {
+            let mut index = 2;
+            let mut arguments = a2;
+
+            let output = format(&kernel.read_string(a1), || {
+                if function == 19 {
+                    index += 1;
+                    kernel.argument(cpu, index - 1)
+                } else {
+                    arguments += 4;
+                    kernel.read_u32(arguments - 4)
+                }
+            }, |address| kernel.read_string(address));
+
+            let length = output.len() as u32;
+            kernel.write_bytes(a0, &[output, vec![0]].concat());
+            Some(length)
+        },
+        //strcat, strcpy, strncat and strncpy(destination, source[, length])
+        20 | 28 => {
+            let end = a0 + kernel.read_string(a0).len() as u32;
+            let mut source = kernel.read_string(a1);
+
+            if function == 28 {
+                source.truncate(a2 as usize);
+            }
+
+            source.push(0);
+            kernel.write_bytes(end, &source);
+            Some(a0)
+        },
+        23 => {
+            let mut source = kernel.read_string(a1);
+            source.push(0);
+            kernel.write_bytes(a0, &source);
+            Some(a0)
+        },
+        30 => {
+            let mut source = kernel.read_string(a1);
+            source.truncate(a2 as usize);
+            source.resize(IopKernel::clamp_length(a0, a2), 0);
+            kernel.write_bytes(a0, &source);
+            Some(a0)
+        },
+        //strchr/index and strrchr/rindex, the terminator can be searched for too
+        21 | 25 | 26 | 32 => {
+            let mut string = kernel.read_string(a0);
+            string.push(0);
+
+            let offset = if function == 21 || function == 25 {
+                string.iter().position(|character| *character == a1 as u8)
+            } else {
+                string.iter().rposition(|character| *character == a1 as u8)
+            };
+
+            Some(offset.map_or(0, |offset| a0 + offset as u32))
+        },
+        22 | 29 => {
+            let (mut a, mut b) = (kernel.read_string(a0), kernel.read_string(a1));
+
+            if function == 29 {
+                a.truncate(a2 as usize);
+                b.truncate(a2 as usize);
+            }
+
+            Some(compare(&a, &b))
+        },
+        27 => Some(kernel.read_string(a0).len() as u32),
+        34 => {
+            let (haystack, needle) = (kernel.read_string(a0), kernel.read_string(a1));
+
+            if needle.is_empty() {
+                return Some(a0);
+            }
+
+            Some(haystack.windows(needle.len()).position(|window| window == &needle[..]).map_or(0, |offset| a0 + offset as u32))
+        },
+        36 | 38 => Some(parse_integer(kernel, a0, a1, a2)),
+        _ => kernel.unimplemented("sysclib", function),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::call;
+    use super::super::kernel::IopKernel;
+    use crate::io_processor::{create_iop_ram, IopCpu};
+    use crate::sif::create_sif;
+
+    #[test]
+    fn test_lengths_stop_at_the_end_of_ram() {
+        let ram = create_iop_ram();
+        let mut cpu = IopCpu::new(&[], ram.clone(), create_sif());
+        let mut kernel = IopKernel::new(ram);
+        kernel.write_u32(0, 0x12345678);
+
+        //memset(0x1ffff0, 0xaa, 0xffffffff) fills the last 16 bytes and doesn't wrap round to 0
+        for (register, value) in [(4, 0x1ffff0), (5, 0xaa), (6, 0xffffffff)] {
+            cpu.write_register(register, value);
+        }
+
+        assert_eq!(call(&mut kernel, &mut cpu, 14), Some(0x1ffff0));
+        assert_eq!(kernel.read_u32(0x1ffffc), 0xaaaaaaaa);
+        assert_eq!(kernel.read_u32(0), 0x12345678);
+    }
+}
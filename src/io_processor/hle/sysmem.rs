@@ -0,0 +1,144 @@
+use super::kernel::IopKernel;
+use super::super::cpu::IopCpu;
+
+//AllocSysMemory modes
+pub const ALLOC_FIRST: u32 = 0;
+pub const ALLOC_LAST: u32 = 1;
+pub const ALLOC_ADDRESS: u32 = 2;
+
+//Blocks are handed out in 256 byte units like the real sysmem
+const BLOCK_ALIGNMENT: u32 = 0x100;
+
+//Allocated blocks of IOP RAM as (start, size), kept in address order
+pub struct SysMem {
+    start: u32,
+    end: u32,
+    blocks: Vec<(u32, u32)>,
+}
+
+fn align_up(value: u32) -> u32 {
+    (value + BLOCK_ALIGNMENT - 1) & !(BLOCK_ALIGNMENT - 1)
+}
+
+impl SysMem {
+    pub fn new(start: u32, end: u32) -> SysMem {
+        SysMem {
+            start: align_up(start),
+            end: end & !(BLOCK_ALIGNMENT - 1),
+            blocks: vec![],
+        }
+    }
+
+    //Every free stretch of memory as (start, size)
+    fn free_blocks(&self) -> Vec<(u32, u32)> {
+        let mut free = vec![];
+        let mut position = self.start;
+
+        for (start, size) in self.blocks.iter().copied() {
+            if start > position {
+                free.push((position, start - position));
+            }
+
+            position = start + size;
+        }
+
+        if self.end > position {
+            free.push((position, self.end - position));
+        }
+
+        free
+    }
+
+    pub fn alloc(&mut self, mode: u32, size: u32, address: u32) -> Option<u32> {
+        let size = align_up(size.max(1));
+        let free = self.free_blocks();
+
+        let start = match mode {
+            ALLOC_FIRST => free.iter().find(|(_, free_size)| *free_size >= size).map(|(start, _)| *start),
+            ALLOC_LAST => free.iter().rev().find(|(_, free_size)| *free_size >= size).map(|(start, free_size)| start + free_size - size),
+            ALLOC_ADDRESS => {
+                let address = address & !(BLOCK_ALIGNMENT - 1);
+                free.iter().find(|(start, free_size)| address >= *start && address + size <= start + free_size).map(|_| address)
+            },
+            _ => None,
+        }?;
+
+        let index = self.blocks.iter().position(|(block, _)| *block > start).unwrap_or(self.blocks.len());
+        self.blocks.insert(index, (start, size));
+
+        Some(start)
+    }
+
+    pub fn free(&mut self, address: u32) -> bool {
+        match self.blocks.iter().position(|(start, _)| *start == address) {
+            Some(index) => {
+                self.blocks.remove(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn memory_size(&self) -> u32 {
+        self.end
+    }
+
+    pub fn max_free(&self) -> u32 {
+        self.free_blocks().iter().map(|(_, size)| *size).max().unwrap_or(0)
+    }
+
+    pub fn total_free(&self) -> u32 {
+        self.free_blocks().iter().map(|(_, size)| *size).sum()
+    }
+
+    //The block an address falls in as (start, size)
+    pub fn block(&self, address: u32) -> Option<(u32, u32)> {
+        self.blocks.iter().copied().find(|(start, size)| address >= *start && address < start + size)
+    }
+}
+
+pub fn call(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1, a2) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1), kernel.argument(cpu, 2));
+
+    match function {
+        //AllocSysMemory(mode, size, address), 0 when there's no room
+        4 => Some(kernel.sysmem.alloc(a0, a1, a2).unwrap_or(0)),
+        5 => Some(if kernel.sysmem.free(a0) { 0 } else { -1i32 as u32 }),
+        6 => Some(kernel.sysmem.memory_size()),
+        7 => Some(kernel.sysmem.max_free()),
+        8 => Some(kernel.sysmem.total_free()),
+        9 => Some(kernel.sysmem.block(a0).map_or(0, |(start, _)| start)),
+        10 => Some(kernel.sysmem.block(a0).map_or(0, |(_, size)| size)),
+        //Kprintf(format, ...)
+        14 => {
+            let output = kernel.printf(cpu, 0);
+            kernel.tty.write(&output);
+            Some(output.len() as u32)
+        },
+        _ => kernel.unimplemented("sysmem", function),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SysMem, ALLOC_ADDRESS, ALLOC_FIRST, ALLOC_LAST};
+
+    #[test]
+    fn test_alloc_and_free() {
+        let mut sysmem = SysMem::new(0x10000, 0x20000);
+
+        assert_eq!(sysmem.alloc(ALLOC_FIRST, 0x10, 0), Some(0x10000));
+        assert_eq!(sysmem.alloc(ALLOC_FIRST, 0x180, 0), Some(0x10100));
+        assert_eq!(sysmem.alloc(ALLOC_LAST, 0x1000, 0), Some(0x1f000));
+        assert_eq!(sysmem.block(0x1f800), Some((0x1f000, 0x1000)));
+
+        //The gap left behind is used again, and an address that's taken can't be allocated
+        assert!(sysmem.free(0x10000));
+        assert_eq!(sysmem.alloc(ALLOC_ADDRESS, 0x100, 0x10100), None);
+        assert_eq!(sysmem.alloc(ALLOC_ADDRESS, 0x100, 0x10000), Some(0x10000));
+        assert_eq!(sysmem.alloc(ALLOC_FIRST, 0x20000, 0), None);
+
+        assert_eq!(sysmem.total_free(), 0x10000 - 0x1300);
+        assert_eq!(sysmem.max_free(), 0x1f000 - 0x10300);
+    }
+}
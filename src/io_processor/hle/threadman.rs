@@ -0,0 +1,749 @@
+use super::kernel::{IopKernel, IOP_CYCLES_PER_SECOND};
+use super::sysmem::ALLOC_LAST;
+use super::super::cpu::{IopCpu, A0_REG, GP_REG, SP_REG, RA_REG, V0_REG};
+
+//Kernel error codes from kerror.h, returned negated
+const KE_NO_MEMORY: i32 = -400;
+const KE_ILLEGAL_PRIORITY: i32 = -403;
+const KE_ILLEGAL_THID: i32 = -406;
+const KE_UNKNOWN_THID: i32 = -407;
+const KE_UNKNOWN_SEMID: i32 = -408;
+const KE_UNKNOWN_EVFID: i32 = -409;
+const KE_DORMANT: i32 = -413;
+const KE_NOT_DORMANT: i32 = -414;
+const KE_SEMA_ZERO: i32 = -419;
+const KE_SEMA_OVF: i32 = -420;
+const KE_EVF_COND: i32 = -421;
+const KE_EVF_ILPAT: i32 = -423;
+const KE_WAIT_DELETE: i32 = -425;
+
+//WaitEventFlag modes
+const WEF_OR: u32 = 0x01;
+const WEF_CLEAR: u32 = 0x10;
+const WEF_CLEARALL: u32 = 0x20;
+
+const LOWEST_PRIORITY: u32 = 126;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    pub registers: [u32; 32],
+    pub hi: u32,
+    pub lo: u32,
+    pub pc: u32,
+}
+
+impl Context {
+    pub fn save(cpu: &IopCpu) -> Context {
+        let mut registers = [0; 32];
+
+        for (register, value) in registers.iter_mut().enumerate() {
+            *value = cpu.read_register(register as u8);
+        }
+
+        Context {
+            registers,
+            hi: cpu.hi,
+            lo: cpu.lo,
+            pc: cpu.pc,
+        }
+    }
+
+    pub fn restore(&self, cpu: &mut IopCpu) {
+        for (register, value) in self.registers.iter().copied().enumerate() {
+            cpu.write_register(register as u8, value);
+        }
+
+        cpu.hi = self.hi;
+        cpu.lo = self.lo;
+        cpu.set_pc(self.pc);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WaitReason {
+    Sleep,
+    //Cycle to wake up on
+    Delay(u64),
+    Semaphore(u32),
+    //result is where the flag's bits go once the wait is over
+    EventFlag {
+        id: u32,
+        bits: u32,
+        mode: u32,
+        result: u32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ThreadState {
+    Dormant,
+    Ready,
+    Running,
+    Waiting(WaitReason),
+}
+
+#[derive(Clone, Debug)]
+pub struct Thread {
+    pub entry: u32,
+    pub stack: u32,
+    pub stack_size: u32,
+    pub priority: u32,
+    pub gp: u32,
+    pub state: ThreadState,
+    context: Context,
+    //When the thread last became ready or started waiting, the oldest goes first
+    order: u64,
+    wakeup_count: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Semaphore {
+    pub attr: u32,
+    pub option: u32,
+    pub initial: u32,
+    pub count: u32,
+    pub max: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EventFlag {
+    pub attr: u32,
+    pub option: u32,
+    pub initial: u32,
+    pub bits: u32,
+}
+
+//Threads, semaphores and event flags are numbered from 1 in the order they're created
+pub struct ThreadManager {
+    threads: Vec<Option<Thread>>,
+    semaphores: Vec<Option<Semaphore>>,
+    event_flags: Vec<Option<EventFlag>>,
+    current: Option<u32>,
+    order: u64,
+}
+
+fn add<T>(objects: &mut Vec<Option<T>>, object: T) -> u32 {
+    match objects.iter().position(|object| object.is_none()) {
+        Some(index) => {
+            objects[index] = Some(object);
+            index as u32 + 1
+        },
+        None => {
+            objects.push(Some(object));
+            objects.len() as u32
+        },
+    }
+}
+
+fn get<T>(objects: &mut [Option<T>], id: u32) -> Option<&mut T> {
+    objects.get_mut((id as usize).wrapping_sub(1)).and_then(|object| object.as_mut())
+}
+
+fn event_condition_met(flag: u32, bits: u32, mode: u32) -> bool {
+    if mode & WEF_OR != 0 {
+        flag & bits != 0
+    } else {
+        flag & bits == bits
+    }
+}
+
+fn clear_event_bits(flag: &mut EventFlag, bits: u32, mode: u32) {
+    if mode & WEF_CLEARALL != 0 {
+        flag.bits = 0;
+    } else if mode & WEF_CLEAR != 0 {
+        flag.bits &= !bits;
+    }
+}
+
+impl ThreadManager {
+    pub fn new() -> ThreadManager {
+        ThreadManager {
+            threads: vec![],
+            semaphores: vec![],
+            event_flags: vec![],
+            current: None,
+            order: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<u32> {
+        self.current
+    }
+
+    pub fn thread(&self, id: u32) -> Option<&Thread> {
+        self.threads.get((id as usize).wrapping_sub(1)).and_then(|thread| thread.as_ref())
+    }
+
+    pub fn semaphore(&self, id: u32) -> Option<&Semaphore> {
+        self.semaphores.get((id as usize).wrapping_sub(1)).and_then(|semaphore| semaphore.as_ref())
+    }
+
+    pub fn event_flag(&self, id: u32) -> Option<&EventFlag> {
+        self.event_flags.get((id as usize).wrapping_sub(1)).and_then(|flag| flag.as_ref())
+    }
+
+    fn next_order(&mut self) -> u64 {
+        self.order += 1;
+        self.order
+    }
+
+    fn set_state(&mut self, id: u32, state: ThreadState) {
+        let order = self.next_order();
+
+        if let Some(thread) = get(&mut self.threads, id) {
+            thread.state = state;
+            thread.order = order;
+        }
+    }
+
+    //Ends a wait with result in v0
+    fn wake(&mut self, id: u32, result: i32) {
+        self.set_state(id, ThreadState::Ready);
+
+        if let Some(thread) = get(&mut self.threads, id) {
+            thread.context.registers[V0_REG as usize] = result as u32;
+        }
+    }
+
+    //The running thread stops here, its context is saved when the next one is picked
+    fn block_current(&mut self, reason: WaitReason) {
+        if let Some(id) = self.current {
+            self.set_state(id, ThreadState::Waiting(reason));
+        }
+    }
+
+    //Oldest waiter first
+    fn waiting_threads<F: Fn(&WaitReason) -> bool>(&self, filter: F) -> Vec<(u32, WaitReason)> {
+        let mut waiting: Vec<(u64, u32, WaitReason)> = self.threads.iter().enumerate()
+            .filter_map(|(index, thread)| match thread {
+                Some(Thread { state: ThreadState::Waiting(reason), order, .. }) if filter(reason) => Some((*order, index as u32 + 1, *reason)),
+                _ => None,
+            })
+            .collect();
+
+        waiting.sort_by_key(|(order, _, _)| *order);
+        waiting.into_iter().map(|(_, id, reason)| (id, reason)).collect()
+    }
+
+    pub fn create_thread(&mut self, entry: u32, stack: u32, stack_size: u32, priority: u32, gp: u32) -> u32 {
+        add(&mut self.threads, Thread {
+            entry,
+            stack,
+            stack_size,
+            priority,
+            gp,
+            state: ThreadState::Dormant,
+            context: Context::default(),
+            order: 0,
+            wakeup_count: 0,
+        })
+    }
+
+    //Sets the thread up to call entry(a0, a1) and come back to exit when it returns
+    pub fn start_thread(&mut self, id: u32, arguments: (u32, u32), exit: u32) -> i32 {
+        let order = self.next_order();
+
+        let thread = match get(&mut self.threads, id) {
+            Some(thread) => thread,
+            None => return KE_UNKNOWN_THID,
+        };
+
+        if thread.state != ThreadState::Dormant {
+            return KE_NOT_DORMANT;
+        }
+
+        thread.context = Context::default();
+        thread.context.pc = thread.entry;
+        thread.context.registers[A0_REG as usize] = arguments.0;
+        thread.context.registers[A0_REG as usize + 1] = arguments.1;
+        thread.context.registers[GP_REG as usize] = thread.gp;
+        thread.context.registers[SP_REG as usize] = (thread.stack + thread.stack_size - 0x10) & !0xf;
+        thread.context.registers[RA_REG as usize] = exit;
+        thread.state = ThreadState::Ready;
+        thread.order = order;
+        thread.wakeup_count = 0;
+
+        0
+    }
+
+    //Returns the stack so it can be freed
+    pub fn delete_thread(&mut self, id: u32) -> Result<u32, i32> {
+        let thread = match get(&mut self.threads, id) {
+            Some(thread) => thread,
+            None => return Err(KE_UNKNOWN_THID),
+        };
+
+        if thread.state != ThreadState::Dormant && Some(id) != self.current {
+            return Err(KE_NOT_DORMANT);
+        }
+
+        let stack = thread.stack;
+        self.threads[id as usize - 1] = None;
+
+        Ok(stack)
+    }
+
+    pub fn terminate_thread(&mut self, id: u32) -> i32 {
+        match self.thread(id).map(|thread| thread.state) {
+            None => KE_UNKNOWN_THID,
+            Some(ThreadState::Dormant) => KE_DORMANT,
+            Some(_) => {
+                self.set_state(id, ThreadState::Dormant);
+                0
+            },
+        }
+    }
+
+    pub fn change_priority(&mut self, id: u32, priority: u32) -> i32 {
+        if !(1..=LOWEST_PRIORITY).contains(&priority) {
+            return KE_ILLEGAL_PRIORITY;
+        }
+
+        match get(&mut self.threads, id) {
+            Some(thread) => {
+                thread.priority = priority;
+                0
+            },
+            None => KE_UNKNOWN_THID,
+        }
+    }
+
+    pub fn sleep(&mut self) -> Option<i32> {
+        let thread = get(&mut self.threads, self.current?)?;
+
+        if thread.wakeup_count > 0 {
+            thread.wakeup_count -= 1;
+            return Some(0);
+        }
+
+        self.block_current(WaitReason::Sleep);
+        None
+    }
+
+    pub fn wakeup(&mut self, id: u32) -> i32 {
+        match self.thread(id).map(|thread| thread.state) {
+            None => KE_UNKNOWN_THID,
+            Some(ThreadState::Dormant) => KE_DORMANT,
+            Some(ThreadState::Waiting(WaitReason::Sleep)) => {
+                self.wake(id, 0);
+                0
+            },
+            Some(_) => {
+                if let Some(thread) = get(&mut self.threads, id) {
+                    thread.wakeup_count += 1;
+                }
+
+                0
+            },
+        }
+    }
+
+    pub fn delay(&mut self, until: u64) {
+        self.block_current(WaitReason::Delay(until));
+    }
+
+    pub fn wake_delayed(&mut self, cycles: u64) {
+        for (id, _) in self.waiting_threads(|reason| matches!(reason, WaitReason::Delay(until) if *until <= cycles)) {
+            self.wake(id, 0);
+        }
+    }
+
+    pub fn next_wakeup(&self) -> Option<u64> {
+        self.threads.iter().filter_map(|thread| match thread {
+            Some(Thread { state: ThreadState::Waiting(WaitReason::Delay(until)), .. }) => Some(*until),
+            _ => None,
+        }).min()
+    }
+
+    pub fn create_semaphore(&mut self, attr: u32, option: u32, initial: u32, max: u32) -> u32 {
+        add(&mut self.semaphores, Semaphore {
+            attr,
+            option,
+            initial,
+            count: initial,
+            max,
+        })
+    }
+
+    pub fn semaphore_waiters(&self, id: u32) -> u32 {
+        self.waiting_threads(|reason| *reason == WaitReason::Semaphore(id)).len() as u32
+    }
+
+    pub fn delete_semaphore(&mut self, id: u32) -> i32 {
+        if get(&mut self.semaphores, id).is_none() {
+            return KE_UNKNOWN_SEMID;
+        }
+
+        self.semaphores[id as usize - 1] = None;
+
+        for (thread, _) in self.waiting_threads(|reason| *reason == WaitReason::Semaphore(id)) {
+            self.wake(thread, KE_WAIT_DELETE);
+        }
+
+        0
+    }
+
+    //A waiting thread gets the signal directly, otherwise the count goes up
+    pub fn signal_semaphore(&mut self, id: u32) -> i32 {
+        if get(&mut self.semaphores, id).is_none() {
+            return KE_UNKNOWN_SEMID;
+        }
+
+        if let Some((thread, _)) = self.waiting_threads(|reason| *reason == WaitReason::Semaphore(id)).first() {
+            self.wake(*thread, 0);
+            return 0;
+        }
+
+        match get(&mut self.semaphores, id) {
+            Some(semaphore) if semaphore.count >= semaphore.max => KE_SEMA_OVF,
+            Some(semaphore) => {
+                semaphore.count += 1;
+                0
+            },
+            None => KE_UNKNOWN_SEMID,
+        }
+    }
+
+    //None when the thread has to wait
+    pub fn wait_semaphore(&mut self, id: u32) -> Option<i32> {
+        match self.poll_semaphore(id) {
+            KE_SEMA_ZERO => {
+                self.block_current(WaitReason::Semaphore(id));
+                None
+            },
+            result => Some(result),
+        }
+    }
+
+    pub fn poll_semaphore(&mut self, id: u32) -> i32 {
+        match get(&mut self.semaphores, id) {
+            Some(semaphore) if semaphore.count > 0 => {
+                semaphore.count -= 1;
+                0
+            },
+            Some(_) => KE_SEMA_ZERO,
+            None => KE_UNKNOWN_SEMID,
+        }
+    }
+
+    pub fn create_event_flag(&mut self, attr: u32, option: u32, bits: u32) -> u32 {
+        add(&mut self.event_flags, EventFlag {
+            attr,
+            option,
+            initial: bits,
+            bits,
+        })
+    }
+
+    pub fn event_flag_waiters(&self, id: u32) -> u32 {
+        self.waiting_threads(|reason| matches!(reason, WaitReason::EventFlag { id: flag, .. } if *flag == id)).len() as u32
+    }
+
+    pub fn delete_event_flag(&mut self, id: u32) -> i32 {
+        if get(&mut self.event_flags, id).is_none() {
+            return KE_UNKNOWN_EVFID;
+        }
+
+        self.event_flags[id as usize - 1] = None;
+
+        for (thread, _) in self.waiting_threads(|reason| matches!(reason, WaitReason::EventFlag { id: flag, .. } if *flag == id)) {
+            self.wake(thread, KE_WAIT_DELETE);
+        }
+
+        0
+    }
+
+    //Wakes every waiter whose condition is now met, hands back the (address, bits) each one wants written
+    pub fn set_event_flag(&mut self, id: u32, bits: u32) -> Result<Vec<(u32, u32)>, i32> {
+        match get(&mut self.event_flags, id) {
+            Some(flag) => flag.bits |= bits,
+            None => return Err(KE_UNKNOWN_EVFID),
+        }
+
+        let mut results = vec![];
+
+        for (thread, reason) in self.waiting_threads(|reason| matches!(reason, WaitReason::EventFlag { id: flag, .. } if *flag == id)) {
+            if let WaitReason::EventFlag { bits, mode, result, .. } = reason {
+                let flag = match get(&mut self.event_flags, id) {
+                    Some(flag) => flag,
+                    None => break,
+                };
+
+                if event_condition_met(flag.bits, bits, mode) {
+                    if result != 0 {
+                        results.push((result, flag.bits));
+                    }
+
+                    clear_event_bits(flag, bits, mode);
+                    self.wake(thread, 0);
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    //Bits not in the pattern are cleared
+    pub fn clear_event_flag(&mut self, id: u32, pattern: u32) -> i32 {
+        match get(&mut self.event_flags, id) {
+            Some(flag) => {
+                flag.bits &= pattern;
+                0
+            },
+            None => KE_UNKNOWN_EVFID,
+        }
+    }
+
+    //Ok(Some(bits)) when the condition is already met, Ok(None) when the thread has to wait
+    pub fn wait_event_flag(&mut self, id: u32, bits: u32, mode: u32, result: u32) -> Result<Option<u32>, i32> {
+        match self.poll_event_flag(id, bits, mode) {
+            Err(KE_EVF_COND) => {
+                self.block_current(WaitReason::EventFlag { id, bits, mode, result });
+                Ok(None)
+            },
+            other => other.map(Some),
+        }
+    }
+
+    pub fn poll_event_flag(&mut self, id: u32, bits: u32, mode: u32) -> Result<u32, i32> {
+        if bits == 0 {
+            return Err(KE_EVF_ILPAT);
+        }
+
+        match get(&mut self.event_flags, id) {
+            Some(flag) if event_condition_met(flag.bits, bits, mode) => {
+                let value = flag.bits;
+                clear_event_bits(flag, bits, mode);
+                Ok(value)
+            },
+            Some(_) => Err(KE_EVF_COND),
+            None => Err(KE_UNKNOWN_EVFID),
+        }
+    }
+
+    //The exiting thread goes back to dormant, it can be started again
+    pub fn exit_current(&mut self) {
+        if let Some(id) = self.current {
+            self.set_state(id, ThreadState::Dormant);
+        }
+    }
+
+    //Highest priority (lowest number) ready thread, the one that's been ready longest wins a tie
+    fn next_ready(&self) -> Option<(u32, u32)> {
+        self.threads.iter().enumerate()
+            .filter_map(|(index, thread)| match thread {
+                Some(thread) if thread.state == ThreadState::Ready => Some((thread.priority, thread.order, index as u32 + 1)),
+                _ => None,
+            })
+            .min()
+            .map(|(priority, _, id)| (id, priority))
+    }
+
+    //Keeps the running thread unless something more important is ready, or it has stopped running
+    pub fn reschedule(&mut self, cpu: &mut IopCpu) {
+        let next = self.next_ready();
+
+        if let Some(id) = self.current {
+            let (state, priority) = match self.thread(id) {
+                Some(thread) => (Some(thread.state), thread.priority),
+                None => (None, 0),
+            };
+
+            match (state, next) {
+                (Some(ThreadState::Running), Some((_, next_priority))) if next_priority < priority => self.set_state(id, ThreadState::Ready),
+                (Some(ThreadState::Running), _) => return,
+                _ => {},
+            }
+
+            if let Some(thread) = get(&mut self.threads, id) {
+                thread.context = Context::save(cpu);
+            }
+
+            self.current = None;
+        }
+
+        //The running thread may have gone back in the queue so look again
+        if let Some((id, _)) = self.next_ready() {
+            if let Some(thread) = get(&mut self.threads, id) {
+                thread.state = ThreadState::Running;
+                thread.context.restore(cpu);
+            }
+
+            self.current = Some(id);
+        }
+    }
+}
+
+fn result(value: i32) -> Option<u32> {
+    Some(value as u32)
+}
+
+fn current_or(threads: &ThreadManager, id: u32) -> u32 {
+    if id == 0 { threads.current().unwrap_or(0) } else { id }
+}
+
+//thbase, the i versions for interrupt handlers behave the same since nothing switches until the handler returns
+pub fn call_thbase(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1, a2) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1), kernel.argument(cpu, 2));
+
+    match function {
+        //CreateThread(iop_thread_t *) with attr, option, entry, stack size and priority
+        4 => {
+            let (entry, stack_size, priority) = (kernel.read_u32(a0 + 8), (kernel.read_u32(a0 + 12) + 0xff) & !0xff, kernel.read_u32(a0 + 16));
+
+            if !(1..=LOWEST_PRIORITY).contains(&priority) {
+                return result(KE_ILLEGAL_PRIORITY);
+            }
+
+            match kernel.sysmem.alloc(ALLOC_LAST, stack_size, 0) {
+                Some(stack) => Some(kernel.threads.create_thread(entry, stack, stack_size, priority, cpu.read_register(GP_REG))),
+                None => result(KE_NO_MEMORY),
+            }
+        },
+        5 => {
+            if a0 == 0 || Some(a0) == kernel.threads.current() {
+                return result(KE_ILLEGAL_THID);
+            }
+
+            match kernel.threads.delete_thread(a0) {
+                Ok(stack) => {
+                    kernel.sysmem.free(stack);
+                    result(0)
+                },
+                Err(error) => result(error),
+            }
+        },
+        6 => result(kernel.threads.start_thread(a0, (a1, 0), kernel.thread_exit())),
+        7 => result(kernel.threads.start_thread(a0, (a1, a2), kernel.thread_exit())),
+        8 => {
+            kernel.threads.exit_current();
+            None
+        },
+        9 => {
+            if let Some(id) = kernel.threads.current() {
+                kernel.threads.exit_current();
+
+                if let Ok(stack) = kernel.threads.delete_thread(id) {
+                    kernel.sysmem.free(stack);
+                }
+            }
+
+            None
+        },
+        10 | 11 => result(kernel.threads.terminate_thread(a0)),
+        14 | 15 => result(kernel.threads.change_priority(current_or(&kernel.threads, a0), a1)),
+        20 => Some(kernel.threads.current().unwrap_or(0)),
+        24 => kernel.threads.sleep().map(|value| value as u32),
+        25 | 26 => result(kernel.threads.wakeup(a0)),
+        33 => {
+            let cycles = a0 as u64 * IOP_CYCLES_PER_SECOND / 1_000_000;
+            kernel.threads.delay(cpu.cycles + cycles);
+            None
+        },
+        //GetSystemTime(iop_sys_clock_t *), the clock is the IOP's own
+        34 => {
+            kernel.write_u32(a0, cpu.cycles as u32);
+            kernel.write_u32(a0 + 4, (cpu.cycles >> 32) as u32);
+            result(0)
+        },
+        //USec2SysClock(usec, iop_sys_clock_t *)
+        39 => {
+            let cycles = a0 as u64 * IOP_CYCLES_PER_SECOND / 1_000_000;
+            kernel.write_u32(a1, cycles as u32);
+            kernel.write_u32(a1 + 4, (cycles >> 32) as u32);
+            result(0)
+        },
+        //SysClock2USec(iop_sys_clock_t *, u32 *sec, u32 *usec)
+        40 => {
+            let cycles = kernel.read_u32(a0) as u64 | ((kernel.read_u32(a0 + 4) as u64) << 32);
+            let microseconds = cycles * 1_000_000 / IOP_CYCLES_PER_SECOND;
+            kernel.write_u32(a1, (microseconds / 1_000_000) as u32);
+            kernel.write_u32(a2, (microseconds % 1_000_000) as u32);
+            result(0)
+        },
+        _ => kernel.unimplemented("thbase", function),
+    }
+}
+
+//The status structs are a run of words starting at address
+fn write_words(kernel: &IopKernel, address: u32, words: &[u32]) {
+    for (index, word) in words.iter().copied().enumerate() {
+        kernel.write_u32(address + index as u32 * 4, word);
+    }
+}
+
+pub fn call_thsemap(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1));
+
+    match function {
+        //CreateSema(iop_sema_t *) with attr, option, initial and max
+        4 => Some(kernel.threads.create_semaphore(kernel.read_u32(a0), kernel.read_u32(a0 + 4), kernel.read_u32(a0 + 8), kernel.read_u32(a0 + 12))),
+        5 => result(kernel.threads.delete_semaphore(a0)),
+        6 | 7 => result(kernel.threads.signal_semaphore(a0)),
+        8 => kernel.threads.wait_semaphore(a0).map(|value| value as u32),
+        9 => result(kernel.threads.poll_semaphore(a0)),
+        //ReferSemaStatus(id, iop_sema_info_t *) and iReferSemaStatus, attr, option, initial, max, current and
+        //how many threads are waiting
+        11 | 12 => match kernel.threads.semaphore(a0).copied() {
+            Some(semaphore) => {
+                let waiters = kernel.threads.semaphore_waiters(a0);
+                write_words(kernel, a1, &[semaphore.attr, semaphore.option, semaphore.initial, semaphore.max, semaphore.count, waiters]);
+                result(0)
+            },
+            None => result(KE_UNKNOWN_SEMID),
+        },
+        _ => kernel.unimplemented("thsemap", function),
+    }
+}
+
+pub fn call_thevent(kernel: &mut IopKernel, cpu: &mut IopCpu, function: u16) -> Option<u32> {
+    let (a0, a1, a2, a3) = (kernel.argument(cpu, 0), kernel.argument(cpu, 1), kernel.argument(cpu, 2), kernel.argument(cpu, 3));
+
+    match function {
+        //CreateEventFlag(iop_event_t *) with attr, option and the initial bits
+        4 => Some(kernel.threads.create_event_flag(kernel.read_u32(a0), kernel.read_u32(a0 + 4), kernel.read_u32(a0 + 8))),
+        5 => result(kernel.threads.delete_event_flag(a0)),
+        6 | 7 => match kernel.threads.set_event_flag(a0, a1) {
+            Ok(writes) => {
+                for (address, bits) in writes {
+                    kernel.write_u32(address, bits);
+                }
+
+                result(0)
+            },
+            Err(error) => result(error),
+        },
+        8 | 9 => result(kernel.threads.clear_event_flag(a0, a1)),
+        //WaitEventFlag(id, bits, mode, u32 *result) and PollEventFlag with the same arguments
+        10 | 11 => {
+            let outcome = if function == 10 {
+                kernel.threads.wait_event_flag(a0, a1, a2, a3)
+            } else {
+                kernel.threads.poll_event_flag(a0, a1, a2).map(Some)
+            };
+
+            match outcome {
+                Ok(Some(bits)) => {
+                    if a3 != 0 {
+                        kernel.write_u32(a3, bits);
+                    }
+
+                    result(0)
+                },
+                Ok(None) => None,
+                Err(error) => result(error),
+            }
+        },
+        //ReferEventFlagStatus(id, iop_event_info_t *) and iReferEventFlagStatus, attr, option, initial bits,
+        //current bits and how many threads are waiting
+        13 | 14 => match kernel.threads.event_flag(a0).copied() {
+            Some(flag) => {
+                let waiters = kernel.threads.event_flag_waiters(a0);
+                write_words(kernel, a1, &[flag.attr, flag.option, flag.initial, flag.bits, waiters]);
+                result(0)
+            },
+            None => result(KE_UNKNOWN_EVFID),
+        },
+        _ => kernel.unimplemented("thevent", function),
+    }
+}
@@ -0,0 +1,104 @@
+pub const I_STAT: u32 = 0x1F801070;
+pub const I_MASK: u32 = 0x1F801074;
+pub const I_CTRL: u32 = 0x1F801078;
+
+//Only the bits that have a source on the PS2's IOP
+const INTERRUPT_MASK: u32 = 0x03ffffff;
+
+//Bit positions in I_STAT/I_MASK
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IopInterrupt {
+    Vblank = 0,
+    Gpu = 1,
+    Cdvd = 2,
+    Dma = 3,
+    Timer0 = 4,
+    Timer1 = 5,
+    Timer2 = 6,
+    Sio0 = 7,
+    Sio1 = 8,
+    Spu2 = 9,
+    Pio = 10,
+    VblankEnd = 11,
+    Dvd = 12,
+    Dev9 = 13,
+    Timer3 = 14,
+    Timer4 = 15,
+    Timer5 = 16,
+    Sio2 = 17,
+    Usb = 22,
+    FireWire = 24,
+    FireWireDma = 25,
+}
+
+//Every device interrupt ends up in I_STAT, the CPU only sees a single line which is
+//up while a masked in bit is set and I_CTRL has interrupts enabled
+pub struct Intc {
+    stat: u32,
+    mask: u32,
+    ctrl: u32,
+}
+
+impl Intc {
+    pub fn new() -> Intc {
+        Intc {
+            stat: 0,
+            mask: 0,
+            ctrl: 0,
+        }
+    }
+
+    pub fn raise(&mut self, interrupt: IopInterrupt) {
+        self.stat |= 1 << interrupt as u32;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.ctrl & 1 != 0 && self.stat & self.mask != 0
+    }
+
+    //Reading I_CTRL hands back the old value and disables interrupts, the kernel uses it as a lock
+    pub fn read(&mut self, address: u32) -> u32 {
+        match address {
+            I_STAT => self.stat,
+            I_MASK => self.mask,
+            I_CTRL => std::mem::replace(&mut self.ctrl, 0),
+            _ => 0,
+        }
+    }
+
+    //Only the bits in write_mask were written, I_STAT bits are acknowledged by writing a 0
+    pub fn write(&mut self, address: u32, value: u32, write_mask: u32) {
+        match address {
+            I_STAT => self.stat &= value | !write_mask,
+            I_MASK => self.mask = ((self.mask & !write_mask) | (value & write_mask)) & INTERRUPT_MASK,
+            I_CTRL => self.ctrl = (self.ctrl & !write_mask) | (value & write_mask & 1),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Intc, IopInterrupt, I_CTRL, I_MASK, I_STAT};
+
+    #[test]
+    fn test_mask_acknowledge_and_ctrl() {
+        let mut intc = Intc::new();
+        intc.write(I_CTRL, 1, 0xffffffff);
+        intc.raise(IopInterrupt::Timer5);
+        intc.raise(IopInterrupt::Dma);
+        assert!(!intc.pending());
+
+        intc.write(I_MASK, 1 << 16, 0xffffffff);
+        assert!(intc.pending());
+
+        //A 16 bit write only acknowledges the low half so timer 5 stays pending
+        intc.write(I_STAT, 0, 0xffff);
+        assert_eq!(intc.read(I_STAT), 1 << 16);
+        assert!(intc.pending());
+
+        //Reading I_CTRL turns interrupts off
+        assert_eq!(intc.read(I_CTRL), 1);
+        assert!(!intc.pending());
+    }
+}
@@ -0,0 +1,12 @@
+mod bus;
+mod cop0;
+mod cpu;
+mod decoder;
+mod dma;
+mod hle;
+mod intc;
+mod timers;
+
+pub use bus::{create_iop_ram, IopRam};
+pub use cpu::IopCpu;
+pub use hle::{IopHle, IopKernel};
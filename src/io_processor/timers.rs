@@ -0,0 +1,215 @@
+use super::intc::{Intc, IopInterrupt};
+
+//Counters 0-2 are the PS1's 16 bit ones, 3-5 were added for the PS2 and are 32 bits wide
+const PS1_COUNTERS_BASE: u32 = 0x1F801100;
+const PS2_COUNTERS_BASE: u32 = 0x1F801480;
+const COUNTER_COUNT: usize = 6;
+
+//There is no GS timing to follow yet so the video clocks are approximated from the IOP clock
+//13.5MHz pixel clock and the NTSC line rate of 15734Hz against 36.864MHz
+const IOP_CYCLES_PER_PIXEL: u32 = 2;
+const IOP_CYCLES_PER_HBLANK: u32 = 2343;
+
+const MODE_RESET_ON_TARGET: u32 = 1 << 3;
+const MODE_IRQ_ON_TARGET: u32 = 1 << 4;
+const MODE_IRQ_ON_OVERFLOW: u32 = 1 << 5;
+const MODE_IRQ_REPEAT: u32 = 1 << 6;
+const MODE_IRQ_TOGGLE: u32 = 1 << 7;
+const MODE_EXTERNAL_CLOCK: u32 = 1 << 8;
+const MODE_DIVIDE_BY_8: u32 = 1 << 9;
+//Active low, cleared while an interrupt is being requested
+const MODE_IRQ_REQUEST: u32 = 1 << 10;
+const MODE_REACHED_TARGET: u32 = 1 << 11;
+const MODE_REACHED_OVERFLOW: u32 = 1 << 12;
+const MODE_WRITABLE: u32 = 0x63ff;
+
+const INTERRUPTS: [IopInterrupt; COUNTER_COUNT] = [
+    IopInterrupt::Timer0, IopInterrupt::Timer1, IopInterrupt::Timer2,
+    IopInterrupt::Timer3, IopInterrupt::Timer4, IopInterrupt::Timer5,
+];
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Counter {
+    count: u32,
+    mode: u32,
+    target: u32,
+    //IOP cycles that haven't added up to a full tick of the counter's clock yet
+    remainder: u32,
+    //One shot interrupts only fire once until the mode is written again
+    fired: bool,
+}
+
+pub struct RootCounters {
+    counters: [Counter; COUNTER_COUNT],
+}
+
+fn counter_for_address(address: u32) -> Option<(usize, u32)> {
+    match address {
+        0x1F801100..=0x1F80112F => Some((((address - PS1_COUNTERS_BASE) >> 4) as usize, address & 0xf)),
+        0x1F801480..=0x1F8014AF => Some((((address - PS2_COUNTERS_BASE) >> 4) as usize + 3, address & 0xf)),
+        _ => None,
+    }
+}
+
+fn counter_max(index: usize) -> u32 {
+    if index < 3 { 0xffff } else { 0xffffffff }
+}
+
+//How many IOP cycles make up one tick of the counter's source
+fn clock_divider(index: usize, mode: u32) -> u32 {
+    match index {
+        0 if mode & MODE_EXTERNAL_CLOCK != 0 => IOP_CYCLES_PER_PIXEL,
+        1 | 3 if mode & MODE_EXTERNAL_CLOCK != 0 => IOP_CYCLES_PER_HBLANK,
+        2 if mode & MODE_DIVIDE_BY_8 != 0 => 8,
+        //The PS2 counters have a prescaler instead of an external clock
+        4 | 5 => match mode >> 13 & 3 {
+            0 => 1,
+            1 => 8,
+            2 => 16,
+            _ => 256,
+        },
+        _ => 1,
+    }
+}
+
+impl Counter {
+    fn request_interrupt(&mut self) -> bool {
+        if self.fired && self.mode & MODE_IRQ_REPEAT == 0 {
+            return false;
+        }
+
+        self.fired = true;
+
+        //Toggle mode flips the request bit and only interrupts when it goes low, pulse mode always interrupts
+        if self.mode & MODE_IRQ_TOGGLE != 0 {
+            self.mode ^= MODE_IRQ_REQUEST;
+            self.mode & MODE_IRQ_REQUEST == 0
+        } else {
+            true
+        }
+    }
+
+    fn advance(&mut self, ticks: u64, max: u32) -> bool {
+        let (old, new) = (self.count as u64, self.count as u64 + ticks);
+        let target = self.target as u64;
+        let mut interrupt = false;
+
+        if old < target && new >= target {
+            self.mode |= MODE_REACHED_TARGET;
+            interrupt |= self.mode & MODE_IRQ_ON_TARGET != 0 && self.request_interrupt();
+
+            if self.mode & MODE_RESET_ON_TARGET != 0 {
+                self.count = (new - target) as u32;
+                return interrupt;
+            }
+        }
+
+        if new > max as u64 {
+            self.mode |= MODE_REACHED_OVERFLOW;
+            interrupt |= self.mode & MODE_IRQ_ON_OVERFLOW != 0 && self.request_interrupt();
+        }
+
+        self.count = (new % (max as u64 + 1)) as u32;
+        interrupt
+    }
+}
+
+impl RootCounters {
+    pub fn new() -> RootCounters {
+        RootCounters {
+            counters: [Counter { mode: MODE_IRQ_REQUEST, ..Counter::default() }; COUNTER_COUNT],
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32, intc: &mut Intc) {
+        for (index, counter) in self.counters.iter_mut().enumerate() {
+            let divider = clock_divider(index, counter.mode);
+            counter.remainder += cycles;
+
+            let ticks = counter.remainder / divider;
+            counter.remainder %= divider;
+
+            if ticks != 0 && counter.advance(ticks as u64, counter_max(index)) {
+                intc.raise(INTERRUPTS[index]);
+            }
+        }
+    }
+
+    //Reading the mode clears the reached target/overflow bits
+    pub fn read(&mut self, address: u32) -> u32 {
+        let (index, register) = match counter_for_address(address) {
+            Some(counter) => counter,
+            None => return 0,
+        };
+        let counter = &mut self.counters[index];
+
+        match register {
+            0x0 => counter.count,
+            0x4 => {
+                let mode = counter.mode;
+                counter.mode &= !(MODE_REACHED_TARGET | MODE_REACHED_OVERFLOW);
+                mode
+            },
+            0x8 => counter.target,
+            _ => 0,
+        }
+    }
+
+    //Writing the mode restarts the counter from 0
+    pub fn write(&mut self, address: u32, value: u32, write_mask: u32) {
+        let (index, register) = match counter_for_address(address) {
+            Some(counter) => counter,
+            None => return,
+        };
+        let max = counter_max(index);
+        let counter = &mut self.counters[index];
+
+        match register {
+            0x0 => counter.count = ((counter.count & !write_mask) | (value & write_mask)) & max,
+            0x4 => {
+                counter.mode = (value & write_mask & MODE_WRITABLE) | MODE_IRQ_REQUEST;
+                counter.count = 0;
+                counter.remainder = 0;
+                counter.fired = false;
+            },
+            0x8 => counter.target = ((counter.target & !write_mask) | (value & write_mask)) & max,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RootCounters;
+    use crate::io_processor::intc::{Intc, I_STAT};
+
+    #[test]
+    fn test_target_and_overflow_interrupts() {
+        let mut intc = Intc::new();
+        let mut counters = RootCounters::new();
+
+        //Counter 5 with a /8 prescaler, repeating interrupts on target and reset on target
+        counters.write(0x1F8014A8, 100, 0xffffffff);
+        counters.write(0x1F8014A4, 1 << 13 | 0x58, 0xffffffff);
+        counters.tick(799, &mut intc);
+        assert_eq!(counters.read(0x1F8014A0), 99);
+        assert_eq!(intc.read(I_STAT), 0);
+
+        counters.tick(1, &mut intc);
+        assert_eq!(counters.read(0x1F8014A0), 0);
+        assert_eq!(intc.read(I_STAT), 1 << 16);
+        assert_eq!(counters.read(0x1F8014A4) & 0x1800, 0x0800);
+        assert_eq!(counters.read(0x1F8014A4) & 0x1800, 0);
+
+        //Counter 0 is 16 bits and wraps, a one shot interrupt only fires the first time
+        counters.write(0x1F801104, 0x20, 0xffff);
+        counters.write(0x1F801100, 0xffff, 0xffff);
+        counters.tick(1, &mut intc);
+        assert_eq!(counters.read(0x1F801100), 0);
+        assert_eq!(intc.read(I_STAT), 1 << 16 | 1 << 4);
+
+        intc.write(I_STAT, 0, 0xffffffff);
+        counters.tick(0x10000, &mut intc);
+        assert_eq!(intc.read(I_STAT) & 1 << 4, 0);
+    }
+}
@@ -1,38 +1,192 @@
+mod bios;
 mod emotion_engine;
 mod io;
+mod io_processor;
+mod mips;
+mod scheduler;
+mod sif;
+mod system;
+#[cfg(test)]
+mod test_utils;
+mod tty;
 
-fn main() {
-    let bios_data = Box::new([0u8; 4 * 1024 * 1024]);
-    let mut cpu = emotion_engine::Cpu::new(&bios_data, 0);
+use std::error::Error;
+
+//Options taking a value, the value is the argument after the name
+fn option<'a>(arguments: &'a [String], name: &str) -> Result<Option<&'a String>, Box<dyn Error>> {
+    match arguments.iter().position(|argument| argument == name) {
+        Some(index) => match arguments.get(index + 1) {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("{} needs a value", name).into()),
+        },
+        None => Ok(None),
+    }
+}
+
+fn flag(arguments: &[String], name: &str) -> bool {
+    arguments.iter().any(|argument| argument == name)
+}
+
+//Errors from a file the user named are prefixed with the path so it's clear which one was wrong
+fn with_path<T, E: std::fmt::Display>(path: &str, result: Result<T, E>) -> Result<T, Box<dyn Error>> {
+    result.map_err(|error| format!("{}: {}", path, error).into())
+}
+
+//Versions are major in the high byte and minor in the low one
+fn print_irx_tables(imports: &[io::IrxImport], exports: &[io::IrxExport]) {
+    for import in imports {
+        let indices: Vec<String> = import.stubs.iter().map(|stub| stub.index.to_string()).collect();
+        println!("[IRX]   imports {} {}.{} functions {}", import.library, import.version >> 8, import.version & 0xff, indices.join(", "));
+    }
+
+    for export in exports {
+        println!("[IRX]   exports {} {}.{} with {} functions at {:#x}", export.library, export.version >> 8, export.version & 0xff, export.functions.len(), export.address);
+    }
+}
+
+fn run(arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    //--inspect-irx <module> lists what a module imports and exports without running anything
+    if let Some(path) = option(arguments, "--inspect-irx")? {
+        let module = with_path(path, std::fs::read(path).map_err(|error| error.into()).and_then(|data| io::IrxModule::parse(&data)))?;
+        println!("[IRX] {} {}.{}", module.name, module.version >> 8, module.version & 0xff);
+        print_irx_tables(&module.imports(), &module.exports());
+    }
+
+    //--build-iso <directory> <image> masters a directory on the host into an ISO stamped with the current time
+    //--iso-boot <elf> adds a SYSTEM.CNF that boots that ELF from the image
+    if let Some(index) = arguments.iter().position(|argument| argument == "--build-iso") {
+        match (arguments.get(index + 1), arguments.get(index + 2)) {
+            (Some(directory), Some(image_path)) => {
+                let mut builder = with_path(directory, io::IsoBuilder::from_host_directory(std::path::Path::new(directory), "PS2EMU"))?;
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+                builder.set_recording_date(io::RecordingDate::from_unix_timestamp(now.as_secs() as i64));
+
+                if let Some(elf) = option(arguments, "--iso-boot")? {
+                    builder.set_boot_executable(elf)?;
+                }
 
-    let rd: u8 = 1;
-    let rs: u8 = 2;
-    let rt: u8 = 3;
+                with_path(image_path, builder.write_to_file(std::path::Path::new(image_path)))?;
+                println!("[ISO] wrote {} to {}", directory, image_path);
+            },
+            _ => return Err("--build-iso needs a directory and an image file".into()),
+        }
+    }
 
-    //cpu.write_ee_register_32(rs, 1);
-    //cpu.write_ee_register_32(rt, 2);
+    //--bios <path> boots from a BIOS dump, without one the EE starts on an empty ROM
+    //--bios-hashes <file> checks the dump against a list of known good MD5s
+    //--bios-catalog lists every module in the dump along with its EXTINFO
+    if let Some(path) = option(arguments, "--bios")? {
+        let hash_list = match option(arguments, "--bios-hashes")? {
+            Some(hashes) => with_path(hashes, io::BiosHashList::from_file(hashes))?,
+            None => io::BiosHashList::new(),
+        };
+        let mut reader = with_path(path, io::BiosFileReader::new(path))?;
+        let identification = with_path(path, reader.identify(&hash_list))?;
 
-    //emotion_engine::instruction_impl::ADD(&mut cpu, rd, rs, rt);
+        match identification.known_bios {
+            Some(known_bios) => println!("[BIOS] {} {} ({}) {:?}", known_bios.region, known_bios.revision, known_bios.models.join(", "), identification.status),
+            None => println!("[BIOS] ROMVER {} {:?}", identification.version.romver, identification.status),
+        }
 
-    //println!("{:?}", cpu.read_ee_register_32(rd));
-    
-    //let mut bios_file = io::BiosFileReader::new("/Users/riley/Downloads/ps2_bios/SCPH-70004_BIOS_V12_PAL_200.BIN").unwrap();
+        //Enough to look the dump up by hand or add it to a --bios-hashes file
+        if identification.status != io::BiosDumpStatus::Verified {
+            println!("[BIOS] MD5 {} CRC32 {:08x}, {} bytes", identification.hashes.md5, identification.hashes.crc32, identification.hashes.size);
+        }
 
-    //println!("{:?}", bios_file.get_all_rom_dir_identifiers());
+        for problem in identification.problems {
+            println!("[BIOS] {}", problem);
+        }
 
-    //println!("{:?}", std::str::from_utf8(&bios_file.read_rom_dir_data("ROMVER").unwrap()));
-    //println!("{:?}", bios_file.get_bios_version());
+        if flag(arguments, "--bios-catalog") {
+            for entry in reader.get_rom_dir_catalog() {
+                println!("[BIOS] {}", entry);
+            }
+        }
+    }
 
-    /*let mut iso_file = io::ISOFileReader::new("/Users/riley/Downloads/rc3.iso").unwrap();
+    let roms = match option(arguments, "--bios")? {
+        Some(path) => with_path(path, emotion_engine::RomImages::from_bios_path(path))?,
+        None => emotion_engine::RomImages::new(vec![0u8; 4 * 1024 * 1024]),
+    };
+    let mut system = system::System::from_roms(&roms)?;
 
-    let data = iso_file.read_file("CNF/SYS_NET.ICO;1").unwrap();
+    //--trace-syscalls logs every kernel call the EE makes along with what it returned
+    if flag(arguments, "--trace-syscalls") {
+        system.ee.set_syscall_tracing(true);
+    }
 
-    println!("{:?}", &data);*/
+    //--hle-iop answers the EE's SIF RPCs directly instead of running the IOP's own modules
+    //--host <directory> is where host: paths are read from and written to
+    //--disc <image> puts an ISO, BIN/CUE, lone BIN, CHD or CSO on cdrom0:
+    //--hold-buttons <mask> keeps those buttons held on the pad in port 1, there's no live input yet
+    //All of those are HLE devices so they turn on --hle-iop
+    let (host, disc) = (option(arguments, "--host")?, option(arguments, "--disc")?);
+    let buttons = match option(arguments, "--hold-buttons")? {
+        Some(mask) => Some(u16::from_str_radix(mask.trim_start_matches("0x"), 16).map_err(|_| format!("--hold-buttons needs a hex mask, got {}", mask))?),
+        None => None,
+    };
 
-    /*println!("{:?}", iso_file);
+    if flag(arguments, "--hle-iop") || host.is_some() || disc.is_some() || buttons.is_some() {
+        system.enable_iop_hle();
+    }
+
+    if let Some(hle) = &mut system.iop_hle {
+        if let Some(root) = host {
+            hle.mount_host(root);
+        }
+
+        if let Some(path) = disc {
+            with_path(path, hle.mount_disc(path))?;
+        }
+
+        if let Some(buttons) = buttons {
+            hle.padman.set_buttons(0, buttons);
+        }
+    }
+
+    //--hle-kernel handles the IOP's kernel calls here, --irx <module> (repeatable) loads and starts a module
+    //on it so it turns on --hle-kernel
+    let modules: Vec<&String> = arguments.iter().zip(arguments.iter().skip(1)).filter(|(argument, _)| *argument == "--irx").map(|(_, path)| path).collect();
+
+    if arguments.last().is_some_and(|argument| argument == "--irx") {
+        return Err("--irx needs a value".into());
+    }
+
+    if flag(arguments, "--hle-kernel") || !modules.is_empty() {
+        system.enable_iop_kernel();
+    }
+
+    if let Some(kernel) = &mut system.iop_kernel {
+        for path in modules {
+            let module = with_path(path, std::fs::read(path).map_err(|error| error.into()).and_then(|data| kernel.load_module(&data, &[])))?;
+            println!("[IRX] {} loaded at {:#x}, {} unresolved imports", module.name, module.base, module.unresolved.len());
+            print_irx_tables(&module.imports, &module.exports);
+        }
+    }
+
+    //--tty-log <ee file> <iop file> writes what each processor prints to its own file
+    if let Some(index) = arguments.iter().position(|argument| argument == "--tty-log") {
+        match (arguments.get(index + 1), arguments.get(index + 2)) {
+            (Some(ee_path), Some(iop_path)) => system.log_tty_to_files(ee_path, iop_path)?,
+            _ => return Err("--tty-log needs an EE and an IOP file".into()),
+        }
+    }
+
+    //--cycles <count> runs the EE and IOP for that many EE cycles before exiting
+    let cycles = match option(arguments, "--cycles")? {
+        Some(cycles) => cycles.parse().map_err(|_| format!("--cycles needs a number, got {}", cycles))?,
+        None => 0,
+    };
+    system.run(cycles);
+
+    Ok(())
+}
+
+fn main() {
+    let arguments: Vec<String> = std::env::args().collect();
 
-    println!("{:?}", std::str::from_utf8(&iso_file.primary_volume.volume_set_identifier).unwrap());
-    println!("{:?}", std::str::from_utf8(&iso_file.primary_volume.publisher_identifier).unwrap());
-    println!("{:?}", std::str::from_utf8(&iso_file.primary_volume.data_preparer_identifier).unwrap());
-    println!("{:?}", std::str::from_utf8(&iso_file.primary_volume.application_identifier).unwrap());*/
+    if let Err(error) = run(&arguments) {
+        eprintln!("[PS2EMU] {}", error);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,198 @@
+use super::instructions::Instruction;
+
+//Decoding is split into layers, each CPU tries its own encodings first and falls back to the ones below
+//R5900: instruction_parser -> decode_mips_ii -> decode_mips_i
+//R3000A: io_processor::decoder -> decode_mips_i
+
+#[inline(always)]
+pub fn opcode(word: u32) -> u8 {
+    (word >> 26) as u8
+}
+
+#[inline(always)]
+pub fn rs(word: u32) -> u8 {
+    (word >> 21 & 0x1f) as u8
+}
+
+#[inline(always)]
+pub fn rt(word: u32) -> u8 {
+    (word >> 16 & 0x1f) as u8
+}
+
+#[inline(always)]
+pub fn rd(word: u32) -> u8 {
+    (word >> 11 & 0x1f) as u8
+}
+
+#[inline(always)]
+pub fn sa(word: u32) -> u8 {
+    (word >> 6 & 0x1f) as u8
+}
+
+#[inline(always)]
+pub fn funct(word: u32) -> u8 {
+    (word & 0x3f) as u8
+}
+
+#[inline(always)]
+pub fn imm(word: u32) -> u16 {
+    (word & 0xffff) as u16
+}
+
+#[inline(always)]
+pub fn target(word: u32) -> u32 {
+    word & 0x03ffffff
+}
+
+//SYSCALL and BREAK carry 20 bits, the register traps only 10
+#[inline(always)]
+pub fn code(word: u32) -> u32 {
+    word >> 6 & 0x000fffff
+}
+
+#[inline(always)]
+pub fn trap_code(word: u32) -> u16 {
+    (word >> 6 & 0x03ff) as u16
+}
+
+//MIPS I, understood the same way by both the R3000A and R5900
+pub fn decode_mips_i(word: u32) -> Option<Instruction> {
+    let (rs, rt, rd, sa) = (rs(word), rt(word), rd(word), sa(word));
+    let (imm, off) = (imm(word), imm(word));
+
+    Some(match opcode(word) {
+        0x00 => match funct(word) {
+            0x00 => Instruction::SLL { rt, rd, sa },
+            0x02 => Instruction::SRL { rt, rd, sa },
+            0x03 => Instruction::SRA { rt, rd, sa },
+            0x04 => Instruction::SLLV { rd, rt, rs },
+            0x06 => Instruction::SRLV { rd, rt, rs },
+            0x07 => Instruction::SRAV { rd, rt, rs },
+            0x08 => Instruction::JR { rs },
+            0x09 => Instruction::JALR { rd, rs },
+            0x0c => Instruction::SYSCALL { code: code(word) },
+            0x0d => Instruction::BREAK { code: code(word) },
+            0x10 => Instruction::MFHI { rd },
+            0x11 => Instruction::MTHI { rs },
+            0x12 => Instruction::MFLO { rd },
+            0x13 => Instruction::MTLO { rs },
+            0x18 => Instruction::MULT { rd, rs, rt },
+            0x19 => Instruction::MULTU { rd, rs, rt },
+            0x1a => Instruction::DIV { rs, rt },
+            0x1b => Instruction::DIVU { rs, rt },
+            0x20 => Instruction::ADD { rd, rs, rt },
+            0x21 => Instruction::ADDU { rd, rs, rt },
+            0x22 => Instruction::SUB { rd, rt, rs },
+            0x23 => Instruction::SUBU { rd, rt, rs },
+            0x24 => Instruction::AND { rd, rs, rt },
+            0x25 => Instruction::OR { rd, rs, rt },
+            0x26 => Instruction::XOR { rd, rs, rt },
+            0x27 => Instruction::NOR { rd, rs, rt },
+            0x2a => Instruction::SLT { rd, rt, rs },
+            0x2b => Instruction::SLTU { rd, rt, rs },
+            _ => return None,
+        },
+        0x01 => match rt {
+            0x00 => Instruction::BLTZ { rs, off },
+            0x01 => Instruction::BGEZ { rs, off },
+            0x10 => Instruction::BLTZAL { rs, off },
+            0x11 => Instruction::BGEZAL { rs, off },
+            _ => return None,
+        },
+        0x02 => Instruction::J { off: target(word) },
+        0x03 => Instruction::JAL { off: target(word) },
+        0x04 => Instruction::BEQ { rs, rt, off },
+        0x05 => Instruction::BNE { rs, rt, off },
+        0x06 => Instruction::BLEZ { rs, off },
+        0x07 => Instruction::BGTZ { rs, off },
+        0x08 => Instruction::ADDI { rt, rs, imm },
+        0x09 => Instruction::ADDIU { rt, rs, imm: imm as i16 },
+        0x0a => Instruction::SLTI { rt, rs, imm: imm as i16 },
+        0x0b => Instruction::SLTIU { rt, rs, imm },
+        0x0c => Instruction::ANDI { rt, rs, imm },
+        0x0d => Instruction::ORI { rt, rs, imm },
+        0x0e => Instruction::XORI { rt, rs, imm },
+        0x0f => Instruction::LUI { rt, imm },
+        0x10 => match rs {
+            0x00 => Instruction::MFC0 { rt, rd },
+            0x04 => Instruction::MTC0 { rt, rd },
+            _ => return None,
+        },
+        0x20 => Instruction::LB { rt, off, base: rs },
+        0x21 => Instruction::LH { rt, off, base: rs },
+        0x22 => Instruction::LWL { rt, off, base: rs },
+        0x23 => Instruction::LW { rt, off, base: rs },
+        0x24 => Instruction::LBU { rt, off, base: rs },
+        0x25 => Instruction::LHU { rt, off, base: rs },
+        0x26 => Instruction::LWR { rt, off, base: rs },
+        0x28 => Instruction::SB { rt, off, base: rs },
+        0x29 => Instruction::SH { rt, off, base: rs },
+        0x2a => Instruction::SWL { rt, off, base: rs },
+        0x2b => Instruction::SW { rt, off, base: rs },
+        0x2e => Instruction::SWR { rt, off, base: rs },
+        _ => return None,
+    })
+}
+
+//MIPS II additions, the R5900 has these but the R3000A doesn't
+pub fn decode_mips_ii(word: u32) -> Option<Instruction> {
+    let (rs, rt) = (rs(word), rt(word));
+    let off = imm(word);
+
+    let instruction = match opcode(word) {
+        0x00 => match funct(word) {
+            0x0f => Some(Instruction::SYNC { stype: sa(word) }),
+            0x30 => Some(Instruction::TGE { rs, rt, code: trap_code(word) }),
+            0x31 => Some(Instruction::TGEU { rs, rt, code: trap_code(word) }),
+            0x32 => Some(Instruction::TLT { rs, rt, code: trap_code(word) }),
+            0x33 => Some(Instruction::TLTU { rs, rt, code: trap_code(word) }),
+            0x34 => Some(Instruction::TEQ { rs, rt, code: trap_code(word) }),
+            0x36 => Some(Instruction::TNE { rs, rt, code: trap_code(word) }),
+            _ => None,
+        },
+        0x01 => match rt {
+            0x02 => Some(Instruction::BLTZL { rs, off }),
+            0x03 => Some(Instruction::BGEZL { rs, off }),
+            0x08 => Some(Instruction::TGEI { rs, imm: off as i16 }),
+            0x09 => Some(Instruction::TGEIU { rs, imm: off as i16 }),
+            0x0a => Some(Instruction::TLTI { rs, imm: off as i16 }),
+            0x0b => Some(Instruction::TLTIU { rs, imm: off as i16 }),
+            0x0c => Some(Instruction::TEQI { rs, imm: off as i16 }),
+            0x0e => Some(Instruction::TNEI { rs, imm: off as i16 }),
+            0x12 => Some(Instruction::BLTZALL { rs, off }),
+            0x13 => Some(Instruction::BGEZALL { rs, off }),
+            _ => None,
+        },
+        0x14 => Some(Instruction::BEQL { rs, rt, off }),
+        0x15 => Some(Instruction::BNEL { rs, rt, off }),
+        0x16 => Some(Instruction::BLEZL { rs, off }),
+        0x17 => Some(Instruction::BGTZL { rs, off }),
+        _ => None,
+    };
+
+    instruction.or_else(|| decode_mips_i(word))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_mips_i, decode_mips_ii};
+    use super::super::instructions::Instruction;
+
+    #[test]
+    fn test_decode_layers() {
+        //addiu $sp, $sp, -16
+        assert_eq!(decode_mips_i(0x27bdfff0), Some(Instruction::ADDIU { rt: 29, rs: 29, imm: -16 }));
+        //lw $ra, 12($sp)
+        assert_eq!(decode_mips_i(0x8fbf000c), Some(Instruction::LW { rt: 31, off: 12, base: 29 }));
+        //jr $ra
+        assert_eq!(decode_mips_i(0x03e00008), Some(Instruction::JR { rs: 31 }));
+        //mfc0 $k0, $14
+        assert_eq!(decode_mips_i(0x401a7000), Some(Instruction::MFC0 { rt: 26, rd: 14 }));
+
+        //beql $a0, $zero, +4 only exists from MIPS II up
+        assert_eq!(decode_mips_i(0x50800004), None);
+        assert_eq!(decode_mips_ii(0x50800004), Some(Instruction::BEQL { rs: 4, rt: 0, off: 4 }));
+        assert_eq!(decode_mips_ii(0x0000000f), Some(Instruction::SYNC { stype: 0 }));
+        assert_eq!(decode_mips_ii(0x27bdfff0), decode_mips_i(0x27bdfff0));
+    }
+}
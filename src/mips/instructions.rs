@@ -3,6 +3,9 @@ pub const NOP: Instruction = Instruction::SLL {
     rd: 0,
     sa: 0,
 };
+
+//Decoded form shared by the EE and IOP, see mips::decoder for which CPU understands what
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Instruction {
     ADD {
         rd: u8,
@@ -14,7 +17,7 @@ pub enum Instruction {
         rs: u8,
         imm: u16,
     },
-    ADDUI {
+    ADDIU {
         rt: u8,
         rs: u8,
         imm: i16,
@@ -34,6 +37,30 @@ pub enum Instruction {
         rs: u8,
         imm: u16, 
     },
+    BC1F {
+        off: u16,
+    },
+    BC1FL {
+        off: u16,
+    },
+    BC1T {
+        off: u16,
+    },
+    BC1TL {
+        off: u16,
+    },
+    BC2F {
+        off: u16,
+    },
+    BC2FL {
+        off: u16,
+    },
+    BC2T {
+        off: u16,
+    },
+    BC2TL {
+        off: u16,
+    },
     BEQ {
         rs: u8,
         rt: u8,
@@ -105,6 +132,34 @@ pub enum Instruction {
     BREAK {
         code: u32
     },
+    CACHE {
+        op: u8,
+        off: u16,
+        base: u8,
+    },
+    CFC1 {
+        rt: u8,
+        fs: u8,
+    },
+    CFC2 {
+        rt: u8,
+        rd: u8,
+    },
+    COP1 {
+        fmt: u8,
+        ft: u8,
+        fs: u8,
+        fd: u8,
+        function: u8,
+    },
+    CTC1 {
+        rt: u8,
+        fs: u8,
+    },
+    CTC2 {
+        rt: u8,
+        rd: u8,
+    },
     DADD {
         rd: u8,
         rs: u8,
@@ -125,6 +180,7 @@ pub enum Instruction {
         rs: u8,
         rt: u8,
     },
+    DI,
     DIV {
         rs: u8,
         rt: u8,
@@ -188,6 +244,8 @@ pub enum Instruction {
         rs: u8,
         rt: u8,
     },
+    EI,
+    ERET,
     J {
         off: u32,
     },
@@ -236,6 +294,16 @@ pub enum Instruction {
         off: u16,
         base: u8,
     },
+    LQ {
+        rt: u8,
+        off: u16,
+        base: u8,
+    },
+    LQC2 {
+        ft: u8,
+        off: u16,
+        base: u8,
+    },
     LUI {
         rt: u8,
         imm: u16,
@@ -245,6 +313,11 @@ pub enum Instruction {
         off: u16,
         base: u8,
     },
+    LWC1 {
+        ft: u8,
+        off: u16,
+        base: u8,
+    },
     LWL {
         rt: u8,
         off: u16,
@@ -260,12 +333,30 @@ pub enum Instruction {
         off: u16,
         base: u8,
     },
+    MFC0 {
+        rt: u8,
+        rd: u8,
+    },
+    MFC1 {
+        rt: u8,
+        fs: u8,
+    },
     MFHI {
         rd: u8,
     },
     MFLO {
         rd: u8,
     },
+    MFSA {
+        rd: u8,
+    },
+    MMI {
+        function: u8,
+        sub_function: u8,
+        rd: u8,
+        rs: u8,
+        rt: u8,
+    },
     MOVN {
         rd: u8,
         rs: u8,
@@ -276,17 +367,38 @@ pub enum Instruction {
         rs: u8,
         rt: u8,
     },
+    MTC0 {
+        rt: u8,
+        rd: u8,
+    },
+    MTC1 {
+        rt: u8,
+        fs: u8,
+    },
     MTHI {
         rs: u8,
     },
     MTLO {
         rs: u8,
     },
+    MTSA {
+        rs: u8,
+    },
+    MTSAB {
+        rs: u8,
+        imm: u16,
+    },
+    MTSAH {
+        rs: u8,
+        imm: u16,
+    },
     MULT {
+        rd: u8,
         rs: u8,
         rt: u8,
     },
     MULTU {
+        rd: u8,
         rs: u8,
         rt: u8,
     },
@@ -310,6 +422,15 @@ pub enum Instruction {
         off: u16,
         base: u8,
     },
+    QMFC2 {
+        rt: u8,
+        rd: u8,
+    },
+    QMTC2 {
+        rt: u8,
+        rd: u8,
+    },
+    RFE,
     SB {
         rt: u8,
         off: u16,
@@ -365,6 +486,16 @@ pub enum Instruction {
         rt: u8,
         rs: u8,
     },
+    SQ {
+        rt: u8,
+        off: u16,
+        base: u8,
+    },
+    SQC2 {
+        ft: u8,
+        off: u16,
+        base: u8,
+    },
     SRA {
         rt: u8,
         rd: u8,
@@ -400,6 +531,11 @@ pub enum Instruction {
         off: u16,
         base: u8,
     },
+    SWC1 {
+        ft: u8,
+        off: u16,
+        base: u8,
+    },
     SWL {
         rt: u8,
         off: u16,
@@ -470,6 +606,9 @@ pub enum Instruction {
         rs: u8,
         imm: i16,
     },
+    VU0MACRO {
+        word: u32,
+    },
     XOR {
         rd: u8,
         rs: u8,
@@ -480,4 +619,4 @@ pub enum Instruction {
         rs: u8,
         imm: u16,
     },
-}
\ No newline at end of file
+}
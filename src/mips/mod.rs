@@ -0,0 +1,4 @@
+pub mod decoder;
+mod instructions;
+
+pub use instructions::{Instruction, NOP};
@@ -0,0 +1,51 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+//Everything is timed in EE cycles, the IOP and bus clocks are divided down from it
+pub const EE_CLOCK_HZ: u64 = 294_912_000;
+pub const IOP_CLOCK_DIVIDER: u64 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    //The IOP has caught up to this point and gets its next timeslice
+    IopTimeslice,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    cycles: u64,
+    //Ordered by cycle then by insertion so events due at the same time run in the order they were scheduled
+    events: BinaryHeap<Reverse<(u64, u64, Event)>>,
+    next_sequence: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn schedule(&mut self, event: Event, delay: u64) {
+        self.events.push(Reverse((self.cycles + delay, self.next_sequence, event)));
+        self.next_sequence += 1;
+    }
+
+    pub fn next_event_cycle(&self) -> Option<u64> {
+        self.events.peek().map(|Reverse((cycle, _, _))| *cycle)
+    }
+
+    //Never moves backwards, callers should stop at next_event_cycle so nothing fires late
+    pub fn advance_to(&mut self, cycle: u64) {
+        self.cycles = self.cycles.max(cycle);
+    }
+
+    pub fn pop_due(&mut self) -> Option<Event> {
+        match self.events.peek() {
+            Some(Reverse((cycle, _, _))) if *cycle <= self.cycles => self.events.pop().map(|Reverse((_, _, event))| event),
+            _ => None,
+        }
+    }
+}
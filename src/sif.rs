@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+//The same registers show up on both sides, the EE at 0x1000F200 and the IOP at 0x1D000000
+pub const EE_SIF_BASE: u32 = 0x1000F200;
+pub const IOP_SIF_BASE: u32 = 0x1D000000;
+
+pub const SIF_MSCOM: u32 = 0x00;
+pub const SIF_SMCOM: u32 = 0x10;
+pub const SIF_MSFLG: u32 = 0x20;
+pub const SIF_SMFLG: u32 = 0x30;
+pub const SIF_CTRL: u32 = 0x40;
+pub const SIF_BD6: u32 = 0x60;
+
+//Bits that always read back set in CTRL
+const CTRL_READ_ONES: u32 = 0xF0000102;
+
+//Everything in a FIFO is moved in quadwords, tags are one quadword with the useful part in the first two words
+pub const SIF_TAG_WORDS: usize = 4;
+
+//SIF0 carries data from the IOP to the EE and SIF1 from the EE to the IOP
+//The FIFOs are 32 quadwords on hardware but the DMA controllers here move whole packets so they are left unbounded
+#[derive(Debug, Default)]
+pub struct Sif {
+    mscom: u32,
+    smcom: u32,
+    msflg: u32,
+    smflg: u32,
+    ctrl: u32,
+    bd6: u32,
+    pub sif0_fifo: VecDeque<u32>,
+    pub sif1_fifo: VecDeque<u32>,
+}
+
+//Both CPUs and both DMA controllers hold on to the same SIF
+pub type SharedSif = Rc<RefCell<Sif>>;
+
+pub fn create_sif() -> SharedSif {
+    Rc::new(RefCell::new(Sif::default()))
+}
+
+impl Sif {
+    //Reads have no side effects and look the same from either side
+    pub fn read(&self, register: u32) -> u32 {
+        match register {
+            SIF_MSCOM => self.mscom,
+            SIF_SMCOM => self.smcom,
+            SIF_MSFLG => self.msflg,
+            SIF_SMFLG => self.smflg,
+            SIF_CTRL => self.ctrl | CTRL_READ_ONES,
+            SIF_BD6 => self.bd6,
+            _ => 0,
+        }
+    }
+
+    //Each side owns one mailbox and one set of flags, it can set its own flags and only clear the other side's
+    pub fn write_ee(&mut self, register: u32, value: u32) {
+        match register {
+            SIF_MSCOM => self.mscom = value,
+            SIF_MSFLG => self.msflg |= value,
+            SIF_SMFLG => self.smflg &= !value,
+            SIF_CTRL => {
+                if value & 0x100 != 0 {
+                    self.ctrl |= 0x100;
+                } else {
+                    self.ctrl &= !0x100;
+                }
+            },
+            SIF_BD6 => self.bd6 = 0,
+            _ => {},
+        }
+    }
+
+    pub fn write_iop(&mut self, register: u32, value: u32) {
+        match register {
+            SIF_SMCOM => self.smcom = value,
+            SIF_MSFLG => self.msflg &= !value,
+            SIF_SMFLG => self.smflg |= value,
+            //Bits 4-7 toggle, the IOP uses them to signal a reset of its side
+            SIF_CTRL => {
+                let toggle = value & 0xf0;
+
+                if value & 0xa0 != 0 {
+                    self.ctrl = (self.ctrl & !0xf000) | 0x2000;
+                }
+
+                self.ctrl ^= toggle;
+            },
+            SIF_BD6 => self.bd6 = value,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create_sif, SIF_MSCOM, SIF_MSFLG, SIF_SMCOM, SIF_SMFLG};
+
+    #[test]
+    fn test_mailboxes_and_flags() {
+        let sif = create_sif();
+        let mut sif = sif.borrow_mut();
+
+        sif.write_ee(SIF_MSCOM, 0x1234);
+        sif.write_iop(SIF_SMCOM, 0x5678);
+        //The other side's mailbox is read only
+        sif.write_iop(SIF_MSCOM, 0);
+        sif.write_ee(SIF_SMCOM, 0);
+        assert_eq!(sif.read(SIF_MSCOM), 0x1234);
+        assert_eq!(sif.read(SIF_SMCOM), 0x5678);
+
+        //The IOP sets SMFLG bits as it boots and the EE acknowledges them
+        sif.write_iop(SIF_SMFLG, 0x10000);
+        sif.write_iop(SIF_SMFLG, 0x20000);
+        sif.write_ee(SIF_SMFLG, 0x10000);
+        assert_eq!(sif.read(SIF_SMFLG), 0x20000);
+
+        sif.write_ee(SIF_MSFLG, 0x3);
+        sif.write_iop(SIF_MSFLG, 0x1);
+        assert_eq!(sif.read(SIF_MSFLG), 0x2);
+    }
+}
@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use crate::emotion_engine::{Cpu, RomImages};
+use crate::io_processor::{IopCpu, IopHle, IopKernel};
+use crate::scheduler::{Event, Scheduler, IOP_CLOCK_DIVIDER};
+
+//IOP cycles run each time it is scheduled, smaller is more accurate but slower
+const IOP_TIMESLICE: u64 = 128;
+
+//Owns both processors and keeps them in step
+pub struct System {
+    pub ee: Cpu,
+    pub iop: IopCpu,
+    //Answers SIF commands in place of the IOP when there is no IOP code to run
+    pub iop_hle: Option<IopHle>,
+    //Runs IOP modules without the BIOS kernel
+    pub iop_kernel: Option<IopKernel>,
+    pub scheduler: Scheduler,
+    //IOP TTY log opened before the kernel was enabled, it's handed over once there is one
+    iop_tty_log: Option<Box<dyn Write>>,
+}
+
+impl System {
+    pub fn from_roms(roms: &RomImages) -> Result<System, Box<dyn Error>> {
+        Ok(System::with_ee(Cpu::from_roms(roms)?, &roms.rom0))
+    }
+
+    fn with_ee(ee: Cpu, bios: &[u8]) -> System {
+        let iop = IopCpu::new(bios, ee.memory.iop_ram(), ee.memory.sif());
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::IopTimeslice, IOP_TIMESLICE * IOP_CLOCK_DIVIDER);
+
+        System {
+            ee,
+            iop,
+            iop_hle: None,
+            iop_kernel: None,
+            scheduler,
+            iop_tty_log: None,
+        }
+    }
+
+    //The IOP stops executing and the HLE takes over its side of SIF
+    pub fn enable_iop_hle(&mut self) {
+        self.iop_hle = Some(IopHle::new(self.ee.memory.iop_ram(), self.ee.memory.sif()));
+    }
+
+    //The IOP keeps executing but kernel calls from modules are handled here instead of by the BIOS
+    pub fn enable_iop_kernel(&mut self) {
+        let mut kernel = IopKernel::new(self.ee.memory.iop_ram());
+
+        if let Some(log) = self.iop_tty_log.take() {
+            kernel.tty.log_to(log);
+        }
+
+        self.iop_kernel = Some(kernel);
+    }
+
+    //EE and IOP output go to their own files. The IOP only prints anything with its kernel HLEd, if
+    //that isn't enabled yet its file is kept until it is
+    pub fn log_tty_to_files(&mut self, ee_path: &str, iop_path: &str) -> Result<(), Box<dyn Error>> {
+        self.ee.memory.tty.log_to_file(ee_path)?;
+        let iop_log = Box::new(File::create(iop_path)?);
+
+        match &mut self.iop_kernel {
+            Some(kernel) => kernel.tty.log_to(iop_log),
+            None => self.iop_tty_log = Some(iop_log),
+        }
+
+        Ok(())
+    }
+
+    pub fn run(&mut self, ee_cycles: u64) {
+        let target = self.scheduler.cycles() + ee_cycles;
+
+        while self.scheduler.cycles() < target {
+            let next_event = self.scheduler.next_event_cycle().unwrap_or(target).min(target);
+
+            //The EE runs one instruction per cycle up to the next event
+            while self.scheduler.cycles() < next_event {
+                self.ee.step();
+                self.scheduler.advance_to(self.scheduler.cycles() + 1);
+            }
+
+            while let Some(event) = self.scheduler.pop_due() {
+                self.handle_event(event);
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::IopTimeslice => {
+                //SIF transfers stall whenever one side is waiting on the other, both get another go once the IOP has run
+                match &mut self.iop_hle {
+                    Some(hle) => {
+                        self.ee.memory.run_dma();
+                        hle.process(&self.ee.memory);
+                        self.ee.memory.run_dma();
+                    },
+                    None => {
+                        match &mut self.iop_kernel {
+                            Some(kernel) => kernel.run(&mut self.iop, IOP_TIMESLICE),
+                            None => self.iop.run(IOP_TIMESLICE),
+                        }
+
+                        self.iop.bus.run_dma();
+                        self.ee.memory.run_dma();
+                    },
+                }
+
+                self.scheduler.schedule(Event::IopTimeslice, IOP_TIMESLICE * IOP_CLOCK_DIVIDER);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::System;
+    use crate::emotion_engine::RomImages;
+    use crate::test_utils::TempPath;
+    use std::fs;
+
+    #[test]
+    fn test_iop_runs_at_an_eighth_of_the_ee_clock() {
+        //A zeroed BIOS is all NOPs
+        let mut system = System::from_roms(&RomImages::new(vec![0; 4 * 1024 * 1024])).unwrap();
+
+        system.run(8 * 1024);
+
+        assert_eq!(system.scheduler.cycles(), 8 * 1024);
+        assert_eq!(system.iop.cycles, 1024);
+        assert_eq!(system.iop.pc, 0xBFC00000 + 1024 * 4);
+        assert_eq!(system.ee.pc, 0xBFC00000 + 8 * 1024 * 4);
+
+        //IOP RAM is the same memory the EE sees at 0x1C000000
+        system.iop.bus.write32(0x100, 0x12345678).unwrap();
+        assert_eq!(system.ee.memory.read_address(0x1C000100), 0x78);
+    }
+
+    #[test]
+    fn test_sif_is_shared_between_the_ee_and_iop() {
+        let mut system = System::from_roms(&RomImages::new(vec![0; 4 * 1024 * 1024])).unwrap();
+
+        //MSCOM written by the EE shows up on the IOP side at 0x1D000000
+        system.ee.memory.write_address(0x1000F200, 4, &0xdeadbeefu32.to_le_bytes());
+        assert_eq!(system.iop.bus.read32(0xBD000000), Some(0xdeadbeef));
+
+        //SMFLG is set by the IOP and acknowledged by the EE
+        system.iop.bus.write32(0xBD000030, 0x10000).unwrap();
+        assert_eq!(system.ee.memory.read_address(0x1000F232), 0x01);
+        system.ee.memory.write_address(0x1000F230, 4, &0x10000u32.to_le_bytes());
+        assert_eq!(system.iop.bus.read32(0xBD000030), Some(0));
+    }
+
+    #[test]
+    fn test_iop_tty_log_waits_for_the_kernel() {
+        let (ee_log, iop_log) = (TempPath::new("ee_tty"), TempPath::new("iop_tty"));
+        let mut system = System::from_roms(&RomImages::new(vec![0; 4 * 1024 * 1024])).unwrap();
+        system.log_tty_to_files(ee_log.path().to_str().unwrap(), iop_log.path().to_str().unwrap()).unwrap();
+        system.enable_iop_kernel();
+
+        let tty = &mut system.iop_kernel.as_mut().unwrap().tty;
+        tty.echo = false;
+        tty.write(b"IOP says hi\n");
+
+        assert_eq!(fs::read_to_string(iop_log.path()).unwrap(), "IOP says hi\n");
+        assert_eq!(fs::read_to_string(ee_log.path()).unwrap(), "");
+    }
+}
@@ -0,0 +1,55 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_TEMP_PATH: AtomicUsize = AtomicUsize::new(0);
+
+//Something to hand to code that wants a Write while the test keeps a way to see what was written
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//A file or directory in the temp dir that's removed again when the test is done with it, the name
+//is unique to the process and call so tests running in parallel never share one
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> TempPath {
+        let unique = NEXT_TEMP_PATH.fetch_add(1, Ordering::Relaxed);
+        TempPath(std::env::temp_dir().join(format!("ps2emu_{}_{}_{}", name, process::id(), unique)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = if self.0.is_dir() { fs::remove_dir_all(&self.0) } else { fs::remove_file(&self.0) };
+    }
+}
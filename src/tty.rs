@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+//Debug text from one CPU, held until a whole line is there so the EE and IOP don't end up mixed
+//together mid line. Lines go to stdout with the prefix and to the log as they were printed
+pub struct Tty {
+    prefix: &'static str,
+    line: Vec<u8>,
+    log: Option<Box<dyn Write>>,
+    pub echo: bool,
+}
+
+impl Tty {
+    pub fn new(prefix: &'static str) -> Tty {
+        Tty {
+            prefix,
+            line: vec![],
+            log: None,
+            echo: true,
+        }
+    }
+
+    //Anything already printed isn't written, the file is replaced if it exists
+    pub fn log_to_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.log_to(Box::new(File::create(path)?));
+        Ok(())
+    }
+
+    pub fn log_to(&mut self, log: Box<dyn Write>) {
+        self.log = Some(log);
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        for byte in data.iter().copied() {
+            match byte {
+                b'\n' => self.flush(),
+                //CRLF line endings are common, a lone CR would just garble the console
+                b'\r' => {},
+                byte => self.line.push(byte),
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).to_string();
+        self.line.clear();
+
+        if self.echo {
+            println!("[{}] {}", self.prefix, line);
+        }
+
+        if let Some(log) = &mut self.log {
+            if writeln!(log, "{}", line).is_err() {
+                println!("[{}] Couldn't write to the TTY log, it's been closed", self.prefix);
+                self.log = None;
+            }
+        }
+    }
+}
+
+//Whatever was printed without a newline at the end still shows up
+impl Drop for Tty {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tty;
+    use crate::test_utils::SharedBuffer;
+
+    #[test]
+    fn test_lines_are_logged() {
+        let log = SharedBuffer::new();
+        let mut tty = Tty::new("TEST TTY");
+        tty.echo = false;
+        tty.log_to(Box::new(log.clone()));
+
+        //Nothing is written until the line is finished
+        tty.write(b"loading ");
+        tty.write(b"module\r\nsec");
+        assert_eq!(log.text(), "loading module\n");
+
+        tty.write(b"ond\n");
+        assert_eq!(log.text(), "loading module\nsecond\n");
+
+        //A last line without a newline is flushed when the TTY goes away
+        tty.write(b"exiting");
+        drop(tty);
+        assert_eq!(log.text(), "loading module\nsecond\nexiting\n");
+    }
+}